@@ -0,0 +1,152 @@
+//! Benchmarks for parsing and ORF trimming. clean-genes is a binary crate, so
+//! the modules under test are compiled directly into this benchmark. Their unit
+//! test modules are compiled without being run, leaving test imports unused.
+#![allow(clippy::allow_attributes)]
+#![allow(unused_imports)]
+
+#[path = "../src/alignment.rs"]
+mod alignment;
+#[path = "../src/fasta_manager.rs"]
+mod fasta_manager;
+#[path = "../src/limits.rs"]
+mod limits;
+#[path = "../src/math.rs"]
+mod math;
+#[path = "../src/orf_trimmer.rs"]
+mod orf_trimmer;
+#[path = "../src/packed.rs"]
+mod packed;
+#[path = "../src/progress.rs"]
+mod progress;
+#[path = "../src/translation.rs"]
+mod translation;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use fasta_manager::{
+    open_fasta, read_fasta, write_records, FastaEntry, LineEnding, DEFAULT_LINE_WIDTH,
+};
+use limits::Limits;
+use orf_trimmer::{trim_to_orf, TrimToOrfOptions};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::hint::black_box;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+const FIXTURE: &str = "test_data/a_ha_h3_raw_500.fna";
+const SYNTHETIC_ENTRIES: usize = 50_000;
+
+/// Writes a synthetic alignment of `SYNTHETIC_ENTRIES` sequences built from the
+/// fixture's sequences, wrapped at 60 characters, and returns its path
+fn synthetic_fasta() -> PathBuf {
+    let fixture = open_fasta(FIXTURE).expect("failed to open benchmark fixture");
+    let mut text = String::new();
+    for i in 0..SYNTHETIC_ENTRIES {
+        let entry = fixture.indexed_entry(i % fixture.num_entries());
+        let _ = writeln!(text, ">synthetic_{i}");
+        for line in entry.sequence().chunks(60) {
+            let _ = writeln!(text, "{}", String::from_utf8_lossy(line));
+        }
+    }
+
+    let path = std::env::temp_dir().join("clean_genes_bench_synthetic.fna");
+    std::fs::write(&path, text).expect("failed to write synthetic benchmark input");
+    path
+}
+
+fn bench_open_fasta(c: &mut Criterion) {
+    let synthetic = synthetic_fasta();
+    let mut group = c.benchmark_group("open_fasta");
+    group.bench_function("fixture", |b| b.iter(|| open_fasta(black_box(FIXTURE))));
+    group.sample_size(10);
+    group.bench_function("synthetic_50k", |b| {
+        b.iter(|| open_fasta(black_box(&synthetic)));
+    });
+    group.finish();
+}
+
+fn bench_trim_to_orf(c: &mut Criterion) {
+    let options = TrimToOrfOptions::default();
+    let fixture = open_fasta(FIXTURE).expect("failed to open benchmark fixture");
+    let synthetic = open_fasta(synthetic_fasta()).expect("failed to open synthetic input");
+    let mut group = c.benchmark_group("trim_to_orf");
+    group.bench_function("fixture", |b| {
+        b.iter(|| trim_to_orf(black_box(&fixture), &options));
+    });
+    group.sample_size(10);
+    group.bench_function("synthetic_50k", |b| {
+        b.iter(|| trim_to_orf(black_box(&synthetic), &options));
+    });
+    group.finish();
+}
+
+/// Compares reading and trimming with packed (`--low-memory`) storage, and
+/// prints the heap bytes held by the sequences of the synthetic input when
+/// stored plain, including spare capacity left by reading, and when packed.
+fn bench_low_memory(c: &mut Criterion) {
+    let synthetic = synthetic_fasta();
+    let plain = open_fasta(&synthetic).expect("failed to open synthetic input");
+    let packed = read_fasta(&synthetic, true, false, false, Limits::default())
+        .expect("failed to open synthetic input");
+    let plain_bytes: usize = (&plain)
+        .into_iter()
+        .map(FastaEntry::sequence_heap_size)
+        .sum();
+    let packed_bytes: usize = (&packed)
+        .into_iter()
+        .map(FastaEntry::sequence_heap_size)
+        .sum();
+    println!(
+        "sequence storage for synthetic_50k: plain {plain_bytes} bytes, packed {packed_bytes} bytes"
+    );
+
+    let options = TrimToOrfOptions::default();
+    let mut group = c.benchmark_group("low_memory");
+    group.sample_size(10);
+    group.bench_function("open_synthetic_50k", |b| {
+        b.iter(|| read_fasta(black_box(&synthetic), true, false, false, Limits::default()));
+    });
+    group.bench_function("trim_synthetic_50k", |b| {
+        b.iter(|| trim_to_orf(black_box(&packed), &options));
+    });
+    group.finish();
+}
+
+/// Compares writing the fixture to /dev/null by formatting each record into a
+/// String first, as the output path used to, against writing the records
+/// straight into a buffered writer. Both produce identical bytes.
+fn bench_write_fasta(c: &mut Criterion) {
+    let fixture = open_fasta(FIXTURE).expect("failed to open benchmark fixture");
+    let mut group = c.benchmark_group("write_fasta");
+    group.bench_function("string_per_record", |b| {
+        b.iter(|| {
+            let mut out = File::create("/dev/null").expect("failed to open /dev/null");
+            let mut record = String::new();
+            for entry in &fixture {
+                record.clear();
+                let _ = entry.write_record(&mut record, DEFAULT_LINE_WIDTH);
+                out.write_all(record.as_bytes())
+                    .expect("failed to write to /dev/null");
+            }
+        });
+    });
+    group.bench_function("buffered_direct", |b| {
+        b.iter(|| {
+            let mut out =
+                BufWriter::new(File::create("/dev/null").expect("failed to open /dev/null"));
+            write_records(black_box(&fixture), &mut out, LineEnding::Lf)
+                .and_then(|()| out.flush())
+                .expect("failed to write to /dev/null");
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_open_fasta,
+    bench_trim_to_orf,
+    bench_low_memory,
+    bench_write_fasta
+);
+criterion_main!(benches);
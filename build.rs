@@ -0,0 +1,27 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Records the short hash of the commit being built as `GIT_COMMIT_HASH`, or
+/// `unknown` when git or the repository isn't available, such as when building
+/// from a tarball
+fn main() {
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .filter(|hash| !hash.is_empty())
+        .unwrap_or_else(|| String::from("unknown"));
+    println!("cargo:rustc-env=GIT_COMMIT_HASH={hash}");
+
+    // Rebuild when HEAD moves, but don't force a rebuild every time when there
+    // is no repository to watch
+    for path in [".git/HEAD", ".git/refs/heads"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,588 @@
+use crate::fasta_manager::{is_gap, CleaningPolicy, Fasta, FastaEntry};
+use crate::limits::{LimitError, Limits};
+use crate::math::hamming_distance;
+use std::fmt;
+use std::fmt::Write as _;
+use std::path::Path;
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum AlignmentError {
+    UnequalLengths {
+        defline: String,
+        expected: usize,
+        found: usize,
+    },
+    Limit(LimitError),
+}
+
+impl fmt::Display for AlignmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlignmentError::UnequalLengths {
+                defline,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Sequence '{defline}' has length {found} but the alignment length is {expected}"
+            ),
+            AlignmentError::Limit(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for AlignmentError {}
+
+/// Returns the length shared by every sequence in the alignment, or an error
+/// naming the first sequence whose length differs from the first sequence.
+/// An empty Fasta has an alignment length of 0.
+pub(crate) fn alignment_length(fasta: &Fasta) -> Result<usize, AlignmentError> {
+    let mut entries = fasta.into_iter();
+    let Some(first) = entries.next() else {
+        return Ok(0);
+    };
+
+    let expected = first.sequence().len();
+    for entry in entries {
+        if entry.sequence().len() != expected {
+            return Err(AlignmentError::UnequalLengths {
+                defline: entry.defline(),
+                expected,
+                found: entry.sequence().len(),
+            });
+        }
+    }
+    Ok(expected)
+}
+
+/// The main function of the `TransposeAlignment` module. Treats each column of
+/// the alignment as a sequence, so the output has one entry per column, named
+/// `col_<n>` with columns starting at 0, holding that column's character from
+/// each input sequence in input order.
+pub(crate) fn transpose_alignment(
+    fasta: &Fasta,
+    out_fasta: impl AsRef<Path>,
+) -> Result<Fasta, AlignmentError> {
+    let num_columns = alignment_length(fasta)?;
+    let mut columns: Vec<Vec<u8>> = vec![Vec::with_capacity(fasta.num_entries()); num_columns];
+    for entry in fasta {
+        for (column, &base) in columns.iter_mut().zip(entry.sequence().iter()) {
+            column.push(base);
+        }
+    }
+
+    let mut transposed = Fasta::new(out_fasta);
+    for (i, column) in columns.into_iter().enumerate() {
+        transposed.add(FastaEntry::new(format!("col_{i}"), column, i));
+    }
+    Ok(transposed)
+}
+
+/// The main function of the `SelectBlocks` module, a simplified Gblocks. A
+/// column is conserved when at least `min_occupancy` of the sequences have a
+/// non-gap character there. Only runs of at least `min_block_length`
+/// consecutive conserved columns are kept, so isolated conserved columns
+/// flanking gappy regions are trimmed along with the gappy columns. Returns the
+/// Fasta of kept columns and the kept column indices, starting at 0.
+pub(crate) fn select_blocks(
+    fasta: &Fasta,
+    min_occupancy: f64,
+    min_block_length: usize,
+    out_fasta: impl AsRef<Path>,
+) -> Result<(Fasta, Vec<usize>), AlignmentError> {
+    let num_columns = alignment_length(fasta)?;
+    let mut occupied = vec![0_usize; num_columns];
+    for entry in fasta {
+        for (count, &base) in occupied.iter_mut().zip(entry.sequence().iter()) {
+            if !is_gap(base) {
+                *count += 1;
+            }
+        }
+    }
+
+    #[expect(clippy::cast_precision_loss)]
+    let min_occupied = min_occupancy * fasta.num_entries() as f64;
+    #[expect(clippy::cast_precision_loss)]
+    let conserved: Vec<bool> = occupied
+        .iter()
+        .map(|&count| count > 0 && count as f64 >= min_occupied)
+        .collect();
+
+    let mut kept_columns = Vec::new();
+    let mut column = 0;
+    while column < num_columns {
+        let run_length = conserved[column..]
+            .iter()
+            .take_while(|&&is_conserved| is_conserved)
+            .count();
+        if run_length >= min_block_length {
+            kept_columns.extend(column..column + run_length);
+        }
+        column += run_length.max(1);
+    }
+
+    let mut selected = Fasta::new(out_fasta);
+    for entry in fasta {
+        let sequence = entry.sequence();
+        let kept = kept_columns
+            .iter()
+            .map(|&column| sequence[column])
+            .collect();
+        selected
+            .add(FastaEntry::new(entry.defline(), kept, entry.entry_num()).with_meta_from(entry));
+    }
+    Ok((selected, kept_columns))
+}
+
+/// Returns the majority-rule consensus of the alignment. Each column holds the
+/// most common non-gap character, compared case-insensitively and written in
+/// upper case, with ties going to the character that sorts first. Columns that
+/// are gaps in every sequence are '-'.
+pub(crate) fn consensus(fasta: &Fasta) -> Result<Vec<u8>, AlignmentError> {
+    Ok(column_counts(fasta)?
+        .iter()
+        .map(|column| majority(column).0)
+        .collect())
+}
+
+/// Counts the non-gap characters in each column of the alignment, upper cased
+fn column_counts(fasta: &Fasta) -> Result<Vec<[usize; 256]>, AlignmentError> {
+    let num_columns = alignment_length(fasta)?;
+    let mut counts = vec![[0_usize; 256]; num_columns];
+    for entry in fasta {
+        for (column, &base) in counts.iter_mut().zip(entry.sequence().iter()) {
+            if !is_gap(base) {
+                column[usize::from(base.to_ascii_uppercase())] += 1;
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// Returns the most common character of one column's counts and its count,
+/// with ties going to the character that sorts first, or '-' and 0 for a
+/// column of gaps
+fn majority(column: &[usize; 256]) -> (u8, usize) {
+    let (base, &count) = column
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|&(_, count)| count)
+        .unwrap_or((0, &0));
+    if count == 0 {
+        (b'-', 0)
+    } else {
+        (u8::try_from(base).unwrap_or(b'-'), count)
+    }
+}
+
+/// Returns the fraction of columns where `sequence` matches `consensus`,
+/// ignoring case, out of the columns where either one has a non-gap character.
+/// Columns where either one has a base that `policy` cleans out, beyond gaps,
+/// are skipped. A sequence with nothing to compare has an identity of 0.
+pub(crate) fn identity(sequence: &[u8], consensus: &[u8], policy: CleaningPolicy) -> f64 {
+    let cleaned = |base: u8| !is_gap(base) && policy.removes(base);
+    let mut compared = 0_usize;
+    let mut matches = 0_usize;
+    for (&base, &expected) in sequence.iter().zip(consensus) {
+        if cleaned(base) || cleaned(expected) {
+            continue;
+        }
+        match (is_gap(base), is_gap(expected)) {
+            (true, true) => {}
+            (false, false) if base.eq_ignore_ascii_case(&expected) => {
+                compared += 1;
+                matches += 1;
+            }
+            _ => compared += 1,
+        }
+    }
+    if compared == 0 {
+        return 0.0;
+    }
+    #[expect(clippy::cast_precision_loss)]
+    let identity = matches as f64 / compared as f64;
+    identity
+}
+
+/// The kept entries and the outliers with their identities
+type Flagged<'a> = (Fasta, Vec<(&'a FastaEntry, f64)>);
+
+/// The main function of the `FlagOutliers` module. Builds the consensus of the
+/// alignment and removes entries whose identity to it is below
+/// `min_identity`, so they can be reviewed by hand, with `policy` deciding which
+/// bases are left out of the comparison. The identity of each kept entry is
+/// recorded as `identity` metadata. Returns the kept Fasta and the outliers
+/// with their identities.
+pub(crate) fn flag_outliers(
+    inp_fasta: &Fasta,
+    min_identity: f64,
+    policy: CleaningPolicy,
+    out_fasta: impl AsRef<Path>,
+) -> Result<Flagged<'_>, AlignmentError> {
+    let consensus = consensus(inp_fasta)?;
+    let mut kept_fasta = Fasta::new(out_fasta);
+    let mut outliers = Vec::new();
+    for entry in inp_fasta {
+        let entry_identity = identity(&entry.sequence(), &consensus, policy);
+        if entry_identity < min_identity {
+            outliers.push((entry, entry_identity));
+        } else {
+            let mut kept = entry.clone();
+            kept.set_meta("identity", format!("{entry_identity:.3}"));
+            kept_fasta.add(kept);
+        }
+    }
+    Ok((kept_fasta, outliers))
+}
+
+/// One base changed by `polish_to_consensus`. Columns start at 0.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Correction {
+    pub(crate) defline: String,
+    pub(crate) column: usize,
+    pub(crate) from: u8,
+    pub(crate) to: u8,
+    pub(crate) support: f64,
+}
+
+/// The main function of the `PolishToConsensus` module. Corrects isolated
+/// disagreements with a strongly supported consensus, as left by sequencing or
+/// basecalling errors in closely related sequences. A base is changed to the
+/// consensus base of its column when the fraction of the column's non-gap,
+/// non-N characters that are the consensus base is at least `min_support`, and
+/// the same sequence agrees with the consensus in the columns on either side.
+/// Ns are left out of the consensus, so a base is never changed to N. Gaps and
+/// Ns are never changed, nor are the first and last columns, and corrected
+/// bases keep their case. Returns the polished Fasta and every change made, in
+/// entry order.
+pub(crate) fn polish_to_consensus(
+    inp_fasta: &Fasta,
+    min_support: f64,
+    out_fasta: impl AsRef<Path>,
+) -> Result<(Fasta, Vec<Correction>), AlignmentError> {
+    let columns: Vec<(u8, f64)> = column_counts(inp_fasta)?
+        .iter()
+        .map(|column| {
+            let mut column = *column;
+            column[usize::from(b'N')] = 0;
+            let (base, count) = majority(&column);
+            let num_bases: usize = column.iter().sum();
+            #[expect(clippy::cast_precision_loss)]
+            let support = count as f64 / num_bases.max(1) as f64;
+            (base, support)
+        })
+        .collect();
+    let agrees =
+        |base: u8, column: usize| !is_gap(base) && base.eq_ignore_ascii_case(&columns[column].0);
+
+    let mut polished_fasta = Fasta::new(out_fasta);
+    let mut corrections = Vec::new();
+    for entry in inp_fasta {
+        let mut sequence = entry.sequence().to_vec();
+        for column in 1..sequence.len().saturating_sub(1) {
+            let base = sequence[column];
+            let (consensus_base, support) = columns[column];
+            if is_gap(base)
+                || base.eq_ignore_ascii_case(&b'N')
+                || is_gap(consensus_base)
+                || agrees(base, column)
+                || support < min_support
+                || !agrees(sequence[column - 1], column - 1)
+                || !agrees(sequence[column + 1], column + 1)
+            {
+                continue;
+            }
+            let to = if base.is_ascii_lowercase() {
+                consensus_base.to_ascii_lowercase()
+            } else {
+                consensus_base
+            };
+            sequence[column] = to;
+            corrections.push(Correction {
+                defline: entry.defline(),
+                column,
+                from: base,
+                to,
+                support,
+            });
+        }
+        polished_fasta.add(
+            FastaEntry::new(entry.defline(), sequence, entry.entry_num()).with_meta_from(entry),
+        );
+    }
+    Ok((polished_fasta, corrections))
+}
+
+/// Builds the TSV log of `polish_to_consensus`: one row per changed base with
+/// its defline, column counted from 1, old and new base and the consensus
+/// support
+pub(crate) fn polish_log(corrections: &[Correction]) -> String {
+    let mut log = String::from("defline\tcolumn\tfrom\tto\tsupport\n");
+    for correction in corrections {
+        let _ = writeln!(
+            log,
+            "{}\t{}\t{}\t{}\t{:.3}",
+            correction.defline,
+            correction.column + 1,
+            char::from(correction.from),
+            char::from(correction.to),
+            correction.support
+        );
+    }
+    log
+}
+
+/// The main function of the `NearestNeighbor` module. Builds a TSV with one row
+/// per sequence naming the other sequence with the smallest `hamming_distance`
+/// to it and that distance, for spotting near-identical sequences. Ties go to
+/// the earlier sequence. A lone sequence has no neighbor, so its last two
+/// columns are empty. The time budget in `limits` is checked before each row,
+/// as the work grows with the square of the number of sequences.
+pub(crate) fn nearest_neighbor_table(
+    inp_fasta: &Fasta,
+    limits: &Limits,
+) -> Result<String, AlignmentError> {
+    alignment_length(inp_fasta)?;
+    let entries: Vec<&FastaEntry> = inp_fasta.into_iter().collect();
+    let sequences: Vec<_> = entries.iter().map(|entry| entry.sequence()).collect();
+    let mut table = String::from("defline\tnearest\thamming_distance\n");
+    for (i, entry) in entries.iter().enumerate() {
+        limits.check_time().map_err(AlignmentError::Limit)?;
+        let nearest = sequences
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .filter_map(|(j, other)| Some((hamming_distance(&sequences[i], other)?, j)))
+            .min();
+        let _ = match nearest {
+            Some((distance, j)) => writeln!(
+                table,
+                "{}\t{}\t{distance}",
+                entry.defline(),
+                entries[j].defline()
+            ),
+            None => writeln!(table, "{}\t\t", entry.defline()),
+        };
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        alignment_length, consensus, flag_outliers, identity, nearest_neighbor_table, polish_log,
+        polish_to_consensus, select_blocks, transpose_alignment, AlignmentError,
+    };
+    use crate::fasta_manager::{open_fasta, CleaningPolicy, Fasta, FastaEntry};
+    use crate::limits::{LimitError, Limits};
+    use std::time::Duration;
+
+    fn three_by_four() -> Fasta {
+        let mut fasta = Fasta::new("three_by_four.fna");
+        fasta.add(FastaEntry::new(String::from("a"), b"ACGT".to_vec(), 0));
+        fasta.add(FastaEntry::new(String::from("b"), b"A-GA".to_vec(), 1));
+        fasta.add(FastaEntry::new(String::from("c"), b"TCCN".to_vec(), 2));
+        fasta
+    }
+
+    #[test]
+    fn nearest_neighbors() {
+        assert_eq!(
+            nearest_neighbor_table(&three_by_four(), &Limits::default()).unwrap(),
+            "defline\tnearest\thamming_distance\n\
+            a\tb\t2\n\
+            b\ta\t2\n\
+            c\ta\t3\n"
+        );
+
+        let mut lone = Fasta::new("lone.fna");
+        lone.add(FastaEntry::new(String::from("a"), b"ACGT".to_vec(), 0));
+        assert!(nearest_neighbor_table(&lone, &Limits::default())
+            .unwrap()
+            .ends_with("\na\t\t\n"));
+    }
+
+    #[test]
+    fn nearest_neighbors_time_out() {
+        let limits = Limits::new(None, Some(Duration::from_nanos(1)));
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(
+            nearest_neighbor_table(&three_by_four(), &limits),
+            Err(AlignmentError::Limit(LimitError::TimedOut(
+                Duration::from_nanos(1)
+            )))
+        );
+    }
+
+    #[test]
+    fn polish_single_base_error() {
+        let mut fasta = Fasta::new("polish.fna");
+        for (i, sequence) in [
+            "ATGAAACCC",
+            "ATGAAACCC",
+            "ATGAAATCC",
+            "ATGATACCC",
+            "ATGAAATCC",
+            "atgaaacc-",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            fasta.add(FastaEntry::new(
+                format!("seq{i}"),
+                sequence.as_bytes().to_vec(),
+                i,
+            ));
+        }
+
+        let (polished, corrections) = polish_to_consensus(&fasta, 0.8, "polished.fna").unwrap();
+        // The lone T at column 5 is corrected; the T shared by two sequences
+        // at column 7 is a variant with too little support to touch
+        assert_eq!(&*polished.indexed_entry(3).sequence(), b"ATGAAACCC");
+        assert_eq!(&*polished.indexed_entry(2).sequence(), b"ATGAAATCC");
+        assert_eq!(&*polished.indexed_entry(5).sequence(), b"atgaaacc-");
+        assert_eq!(
+            polish_log(&corrections),
+            "defline\tcolumn\tfrom\tto\tsupport\n\
+            seq3\t5\tT\tA\t0.833\n"
+        );
+
+        let (_, strict) = polish_to_consensus(&fasta, 0.9, "polished.fna").unwrap();
+        assert!(strict.is_empty());
+    }
+
+    #[test]
+    fn polish_never_corrects_to_n() {
+        let mut fasta = Fasta::new("mostly_n.fna");
+        for (i, sequence) in ["ATGAAA", "ANGAAA", "ANGAAA", "ANGAAA"]
+            .into_iter()
+            .enumerate()
+        {
+            fasta.add(FastaEntry::new(
+                format!("seq{i}"),
+                sequence.as_bytes().to_vec(),
+                i,
+            ));
+        }
+        let (polished, corrections) = polish_to_consensus(&fasta, 0.5, "polished.fna").unwrap();
+        assert!(corrections.is_empty());
+        assert_eq!(&*polished.indexed_entry(0).sequence(), b"ATGAAA");
+    }
+
+    #[test]
+    fn transpose_three_by_four() {
+        let transposed = transpose_alignment(&three_by_four(), "./output.fasta").unwrap();
+        assert_eq!(transposed.num_entries(), 4);
+        let expected = [b"AAT", b"C-C", b"GGC", b"TAN"];
+        for (i, entry) in transposed.into_iter().enumerate() {
+            assert_eq!(entry.defline(), format!("col_{i}"));
+            assert_eq!(&*entry.sequence(), expected[i]);
+        }
+    }
+
+    #[test]
+    fn transpose_twice_is_identity() {
+        let fasta = three_by_four();
+        let once = transpose_alignment(&fasta, "once.fna").unwrap();
+        let twice = transpose_alignment(&once, "twice.fna").unwrap();
+        for (orig, round_trip) in fasta.into_iter().zip(&twice) {
+            assert_eq!(orig.sequence(), round_trip.sequence());
+        }
+    }
+
+    #[test]
+    fn unequal_lengths() {
+        let fake_fasta_short = open_fasta("fake_short.fna").unwrap();
+        assert_eq!(alignment_length(&fake_fasta_short), Ok(13));
+
+        let mut fasta = three_by_four();
+        fasta.add(FastaEntry::new(String::from("short"), b"AC".to_vec(), 3));
+        assert_eq!(
+            transpose_alignment(&fasta, "./output.fasta").unwrap_err(),
+            AlignmentError::UnequalLengths {
+                defline: String::from("short"),
+                expected: 4,
+                found: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn select_conserved_blocks() {
+        // columns 0 and 2 are isolated conserved columns, 4-7 are a block of 4,
+        // and 9 is conserved but flanks the end
+        let mut fasta = Fasta::new("blocks.fna");
+        fasta.add(FastaEntry::new(
+            String::from("a"),
+            b"A-T-ACGT-A".to_vec(),
+            0,
+        ));
+        fasta.add(FastaEntry::new(
+            String::from("b"),
+            b"A-T-ACGT-A".to_vec(),
+            1,
+        ));
+        fasta.add(FastaEntry::new(
+            String::from("c"),
+            b"-G--AC-TG-".to_vec(),
+            2,
+        ));
+        fasta.add(FastaEntry::new(
+            String::from("d"),
+            b"--T-ACGT--".to_vec(),
+            3,
+        ));
+
+        let (selected, kept) = select_blocks(&fasta, 0.5, 3, "./output.fasta").unwrap();
+        assert_eq!(kept, [4, 5, 6, 7]);
+        assert_eq!(&*selected.indexed_entry(0).sequence(), b"ACGT");
+        assert_eq!(&*selected.indexed_entry(2).sequence(), b"AC-T");
+
+        let (_, kept) = select_blocks(&fasta, 0.5, 1, "./output.fasta").unwrap();
+        assert_eq!(kept, [0, 2, 4, 5, 6, 7, 9]);
+
+        let (_, kept) = select_blocks(&fasta, 1.0, 1, "./output.fasta").unwrap();
+        assert_eq!(kept, [4, 5, 7]);
+    }
+
+    #[test]
+    fn divergent_sequence_is_outlier() {
+        let mut fasta = Fasta::new("outliers.fna");
+        fasta.add(FastaEntry::new(
+            String::from("a"),
+            b"ATGAAACCCGGG".to_vec(),
+            0,
+        ));
+        fasta.add(FastaEntry::new(
+            String::from("b"),
+            b"ATGAAACCTGGG".to_vec(),
+            1,
+        ));
+        fasta.add(FastaEntry::new(
+            String::from("c"),
+            b"atgaaaccc---".to_vec(),
+            2,
+        ));
+        fasta.add(FastaEntry::new(
+            String::from("odd"),
+            b"TACTTTGGGCCC".to_vec(),
+            3,
+        ));
+
+        assert_eq!(consensus(&fasta).unwrap(), b"ATGAAACCCGGG");
+        let policy = CleaningPolicy::GapsOnly;
+        assert!((identity(b"ATGAAACCC---", b"ATGAAACCCGGG", policy) - 0.75).abs() < 1e-9);
+        assert!((identity(b"ATGNNNCCC", b"ATGAAACCC", policy) - 2.0 / 3.0).abs() < 1e-9);
+        let without_ns = CleaningPolicy::GapsAndNs;
+        assert!((identity(b"ATGNNNCCC", b"ATGAAACCC", without_ns) - 1.0).abs() < 1e-9);
+
+        let (kept, outliers) = flag_outliers(&fasta, 0.7, policy, "./output.fasta").unwrap();
+        let outlier_names: Vec<String> =
+            outliers.iter().map(|(entry, _)| entry.defline()).collect();
+        assert_eq!(outlier_names, ["odd"]);
+        assert!(outliers[0].1 < 0.1);
+        assert_eq!(kept.num_entries(), 3);
+        assert_eq!(kept.indexed_entry(0).get_meta("identity"), Some("1.000"));
+    }
+}
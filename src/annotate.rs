@@ -0,0 +1,54 @@
+use crate::fasta_manager::{Fasta, FastaEntry, SequenceOps};
+use std::path::Path;
+
+/// The main function of the `AnnotateLength` module. Appends ` <key>=<length>`
+/// to each defline, where length is the number of non-gap characters in the
+/// sequence. The rest of the defline and the sequence are unchanged.
+pub(crate) fn annotate_length(
+    inp_fasta: &Fasta,
+    length_key: &str,
+    out_fasta: impl AsRef<Path>,
+) -> Fasta {
+    let mut annotated_fasta = Fasta::new(out_fasta);
+
+    for entry in inp_fasta {
+        let ungapped_len = entry.sequence().degapped().len();
+        let defline = format!("{} {length_key}={ungapped_len}", entry.defline());
+        annotated_fasta.add(
+            FastaEntry::new(defline, entry.sequence().into_owned(), entry.entry_num())
+                .with_meta_from(entry),
+        );
+    }
+
+    annotated_fasta
+}
+
+#[cfg(test)]
+mod test {
+    use super::annotate_length;
+    use crate::fasta_manager::open_fasta;
+
+    #[test]
+    fn annotate_fake_short() {
+        let fake_fasta_short = open_fasta("fake_short.fna").unwrap();
+        let annotated = annotate_length(&fake_fasta_short, "length", "./output.fasta");
+        assert_eq!(annotated.num_entries(), fake_fasta_short.num_entries());
+        assert_eq!(annotated.indexed_entry(0).defline(), "first length=9");
+        assert_eq!(annotated.indexed_entry(2).defline(), "third length=10");
+        assert_eq!(annotated.indexed_entry(7).defline(), "ns length=13");
+        assert_eq!(
+            annotated.indexed_entry(0).sequence(),
+            fake_fasta_short.indexed_entry(0).sequence()
+        );
+    }
+
+    #[test]
+    fn custom_length_key() {
+        let fake_fasta_short = open_fasta("fake_short.fna").unwrap();
+        let annotated = annotate_length(&fake_fasta_short, "ungapped_len", "./output.fasta");
+        assert_eq!(
+            annotated.indexed_entry(8).defline(),
+            "different ungapped_len=9"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use crate::alignment::{alignment_length, AlignmentError};
+use crate::fasta_manager::{is_gap, Fasta, FastaEntry, SequenceOps};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// Why a nucleotide entry could not be threaded through the protein alignment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Mismatch {
+    /// No protein in the alignment has the entry's defline
+    NoProtein,
+    /// The entry's bases are not 3 per residue of its protein, allowing for one
+    /// extra terminal stop codon
+    Length { residues: usize, bases: usize },
+}
+
+impl Mismatch {
+    /// Returns the short code the mismatch is recorded under in the rejects
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            Mismatch::NoProtein => "no_protein",
+            Mismatch::Length { .. } => "length_mismatch",
+        }
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::NoProtein => write!(f, "no protein with this defline"),
+            Mismatch::Length { residues, bases } => {
+                write!(f, "{bases} bases for {residues} residues")
+            }
+        }
+    }
+}
+
+/// The entries of either file that `BackTranslate` could not pair up
+#[derive(Debug, Default)]
+pub(crate) struct BackTranslateReport<'a> {
+    /// Nucleotide entries left out of the output, with the reason
+    pub(crate) mismatched: Vec<(&'a FastaEntry, Mismatch)>,
+    /// Deflines of proteins with no nucleotide entry
+    pub(crate) missing_nucleotides: Vec<String>,
+}
+
+/// Threads one entry's ungapped bases through its aligned protein, one codon
+/// per residue and '---' per gap, or returns why it can't. A terminal stop
+/// codon with no residue of its own is left off, so the result is exactly 3
+/// columns per protein alignment column.
+fn thread_codons(
+    bases: &[u8],
+    aligned_protein: &[u8],
+    stop_codons: &[[u8; 3]],
+) -> Result<Vec<u8>, Mismatch> {
+    let residues = aligned_protein.iter().filter(|&&aa| !is_gap(aa)).count();
+    let length_mismatch = Mismatch::Length {
+        residues,
+        bases: bases.len(),
+    };
+    if !bases.len().is_multiple_of(3) {
+        return Err(length_mismatch);
+    }
+    let terminal_stop = bases.len() == 3 * (residues + 1)
+        && stop_codons
+            .iter()
+            .any(|stop| bases[3 * residues..].same_bases(stop));
+    if bases.len() != 3 * residues && !terminal_stop {
+        return Err(length_mismatch);
+    }
+
+    let mut codons = bases.chunks_exact(3);
+    let mut threaded = Vec::with_capacity(3 * aligned_protein.len());
+    for &aa in aligned_protein {
+        if is_gap(aa) {
+            threaded.extend_from_slice(b"---");
+        } else if let Some(codon) = codons.next() {
+            threaded.extend_from_slice(codon);
+        }
+    }
+    Ok(threaded)
+}
+
+/// The main function of the `BackTranslate` module. Builds a codon alignment by
+/// threading each nucleotide entry, degapped, through the protein with the same
+/// defline in `protein_alignment`: each residue takes the next codon and each
+/// gap becomes '---'. Entries stay in input order and keep their metadata.
+/// Entries with no protein or a length that doesn't match are left out and
+/// returned in the report, along with proteins that have no nucleotide entry.
+/// Where deflines repeat, the first protein is used.
+pub(crate) fn back_translate<'a>(
+    inp_fasta: &'a Fasta,
+    protein_alignment: &Fasta,
+    stop_codons: &[[u8; 3]],
+    out_fasta: impl AsRef<Path>,
+) -> Result<(Fasta, BackTranslateReport<'a>), AlignmentError> {
+    alignment_length(protein_alignment)?;
+    let mut proteins: HashMap<String, &FastaEntry> = HashMap::new();
+    for protein in protein_alignment {
+        proteins.entry(protein.defline()).or_insert(protein);
+    }
+
+    let mut codon_alignment = Fasta::new(out_fasta);
+    let mut report = BackTranslateReport::default();
+    for entry in inp_fasta {
+        let defline = entry.defline();
+        let Some(protein) = proteins.get(&defline) else {
+            report.mismatched.push((entry, Mismatch::NoProtein));
+            continue;
+        };
+        match thread_codons(
+            &entry.sequence().degapped(),
+            &protein.sequence(),
+            stop_codons,
+        ) {
+            Ok(threaded) => {
+                let entry_num = codon_alignment.num_entries();
+                codon_alignment
+                    .add(FastaEntry::new(defline, threaded, entry_num).with_meta_from(entry));
+            }
+            Err(mismatch) => report.mismatched.push((entry, mismatch)),
+        }
+    }
+
+    let nucleotide_deflines: Vec<String> = inp_fasta.into_iter().map(FastaEntry::defline).collect();
+    report.missing_nucleotides = protein_alignment
+        .into_iter()
+        .map(FastaEntry::defline)
+        .filter(|defline| !nucleotide_deflines.contains(defline))
+        .collect();
+    Ok((codon_alignment, report))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{back_translate, Mismatch};
+    use crate::fasta_manager::{Fasta, FastaEntry, SequenceOps};
+    use crate::translation::GeneticCode;
+
+    fn fasta(entries: &[(&str, &str)]) -> Fasta {
+        let mut fasta = Fasta::new("back_translate.fna");
+        for (i, (defline, sequence)) in entries.iter().enumerate() {
+            fasta.add(FastaEntry::new(
+                String::from(*defline),
+                sequence.as_bytes().to_vec(),
+                i,
+            ));
+        }
+        fasta
+    }
+
+    #[test]
+    fn round_trip_codon_alignment() {
+        let nucleotides = fasta(&[
+            ("a", "ATGAAACCCTAA"),
+            ("b", "ATG-CCC"),
+            ("c", "ATGAA"),
+            ("d", "ATG"),
+        ]);
+        let proteins = fasta(&[("a", "MKP"), ("b", "M-P"), ("c", "MK-"), ("e", "MK-")]);
+        let code = GeneticCode::Standard;
+
+        let (codon_alignment, report) =
+            back_translate(&nucleotides, &proteins, &code.stop_codons(), "codons.fna").unwrap();
+        assert_eq!(codon_alignment.num_entries(), 2);
+        assert_eq!(&*codon_alignment.indexed_entry(0).sequence(), b"ATGAAACCC");
+        assert_eq!(&*codon_alignment.indexed_entry(1).sequence(), b"ATG---CCC");
+        for (threaded, protein) in codon_alignment.into_iter().zip(&proteins) {
+            assert_eq!(threaded.sequence().translate(code), &*protein.sequence());
+        }
+
+        let mismatched: Vec<(String, Mismatch)> = report
+            .mismatched
+            .iter()
+            .map(|(entry, mismatch)| (entry.defline(), *mismatch))
+            .collect();
+        assert_eq!(
+            mismatched,
+            [
+                (
+                    String::from("c"),
+                    Mismatch::Length {
+                        residues: 2,
+                        bases: 5
+                    }
+                ),
+                (String::from("d"), Mismatch::NoProtein)
+            ]
+        );
+        assert_eq!(report.missing_nucleotides, ["e"]);
+
+        let unaligned = fasta(&[("a", "MKP"), ("b", "MK")]);
+        assert!(
+            back_translate(&nucleotides, &unaligned, &code.stop_codons(), "codons.fna").is_err()
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use crate::fasta_manager::{Fasta, FastaEntry, SequenceOps};
+use std::fmt::Write;
+
+/// The main function of the `DeflineTable` module. Builds a TSV with one row
+/// per entry: entry number, full defline, accession, gene tag, each remaining
+/// defline field split on `delimiter`, and the degapped sequence length.
+/// Entries whose deflines do not match the `ACCESSION{GENE_TAG}REST` pattern
+/// still get a row, with empty metadata columns. Returns the table and the
+/// number of entries that did not match. Any metadata attached by earlier
+/// modules in a chain follows in one column per key.
+pub(crate) fn defline_table(inp_fasta: &Fasta, delimiter: &str) -> (String, usize) {
+    let parsed: Vec<_> = inp_fasta
+        .into_iter()
+        .map(|entry| (entry, entry.metadata(delimiter)))
+        .collect();
+    let num_fields = parsed
+        .iter()
+        .filter_map(|(_, metadata)| metadata.as_ref().map(|m| m.fields.len()))
+        .max()
+        .unwrap_or(0);
+
+    let mut meta_keys: Vec<&str> = inp_fasta
+        .into_iter()
+        .flat_map(FastaEntry::meta_keys)
+        .collect();
+    meta_keys.sort_unstable();
+    meta_keys.dedup();
+
+    let mut table = String::from("entry_number\tdefline\taccession\tgene_tag");
+    for i in 0..num_fields {
+        let _ = write!(table, "\tfield_{i}");
+    }
+    table.push_str("\tdegapped_length");
+    for key in &meta_keys {
+        let _ = write!(table, "\t{key}");
+    }
+    table.push('\n');
+
+    let mut num_unmatched = 0;
+    for (entry, metadata) in &parsed {
+        let _ = write!(table, "{}\t{}", entry.entry_num(), entry.defline());
+        if let Some(metadata) = metadata {
+            let _ = write!(table, "\t{}\t{}", metadata.accession, metadata.gene_tag);
+            for i in 0..num_fields {
+                let _ = write!(table, "\t{}", metadata.fields.get(i).unwrap_or(&""));
+            }
+        } else {
+            num_unmatched += 1;
+            table.push_str(&"\t".repeat(num_fields + 2));
+        }
+        let _ = write!(table, "\t{}", entry.sequence().degapped().len());
+        for key in &meta_keys {
+            let _ = write!(table, "\t{}", entry.get_meta(key).unwrap_or(""));
+        }
+        table.push('\n');
+    }
+
+    (table, num_unmatched)
+}
+
+#[cfg(test)]
+mod test {
+    use super::defline_table;
+    use crate::fasta_manager::{open_fasta, Fasta, FastaEntry};
+    use crate::filters::{filter_by_field, filter_gappy};
+
+    #[test]
+    fn table_with_fields() {
+        let mut fasta = Fasta::new("meta.fna");
+        fasta.add(FastaEntry::new(
+            String::from("MW585046{A_HA_H3}|H3N2|2019"),
+            b"AT-G".to_vec(),
+            0,
+        ));
+        fasta.add(FastaEntry::new(
+            String::from("CY068505{A_HA_H3}|H3N2"),
+            b"ATGC".to_vec(),
+            1,
+        ));
+        fasta.add(FastaEntry::new(String::from("untagged"), b"A".to_vec(), 2));
+
+        let (table, num_unmatched) = defline_table(&fasta, "|");
+        assert_eq!(num_unmatched, 1);
+        assert_eq!(
+            table,
+            "entry_number\tdefline\taccession\tgene_tag\tfield_0\tfield_1\tdegapped_length\n\
+            0\tMW585046{A_HA_H3}|H3N2|2019\tMW585046\tA_HA_H3\tH3N2\t2019\t3\n\
+            1\tCY068505{A_HA_H3}|H3N2\tCY068505\tA_HA_H3\tH3N2\t\t4\n\
+            2\tuntagged\t\t\t\t\t1\n"
+        );
+    }
+
+    #[test]
+    fn table_for_real_data() {
+        let real_fasta = open_fasta("test_data/a_ha_h3_raw_500.fna").unwrap();
+        let (table, num_unmatched) = defline_table(&real_fasta, "|");
+        assert_eq!(num_unmatched, 0);
+        assert_eq!(table.lines().count(), real_fasta.num_entries() + 1);
+        assert!(table
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("0\tMW585046{A_HA_H3}\tMW585046\tA_HA_H3\t"));
+    }
+
+    #[test]
+    fn metadata_carries_through_chain() {
+        let mut fasta = Fasta::new("chain.fna");
+        fasta.add(FastaEntry::new(String::from("a|keep"), b"AT--".to_vec(), 0));
+        fasta.add(FastaEntry::new(String::from("b|drop"), b"ATGC".to_vec(), 1));
+        fasta.add(FastaEntry::new(String::from("c|keep"), b"A---".to_vec(), 2));
+
+        let (gappy_filtered, _) = filter_gappy(&fasta, 0.5, "./output.fasta");
+        let (field_filtered, _) =
+            filter_by_field(&gappy_filtered, "|", 1, "keep", "./output.fasta");
+        assert_eq!(field_filtered.num_entries(), 1);
+        assert_eq!(
+            field_filtered.indexed_entry(0).get_meta("gap_fraction"),
+            Some("0.500")
+        );
+
+        let (table, _) = defline_table(&field_filtered, "|");
+        assert_eq!(
+            table,
+            "entry_number\tdefline\taccession\tgene_tag\tdegapped_length\tgap_fraction\n\
+            0\ta|keep\t\t\t2\t0.500\n"
+        );
+    }
+}
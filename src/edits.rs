@@ -0,0 +1,118 @@
+use crate::fasta_manager::{is_gap, Fasta};
+
+/// The characters rewritten by `normalize_gaps`. This is wider than `is_gap`,
+/// since these only need to be recognized when cleaning up output.
+const OUTPUT_GAP_CHARS: &[u8] = b"-.~ ";
+
+/// Rewrites every recognized gap character ('-', '.', '~', or space) to `gap`
+/// in place
+pub(crate) fn normalize_gaps(fasta: &mut Fasta, gap: u8) {
+    for entry in fasta {
+        for base in entry.sequence_mut() {
+            if OUTPUT_GAP_CHARS.contains(base) {
+                *base = gap;
+            }
+        }
+    }
+}
+
+/// Uppercases every sequence in place
+pub(crate) fn uppercase_sequences(fasta: &mut Fasta) {
+    for entry in fasta {
+        entry.sequence_mut().make_ascii_uppercase();
+    }
+}
+
+/// Recodes missing data in place, replacing every gap character and every N
+/// (either case) with `missing`
+pub(crate) fn recode_missing(fasta: &mut Fasta, missing: u8) {
+    for entry in fasta {
+        for base in entry.sequence_mut() {
+            if is_gap(*base) || base.eq_ignore_ascii_case(&b'N') {
+                *base = missing;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{normalize_gaps, recode_missing, uppercase_sequences};
+    use crate::fasta_manager::{is_gap, open_fasta, Fasta, FastaEntry};
+
+    /// Applies `edit` to every base by building a new Fasta, the way modules
+    /// did before in-place editing
+    fn copying_edit(fasta: &Fasta, edit: impl Fn(u8) -> u8) -> Fasta {
+        let mut copied = Fasta::new(fasta.filename());
+        for entry in fasta {
+            copied.add(FastaEntry::new(
+                entry.defline(),
+                entry.sequence().iter().map(|&base| edit(base)).collect(),
+                entry.entry_num(),
+            ));
+        }
+        copied
+    }
+
+    fn assert_same_entries(a: &Fasta, b: &Fasta) {
+        assert_eq!(a.num_entries(), b.num_entries());
+        for (x, y) in a.into_iter().zip(b) {
+            assert_eq!(x.defline(), y.defline());
+            assert_eq!(x.sequence(), y.sequence());
+            assert_eq!(x.entry_num(), y.entry_num());
+        }
+    }
+
+    #[test]
+    fn uppercase_matches_copy() {
+        let original = open_fasta("test_data/a_ha_h3_raw_500.fna").unwrap();
+        let copied = copying_edit(&original, |base| base.to_ascii_uppercase());
+        let mut edited = original.clone();
+        uppercase_sequences(&mut edited);
+
+        assert_same_entries(&edited, &copied);
+        assert!(edited.indexed_entry(0).sequence().contains(&b'A'));
+    }
+
+    #[test]
+    fn recode_missing_matches_copy() {
+        let original = open_fasta("fake_short.fna").unwrap();
+        let copied = copying_edit(&original, |base| {
+            if is_gap(base) || base.eq_ignore_ascii_case(&b'N') {
+                b'?'
+            } else {
+                base
+            }
+        });
+        let mut edited = original.clone();
+        recode_missing(&mut edited, b'?');
+
+        assert_same_entries(&edited, &copied);
+        assert_eq!(&*edited.indexed_entry(0).sequence(), b"??ATGATGTAG??");
+    }
+
+    #[test]
+    fn normalize_mixed_gaps() {
+        let mut fasta = Fasta::new("gaps.fna");
+        fasta.add(FastaEntry::new(String::from("mixed"), b"A.C~G".to_vec(), 0));
+        fasta.add(FastaEntry::new(String::from("space"), b"A C-G".to_vec(), 1));
+        normalize_gaps(&mut fasta, b'-');
+        assert_eq!(&*fasta.indexed_entry(0).sequence(), b"A-C-G");
+        assert_eq!(&*fasta.indexed_entry(1).sequence(), b"A-C-G");
+
+        normalize_gaps(&mut fasta, b'.');
+        assert_eq!(&*fasta.indexed_entry(0).sequence(), b"A.C.G");
+    }
+
+    #[test]
+    fn set_defline_in_place() {
+        let mut fasta = open_fasta("fake_short.fna").unwrap();
+        fasta.iter_mut().take(1).for_each(|entry| {
+            let renamed = format!("{}_edited", entry.defline());
+            entry.set_defline(renamed);
+        });
+        assert_eq!(fasta.indexed_entry(0).defline(), "first_edited");
+        assert_eq!(fasta.indexed_entry(1).defline(), "second");
+        assert_eq!(fasta.indexed_entry(8).entry_num(), 8);
+    }
+}
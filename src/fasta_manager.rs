@@ -1,6 +1,21 @@
-use std::{fmt, fs};
+use crate::limits::{LimitedReader, Limits};
+use crate::math::fnv1a_hash;
+use crate::packed::PackedSequence;
+use crate::progress::ProgressReader;
+use crate::translation::GeneticCode;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::{fmt, mem};
+
+/// The number of sequence characters written per line in fasta output
+pub(crate) const DEFAULT_LINE_WIDTH: usize = 60;
 
 ///Represents a fasta file. contains a filename and a vector of `FastaEntry`
+#[derive(Clone)]
 pub(crate) struct Fasta {
     filename: String,
     data: Vec<FastaEntry>,
@@ -8,9 +23,9 @@ pub(crate) struct Fasta {
 
 impl Fasta {
     /// Constructor for Fasta
-    pub(crate) fn new(filename: &str) -> Self {
+    pub(crate) fn new(filename: impl AsRef<Path>) -> Self {
         Fasta {
-            filename: String::from(filename),
+            filename: filename.as_ref().to_string_lossy().into_owned(),
             data: Vec::new(),
         }
     }
@@ -20,40 +35,225 @@ impl Fasta {
         &self.filename
     }
 
+    /// Renames this Fasta, e.g. to the output filename after editing it in
+    /// place
+    pub(crate) fn set_filename(&mut self, filename: impl AsRef<Path>) {
+        self.filename = filename.as_ref().to_string_lossy().into_owned();
+    }
+
+    /// Returns an iterator of mutable references to the entries, for modules
+    /// that edit sequences or deflines in place rather than copying the whole
+    /// Fasta. See `FastaEntry::sequence_mut` and `FastaEntry::set_defline` for
+    /// the invariants edits must keep.
+    pub(crate) fn iter_mut(&mut self) -> std::slice::IterMut<'_, FastaEntry> {
+        self.data.iter_mut()
+    }
+
     /// Add a `FastaEntry` to this Fasta
     pub(crate) fn add(&mut self, new_entry: FastaEntry) {
         self.data.push(new_entry);
     }
 
+    /// Keeps only the entries for which `keep` returns true, in order, and
+    /// renumbers the kept entries so entry numbers still match positions
+    pub(crate) fn retain(&mut self, keep: impl FnMut(&FastaEntry) -> bool) {
+        self.data.retain(keep);
+        self.renumber();
+    }
+
+    /// Sets each entry's number to its position in this Fasta. Modules that
+    /// filter by cloning entries keep the input's numbers, so the pipeline
+    /// renumbers every module's output; each entry's `original_index` is left
+    /// alone.
+    pub(crate) fn renumber(&mut self) {
+        for (entry_num, entry) in self.data.iter_mut().enumerate() {
+            entry.entry_number = entry_num;
+        }
+    }
+
+    /// Removes entries whose defline duplicates an earlier entry's, keeping
+    /// the first occurrence. Each removed entry is reported to stderr. Returns
+    /// the positions the removed entries had before deduplication.
+    pub(crate) fn dedup_by_defline(&mut self) -> Vec<usize> {
+        let mut seen: HashSet<String> = HashSet::new();
+        let keep: Vec<bool> = self
+            .data
+            .iter()
+            .map(|entry| seen.insert(entry.defline()))
+            .collect();
+        self.retain_marked(&keep)
+    }
+
+    /// Removes entries whose defline duplicates a later entry's, keeping the
+    /// last occurrence, e.g. the most recent download when files were
+    /// concatenated. Each removed entry is reported to stderr. Returns the
+    /// positions the removed entries had before deduplication.
+    pub(crate) fn dedup_by_defline_keep_last(&mut self) -> Vec<usize> {
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut keep: Vec<bool> = self
+            .data
+            .iter()
+            .rev()
+            .map(|entry| seen.insert(entry.defline()))
+            .collect();
+        keep.reverse();
+        self.retain_marked(&keep)
+    }
+
+    /// Keeps the entries whose position is marked true in `keep`, reporting
+    /// each removed entry as a duplicate, and returns the removed positions
+    fn retain_marked(&mut self, keep: &[bool]) -> Vec<usize> {
+        let mut removed = Vec::new();
+        let mut position = 0;
+        self.retain(|entry| {
+            let is_kept = keep.get(position).copied().unwrap_or(true);
+            if !is_kept {
+                report_duplicate(entry);
+                removed.push(position);
+            }
+            position += 1;
+            is_kept
+        });
+        removed
+    }
+
     /// Returns the number of `FastaEntry` in this Fasta
     pub(crate) fn num_entries(&self) -> usize {
         self.data.len()
     }
 
     /// Returns a specific `FastaEntry` using its position in the data vector.
-    /// Position starts with 0.
+    /// Position starts with 0. After filtering, this is the position in the
+    /// filtered Fasta, not in the input file; see `FastaEntry::original_index`.
+    /// Panics if the position is out of range, so module code working on user
+    /// data should use `get_entry` instead.
     pub(crate) fn indexed_entry(&self, num_entry: usize) -> &FastaEntry {
         &self.data[num_entry]
     }
+
+    /// Returns a specific `FastaEntry` using its position in the data vector,
+    /// or None if the position is out of range. Position starts with 0.
+    pub(crate) fn get_entry(&self, num_entry: usize) -> Option<&FastaEntry> {
+        self.data.get(num_entry)
+    }
+
+    /// Returns an iterator over every run of `size` consecutive entries, each
+    /// overlapping the last, like `slice::windows`. Yields nothing if `size`
+    /// is greater than the number of entries. Panics if `size` is 0.
+    pub(crate) fn windows(&self, size: usize) -> impl Iterator<Item = Vec<&FastaEntry>> {
+        self.data
+            .windows(size)
+            .map(|window| window.iter().collect())
+    }
+
+    /// Returns an iterator over non-overlapping chunks of `size` entries, like
+    /// `slice::chunks`. The last chunk is shorter if `size` does not divide the
+    /// number of entries. Panics if `size` is 0.
+    pub(crate) fn chunks(&self, size: usize) -> impl Iterator<Item = Vec<&FastaEntry>> {
+        self.data.chunks(size).map(|chunk| chunk.iter().collect())
+    }
+
+    /// Returns the deflines of entries whose sequence is not valid UTF-8. These
+    /// sequences are written lossily, with invalid bytes replaced by U+FFFD.
+    pub(crate) fn non_utf8_entries(&self) -> Vec<String> {
+        self.data
+            .iter()
+            .filter(|entry| std::str::from_utf8(&entry.sequence()).is_err())
+            .map(FastaEntry::defline)
+            .collect()
+    }
+
+    /// Returns the first `FastaEntry` whose defline matches `id`. When
+    /// `ignore_case` is set, ASCII case differences are ignored.
+    pub(crate) fn find_by_id(&self, id: &str, ignore_case: bool) -> Option<&FastaEntry> {
+        self.data.iter().find(|entry| {
+            if ignore_case {
+                entry.defline.eq_ignore_ascii_case(id)
+            } else {
+                entry.defline == id
+            }
+        })
+    }
+
+    /// Returns a stable hash of all sequences in this Fasta concatenated in
+    /// entry order. Deflines are not included.
+    pub(crate) fn sequence_hash(&self) -> u64 {
+        fnv1a_hash(self.data.iter().map(FastaEntry::sequence))
+    }
+
+    /// Returns the full fasta text of this Fasta with sequence lines wrapped at
+    /// `line_width` characters. A `line_width` of 0 disables wrapping.
+    pub(crate) fn to_fasta_string(&self, line_width: usize) -> String {
+        let mut text = String::new();
+        for entry in &self.data {
+            // Writing to a String cannot fail
+            let _ = entry.write_record(&mut text, line_width);
+        }
+        text
+    }
 }
 
-/// For displaying a Fasta simply. Only shows filename
+/// Displays a one line summary of a Fasta for logging: its filename, number of
+/// sequences, and number of columns, taken from the first sequence, or
+/// "variable-length" if the sequences differ in length. Use `to_fasta_string`
+/// for the fasta text.
 impl fmt::Display for Fasta {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "{}", self.filename)
+        write!(
+            f,
+            "Fasta: {} ({} sequences, ",
+            self.filename,
+            self.data.len()
+        )?;
+        let mut lengths = self.data.iter().map(FastaEntry::sequence_len);
+        let first = lengths.next().unwrap_or(0);
+        if lengths.all(|length| length == first) {
+            write!(f, "{first} columns)")
+        } else {
+            write!(f, "variable-length columns)")
+        }
     }
 }
 
-/// For detailed dispay of a Fasta
+/// For detailed dispay of a Fasta. Shows the filename and the defline and
+/// length of every entry. Implemented by hand rather than derived so that
+/// debugging a large alignment does not dump every sequence.
 impl fmt::Debug for Fasta {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries: Vec<(&str, usize)> = self
+            .data
+            .iter()
+            .map(|entry| (entry.defline.as_str(), entry.sequence_len()))
+            .collect();
         fmt.debug_struct("Fasta")
             .field("filename", &self.filename)
-            .field("data", &format_args!("{} sequences", &self.data.len()))
+            .field("num_entries", &self.data.len())
+            .field("entries", &entries)
             .finish()
     }
 }
 
+/// Concatenates two Fastas: the entries of `self` followed by those of `rhs`,
+/// renumbered so entry numbers match positions, under the filename of `self`.
+/// The trait is named in full rather than imported, since with `Add` in scope
+/// `fasta.add(entry)` would resolve to `Add::add` instead of `Fasta::add`.
+impl std::ops::Add<Fasta> for Fasta {
+    type Output = Fasta;
+
+    fn add(mut self, rhs: Fasta) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+/// Appends the entries of `rhs` to this Fasta, renumbered to follow its own
+impl std::ops::AddAssign<Fasta> for Fasta {
+    fn add_assign(&mut self, rhs: Fasta) {
+        self.data.extend(rhs.data);
+        self.renumber();
+    }
+}
+
 /// Allows iteration through the `FastaEntry` in Fasta
 impl IntoIterator for Fasta {
     type Item = FastaEntry; // The type of items we are iterating over
@@ -74,12 +274,32 @@ impl<'a> IntoIterator for &'a Fasta {
     }
 }
 
+/// Allows mutable iteration through the `FastaEntry` in &mut Fasta
+impl<'a> IntoIterator for &'a mut Fasta {
+    type Item = &'a mut FastaEntry;
+    type IntoIter = std::slice::IterMut<'a, FastaEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.iter_mut()
+    }
+}
+
 /// Represents a defline-sequence pair from a fasta file
 #[derive(Clone)]
 pub(crate) struct FastaEntry {
     defline: String,
-    sequence: Vec<u8>,
+    sequence: SequenceData,
     entry_number: usize,
+    original_index: usize,
+    meta: HashMap<String, String>,
+}
+
+/// How a `FastaEntry` stores its sequence. Packed sequences use about half the
+/// memory but are decoded each time they are read.
+#[derive(Clone)]
+enum SequenceData {
+    Plain(Vec<u8>),
+    Packed(PackedSequence),
 }
 
 /// Allows simple display for `FastaEntry`. Shows only the defline and the
@@ -98,26 +318,94 @@ impl fmt::Display for FastaEntry {
 /// than a vector of u8s, which is how it is stored in clean-genes
 impl fmt::Debug for FastaEntry {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let sequence_string = String::from_utf8(self.sequence.clone())
+        let sequence_string = String::from_utf8(self.sequence().into_owned())
             .unwrap_or_else(|_| "Invalid UTF-8 in fasta entry".to_string());
 
         fmt.debug_struct("Fasta")
             .field("defline", &self.defline)
             .field("entry_number", &self.entry_number)
+            .field("original_index", &self.original_index)
             .field("sequence_data", &sequence_string)
+            .field("packed", &matches!(self.sequence, SequenceData::Packed(_)))
+            .field("meta", &self.meta)
             .finish()
     }
 }
 
 impl FastaEntry {
-    /// Constructor for `FastaEntry`
+    /// Constructor for `FastaEntry`. The sequence is taken as it is; use
+    /// `new_checked` where it must hold only valid residues.
     pub(crate) fn new(defline: String, sequence: Vec<u8>, entry_number: usize) -> Self {
-        //do quality check first including looking for empty sequence
-
         FastaEntry {
             defline,
-            sequence,
+            sequence: SequenceData::Plain(sequence),
             entry_number,
+            original_index: entry_number,
+            meta: HashMap::new(),
+        }
+    }
+
+    /// Constructor for `FastaEntry` that first checks the sequence holds only
+    /// characters valid for `molecule_type`. Nucleotide sequences are not
+    /// checked here; the `Validate` module covers them.
+    pub(crate) fn new_checked(
+        defline: String,
+        sequence: Vec<u8>,
+        entry_number: usize,
+        molecule_type: MoleculeType,
+    ) -> Result<Self, ResidueError> {
+        if molecule_type == MoleculeType::Protein {
+            check_protein(&defline, &sequence)?;
+        }
+        Ok(FastaEntry::new(defline, sequence, entry_number))
+    }
+
+    /// Copies the metadata and `original_index` of `source` onto this entry.
+    /// Modules that build a new entry from an input entry use this to carry
+    /// metadata and provenance through a chain unchanged.
+    pub(crate) fn with_meta_from(mut self, source: &FastaEntry) -> Self {
+        self.meta.clone_from(&source.meta);
+        self.original_index = source.original_index;
+        self
+    }
+
+    /// Attaches a metadata value to this entry, such as a computed statistic
+    /// or QC flag, replacing any earlier value for `key`. Metadata describes
+    /// the entry when it was set and is not updated if the sequence changes.
+    pub(crate) fn set_meta(&mut self, key: &str, value: impl fmt::Display) {
+        self.meta.insert(String::from(key), value.to_string());
+    }
+
+    /// Returns the metadata value for `key`, if one was set
+    pub(crate) fn get_meta(&self, key: &str) -> Option<&str> {
+        self.meta.get(key).map(String::as_str)
+    }
+
+    /// Returns the keys of this entry's metadata, in sorted order
+    pub(crate) fn meta_keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self.meta.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Switches this `FastaEntry` to packed sequence storage, which uses about
+    /// half the memory. Reading a packed sequence decodes it on demand.
+    pub(crate) fn pack(&mut self) {
+        if let SequenceData::Plain(sequence) = &self.sequence {
+            self.sequence = SequenceData::Packed(PackedSequence::pack(sequence));
+        }
+    }
+
+    /// Returns true if the sequence is stored packed
+    pub(crate) fn is_packed(&self) -> bool {
+        matches!(self.sequence, SequenceData::Packed(_))
+    }
+
+    /// Returns the number of heap bytes used to store the sequence
+    pub(crate) fn sequence_heap_size(&self) -> usize {
+        match &self.sequence {
+            SequenceData::Plain(sequence) => sequence.capacity(),
+            SequenceData::Packed(packed) => packed.heap_size(),
         }
     }
 
@@ -132,87 +420,2312 @@ impl FastaEntry {
         self.entry_number
     }
 
-    /// Returns the sequence associated with this `FastaEntry`
-    pub(crate) fn sequence(&self) -> &Vec<u8> {
-        &self.sequence
+    /// Returns the position of this `FastaEntry` in the input file it was read
+    /// from, starting at 0, which filtering and renumbering leave unchanged.
+    /// An entry built by a module without `with_meta_from` starts from its own
+    /// entry number.
+    pub(crate) fn original_index(&self) -> usize {
+        self.original_index
+    }
+
+    /// Returns the length of the sequence, without decoding it if it is packed
+    pub(crate) fn sequence_len(&self) -> usize {
+        match &self.sequence {
+            SequenceData::Plain(sequence) => sequence.len(),
+            SequenceData::Packed(packed) => packed.len(),
+        }
+    }
+
+    /// Returns the sequence associated with this `FastaEntry`. This borrows
+    /// the sequence unless it is packed, in which case it is decoded.
+    pub(crate) fn sequence(&self) -> Cow<'_, [u8]> {
+        match &self.sequence {
+            SequenceData::Plain(sequence) => Cow::Borrowed(sequence),
+            SequenceData::Packed(packed) => Cow::Owned(packed.unpack()),
+        }
+    }
+
+    /// Returns the sequence for editing in place, unpacking it first if it is
+    /// packed. The entry number is not affected, so edits must not change
+    /// which record this is; bytes outside ASCII will be written lossily.
+    pub(crate) fn sequence_mut(&mut self) -> &mut Vec<u8> {
+        if let SequenceData::Packed(packed) = &self.sequence {
+            self.sequence = SequenceData::Plain(packed.unpack());
+        }
+        match &mut self.sequence {
+            SequenceData::Plain(sequence) => sequence,
+            SequenceData::Packed(_) => unreachable!("sequence was just unpacked"),
+        }
+    }
+
+    /// Replaces the defline. Being a String, it is always valid UTF-8; it must
+    /// not contain a newline, or the written fasta would gain a record.
+    pub(crate) fn set_defline(&mut self, defline: String) {
+        debug_assert!(!defline.contains('\n'), "defline contains a newline");
+        self.defline = defline;
+    }
+
+    /// Returns true if every base is a gap character, including when the
+    /// sequence is empty
+    pub(crate) fn is_all_gaps(&self) -> bool {
+        self.sequence().iter().all(|&base| is_gap(base))
+    }
+
+    /// Returns true if every non-gap base is N or n. Entries made up only of
+    /// gaps, or empty, also count, since none carry any information.
+    pub(crate) fn is_all_n(&self) -> bool {
+        self.sequence()
+            .iter()
+            .all(|&base| is_gap(base) || base.eq_ignore_ascii_case(&b'N'))
+    }
+
+    /// Returns true if the sequence holds only IUPAC ambiguity codes (N among
+    /// them) and gaps. U counts as a base, and a character that is not a IUPAC
+    /// code at all makes this false.
+    pub(crate) fn is_ambiguous_only(&self) -> bool {
+        self.sequence()
+            .iter()
+            .all(|&base| is_gap(base) || iupac_mask(base).count_ones() > 1)
+    }
+
+    /// Builds a `CoordinateMap` between this entry's alignment columns and its
+    /// ungapped base positions
+    pub(crate) fn coordinate_map(&self) -> CoordinateMap {
+        CoordinateMap::new(&self.sequence())
+    }
+
+    /// Splits the defline on `delimiter` into fields, e.g. an NCBI-style
+    /// `accession|organism|segment` defline. Fields are numbered from 0.
+    pub(crate) fn defline_fields(&self, delimiter: &str) -> Vec<&str> {
+        self.defline.split(delimiter).collect()
+    }
+
+    /// Parses a defline of the form `ACCESSION{GENE_TAG}REST`, splitting the
+    /// remainder on `delimiter`, e.g. `MW585046{A_HA_H3}|H3N2|2019` gives
+    /// fields `["H3N2", "2019"]`. Returns None if the defline has no brace tag.
+    pub(crate) fn metadata(&self, delimiter: &str) -> Option<DeflineMetadata<'_>> {
+        let (accession, rest) = self.defline.split_once('{')?;
+        let (gene_tag, rest) = rest.split_once('}')?;
+        let rest = rest.trim_start();
+        let rest = rest.strip_prefix(delimiter).unwrap_or(rest);
+        let fields = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split(delimiter).collect()
+        };
+
+        Some(DeflineMetadata {
+            accession: accession.trim(),
+            gene_tag,
+            fields,
+        })
+    }
+
+    /// Parses the influenza type, gene, and subtype out of the `{...}` tag of
+    /// a defline following our lab's `ACCESSION{TYPE_GENE_SUBTYPE}` naming,
+    /// e.g. `MW585046{A_HA_H3}`. Returns None if the defline has no tag or the
+    /// tag does not follow the convention.
+    pub(crate) fn gene_tag(&self) -> Option<GeneTag<'_>> {
+        let (_, rest) = self.defline.split_once('{')?;
+        let (tag, _) = rest.split_once('}')?;
+        GeneTag::parse(tag)
+    }
+
+    /// Writes this `FastaEntry` as a fasta record with sequence lines wrapped
+    /// at `line_width` characters. A `line_width` of 0 disables wrapping. All
+    /// fasta output goes through here so the formats cannot drift apart.
+    pub(crate) fn write_record(&self, out: &mut impl fmt::Write, line_width: usize) -> fmt::Result {
+        self.write_record_with_ending(out, line_width, LineEnding::Lf)
+    }
+
+    /// Writes this `FastaEntry` as a fasta record like `write_record`, ending
+    /// every line with `line_ending`
+    pub(crate) fn write_record_with_ending(
+        &self,
+        out: &mut impl fmt::Write,
+        line_width: usize,
+        line_ending: LineEnding,
+    ) -> fmt::Result {
+        self.write_record_pieces(line_width, line_ending, |piece| out.write_str(piece))
+    }
+
+    /// Writes this `FastaEntry` as a fasta record like
+    /// `write_record_with_ending`, but straight to an `io::Write` without
+    /// building the record as a String first
+    pub(crate) fn write_record_to(
+        &self,
+        out: &mut impl io::Write,
+        line_width: usize,
+        line_ending: LineEnding,
+    ) -> io::Result<()> {
+        self.write_record_pieces(line_width, line_ending, |piece| {
+            out.write_all(piece.as_bytes())
+        })
+    }
+
+    /// Passes the pieces of this entry's fasta record to `emit` in order. The
+    /// sequence is only copied when it is not valid UTF-8 and must be written
+    /// lossily.
+    fn write_record_pieces<E>(
+        &self,
+        line_width: usize,
+        line_ending: LineEnding,
+        mut emit: impl FnMut(&str) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let ending = line_ending.as_str();
+        emit(">")?;
+        emit(&self.defline)?;
+        emit(ending)?;
+        let sequence = self.sequence();
+        if line_width == 0 {
+            emit(&String::from_utf8_lossy(&sequence))?;
+            emit(ending)?;
+        } else {
+            for line in sequence.chunks(line_width) {
+                emit(&String::from_utf8_lossy(line))?;
+                emit(ending)?;
+            }
+        }
+        Ok(())
     }
 
     /// Prints the data contained in a `FastaEntry` to stdout.
     pub(crate) fn print_entry(&self) {
-        println!(
-            ">{header}\n{sequence}",
-            header = self.defline(),
-            sequence = String::from_utf8_lossy(self.sequence())
+        let mut stdout = BufWriter::new(io::stdout().lock());
+        let _ = self
+            .write_record_to(&mut stdout, DEFAULT_LINE_WIDTH, LineEnding::Lf)
+            .and_then(|()| stdout.flush());
+    }
+}
+
+/// The metadata packed into a defline, as parsed by `FastaEntry::metadata`
+#[derive(Debug, PartialEq)]
+pub(crate) struct DeflineMetadata<'a> {
+    pub(crate) accession: &'a str,
+    pub(crate) gene_tag: &'a str,
+    pub(crate) fields: Vec<&'a str>,
+}
+
+/// An influenza gene tag such as `A_HA_H3`, as parsed by `FastaEntry::gene_tag`.
+/// Influenza B tags like `B_HA` carry no subtype.
+#[derive(Debug, PartialEq)]
+pub(crate) struct GeneTag<'a> {
+    pub(crate) flu_type: &'a str,
+    pub(crate) gene: &'a str,
+    pub(crate) subtype: Option<&'a str>,
+}
+
+impl<'a> GeneTag<'a> {
+    /// Parses a `TYPE_GENE_SUBTYPE` or `TYPE_GENE` tag
+    fn parse(tag: &'a str) -> Option<Self> {
+        let mut parts = tag.splitn(3, '_');
+        let flu_type = parts.next().filter(|part| !part.is_empty())?;
+        let gene = parts.next().filter(|part| !part.is_empty())?;
+        let subtype = match parts.next() {
+            Some("") => return None,
+            subtype => subtype,
+        };
+        Some(GeneTag {
+            flu_type,
+            gene,
+            subtype,
+        })
+    }
+}
+
+/// Reports an entry removed for having a duplicate defline to stderr
+fn report_duplicate(entry: &FastaEntry) {
+    eprintln!(
+        "Removed entry {} with duplicate defline '{}'",
+        entry.entry_number, entry.defline
+    );
+}
+
+/// Converts between alignment columns and positions in a sequence's ungapped
+/// bases. Built once per sequence; `seq_to_align` is O(1) and the others are
+/// O(log n). Columns and positions both start at 0.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CoordinateMap {
+    base_columns: Vec<usize>,
+    num_columns: usize,
+}
+
+impl CoordinateMap {
+    /// Builds the map for an aligned sequence
+    pub(crate) fn new(sequence: &[u8]) -> Self {
+        CoordinateMap {
+            base_columns: (0..sequence.len())
+                .filter(|&col| !is_gap(sequence[col]))
+                .collect(),
+            num_columns: sequence.len(),
+        }
+    }
+
+    /// Returns the ungapped position of the base in column `col`, or None if
+    /// the column is a gap (including leading and trailing gaps) or is past
+    /// the end of the alignment
+    pub(crate) fn align_to_seq(&self, col: usize) -> Option<usize> {
+        self.base_columns.binary_search(&col).ok()
+    }
+
+    /// Returns the alignment column holding ungapped base `pos`, or None if
+    /// the sequence has fewer than `pos + 1` bases
+    pub(crate) fn seq_to_align(&self, pos: usize) -> Option<usize> {
+        self.base_columns.get(pos).copied()
+    }
+
+    /// Returns the number of bases in the columns before `col`, which is also
+    /// the ungapped position of the first base at or after `col`. Columns past
+    /// the end count every base.
+    pub(crate) fn bases_before(&self, col: usize) -> usize {
+        self.base_columns
+            .partition_point(|&base_col| base_col < col)
+    }
+
+    /// Returns the number of ungapped bases
+    pub(crate) fn num_bases(&self) -> usize {
+        self.base_columns.len()
+    }
+
+    /// Returns the number of alignment columns, gaps included
+    pub(crate) fn num_columns(&self) -> usize {
+        self.num_columns
+    }
+}
+
+/// Returns true if the byte is an alignment gap character ('-' or '.')
+pub(crate) fn is_gap(base: u8) -> bool {
+    base == b'-' || base == b'.'
+}
+
+/// Returns the set of nucleotides a IUPAC code can represent as a bitmask with
+/// A = 1, C = 2, G = 4, T/U = 8. Unrecognized characters (including gaps)
+/// return 0.
+fn iupac_mask(base: u8) -> u8 {
+    match base.to_ascii_uppercase() {
+        b'A' => 0b0001,
+        b'C' => 0b0010,
+        b'G' => 0b0100,
+        b'T' | b'U' => 0b1000,
+        b'R' => 0b0101,
+        b'Y' => 0b1010,
+        b'S' => 0b0110,
+        b'W' => 0b1001,
+        b'K' => 0b1100,
+        b'M' => 0b0011,
+        b'B' => 0b1110,
+        b'D' => 0b1101,
+        b'H' => 0b1011,
+        b'V' => 0b0111,
+        b'N' => 0b1111,
+        _ => 0,
+    }
+}
+
+/// Returns true if the byte is a IUPAC nucleotide code in either case,
+/// including U
+pub(crate) fn is_iupac(base: u8) -> bool {
+    iupac_mask(base) != 0
+}
+
+/// Which characters `clean_sequence` removes from a sequence
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+#[expect(clippy::enum_variant_names)]
+pub(crate) enum CleaningPolicy {
+    /// Only gap characters
+    #[default]
+    GapsOnly,
+    /// Gaps and Ns
+    GapsAndNs,
+    /// Gaps and anything that is not A, C, G, or T/U, so Ns, other IUPAC
+    /// ambiguity codes, and unrecognized characters
+    #[value(name = "gaps-ns-ambiguous")]
+    GapsNsAndAmbiguous,
+}
+
+impl CleaningPolicy {
+    /// Returns true if the policy removes this character, ignoring case
+    pub(crate) fn removes(self, base: u8) -> bool {
+        match self {
+            CleaningPolicy::GapsOnly => is_gap(base),
+            CleaningPolicy::GapsAndNs => is_gap(base) || base.eq_ignore_ascii_case(&b'N'),
+            CleaningPolicy::GapsNsAndAmbiguous => iupac_mask(base).count_ones() != 1,
+        }
+    }
+}
+
+/// Displays the policy as it is given on the command line
+impl fmt::Display for CleaningPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CleaningPolicy::GapsOnly => "gaps-only",
+            CleaningPolicy::GapsAndNs => "gaps-and-ns",
+            CleaningPolicy::GapsNsAndAmbiguous => "gaps-ns-ambiguous",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Returns the sequence with every character the policy removes taken out
+pub(crate) fn clean_sequence(seq: &[u8], policy: CleaningPolicy) -> Vec<u8> {
+    seq.iter()
+        .copied()
+        .filter(|&base| !policy.removes(base))
+        .collect()
+}
+
+/// Returns the IUPAC complement of a nucleotide, preserving case. Gaps and
+/// unrecognized characters are returned unchanged.
+fn complement(base: u8) -> u8 {
+    let comp = match base.to_ascii_uppercase() {
+        b'A' => b'T',
+        b'T' | b'U' => b'A',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'R' => b'Y',
+        b'Y' => b'R',
+        b'K' => b'M',
+        b'M' => b'K',
+        b'B' => b'V',
+        b'V' => b'B',
+        b'D' => b'H',
+        b'H' => b'D',
+        _ => return base,
+    };
+
+    if base.is_ascii_lowercase() {
+        comp.to_ascii_lowercase()
+    } else {
+        comp
+    }
+}
+
+/// The main function of the `ReplaceBase` module. Returns a copy of `fasta`
+/// with every `from` replaced by `to`, e.g. `.` gaps by `-` or U by T. Unless
+/// `case_sensitive` is set, both cases of `from` are replaced with the
+/// uppercase version of `to`.
+pub(crate) fn replace_base(fasta: &Fasta, from: u8, to: u8, case_sensitive: bool) -> Fasta {
+    let mut replaced = Fasta::new(fasta.filename());
+    for entry in fasta {
+        let sequence = entry
+            .sequence()
+            .iter()
+            .map(|&base| {
+                if case_sensitive && base == from {
+                    to
+                } else if !case_sensitive && base.eq_ignore_ascii_case(&from) {
+                    to.to_ascii_uppercase()
+                } else {
+                    base
+                }
+            })
+            .collect();
+        replaced.add(
+            FastaEntry::new(entry.defline(), sequence, entry.entry_num()).with_meta_from(entry),
         );
     }
+    replaced
 }
 
-/// Reads a fasta file and stores it in a Fasta object.
-pub(crate) fn open_fasta(inp_fasta_name: &str) -> Result<Fasta, std::io::Error> {
-    let contents = fs::read_to_string(inp_fasta_name)?;
+/// Common operations on nucleotide sequences stored as bytes. Implemented once
+/// here so every module treats case, gaps, and IUPAC codes the same way.
+pub(crate) trait SequenceOps {
+    /// Returns the reverse complement, preserving case and gaps
+    fn reverse_complement(&self) -> Vec<u8>;
 
-    let mut this_fasta = Fasta::new(inp_fasta_name);
-    let mut last_defline = String::new();
-    let mut last_seq: Vec<u8> = Vec::new();
-    let mut entry_num = 0;
-    for line in contents.lines() {
-        if line.starts_with('>') {
-            if !last_seq.is_empty() {
-                let this_entry = FastaEntry::new(last_defline.clone(), last_seq.clone(), entry_num);
-                this_fasta.add(this_entry);
-                last_seq.clear();
-                entry_num += 1;
-            }
-            if let Some(suffix) = line.strip_prefix(">") {
-                last_defline = String::from(suffix);
+    /// Translates the sequence codon by codon from its first base. A trailing
+    /// partial codon is ignored.
+    fn translate(&self, code: GeneticCode) -> Vec<u8>;
+
+    /// Returns the sequence with all gap characters removed
+    fn degapped(&self) -> Vec<u8>;
+
+    /// Returns the fraction of A, C, G, and T/U bases that are G or C, or None
+    /// if there are no such bases
+    fn gc_fraction(&self) -> Option<f64>;
+
+    /// Returns the number of IUPAC ambiguity codes, including N
+    fn count_ambiguous(&self) -> usize;
+
+    /// Returns true if `other` is the same length and every position could
+    /// represent the same nucleotide under IUPAC ambiguity codes
+    fn iupac_matches(&self, other: &[u8]) -> bool;
+
+    /// Returns true if `other` has exactly the same bases, ignoring case and
+    /// treating U as T
+    fn same_bases(&self, other: &[u8]) -> bool;
+}
+
+impl SequenceOps for [u8] {
+    fn reverse_complement(&self) -> Vec<u8> {
+        self.iter().rev().map(|&base| complement(base)).collect()
+    }
+
+    fn translate(&self, code: GeneticCode) -> Vec<u8> {
+        self.chunks_exact(3)
+            .map(|codon| code.translate_codon(codon))
+            .collect()
+    }
+
+    fn degapped(&self) -> Vec<u8> {
+        clean_sequence(self, CleaningPolicy::GapsOnly)
+    }
+
+    #[expect(clippy::cast_precision_loss)]
+    fn gc_fraction(&self) -> Option<f64> {
+        let mut gc: usize = 0;
+        let mut total: usize = 0;
+        for &base in self {
+            match base.to_ascii_uppercase() {
+                b'G' | b'C' => {
+                    gc += 1;
+                    total += 1;
+                }
+                b'A' | b'T' | b'U' => total += 1,
+                _ => {}
             }
+        }
+
+        if total == 0 {
+            None
         } else {
-            last_seq.extend(line.as_bytes());
+            Some(gc as f64 / total as f64)
         }
     }
 
-    let this_entry = FastaEntry::new(last_defline.clone(), last_seq.clone(), entry_num);
-    this_fasta.add(this_entry);
+    fn count_ambiguous(&self) -> usize {
+        self.iter()
+            .filter(|&&base| iupac_mask(base).count_ones() > 1)
+            .count()
+    }
 
-    Ok(this_fasta)
+    fn iupac_matches(&self, other: &[u8]) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .zip(other)
+                .all(|(&a, &b)| iupac_mask(a) & iupac_mask(b) != 0)
+    }
+
+    fn same_bases(&self, other: &[u8]) -> bool {
+        let normalize = |base: u8| match base.to_ascii_uppercase() {
+            b'U' => b'T',
+            upper => upper,
+        };
+        self.len() == other.len()
+            && self
+                .iter()
+                .zip(other)
+                .all(|(&a, &b)| normalize(a) == normalize(b))
+    }
 }
 
-/// Writes a Fasta object to a fasta file
-pub(crate) fn write_fasta(fasta_obj: &Fasta) {
-    for entry in fasta_obj {
-        entry.print_entry();
+/// The file formats clean-genes can read sequences from
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub(crate) enum InputFormat {
+    Fasta,
+    #[value(name = "genbank")]
+    GenBank,
+    Embl,
+}
+
+/// The kind of sequence an input fasta holds
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub(crate) enum MoleculeType {
+    #[default]
+    Nucleotide,
+    Protein,
+}
+
+/// A character that is not valid for the molecule type of a sequence
+#[derive(Debug, PartialEq)]
+pub(crate) struct ResidueError {
+    defline: String,
+    residue: u8,
+    position: usize,
+}
+
+impl fmt::Display for ResidueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Sequence '{}' has '{}' at position {}, which is not an amino acid, '*', 'X', or a gap",
+            self.defline,
+            self.residue.escape_ascii(),
+            self.position + 1
+        )
     }
 }
 
-mod test {
-    use super::{open_fasta, Fasta};
+impl std::error::Error for ResidueError {}
 
-    const FASTA_NAME_1: &str = "test_data/a_ha_h3_raw_500.fna";
+/// Returns true if the byte is one of the 20 standard amino acids in either
+/// case, the stop indicator '*', the ambiguity character 'X', or a gap
+pub(crate) fn is_protein_char(residue: u8) -> bool {
+    b"ACDEFGHIKLMNPQRSTVWYX*".contains(&residue.to_ascii_uppercase()) || is_gap(residue)
+}
 
-    #[test]
-    fn test_fasta_1() {
-        let mut fasta = test_fasta_file(FASTA_NAME_1, 17);
-        test_fasta_defline(&mut fasta, 0, "MW585046{A_HA_H3}");
-        test_fasta_seq(&mut fasta, 0, "-----------------------------atgaagacaacca------ttattttgatactactgacccattgggcttacagtcaaaa---cccaatcaatg---acaacaacacagccacattgtgtctaggacaccatgcagtagcaaatggaacattggtaaaaacaataagtgatgatcaaattgaggtgacaaatgctacagaattagttcagagcattccaatggggaaaatatgcaacaattcgtatagaattctagat---ggaaagaattgcacattaatagatgcaatgctaggagacccccactgtgacgcctttcagtatgagaattgggacctctttatagaaagaagcagcgccttcagcaattgcta-cccatatgacatccctaactatgcatcgctccgatccattgtagcatcctcaggaacattggaattcacagcagagggattcacatggacaggtgtcactcaaaacggaagaagcggatcctgcaaaaggggatcagccgatagtttctttagccgactgaattggctaacaaaatccggaagctcttaccccacattgaatgtgacaatgcctaacaataaaaacttcgacaagctatacatctgggggatccatcacccgagctcaactaaagagcagacaaaattgtatatccaggaatcagggcgagtaacagtctcaacaaaaagaagtcaacaaacaataatccctaacattgggtctagaccatggatcagaggtcaatcaggtaggataagcatatactggaccattgtaaaacctggagatatcctaatgataaacagtaatggcaacttagttgcaccgcggggatactttaaattgaaaacagggaaaagctctgtaatgagatcagatg---tacccataga-catttgtgtgtctgaat-gtattacaccaaatggaagcatctccaacgacaagccattccaaaatgtgaacaaagttacatatggaaaatgtcccaagtatatcagacaaaacactttaaagctggccactgggatgaggaatgtaccagaaaagcaaatcagaggaatctttggggcaatagcgggattcatcgaaaacggctgggaaggaatggttgatggatggtatgggttccgataccaaaactctgaaggaacagggcaagctgcagatctaaagagcactcaagcagccatcgaccagatcaatggaaagttaaacagagtgattgaaagaaccaatgagaaattccatcaaatagagaaggaattctcagaagtagaaggaagaattcaggacttggagaaatatgtagaagacaccaaaatagacctatggtcctacaatgcagaattgctggtggctctagaaaatcaacatacaattgacttaacagatgcagaaatgaataaattgtttgagagaactagacgcctgttaagagaaaacgcagaagacatgggaggtggatgtttcaagatttaccacaaatgtaataatgcatgcattggatcaataagaaatgggacatatgaccattacatatacagagatgaagcattaaacaaccgatttcagatcaaaggtgtagagttgaaatcaggctacaaagattggatactctggatttcattcgccatatcatgcttcttaatttgcgttgttctattgggttt------------------------------------------------------------------------------------------------------");
-        test_fasta_defline(&mut fasta, 16, "KY583624{A_HA_H3}");
-        test_fasta_seq(&mut fasta, 16, "-----------------------------atgaagactatca------ttgctttgagctacattctatgtctggttttcgctcaaaaaattcctggaaatg---acaatagcacggcaacgctgtgccttgggcaccatgcagtaccaaacggaacgatagtgaaaacaatcacaaatg");
+/// Confirms that a sequence holds only protein characters, naming the first
+/// character that is not
+pub(crate) fn check_protein(defline: &str, sequence: &[u8]) -> Result<(), ResidueError> {
+    match sequence
+        .iter()
+        .position(|&residue| !is_protein_char(residue))
+    {
+        Some(position) => Err(ResidueError {
+            defline: String::from(defline),
+            residue: sequence[position],
+            position,
+        }),
+        None => Ok(()),
     }
+}
 
-    fn test_fasta_file(fasta_name: &str, s: usize) -> Fasta {
-        let fasta = open_fasta(fasta_name).unwrap();
-        assert_eq!(fasta.num_entries(), s);
-        assert_eq!(fasta.filename(), fasta_name);
-        fasta
+/// The line ending written after every line of fasta output. Some Windows
+/// tools expect CRLF.
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub(crate) enum LineEnding {
+    #[default]
+    Lf,
+    #[value(name = "crlf")]
+    CrLf,
+}
+
+impl LineEnding {
+    /// Returns the characters that end a line
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
     }
+}
 
-    fn test_fasta_seq(fasta: &mut Fasta, i: usize, seq: &str) {
-        use std::str;
-        let seq_orig = fasta.indexed_entry(i).sequence();
-        assert_eq!(str::from_utf8(seq_orig).unwrap(), seq);
+impl InputFormat {
+    /// Guesses the format of a file from its extension. GenBank files end in
+    /// .gb or .gbk, EMBL files in .embl or .dat, and everything else is
+    /// treated as fasta.
+    pub(crate) fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("gb") || ext.eq_ignore_ascii_case("gbk") => {
+                InputFormat::GenBank
+            }
+            Some(ext) if ext.eq_ignore_ascii_case("embl") || ext.eq_ignore_ascii_case("dat") => {
+                InputFormat::Embl
+            }
+            _ => InputFormat::Fasta,
+        }
     }
+}
 
-    fn test_fasta_defline(fasta: &mut Fasta, i: usize, defline: &str) {
-        let defline_orig = fasta.indexed_entry(i).defline();
-        assert_eq!(defline_orig, defline);
+/// Reads a sequence file in the given format, or the format implied by its
+/// extension when no format is given, and stores it in a Fasta object. With
+/// `low_memory`, sequences are packed as they are read. `tolerate_formatted`
+/// only applies to fasta, since the flat file readers always strip position
+/// numbers. `progress` likewise only applies to fasta, which is read as a
+/// stream. A file over the byte cap in `limits` is rejected before it is
+/// parsed.
+pub(crate) fn open_input(
+    inp_name: impl AsRef<Path>,
+    format: Option<InputFormat>,
+    low_memory: bool,
+    tolerate_formatted: bool,
+    progress: bool,
+    limits: Limits,
+) -> Result<Fasta, std::io::Error> {
+    limits.check_input_size(fs::metadata(&inp_name)?.len())?;
+    match format.unwrap_or_else(|| InputFormat::from_path(inp_name.as_ref())) {
+        InputFormat::Fasta => {
+            read_fasta(inp_name, low_memory, tolerate_formatted, progress, limits)
+        }
+        format @ (InputFormat::GenBank | InputFormat::Embl) => {
+            let mut fasta = if format == InputFormat::GenBank {
+                open_genbank(inp_name)?
+            } else {
+                open_embl(inp_name)?
+            };
+            if low_memory {
+                fasta.iter_mut().for_each(FastaEntry::pack);
+            }
+            Ok(fasta)
+        }
+    }
+}
+
+/// The fields of a GenBank record used to build a `FastaEntry`
+#[derive(Default)]
+struct GenBankRecord {
+    locus: String,
+    accession: String,
+    definition: String,
+    cds: String,
+    sequence: Vec<u8>,
+}
+
+impl GenBankRecord {
+    /// Converts the record into a `FastaEntry`. The defline is the accession
+    /// (or locus name if there is none) followed by the definition, with the
+    /// location of the first CDS feature appended as ` cds=<location>`.
+    fn into_entry(self, entry_num: usize) -> FastaEntry {
+        let mut defline = if self.accession.is_empty() {
+            self.locus
+        } else {
+            self.accession
+        };
+        if !self.definition.is_empty() {
+            defline.push(' ');
+            defline.push_str(&self.definition);
+        }
+        if !self.cds.is_empty() {
+            defline.push_str(" cds=");
+            defline.push_str(&self.cds);
+        }
+        FastaEntry::new(defline, self.sequence, entry_num)
+    }
+}
+
+/// Reads a GenBank flat file, which may hold several records separated by
+/// "//", and stores it in a Fasta object. Feature tables are ignored apart
+/// from the location of the first CDS.
+pub(crate) fn open_genbank(inp_name: impl AsRef<Path>) -> Result<Fasta, std::io::Error> {
+    let contents = fs::read_to_string(&inp_name)?;
+
+    let mut this_fasta = Fasta::new(inp_name);
+    let mut record = GenBankRecord::default();
+    let mut in_definition = false;
+    let mut in_origin = false;
+    for line in contents.lines() {
+        if line.starts_with("//") {
+            let entry_num = this_fasta.num_entries();
+            this_fasta.add(mem::take(&mut record).into_entry(entry_num));
+            in_definition = false;
+            in_origin = false;
+            continue;
+        }
+
+        if in_origin {
+            record.sequence.extend(
+                line.bytes()
+                    .filter(|base| base.is_ascii_alphabetic() || is_gap(*base)),
+            );
+            continue;
+        }
+
+        //definitions continue onto lines indented past the keyword column
+        if in_definition && line.starts_with(' ') {
+            record.definition.push(' ');
+            record.definition.push_str(line.trim());
+            continue;
+        }
+        in_definition = false;
+
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match keyword {
+            "LOCUS" => {
+                record.locus = rest.split_whitespace().next().unwrap_or("").to_string();
+            }
+            "DEFINITION" => {
+                record.definition = rest.trim().to_string();
+                in_definition = true;
+            }
+            "ACCESSION" => {
+                record.accession = rest.split_whitespace().next().unwrap_or("").to_string();
+            }
+            "ORIGIN" => in_origin = true,
+            "" if record.cds.is_empty() => {
+                if let Some(location) = line.trim_start().strip_prefix("CDS ") {
+                    record.cds = location.trim().to_string();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(this_fasta)
+}
+
+/// Reads an EMBL flat file, which may hold several entries separated by "//",
+/// and stores it in a Fasta object. The defline is the accession from the ID
+/// line followed by the DE description. Annotation lines are skipped, and the
+/// position numbers and spacing in the SQ block are stripped from the sequence.
+pub(crate) fn open_embl(inp_name: impl AsRef<Path>) -> Result<Fasta, std::io::Error> {
+    let contents = fs::read_to_string(&inp_name)?;
+
+    let mut this_fasta = Fasta::new(inp_name);
+    let mut defline = String::new();
+    let mut description = String::new();
+    let mut sequence: Vec<u8> = Vec::new();
+    let mut in_sequence = false;
+    for line in contents.lines() {
+        if line.starts_with("//") {
+            if !description.is_empty() {
+                defline.push(' ');
+                defline.push_str(&description);
+            }
+            let entry_num = this_fasta.num_entries();
+            this_fasta.add(FastaEntry::new(
+                mem::take(&mut defline),
+                mem::take(&mut sequence),
+                entry_num,
+            ));
+            description.clear();
+            in_sequence = false;
+            continue;
+        }
+
+        if in_sequence {
+            sequence.extend(
+                line.bytes()
+                    .filter(|base| base.is_ascii_alphabetic() || is_gap(*base)),
+            );
+            continue;
+        }
+
+        let line_code = line.get(..2).unwrap_or(line);
+        let rest = line.get(2..).unwrap_or("").trim();
+        match line_code {
+            "ID" => defline = rest.split(';').next().unwrap_or("").trim().to_string(),
+            "DE" => {
+                if !description.is_empty() {
+                    description.push(' ');
+                }
+                description.push_str(rest);
+            }
+            "SQ" => in_sequence = true,
+            _ => {}
+        }
+    }
+
+    Ok(this_fasta)
+}
+
+/// Reads a fasta file and stores it in a Fasta object.
+pub(crate) fn open_fasta(inp_fasta_name: impl AsRef<Path>) -> Result<Fasta, std::io::Error> {
+    read_fasta(inp_fasta_name, false, false, false, Limits::default())
+}
+
+/// Looks at the start of a file for signs that it is not fasta at all, and
+/// returns a hint naming the format it seems to be and how to handle it. Only
+/// files that do not start with '>' are examined.
+fn sniff_not_fasta(start: &[u8]) -> Option<&'static str> {
+    if start.starts_with(&[0x1f, 0x8b]) {
+        return Some("it is gzip-compressed; decompress it first, e.g. with gunzip");
+    }
+    if start.starts_with(&[0xef, 0xbb, 0xbf])
+        || start.starts_with(&[0xff, 0xfe])
+        || start.starts_with(&[0xfe, 0xff])
+    {
+        return Some(
+            "it starts with a byte order mark; save it as plain ASCII or UTF-8 without a BOM",
+        );
+    }
+    if start.first().is_none_or(|&byte| byte == b'>') {
+        return None;
+    }
+
+    let mut lines = start.split(|&byte| byte == b'\n').map(trim_line_ending);
+    let first_line = lines.next().unwrap_or_default();
+    if first_line.starts_with(b"@") && lines.nth(1).is_some_and(|line| line.starts_with(b"+")) {
+        return Some("it looks like FASTQ, which is not supported; convert it to fasta first");
+    }
+    if first_line.starts_with(b"LOCUS") {
+        return Some("it looks like GenBank; use --in-format genbank or a .gb extension");
+    }
+    if first_line.starts_with(b"ID   ") {
+        return Some("it looks like EMBL; use --in-format embl or a .embl extension");
+    }
+    if first_line.split(|&byte| byte == b',').count() > 2 {
+        return Some("it looks like a CSV table rather than sequences");
+    }
+    None
+}
+
+/// Opens a fasta file for reading, failing with a hint if it is clearly some
+/// other format. With `progress`, the percent of the file read is shown on
+/// stderr when it is a terminal. Reading fails once it goes past `limits`.
+fn open_fasta_reader(
+    inp_fasta_name: impl AsRef<Path>,
+    progress: bool,
+    limits: Limits,
+) -> Result<BufReader<ProgressReader<LimitedReader<File>>>, io::Error> {
+    let file = File::open(&inp_fasta_name)?;
+    let total_bytes = if progress && io::stderr().is_terminal() {
+        Some(file.metadata()?.len())
+    } else {
+        None
+    };
+    let label = inp_fasta_name.as_ref().display().to_string();
+    let file = LimitedReader::new(file, limits);
+    let mut reader = BufReader::new(ProgressReader::new(file, label, total_bytes));
+    if let Some(hint) = sniff_not_fasta(reader.fill_buf()?) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("input does not look like a fasta file: {hint}"),
+        ));
+    }
+    Ok(reader)
+}
+
+/// Builds an entry from a parsed fasta record. If formatting characters were
+/// stripped from its sequence lines, the count is kept in the `stripped_chars`
+/// metadata so the cleanup never goes unreported.
+fn parsed_entry(
+    defline: String,
+    sequence: Vec<u8>,
+    entry_num: usize,
+    num_stripped: usize,
+    low_memory: bool,
+) -> FastaEntry {
+    let mut this_entry = FastaEntry::new(defline, sequence, entry_num);
+    if num_stripped > 0 {
+        this_entry.set_meta("stripped_chars", num_stripped);
+    }
+    if low_memory {
+        this_entry.pack();
+    }
+    this_entry
+}
+
+/// Reads a fasta file and stores it in a Fasta object, packing each sequence
+/// as soon as it is complete when `low_memory` is set so that only one
+/// unpacked sequence is held at a time. With `tolerate_formatted`, spaces and
+/// digits inside sequence lines are stripped, so that numbered, space-separated
+/// blocks such as "   61 atgaccaatg ccaccattgc" parse to the bare sequence.
+/// With `progress`, the percent of the file read is shown on stderr. Reading
+/// stops with an error once it goes past `limits`. Lines starting with ';' are
+/// legacy fasta comments and are skipped.
+pub(crate) fn read_fasta(
+    inp_fasta_name: impl AsRef<Path>,
+    low_memory: bool,
+    tolerate_formatted: bool,
+    progress: bool,
+    limits: Limits,
+) -> Result<Fasta, std::io::Error> {
+    let mut reader = open_fasta_reader(&inp_fasta_name, progress, limits)?;
+
+    let mut this_fasta = Fasta::new(inp_fasta_name);
+    let mut last_defline = String::new();
+    let mut last_seq: Vec<u8> = Vec::new();
+    let mut defline_buf: Vec<u8> = Vec::new();
+    let mut entry_num = 0;
+    let mut found_record = false;
+    let mut found_defline = false;
+    let mut line_num = 1;
+    let mut whitespace_lines = 0;
+    let mut num_stripped = 0;
+    let mut stripped_entries = 0;
+    let mut total_stripped = 0;
+    //peek at the first byte of each line to decide where it should be read
+    while let Some(&first_byte) = reader.fill_buf()?.first() {
+        //legacy comment lines are skipped wherever they appear
+        if first_byte == b';' {
+            reader.skip_until(b'\n')?;
+            line_num += 1;
+            continue;
+        }
+        found_record = true;
+        if first_byte == b'>' {
+            //a defline with no sequence is still an entry, so empty records
+            //reach the modules that count and report them
+            if found_defline || !last_seq.is_empty() {
+                //the previous sequence length is a good estimate for the next
+                //one in an alignment, so pre-size to avoid regrowing
+                let seq_len = last_seq.len();
+                this_fasta.add(parsed_entry(
+                    mem::take(&mut last_defline),
+                    mem::take(&mut last_seq),
+                    entry_num,
+                    num_stripped,
+                    low_memory,
+                ));
+                last_seq.reserve(seq_len);
+                entry_num += 1;
+            }
+            if num_stripped > 0 {
+                stripped_entries += 1;
+                total_stripped += num_stripped;
+                num_stripped = 0;
+            }
+
+            defline_buf.clear();
+            reader.read_until(b'\n', &mut defline_buf)?;
+            last_defline = String::from_utf8(trim_line_ending(&defline_buf[1..]).to_vec())
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            found_defline = true;
+        } else {
+            let line_start = last_seq.len();
+            if read_sequence_line(&mut reader, &mut last_seq, line_num)? {
+                whitespace_lines += 1;
+            }
+            if tolerate_formatted {
+                num_stripped += strip_formatting(&mut last_seq, line_start);
+            }
+        }
+        line_num += 1;
+    }
+
+    if whitespace_lines > 0 {
+        eprintln!(
+            "Warning: ignored {whitespace_lines} sequence line(s) containing only whitespace in '{}'",
+            this_fasta.filename()
+        );
+    }
+
+    //an empty file contains no entries at all
+    if found_record {
+        if num_stripped > 0 {
+            stripped_entries += 1;
+            total_stripped += num_stripped;
+        }
+        this_fasta.add(parsed_entry(
+            last_defline,
+            last_seq,
+            entry_num,
+            num_stripped,
+            low_memory,
+        ));
+    } else {
+        eprintln!(
+            "Warning: '{}' is empty, so it contains no sequences",
+            this_fasta.filename()
+        );
+    }
+
+    if stripped_entries > 0 {
+        eprintln!(
+            "Warning: stripped {total_stripped} space or digit character(s) from the sequence \
+            lines of {stripped_entries} entr(y/ies) in '{}'",
+            this_fasta.filename()
+        );
+    }
+
+    Ok(this_fasta)
+}
+
+/// Reads one sequence line straight onto the end of `sequence`, then trims it
+/// of surrounding whitespace in place. Returns true if the line held only
+/// whitespace, or an error if it holds a '>'.
+fn read_sequence_line(
+    reader: &mut impl BufRead,
+    sequence: &mut Vec<u8>,
+    line_num: usize,
+) -> io::Result<bool> {
+    let line_start = sequence.len();
+    reader.read_until(b'\n', sequence)?;
+    let line = trim_line_ending(&sequence[line_start..]);
+    if line.contains(&b'>') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "line {line_num} has a '>' inside a sequence line; deflines must start \
+                with '>'"
+            ),
+        ));
+    }
+    let leading = line
+        .iter()
+        .take_while(|byte| byte.is_ascii_whitespace())
+        .count();
+    let line_len = line.trim_ascii().len();
+    let whitespace_only = line_len == 0 && !line.is_empty();
+    sequence.copy_within(
+        line_start + leading..line_start + leading + line_len,
+        line_start,
+    );
+    sequence.truncate(line_start + line_len);
+    Ok(whitespace_only)
+}
+
+/// Removes spaces and digits from `sequence` starting at `start`, returning
+/// how many were removed
+fn strip_formatting(sequence: &mut Vec<u8>, start: usize) -> usize {
+    let mut kept = start;
+    for i in start..sequence.len() {
+        let byte = sequence[i];
+        if !byte.is_ascii_whitespace() && !byte.is_ascii_digit() {
+            sequence[kept] = byte;
+            kept += 1;
+        }
+    }
+    let num_stripped = sequence.len() - kept;
+    sequence.truncate(kept);
+    num_stripped
+}
+
+/// Removes a trailing "\n" or "\r\n" from a line
+fn trim_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Collects the entries removed by filtering modules into one rejects fasta,
+/// `<prefix>.fna`, whose deflines are annotated with the module and reason,
+/// and a companion TSV, `<prefix>.tsv`, with one row of defline, module,
+/// reason, metric value, and position in the input file, counted from 1, per
+/// rejected entry. Both files are created on the
+/// first rejection, so a run that rejects nothing writes nothing, and every
+/// module in a run shares the same pair. Like `write_atomically`, they are
+/// written as `.partial` files that `finish` renames into place, so a failed
+/// run never leaves truncated rejects at the final paths.
+pub(crate) struct Rejects {
+    prefix: PathBuf,
+    writers: Option<(BufWriter<File>, BufWriter<File>)>,
+    num_rejected: usize,
+}
+
+impl Rejects {
+    /// Constructor for `Rejects`. No files are created until the first entry
+    /// is added.
+    pub(crate) fn new(prefix: impl AsRef<Path>) -> Self {
+        Rejects {
+            prefix: prefix.as_ref().to_path_buf(),
+            writers: None,
+            num_rejected: 0,
+        }
+    }
+
+    /// Returns the path of the rejects fasta
+    pub(crate) fn fasta_path(&self) -> PathBuf {
+        self.path_with_suffix(".fna")
+    }
+
+    /// Returns the path of the rejects TSV
+    pub(crate) fn table_path(&self) -> PathBuf {
+        self.path_with_suffix(".tsv")
+    }
+
+    fn path_with_suffix(&self, suffix: &str) -> PathBuf {
+        let mut path = self.prefix.as_os_str().to_owned();
+        path.push(suffix);
+        PathBuf::from(path)
+    }
+
+    /// Returns the number of entries rejected so far
+    pub(crate) fn num_rejected(&self) -> usize {
+        self.num_rejected
+    }
+
+    /// Records a rejected entry. `reason` is a short code such as
+    /// `gap_fraction`, and `metric` the value that caused the rejection, if
+    /// there is one.
+    pub(crate) fn add(
+        &mut self,
+        entry: &FastaEntry,
+        module: &str,
+        reason: &str,
+        metric: Option<f64>,
+    ) -> io::Result<()> {
+        if self.writers.is_none() {
+            let fasta_writer = BufWriter::new(File::create(partial_path(&self.fasta_path()))?);
+            let mut table_writer = BufWriter::new(File::create(partial_path(&self.table_path()))?);
+            writeln!(
+                table_writer,
+                "defline\tmodule\treason\tmetric\tinput_position"
+            )?;
+            self.writers = Some((fasta_writer, table_writer));
+        }
+        let Some((fasta_writer, table_writer)) = self.writers.as_mut() else {
+            unreachable!("rejects writers were just created");
+        };
+
+        let annotated = FastaEntry::new(
+            format!("{} module={module} reason={reason}", entry.defline),
+            entry.sequence().into_owned(),
+            self.num_rejected,
+        );
+        annotated.write_record_to(fasta_writer, DEFAULT_LINE_WIDTH, LineEnding::Lf)?;
+
+        let metric = metric.map(|value| value.to_string()).unwrap_or_default();
+        writeln!(
+            table_writer,
+            "{}\t{module}\t{reason}\t{metric}\t{}",
+            entry.defline,
+            entry.original_index + 1
+        )?;
+        self.num_rejected += 1;
+        Ok(())
+    }
+
+    /// Flushes any rejects written so far and moves them to their final paths
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        if let Some((fasta_writer, table_writer)) = self.writers.take() {
+            commit_partial(fasta_writer, &self.fasta_path())?;
+            commit_partial(table_writer, &self.table_path())?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Rejects {
+    fn drop(&mut self) {
+        if self.writers.take().is_some() {
+            let _ = fs::remove_file(partial_path(&self.fasta_path()));
+            let _ = fs::remove_file(partial_path(&self.table_path()));
+        }
+    }
+}
+
+/// Returns the temporary `<path>.partial` path a file is written to before it
+/// is moved to `path`
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial_name = path.as_os_str().to_owned();
+    partial_name.push(".partial");
+    PathBuf::from(partial_name)
+}
+
+/// Flushes and syncs a writer opened on the partial file of `path`, then
+/// renames the partial file over `path`
+fn commit_partial(writer: BufWriter<File>, path: &Path) -> io::Result<()> {
+    let file = writer
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?;
+    file.sync_all()?;
+    fs::rename(partial_path(path), path)
+}
+
+/// Writes a file atomically: `write_contents` writes to a temporary
+/// `<path>.partial` file in the same directory, which is flushed, synced, and
+/// renamed over `path` only once everything succeeded. On any error the
+/// partial file is removed and the error returned, so a killed or failed run
+/// never leaves a truncated file at `path`.
+pub(crate) fn write_atomically(
+    path: &Path,
+    write_contents: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let partial_path = partial_path(path);
+    let result = File::create(&partial_path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write_contents(&mut writer)?;
+        commit_partial(writer, path)
+    });
+
+    if result.is_err() {
+        let _ = fs::remove_file(&partial_path);
+    }
+    result
+}
+
+/// Writes a Fasta object to a fasta file atomically. Sequences that are not
+/// valid UTF-8 are written lossily and reported on stderr. Writing a Fasta
+/// with no entries is a warning, or an error when `strict` is set.
+pub(crate) fn write_fasta_to_file(
+    fasta_obj: &Fasta,
+    path: &Path,
+    strict: bool,
+    line_ending: LineEnding,
+) -> io::Result<()> {
+    check_not_empty(fasta_obj, strict)?;
+    warn_non_utf8(fasta_obj);
+    write_atomically(path, |writer| write_records(fasta_obj, writer, line_ending))
+}
+
+/// Writes every record of a Fasta to `writer` with the given line ending. The
+/// writer should be buffered, since each record is written in small pieces.
+/// All fasta output files and stdout are written through here, so a wrapping
+/// or compressing writer only has to be added in one place.
+pub(crate) fn write_records(
+    fasta_obj: &Fasta,
+    writer: &mut impl Write,
+    line_ending: LineEnding,
+) -> io::Result<()> {
+    for entry in fasta_obj {
+        entry.write_record_to(writer, DEFAULT_LINE_WIDTH, line_ending)?;
+    }
+    Ok(())
+}
+
+/// Writes a Fasta object to a fasta file atomically, like
+/// `write_fasta_to_file`, along with a samtools-style `<path>.fai` index. The
+/// index has one line per record with the five standard columns: name (the
+/// defline up to the first whitespace), sequence length, byte offset of the
+/// first base, bases per line, and bytes per line. Offsets are tracked as
+/// each record is written, so the index always matches the file.
+pub(crate) fn write_fasta_to_file_with_fai(
+    fasta_obj: &Fasta,
+    path: &Path,
+    strict: bool,
+    line_ending: LineEnding,
+) -> io::Result<()> {
+    check_not_empty(fasta_obj, strict)?;
+    warn_non_utf8(fasta_obj);
+
+    let mut index = String::new();
+    write_atomically(path, |writer| {
+        let mut offset = 0;
+        let mut record = String::new();
+        for entry in fasta_obj {
+            record.clear();
+            // Writing to a String cannot fail
+            let _ = entry.write_record_with_ending(&mut record, DEFAULT_LINE_WIDTH, line_ending);
+            let ending_len = line_ending.as_str().len();
+            let (header, sequence_lines) = record.split_at(entry.defline.len() + 1 + ending_len);
+            let line_bases = sequence_lines.lines().next().map_or(0, str::len);
+            let num_bases = sequence_lines.len() - sequence_lines.lines().count() * ending_len;
+            let name = entry.defline.split_whitespace().next().unwrap_or("");
+            let line_width = if line_bases == 0 {
+                0
+            } else {
+                line_bases + ending_len
+            };
+            let _ = writeln!(
+                index,
+                "{name}\t{num_bases}\t{}\t{line_bases}\t{line_width}",
+                offset + header.len()
+            );
+
+            writer.write_all(record.as_bytes())?;
+            offset += record.len();
+        }
+        Ok(())
+    })?;
+
+    let mut fai_name = path.as_os_str().to_owned();
+    fai_name.push(".fai");
+    write_atomically(Path::new(&fai_name), |writer| {
+        writer.write_all(index.as_bytes())
+    })
+}
+
+/// Warns that a Fasta about to be written has no entries, which in a pipeline
+/// almost always means every sequence was filtered out. Returns an error
+/// instead when `strict` is set.
+fn check_not_empty(fasta_obj: &Fasta, strict: bool) -> io::Result<()> {
+    if fasta_obj.num_entries() > 0 {
+        Ok(())
+    } else if strict {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "output contains no sequences",
+        ))
+    } else {
+        eprintln!(
+            "Warning: writing output '{}' with no sequences",
+            fasta_obj.filename()
+        );
+        Ok(())
+    }
+}
+
+/// Reports entries whose sequence will be written lossily
+fn warn_non_utf8(fasta_obj: &Fasta) {
+    for defline in fasta_obj.non_utf8_entries() {
+        eprintln!(
+            "Warning: sequence '{defline}' contains invalid UTF-8, which was replaced in the output"
+        );
+    }
+}
+
+/// Writes a Fasta object to stdout as fasta text. Sequences that are not valid
+/// UTF-8 are written lossily and reported on stderr. Writing a Fasta with no
+/// entries is a warning, or an error when `strict` is set.
+pub(crate) fn write_fasta(
+    fasta_obj: &Fasta,
+    strict: bool,
+    line_ending: LineEnding,
+) -> io::Result<()> {
+    check_not_empty(fasta_obj, strict)?;
+    warn_non_utf8(fasta_obj);
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    write_records(fasta_obj, &mut stdout, line_ending)?;
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        clean_sequence, open_embl, open_fasta, read_fasta, replace_base, write_atomically,
+        write_fasta_to_file, write_fasta_to_file_with_fai, write_records, CleaningPolicy,
+        CoordinateMap, Fasta, FastaEntry, LineEnding, Rejects, SequenceOps, DEFAULT_LINE_WIDTH,
+    };
+    use crate::limits::Limits;
+    use crate::math::next_random;
+    use crate::translation::GeneticCode;
+    use std::io::{self, Write};
+
+    const FASTA_NAME_1: &str = "test_data/a_ha_h3_raw_500.fna";
+
+    #[test]
+    fn test_fasta_1() {
+        let mut fasta = test_fasta_file(FASTA_NAME_1, 17);
+        test_fasta_defline(&mut fasta, 0, "MW585046{A_HA_H3}");
+        test_fasta_seq(&mut fasta, 0, "-----------------------------atgaagacaacca------ttattttgatactactgacccattgggcttacagtcaaaa---cccaatcaatg---acaacaacacagccacattgtgtctaggacaccatgcagtagcaaatggaacattggtaaaaacaataagtgatgatcaaattgaggtgacaaatgctacagaattagttcagagcattccaatggggaaaatatgcaacaattcgtatagaattctagat---ggaaagaattgcacattaatagatgcaatgctaggagacccccactgtgacgcctttcagtatgagaattgggacctctttatagaaagaagcagcgccttcagcaattgcta-cccatatgacatccctaactatgcatcgctccgatccattgtagcatcctcaggaacattggaattcacagcagagggattcacatggacaggtgtcactcaaaacggaagaagcggatcctgcaaaaggggatcagccgatagtttctttagccgactgaattggctaacaaaatccggaagctcttaccccacattgaatgtgacaatgcctaacaataaaaacttcgacaagctatacatctgggggatccatcacccgagctcaactaaagagcagacaaaattgtatatccaggaatcagggcgagtaacagtctcaacaaaaagaagtcaacaaacaataatccctaacattgggtctagaccatggatcagaggtcaatcaggtaggataagcatatactggaccattgtaaaacctggagatatcctaatgataaacagtaatggcaacttagttgcaccgcggggatactttaaattgaaaacagggaaaagctctgtaatgagatcagatg---tacccataga-catttgtgtgtctgaat-gtattacaccaaatggaagcatctccaacgacaagccattccaaaatgtgaacaaagttacatatggaaaatgtcccaagtatatcagacaaaacactttaaagctggccactgggatgaggaatgtaccagaaaagcaaatcagaggaatctttggggcaatagcgggattcatcgaaaacggctgggaaggaatggttgatggatggtatgggttccgataccaaaactctgaaggaacagggcaagctgcagatctaaagagcactcaagcagccatcgaccagatcaatggaaagttaaacagagtgattgaaagaaccaatgagaaattccatcaaatagagaaggaattctcagaagtagaaggaagaattcaggacttggagaaatatgtagaagacaccaaaatagacctatggtcctacaatgcagaattgctggtggctctagaaaatcaacatacaattgacttaacagatgcagaaatgaataaattgtttgagagaactagacgcctgttaagagaaaacgcagaagacatgggaggtggatgtttcaagatttaccacaaatgtaataatgcatgcattggatcaataagaaatgggacatatgaccattacatatacagagatgaagcattaaacaaccgatttcagatcaaaggtgtagagttgaaatcaggctacaaagattggatactctggatttcattcgccatatcatgcttcttaatttgcgttgttctattgggttt------------------------------------------------------------------------------------------------------");
+        test_fasta_defline(&mut fasta, 16, "KY583624{A_HA_H3}");
+        test_fasta_seq(&mut fasta, 16, "-----------------------------atgaagactatca------ttgctttgagctacattctatgtctggttttcgctcaaaaaattcctggaaatg---acaatagcacggcaacgctgtgccttgggcaccatgcagtaccaaacggaacgatagtgaaaacaatcacaaatg");
+    }
+
+    #[test]
+    fn fasta_text_round_trips() {
+        let fasta = open_fasta("fake_short.fna").unwrap();
+        let expected = std::fs::read_to_string("fake_short.fna").unwrap();
+        assert_eq!(fasta.to_fasta_string(DEFAULT_LINE_WIDTH), expected);
+        assert_eq!(fasta.to_fasta_string(0), expected);
+    }
+
+    #[test]
+    fn display_summary() {
+        let mut fasta = Fasta::new("summary.fna");
+        assert_eq!(
+            fasta.to_string(),
+            "Fasta: summary.fna (0 sequences, 0 columns)"
+        );
+
+        fasta.add(FastaEntry::new(String::from("a"), b"ATG-AA".to_vec(), 0));
+        fasta.add(FastaEntry::new(String::from("b"), b"ATGCAA".to_vec(), 1));
+        fasta.iter_mut().nth(1).unwrap().pack();
+        assert_eq!(
+            fasta.to_string(),
+            "Fasta: summary.fna (2 sequences, 6 columns)"
+        );
+        assert_eq!(
+            format!("{fasta:?}"),
+            "Fasta { filename: \"summary.fna\", num_entries: 2, \
+            entries: [(\"a\", 6), (\"b\", 6)] }"
+        );
+
+        fasta.add(FastaEntry::new(String::from("c"), b"ATG".to_vec(), 2));
+        assert_eq!(
+            fasta.to_string(),
+            "Fasta: summary.fna (3 sequences, variable-length columns)"
+        );
+    }
+
+    #[test]
+    fn concatenate_with_add() {
+        let a = open_fasta("fake_short.fna").unwrap();
+        let b = open_fasta("fake_short.fna").unwrap();
+        let (a_entries, b_entries) = (a.num_entries(), b.num_entries());
+
+        // `a` and `b` are moved into `c`, so using them after this line is a
+        // compile error
+        let c = a + b;
+        assert_eq!(c.num_entries(), a_entries + b_entries);
+        assert_eq!(c.filename(), "fake_short.fna");
+        for (i, entry) in (&c).into_iter().enumerate() {
+            assert_eq!(entry.entry_num(), i);
+        }
+        assert_eq!(c.indexed_entry(a_entries).defline(), "first");
+
+        let mut d = Fasta::new("d.fna");
+        d.add(FastaEntry::new(String::from("only"), b"ATG".to_vec(), 0));
+        d += c;
+        assert_eq!(d.num_entries(), 1 + a_entries + b_entries);
+        assert_eq!(d.filename(), "d.fna");
+        assert_eq!(d.indexed_entry(1).defline(), "first");
+        assert_eq!(d.indexed_entry(1).entry_num(), 1);
+        d += Fasta::new("empty.fna");
+        assert_eq!(d.num_entries(), 1 + a_entries + b_entries);
+    }
+
+    #[test]
+    fn wrapped_fasta_text() {
+        let mut fasta = Fasta::new("wrapped.fna");
+        fasta.add(FastaEntry::new(
+            String::from("seq1"),
+            b"ATGATGTAG".to_vec(),
+            0,
+        ));
+        fasta.add(FastaEntry::new(String::from("seq2"), b"ATGA".to_vec(), 1));
+        assert_eq!(
+            fasta.to_fasta_string(4),
+            ">seq1\nATGA\nTGTA\nG\n>seq2\nATGA\n"
+        );
+    }
+
+    #[test]
+    fn empty_file_has_no_entries() {
+        let fasta = open_fasta("test_data/empty_file.fna").unwrap();
+        assert_eq!(fasta.num_entries(), 0);
+        assert_eq!(fasta.to_fasta_string(DEFAULT_LINE_WIDTH), "");
+    }
+
+    #[test]
+    fn clone_is_independent() {
+        let original = open_fasta("fake_short.fna").unwrap();
+        let mut cloned = original.clone();
+        cloned.add(FastaEntry::new(
+            String::from("extra"),
+            b"ATGTAG".to_vec(),
+            9,
+        ));
+
+        assert_eq!(cloned.num_entries(), 10);
+        assert_eq!(original.num_entries(), 9);
+        assert_eq!(cloned.filename(), original.filename());
+        assert!(format!("{original:?}").starts_with(
+            "Fasta { filename: \"fake_short.fna\", num_entries: 9, entries: [(\"first\", 13), "
+        ));
+    }
+
+    #[test]
+    fn pipe_delimited_fields() {
+        let entry = FastaEntry::new(
+            String::from("CY121680|A/Boston/DOA2107/2012|4"),
+            b"ATG".to_vec(),
+            0,
+        );
+        assert_eq!(
+            entry.defline_fields("|"),
+            vec!["CY121680", "A/Boston/DOA2107/2012", "4"]
+        );
+        assert_eq!(
+            entry.defline_fields(","),
+            vec!["CY121680|A/Boston/DOA2107/2012|4"]
+        );
+    }
+
+    #[test]
+    fn find_by_id_case() {
+        let fasta = open_fasta(FASTA_NAME_1).unwrap();
+        let exact = fasta.find_by_id("MW585046{A_HA_H3}", false).unwrap();
+        assert_eq!(exact.entry_num(), 0);
+        assert!(fasta.find_by_id("mw585046{a_ha_h3}", false).is_none());
+        let ignored = fasta.find_by_id("mw585046{a_ha_h3}", true).unwrap();
+        assert_eq!(ignored.defline(), "MW585046{A_HA_H3}");
+        assert!(fasta.find_by_id("notreal", true).is_none());
+    }
+
+    #[test]
+    fn reverse_complement() {
+        assert_eq!(b"ATGC".reverse_complement(), b"GCAT");
+        assert_eq!(b"atG--c".reverse_complement(), b"g--Cat");
+        assert_eq!(b"RYKMSWBDHVN".reverse_complement(), b"NBDHVWSKMRY");
+        assert_eq!(b"AUG".reverse_complement(), b"CAT");
+        assert!(b"".reverse_complement().is_empty());
+    }
+
+    #[test]
+    fn translate() {
+        assert_eq!(b"ATGAAATAG".translate(GeneticCode::Standard), b"MK*");
+        assert_eq!(b"atg---tgaA".translate(GeneticCode::Standard), b"M-*");
+        assert_eq!(b"TGA".translate(GeneticCode::VertebrateMitochondrial), b"W");
+    }
+
+    #[test]
+    fn degapped() {
+        assert_eq!(b"--AT-G.C--".degapped(), b"ATGC");
+        assert!(b"----".degapped().is_empty());
+    }
+
+    #[test]
+    fn cleaning_policies() {
+        let sequence = b"AC-nGNt.YRWu";
+        assert_eq!(
+            clean_sequence(sequence, CleaningPolicy::GapsOnly),
+            b"ACnGNtYRWu"
+        );
+        assert_eq!(
+            clean_sequence(sequence, CleaningPolicy::GapsAndNs),
+            b"ACGtYRWu"
+        );
+        assert_eq!(
+            clean_sequence(sequence, CleaningPolicy::GapsNsAndAmbiguous),
+            b"ACGtu"
+        );
+        assert_eq!(
+            clean_sequence(b"kmSBDHV?", CleaningPolicy::GapsNsAndAmbiguous),
+            b""
+        );
+    }
+
+    #[test]
+    fn gc_fraction() {
+        assert_eq!(b"GGCC".gc_fraction(), Some(1.0));
+        assert_eq!(b"AT-gc".gc_fraction(), Some(0.5));
+        assert_eq!(b"ACGTNNNN".gc_fraction(), Some(0.5));
+        assert_eq!(b"--NN".gc_fraction(), None);
+    }
+
+    #[test]
+    fn count_ambiguous() {
+        assert_eq!(b"ATGC--".count_ambiguous(), 0);
+        assert_eq!(b"RYatgNn".count_ambiguous(), 4);
+    }
+
+    #[test]
+    fn iupac_matches() {
+        assert!(b"ATG".iupac_matches(b"atg"));
+        assert!(b"ATG".iupac_matches(b"RTN"));
+        assert!(b"AUG".iupac_matches(b"ATG"));
+        assert!(!b"ATG".iupac_matches(b"YTG"));
+        assert!(!b"ATG".iupac_matches(b"AT-"));
+        assert!(!b"ATG".iupac_matches(b"AT"));
+    }
+
+    #[test]
+    fn same_bases() {
+        assert!(b"atg".same_bases(b"ATG"));
+        assert!(b"AUG".same_bases(b"ATG"));
+        assert!(!b"ATN".same_bases(b"ATG"));
+        assert!(!b"AT".same_bases(b"ATG"));
+    }
+
+    #[test]
+    fn entry_sequence_ops() {
+        let fasta = open_fasta("fake_short.fna").unwrap();
+        let entry = fasta.indexed_entry(0);
+        assert_eq!(entry.sequence().degapped(), b"ATGATGTAG");
+        assert_eq!(entry.sequence().reverse_complement(), b"--CTACATCAT--");
+    }
+
+    #[test]
+    fn random_bytes_never_panic() {
+        let path = std::env::temp_dir().join("clean_genes_random_bytes.fna");
+        let alphabet = b">ACGTN-acgtn\n\r;>\xff\xfe\x00 ";
+        let mut state = 0x2545_f491_4f6c_dd1d;
+
+        for round in 0..200 {
+            let len = usize::try_from(next_random(&mut state) % 400).unwrap();
+            let bytes: Vec<u8> = (0..len)
+                .map(|_| {
+                    let value = next_random(&mut state);
+                    if round % 2 == 0 {
+                        alphabet[usize::try_from(value).unwrap() % alphabet.len()]
+                    } else {
+                        value.to_le_bytes()[0]
+                    }
+                })
+                .collect();
+            std::fs::write(&path, &bytes).unwrap();
+
+            if let Ok(fasta) = open_fasta(&path) {
+                let _ = fasta.to_fasta_string(7);
+                let _ = format!("{fasta}{fasta:?}");
+                let _ = fasta.non_utf8_entries();
+                for entry in &fasta {
+                    let _ = format!("{entry}{entry:?}");
+                }
+                assert!(fasta.get_entry(fasta.num_entries()).is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn sequence_lines_are_trimmed() {
+        let path = std::env::temp_dir().join("clean_genes_blank_lines.fna");
+        std::fs::write(&path, ">one\nATG\n\n  \t\nCCC \r\n TAG\n>two\nA\n").unwrap();
+        let fasta = open_fasta(&path).unwrap();
+        assert_eq!(fasta.num_entries(), 2);
+        assert_eq!(&*fasta.indexed_entry(0).sequence(), b"ATGCCCTAG");
+        assert_eq!(&*fasta.indexed_entry(1).sequence(), b"A");
+    }
+
+    #[test]
+    fn empty_record_mid_file() {
+        let path = std::env::temp_dir().join("clean_genes_empty_record.fna");
+        std::fs::write(&path, ">a\n>b\nACGTACGTAC\n>c\n---\n").unwrap();
+        let fasta = open_fasta(&path).unwrap();
+        let entries: Vec<(String, Vec<u8>, usize)> = (&fasta)
+            .into_iter()
+            .map(|entry| {
+                (
+                    entry.defline(),
+                    entry.sequence().into_owned(),
+                    entry.entry_num(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (String::from("a"), Vec::new(), 0),
+                (String::from("b"), b"ACGTACGTAC".to_vec(), 1),
+                (String::from("c"), b"---".to_vec(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn protein_residues() {
+        use super::{FastaEntry as Entry, MoleculeType};
+
+        let protein = |sequence: &[u8]| {
+            Entry::new_checked(
+                String::from("p"),
+                sequence.to_vec(),
+                0,
+                MoleculeType::Protein,
+            )
+        };
+        assert!(protein(b"MKTIIALSYIFCLVFA").is_ok());
+        assert!(protein(b"MKX-*").is_ok());
+        let err = protein(b"MKUL").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Sequence 'p' has 'U' at position 3, which is not an amino acid, '*', 'X', or a gap"
+        );
+        assert!(protein(b"MZK").is_err());
+        assert!(Entry::new_checked(
+            String::from("n"),
+            b"ACGU".to_vec(),
+            0,
+            MoleculeType::Nucleotide
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn direct_writes_match_string_records() {
+        let mut fasta = open_fasta(FASTA_NAME_1).unwrap();
+        fasta.add(FastaEntry::new(String::from("empty"), Vec::new(), 17));
+        fasta.add(FastaEntry::new(
+            String::from("bad"),
+            b"AT\xffG".to_vec(),
+            18,
+        ));
+        for line_width in [0, 7, DEFAULT_LINE_WIDTH] {
+            for line_ending in [LineEnding::Lf, LineEnding::CrLf] {
+                let mut text = String::new();
+                let mut bytes = Vec::new();
+                for entry in &fasta {
+                    entry
+                        .write_record_with_ending(&mut text, line_width, line_ending)
+                        .unwrap();
+                    entry
+                        .write_record_to(&mut bytes, line_width, line_ending)
+                        .unwrap();
+                }
+                assert_eq!(bytes, text.as_bytes());
+            }
+        }
+
+        let mut written = Vec::new();
+        write_records(&fasta, &mut written, LineEnding::Lf).unwrap();
+        assert_eq!(
+            written,
+            fasta.to_fasta_string(DEFAULT_LINE_WIDTH).as_bytes()
+        );
+    }
+
+    #[test]
+    fn wrong_formats_are_named() {
+        let path = std::env::temp_dir().join("clean_genes_wrong_format.fna");
+        let cases: [(&[u8], &str); 6] = [
+            (b"@read1\nACGT\n+\nIIII\n", "FASTQ"),
+            (b"LOCUS       TEST0001 60 bp\n", "--in-format genbank"),
+            (b"ID   TEST0004; SV 1;\n", "--in-format embl"),
+            (b"\x1f\x8b\x08\x00", "gunzip"),
+            (b"\xef\xbb\xbf>one\nATG\n", "byte order mark"),
+            (b"name,subtype,sequence\na,H3,ATG\n", "CSV"),
+        ];
+        for (contents, hint) in cases {
+            std::fs::write(&path, contents).unwrap();
+            let err = open_fasta(&path).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.to_string().contains(hint), "{err}");
+        }
+
+        std::fs::write(&path, b"@one\nATG\n>two\nCCC\n").unwrap();
+        assert!(open_fasta(&path).is_ok());
+    }
+
+    #[test]
+    fn comment_lines_are_skipped() {
+        let path = std::env::temp_dir().join("clean_genes_comments.fna");
+        std::fs::write(
+            &path,
+            ";file comment\n>one\n;about one\nATG\n;between lines\nTAG\n>two\nCCC\n;trailing",
+        )
+        .unwrap();
+        let fasta = open_fasta(&path).unwrap();
+        assert_eq!(fasta.num_entries(), 2);
+        assert_eq!(fasta.indexed_entry(0).defline(), "one");
+        assert_eq!(&*fasta.indexed_entry(0).sequence(), b"ATGTAG");
+        assert_eq!(&*fasta.indexed_entry(1).sequence(), b"CCC");
+
+        std::fs::write(&path, ";only a comment\n").unwrap();
+        assert_eq!(open_fasta(&path).unwrap().num_entries(), 0);
+    }
+
+    #[test]
+    fn angle_bracket_mid_sequence_fails() {
+        let path = std::env::temp_dir().join("clean_genes_mid_bracket.fna");
+        std::fs::write(&path, ">one\nATG\nCC>two\nTAG\n").unwrap();
+        let err = open_fasta(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 3"));
+    }
+
+    #[test]
+    fn truncated_records_never_panic() {
+        let path = std::env::temp_dir().join("clean_genes_truncated.fna");
+        let full = std::fs::read("fake_short.fna").unwrap();
+        for cut in 0..full.len() {
+            std::fs::write(&path, &full[..cut]).unwrap();
+            let fasta = open_fasta(&path).unwrap();
+            let _ = fasta.to_fasta_string(DEFAULT_LINE_WIDTH);
+        }
+    }
+
+    #[test]
+    fn non_utf8_sequence_is_flagged() {
+        let mut fasta = Fasta::new("bad_bytes.fna");
+        fasta.add(FastaEntry::new(String::from("good"), b"ATG".to_vec(), 0));
+        fasta.add(FastaEntry::new(String::from("bad"), b"AT\xffG".to_vec(), 1));
+        assert_eq!(fasta.non_utf8_entries(), vec![String::from("bad")]);
+        assert_eq!(
+            fasta.to_fasta_string(DEFAULT_LINE_WIDTH),
+            ">good\nATG\n>bad\nAT\u{fffd}G\n"
+        );
+    }
+
+    #[test]
+    fn write_file_round_trip() {
+        let path = std::env::temp_dir().join("clean_genes_round_trip.fna");
+        let fasta = open_fasta("fake_short.fna").unwrap();
+        write_fasta_to_file(&fasta, &path, false, LineEnding::Lf).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            std::fs::read_to_string("fake_short.fna").unwrap()
+        );
+        assert!(!std::env::temp_dir()
+            .join("clean_genes_round_trip.fna.partial")
+            .exists());
+    }
+
+    #[test]
+    fn failed_write_leaves_nothing() {
+        let path = std::env::temp_dir().join("clean_genes_failed_write.fna");
+        let partial = std::env::temp_dir().join("clean_genes_failed_write.fna.partial");
+        let result = write_atomically(&path, |writer| {
+            writer.write_all(b">half_written\nATG")?;
+            Err(io::Error::other("disk full"))
+        });
+        assert!(result.is_err());
+        assert!(!path.exists());
+        assert!(!partial.exists());
+
+        let missing_dir = std::env::temp_dir()
+            .join("clean_genes_missing_dir")
+            .join("output.fna");
+        let fasta = open_fasta("fake_short.fna").unwrap();
+        assert!(write_fasta_to_file(&fasta, &missing_dir, false, LineEnding::Lf).is_err());
+        assert!(!missing_dir.exists());
+    }
+
+    #[test]
+    fn strict_empty_write() {
+        let path = std::env::temp_dir().join("clean_genes_strict_empty.fna");
+        let _ = std::fs::remove_file(&path);
+        let empty = Fasta::new(&path);
+        assert!(write_fasta_to_file(&empty, &path, true, LineEnding::Lf).is_err());
+        assert!(!path.exists());
+        write_fasta_to_file(&empty, &path, false, LineEnding::Lf).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    }
+
+    #[test]
+    fn genbank_records() {
+        use super::{open_input, InputFormat};
+
+        let fasta = open_input(
+            "test_data/two_records.gb",
+            None,
+            false,
+            false,
+            false,
+            Limits::default(),
+        )
+        .unwrap();
+        assert_eq!(fasta.num_entries(), 2);
+        assert_eq!(
+            fasta.indexed_entry(0).defline(),
+            "TEST0001 Influenza A virus (A/test/1/2024(H3N2)) segment 4 hemagglutinin \
+            (HA) gene, partial cds. cds=4..21"
+        );
+        assert_eq!(
+            &*fasta.indexed_entry(0).sequence(),
+            b"ccaatgaagactatcattgctaag"
+        );
+        assert_eq!(fasta.indexed_entry(1).entry_num(), 1);
+        assert!(fasta
+            .indexed_entry(1)
+            .defline()
+            .starts_with("TEST0002 Influenza"));
+        assert!(fasta.indexed_entry(1).defline().ends_with("complete cds."));
+        assert_eq!(fasta.indexed_entry(1).sequence().len(), 70);
+        assert!(fasta.indexed_entry(1).sequence().ends_with(b"agcctaatat"));
+
+        assert_eq!(
+            InputFormat::from_path(std::path::Path::new("x.GBK")),
+            InputFormat::GenBank
+        );
+        let err = open_input(
+            "test_data/two_records.gb",
+            Some(InputFormat::Fasta),
+            false,
+            false,
+            false,
+            Limits::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("--in-format genbank"));
+    }
+
+    #[test]
+    fn progress_does_not_change_result() {
+        use super::read_fasta;
+
+        let plain = open_fasta(FASTA_NAME_1).unwrap();
+        let with_progress =
+            read_fasta(FASTA_NAME_1, false, false, true, Limits::default()).unwrap();
+        assert_eq!(plain.to_fasta_string(0), with_progress.to_fasta_string(0));
+    }
+
+    #[test]
+    fn formatted_lines_match_clean_fasta() {
+        use super::read_fasta;
+
+        let pasted = read_fasta(
+            "test_data/pasted_origin.fna",
+            false,
+            true,
+            false,
+            Limits::default(),
+        )
+        .unwrap();
+        let clean = open_fasta("test_data/pasted_origin_clean.fna").unwrap();
+        assert_eq!(pasted.num_entries(), clean.num_entries());
+        for (pasted_entry, clean_entry) in (&pasted).into_iter().zip(&clean) {
+            assert_eq!(pasted_entry.defline(), clean_entry.defline());
+            assert_eq!(pasted_entry.sequence(), clean_entry.sequence());
+        }
+        assert_eq!(
+            pasted.indexed_entry(0).get_meta("stripped_chars"),
+            Some("11")
+        );
+        assert_eq!(pasted.indexed_entry(1).get_meta("stripped_chars"), None);
+
+        let untouched = open_fasta("test_data/pasted_origin.fna").unwrap();
+        assert!(untouched.indexed_entry(0).sequence().starts_with(b"1 atg"));
+    }
+
+    #[test]
+    fn embl_record() {
+        use super::{open_input, InputFormat};
+
+        let fasta = open_input(
+            "test_data/one_record.embl",
+            None,
+            false,
+            false,
+            false,
+            Limits::default(),
+        )
+        .unwrap();
+        assert_eq!(fasta.num_entries(), 1);
+        assert_eq!(
+            fasta.indexed_entry(0).defline(),
+            "TEST0004 Influenza A virus (A/test/4/2024(H3N2)) segment 4 hemagglutinin (HA) \
+            gene, partial cds."
+        );
+        let sequence = fasta.indexed_entry(0).sequence();
+        assert_eq!(sequence.len(), 72);
+        assert!(sequence.starts_with(b"atgaagactatcattgctttgagc"));
+        assert!(sequence.ends_with(b"ctggaaatgacaac"));
+
+        assert_eq!(
+            InputFormat::from_path(std::path::Path::new("sequences.dat")),
+            InputFormat::Embl
+        );
+    }
+
+    #[test]
+    fn embl_non_ascii_lines() {
+        let path = std::env::temp_dir().join("clean_genes_non_ascii.embl");
+        std::fs::write(
+            &path,
+            "ID   TEST0005; SV 1; linear\nAé\nÅÄ   São Paulo\nCC   Zürich\n\
+            DE   Sample from São Paulo\nSQ   Sequence 6 BP;\n     atgtaa 6\n//\n",
+        )
+        .unwrap();
+        let fasta = open_embl(&path).unwrap();
+        assert_eq!(fasta.num_entries(), 1);
+        assert_eq!(
+            fasta.indexed_entry(0).defline(),
+            "TEST0005 Sample from São Paulo"
+        );
+        assert_eq!(&*fasta.indexed_entry(0).sequence(), b"atgtaa");
+    }
+
+    fn test_fasta_file(fasta_name: &str, s: usize) -> Fasta {
+        let fasta = open_fasta(fasta_name).unwrap();
+        assert_eq!(fasta.num_entries(), s);
+        assert_eq!(fasta.filename(), fasta_name);
+        fasta
+    }
+
+    fn test_fasta_seq(fasta: &mut Fasta, i: usize, seq: &str) {
+        use std::str;
+        let seq_orig = fasta.indexed_entry(i).sequence();
+        assert_eq!(str::from_utf8(&seq_orig).unwrap(), seq);
+    }
+
+    fn test_fasta_defline(fasta: &mut Fasta, i: usize, defline: &str) {
+        let defline_orig = fasta.indexed_entry(i).defline();
+        assert_eq!(defline_orig, defline);
+    }
+
+    #[test]
+    fn flu_gene_tag() {
+        let entry = FastaEntry::new(String::from("MW585046{A_HA_H3}"), Vec::new(), 0);
+        let tag = entry.gene_tag().unwrap();
+        assert_eq!(tag.flu_type, "A");
+        assert_eq!(tag.gene, "HA");
+        assert_eq!(tag.subtype, Some("H3"));
+
+        let flu_b = FastaEntry::new(String::from("OQ1{B_NA}|2020"), Vec::new(), 0);
+        assert_eq!(flu_b.gene_tag().unwrap().subtype, None);
+
+        for defline in [
+            "MW585046",
+            "MW585046{HA}",
+            "MW585046{A_HA_}",
+            "MW585046{A_HA",
+        ] {
+            let entry = FastaEntry::new(String::from(defline), Vec::new(), 0);
+            assert_eq!(entry.gene_tag(), None, "{defline}");
+        }
+    }
+
+    #[test]
+    fn defline_metadata() {
+        let entry = FastaEntry::new(String::from("MW585046{A_HA_H3}|H3N2|2019"), Vec::new(), 0);
+        let metadata = entry.metadata("|").unwrap();
+        assert_eq!(metadata.accession, "MW585046");
+        assert_eq!(metadata.gene_tag, "A_HA_H3");
+        assert_eq!(metadata.fields, ["H3N2", "2019"]);
+
+        let bare = FastaEntry::new(String::from("CY068505{A_HA_H3}"), Vec::new(), 0);
+        assert!(bare.metadata("|").unwrap().fields.is_empty());
+
+        let untagged = FastaEntry::new(String::from("first"), Vec::new(), 0);
+        assert_eq!(untagged.metadata("|"), None);
+    }
+
+    /// Builds a Fasta of `num_entries` entries with deflines "0", "1", ...
+    fn numbered_fasta(num_entries: usize) -> Fasta {
+        let mut fasta = Fasta::new("numbered.fna");
+        for i in 0..num_entries {
+            fasta.add(FastaEntry::new(i.to_string(), b"ATG".to_vec(), i));
+        }
+        fasta
+    }
+
+    #[test]
+    fn entry_windows() {
+        let fasta = numbered_fasta(5);
+        let windows: Vec<Vec<usize>> = fasta
+            .windows(3)
+            .map(|window| window.iter().map(|entry| entry.entry_num()).collect())
+            .collect();
+        assert_eq!(windows, [[0, 1, 2], [1, 2, 3], [2, 3, 4]]);
+
+        assert_eq!(fasta.windows(6).count(), 0);
+    }
+
+    #[test]
+    fn entry_chunks() {
+        let fasta = numbered_fasta(7);
+        let chunk_sizes: Vec<usize> = fasta.chunks(3).map(|chunk| chunk.len()).collect();
+        assert_eq!(chunk_sizes, [3, 3, 1]);
+        assert_eq!(fasta.chunks(3).last().unwrap()[0].defline(), "6");
+    }
+
+    #[test]
+    fn low_memory_reads_same_sequences() {
+        let plain = open_fasta("test_data/a_ha_h3_raw_500.fna").unwrap();
+        let packed = read_fasta(
+            "test_data/a_ha_h3_raw_500.fna",
+            true,
+            false,
+            false,
+            Limits::default(),
+        )
+        .unwrap();
+        assert_eq!(packed.num_entries(), plain.num_entries());
+        for (plain_entry, packed_entry) in (&plain).into_iter().zip(&packed) {
+            assert!(packed_entry.is_packed());
+            assert_eq!(packed_entry.sequence(), plain_entry.sequence());
+            assert!(packed_entry.sequence_heap_size() < plain_entry.sequence_heap_size());
+        }
+        assert_eq!(packed.sequence_hash(), plain.sequence_hash());
+        assert_eq!(
+            packed.to_fasta_string(DEFAULT_LINE_WIDTH),
+            plain.to_fasta_string(DEFAULT_LINE_WIDTH)
+        );
+    }
+
+    #[test]
+    fn editing_unpacks() {
+        let mut entry = FastaEntry::new(String::from("seq"), b"acgtNN--".to_vec(), 0);
+        entry.pack();
+        assert!(entry.is_packed());
+        entry.sequence_mut()[0] = b'T';
+        assert!(!entry.is_packed());
+        assert_eq!(&*entry.sequence(), b"TcgtNN--");
+    }
+
+    /// Builds a 5-entry Fasta with deflines a, b, a, c, b whose sequences
+    /// record their original position
+    fn duplicate_deflines() -> Fasta {
+        let mut fasta = Fasta::new("duplicates.fna");
+        for (i, defline) in ["a", "b", "a", "c", "b"].into_iter().enumerate() {
+            fasta.add(FastaEntry::new(
+                String::from(defline),
+                i.to_string().into_bytes(),
+                i,
+            ));
+        }
+        fasta
+    }
+
+    #[test]
+    fn dedup_keeps_first() {
+        let mut fasta = duplicate_deflines();
+        assert_eq!(fasta.dedup_by_defline(), [2, 4]);
+        assert_eq!(fasta.num_entries(), 3);
+        let kept: Vec<(String, Vec<u8>, usize)> = (&fasta)
+            .into_iter()
+            .map(|entry| {
+                (
+                    entry.defline(),
+                    entry.sequence().into_owned(),
+                    entry.entry_num(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                (String::from("a"), b"0".to_vec(), 0),
+                (String::from("b"), b"1".to_vec(), 1),
+                (String::from("c"), b"3".to_vec(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn dedup_keeps_last() {
+        let mut fasta = duplicate_deflines();
+        assert_eq!(fasta.dedup_by_defline_keep_last(), [0, 1]);
+        assert_eq!(fasta.num_entries(), 3);
+        let kept: Vec<(String, Vec<u8>, usize)> = (&fasta)
+            .into_iter()
+            .map(|entry| {
+                (
+                    entry.defline(),
+                    entry.sequence().into_owned(),
+                    entry.entry_num(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                (String::from("a"), b"2".to_vec(), 0),
+                (String::from("c"), b"3".to_vec(), 1),
+                (String::from("b"), b"4".to_vec(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn renumber_keeps_original_index() {
+        let mut fasta = open_fasta("fake_short.fna").unwrap();
+        let third = fasta.indexed_entry(2).defline();
+        fasta.retain(|entry| entry.defline() != third);
+        let fourth = fasta.indexed_entry(2);
+        assert_eq!((fourth.entry_num(), fourth.original_index()), (2, 3));
+
+        let mut copied = Fasta::new("copied.fna");
+        for entry in &fasta {
+            copied.add(
+                FastaEntry::new(entry.defline(), entry.sequence().to_vec(), 7)
+                    .with_meta_from(entry),
+            );
+        }
+        copied.renumber();
+        let fourth = copied.indexed_entry(2);
+        assert_eq!((fourth.entry_num(), fourth.original_index()), (2, 3));
+    }
+
+    #[test]
+    fn rejects_created_lazily() {
+        let prefix = std::env::temp_dir().join("clean_genes_rejects_unit");
+        let rejects = Rejects::new(&prefix);
+        let (fasta_path, table_path) = (rejects.fasta_path(), rejects.table_path());
+        let _ = std::fs::remove_file(&fasta_path);
+        let _ = std::fs::remove_file(&table_path);
+        rejects.finish().unwrap();
+        assert!(!fasta_path.exists());
+
+        let mut rejects = Rejects::new(&prefix);
+        let entry = FastaEntry::new(String::from("gappy"), b"A---".to_vec(), 3);
+        rejects
+            .add(&entry, "FilterGappy", "gap_fraction", Some(0.75))
+            .unwrap();
+        assert_eq!(rejects.num_rejected(), 1);
+        rejects.finish().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&fasta_path).unwrap(),
+            ">gappy module=FilterGappy reason=gap_fraction\nA---\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&table_path).unwrap(),
+            "defline\tmodule\treason\tmetric\tinput_position\n\
+            gappy\tFilterGappy\tgap_fraction\t0.75\t4\n"
+        );
+    }
+
+    #[test]
+    fn unfinished_rejects_leave_nothing() {
+        let prefix = std::env::temp_dir().join("clean_genes_rejects_unfinished");
+        let mut rejects = Rejects::new(&prefix);
+        let (fasta_path, table_path) = (rejects.fasta_path(), rejects.table_path());
+        let _ = std::fs::remove_file(&fasta_path);
+        let _ = std::fs::remove_file(&table_path);
+        let entry = FastaEntry::new(String::from("gappy"), b"A---".to_vec(), 0);
+        rejects
+            .add(&entry, "FilterGappy", "gap_fraction", Some(0.75))
+            .unwrap();
+        assert!(!fasta_path.exists());
+        assert!(!table_path.exists());
+
+        drop(rejects);
+        assert!(!fasta_path.exists());
+        assert!(!table_path.exists());
+        assert!(!super::partial_path(&fasta_path).exists());
+        assert!(!super::partial_path(&table_path).exists());
+    }
+
+    #[test]
+    fn metadata_set_and_get() {
+        let mut entry = FastaEntry::new(String::from("qc"), b"ATGC".to_vec(), 0);
+        assert_eq!(entry.get_meta("gc"), None);
+        entry.set_meta("gc", 0.5);
+        entry.set_meta("flag", "low_coverage");
+        entry.set_meta("gc", 0.25);
+        assert_eq!(entry.get_meta("gc"), Some("0.25"));
+        assert_eq!(entry.meta_keys(), ["flag", "gc"]);
+
+        let copy = FastaEntry::new(String::from("copy"), Vec::new(), 1).with_meta_from(&entry);
+        assert_eq!(copy.get_meta("flag"), Some("low_coverage"));
+    }
+
+    #[test]
+    fn replace_u_with_t() {
+        let mut fasta = Fasta::new("rna.fna");
+        fasta.add(FastaEntry::new(
+            String::from("rna"),
+            b"AUGuuA-Cu".to_vec(),
+            0,
+        ));
+
+        let replaced = replace_base(&fasta, b'U', b't', false);
+        let sequence = replaced.indexed_entry(0).sequence();
+        assert!(!sequence.contains(&b'U') && !sequence.contains(&b'u'));
+        assert_eq!(&*sequence, b"ATGTTA-CT");
+
+        let exact = replace_base(&fasta, b'u', b't', true);
+        assert_eq!(&*exact.indexed_entry(0).sequence(), b"AUGttA-Ct");
+    }
+
+    #[test]
+    fn coordinate_map_edges() {
+        let entry = FastaEntry::new(String::from("gapped"), b"--AT-G..C--".to_vec(), 0);
+        let map = entry.coordinate_map();
+        assert_eq!(map.num_bases(), 4);
+        assert_eq!(map.num_columns(), 11);
+
+        let to_seq: Vec<Option<usize>> = (0..12).map(|col| map.align_to_seq(col)).collect();
+        assert_eq!(
+            to_seq,
+            [
+                None,
+                None,
+                Some(0),
+                Some(1),
+                None,
+                Some(2),
+                None,
+                None,
+                Some(3),
+                None,
+                None,
+                None
+            ]
+        );
+        let to_align: Vec<Option<usize>> = (0..5).map(|pos| map.seq_to_align(pos)).collect();
+        assert_eq!(to_align, [Some(2), Some(3), Some(5), Some(8), None]);
+
+        assert_eq!(map.bases_before(0), 0);
+        assert_eq!(map.bases_before(4), 2);
+        assert_eq!(map.bases_before(9), 4);
+        assert_eq!(map.bases_before(100), 4);
+
+        let empty = CoordinateMap::new(b"");
+        assert_eq!(empty.align_to_seq(0), None);
+        assert_eq!(empty.seq_to_align(0), None);
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let path = std::env::temp_dir().join("clean_genes_crlf.fna");
+        let mut fasta = Fasta::new(&path);
+        fasta.add(FastaEntry::new(String::from("one"), b"A".repeat(70), 0));
+        fasta.add(FastaEntry::new(String::from("two"), b"ATG".to_vec(), 1));
+        write_fasta_to_file_with_fai(&fasta, &path, false, LineEnding::CrLf).unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(written.matches('\n').count(), 5);
+        assert!(written
+            .split_inclusive('\n')
+            .all(|line| line.ends_with("\r\n")));
+
+        let fai = std::fs::read_to_string(path.with_extension("fna.fai")).unwrap();
+        assert_eq!(fai, "one\t70\t6\t60\t62\ntwo\t3\t86\t3\t5\n");
+        assert_eq!(&written[86..89], "ATG");
+    }
+
+    #[test]
+    fn fai_matches_output() {
+        let path = std::env::temp_dir().join("clean_genes_fai_test.fna");
+        let mut fasta = Fasta::new(&path);
+        fasta.add(FastaEntry::new(
+            String::from("long description"),
+            b"A".repeat(130),
+            0,
+        ));
+        fasta.add(FastaEntry::new(String::from("short"), b"ATG".to_vec(), 1));
+        write_fasta_to_file_with_fai(&fasta, &path, false, LineEnding::Lf).unwrap();
+
+        let fai = std::fs::read_to_string(path.with_extension("fna.fai")).unwrap();
+        assert_eq!(fai, "long\t130\t18\t60\t61\nshort\t3\t158\t3\t4\n");
+
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(written[18], b'A');
+        assert_eq!(&written[158..161], b"ATG");
+    }
+
+    #[test]
+    fn no_information_predicates() {
+        let predicates = |sequence: &[u8]| {
+            let entry = FastaEntry::new(String::from("test"), sequence.to_vec(), 0);
+            (
+                entry.is_all_gaps(),
+                entry.is_all_n(),
+                entry.is_ambiguous_only(),
+            )
+        };
+
+        assert_eq!(predicates(b"ATG--TAG"), (false, false, false));
+        assert_eq!(predicates(b"--.--"), (true, true, true));
+        assert_eq!(predicates(b"NNnN"), (false, true, true));
+        assert_eq!(predicates(b"-Nn.-N"), (false, true, true));
+        assert_eq!(predicates(b""), (true, true, true));
+        assert_eq!(predicates(b"RYN-k"), (false, false, true));
+        assert_eq!(predicates(b"NNUN"), (false, false, false));
+        assert_eq!(predicates(b"NN?N"), (false, false, false));
     }
 }
@@ -0,0 +1,407 @@
+use crate::fasta_manager::{is_gap, Fasta, FastaEntry};
+use crate::kmers::KmerSet;
+use std::path::Path;
+
+/// Which occurrence of a duplicated defline the `Dedup` module keeps
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub(crate) enum KeepDuplicate {
+    /// The first occurrence in the input
+    #[default]
+    First,
+    /// The last occurrence, e.g. the most recent download when files were
+    /// concatenated
+    Last,
+}
+
+/// Copies the entries of `inp_fasta` that satisfy `keep` into a new Fasta named
+/// `out_fasta`, renumbering them by their position in the output. Returns the
+/// new Fasta and the entries that were removed.
+fn retain_entries(
+    inp_fasta: &Fasta,
+    out_fasta: impl AsRef<Path>,
+    mut keep: impl FnMut(&FastaEntry) -> bool,
+) -> (Fasta, Vec<&FastaEntry>) {
+    let mut kept_fasta = Fasta::new(out_fasta);
+    let mut dropped = Vec::new();
+
+    for entry in inp_fasta {
+        if keep(entry) {
+            let entry_num = kept_fasta.num_entries();
+            kept_fasta.add(
+                FastaEntry::new(entry.defline(), entry.sequence().into_owned(), entry_num)
+                    .with_meta_from(entry),
+            );
+        } else {
+            dropped.push(entry);
+        }
+    }
+
+    (kept_fasta, dropped)
+}
+
+/// The main function of the `DropEmpty` module. Removes entries whose sequence
+/// is empty or made up entirely of gaps and/or Ns, which carry no information.
+/// Returns the filtered Fasta and the dropped entries.
+pub(crate) fn drop_empty(
+    inp_fasta: &Fasta,
+    out_fasta: impl AsRef<Path>,
+) -> (Fasta, Vec<&FastaEntry>) {
+    retain_entries(inp_fasta, out_fasta, |entry| !entry.is_all_n())
+}
+
+/// The main function of the `FilterByField` module. Keeps entries whose defline,
+/// split on `delimiter`, has `value` in field number `field` (starting at 0).
+/// Returns the filtered Fasta and the dropped entries.
+pub(crate) fn filter_by_field<'a>(
+    inp_fasta: &'a Fasta,
+    delimiter: &str,
+    field: usize,
+    value: &str,
+    out_fasta: impl AsRef<Path>,
+) -> (Fasta, Vec<&'a FastaEntry>) {
+    retain_entries(inp_fasta, out_fasta, |entry| {
+        entry.defline_fields(delimiter).get(field) == Some(&value)
+    })
+}
+
+/// The main function of the `--filter-subtype` option. Keeps entries whose
+/// `{TYPE_GENE_SUBTYPE}` defline tag has the given subtype, ignoring case.
+/// Entries without a tag or subtype are dropped. Returns the filtered Fasta and
+/// the dropped entries.
+pub(crate) fn filter_subtype<'a>(
+    inp_fasta: &'a Fasta,
+    subtype: &str,
+    out_fasta: impl AsRef<Path>,
+) -> (Fasta, Vec<&'a FastaEntry>) {
+    retain_entries(inp_fasta, out_fasta, |entry| {
+        entry
+            .gene_tag()
+            .and_then(|tag| tag.subtype)
+            .is_some_and(|tag_subtype| tag_subtype.eq_ignore_ascii_case(subtype))
+    })
+}
+
+/// The main function of the `Dedup` module. Removes entries whose defline
+/// duplicates another entry's, keeping the first or last occurrence as `keep`
+/// says. Retained entries are always output in input order, whichever
+/// occurrence is kept: the set of seen deflines only decides which entries
+/// survive, never where they go. Returns the filtered Fasta and the dropped
+/// entries.
+pub(crate) fn dedup_by_defline(
+    inp_fasta: &Fasta,
+    keep: KeepDuplicate,
+    out_fasta: impl AsRef<Path>,
+) -> (Fasta, Vec<&FastaEntry>) {
+    let mut kept_fasta = inp_fasta.clone();
+    kept_fasta.set_filename(out_fasta);
+    let removed = match keep {
+        KeepDuplicate::First => kept_fasta.dedup_by_defline(),
+        KeepDuplicate::Last => kept_fasta.dedup_by_defline_keep_last(),
+    };
+    let dropped = removed
+        .into_iter()
+        .filter_map(|position| inp_fasta.get_entry(position))
+        .collect();
+    (kept_fasta, dropped)
+}
+
+/// Returns the fraction of a sequence made up of gap characters. An empty
+/// sequence is treated as entirely gaps.
+pub(crate) fn gap_fraction(sequence: &[u8]) -> f64 {
+    if sequence.is_empty() {
+        return 1.0;
+    }
+    let gaps = sequence.iter().filter(|&&base| is_gap(base)).count();
+    #[expect(clippy::cast_precision_loss)]
+    let fraction = gaps as f64 / sequence.len() as f64;
+    fraction
+}
+
+/// The main function of the `FilterGappy` module. Removes entries whose gap
+/// fraction (gaps / aligned length) is greater than `max_gap_fraction`, and
+/// records the gap fraction of each kept entry as `gap_fraction` metadata.
+/// Returns the filtered Fasta and the dropped entries with their gap fractions.
+pub(crate) fn filter_gappy(
+    inp_fasta: &Fasta,
+    max_gap_fraction: f64,
+    out_fasta: impl AsRef<Path>,
+) -> (Fasta, Vec<(&FastaEntry, f64)>) {
+    let (mut kept_fasta, dropped) = retain_entries(inp_fasta, out_fasta, |entry| {
+        gap_fraction(&entry.sequence()) <= max_gap_fraction
+    });
+    for entry in &mut kept_fasta {
+        let fraction = gap_fraction(&entry.sequence());
+        entry.set_meta("gap_fraction", format!("{fraction:.3}"));
+    }
+
+    let dropped_fractions = dropped
+        .into_iter()
+        .map(|entry| (entry, gap_fraction(&entry.sequence())))
+        .collect();
+    (kept_fasta, dropped_fractions)
+}
+
+/// The main function of the `Screen` module. Removes entries whose k-mer
+/// containment in `references` is below `min_containment`, and records the
+/// containment of each kept entry as `containment` metadata. Returns the
+/// filtered Fasta, the dropped entries with their containments, and every
+/// entry's containment in input order.
+pub(crate) fn screen_contaminants<'a>(
+    inp_fasta: &'a Fasta,
+    references: &KmerSet,
+    min_containment: f64,
+    out_fasta: impl AsRef<Path>,
+) -> (Fasta, Vec<(&'a FastaEntry, f64)>, Vec<f64>) {
+    let containments: Vec<f64> = inp_fasta
+        .into_iter()
+        .map(|entry| references.containment(&entry.sequence()))
+        .collect();
+    let mut scores = containments.iter();
+    let (mut kept_fasta, dropped) = retain_entries(inp_fasta, out_fasta, |_| {
+        scores.next().is_some_and(|&score| score >= min_containment)
+    });
+    let kept_scores = containments
+        .iter()
+        .filter(|&&score| score >= min_containment);
+    for (entry, score) in kept_fasta.iter_mut().zip(kept_scores) {
+        entry.set_meta("containment", format!("{score:.3}"));
+    }
+
+    let dropped_scores = dropped
+        .into_iter()
+        .zip(
+            containments
+                .iter()
+                .copied()
+                .filter(|&score| score < min_containment),
+        )
+        .collect();
+    (kept_fasta, dropped_scores, containments)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        dedup_by_defline, drop_empty, filter_by_field, filter_gappy, filter_subtype,
+        screen_contaminants, KeepDuplicate,
+    };
+    use crate::fasta_manager::{open_fasta, Fasta, FastaEntry};
+    use crate::kmers::KmerSet;
+    use crate::math::next_random;
+
+    /// Returns the deflines of dropped entries
+    fn deflines(dropped: &[&FastaEntry]) -> Vec<String> {
+        dropped.iter().map(|entry| entry.defline()).collect()
+    }
+
+    #[test]
+    fn drop_all_gap_entry() {
+        let mut fasta = Fasta::new("gappy.fna");
+        fasta.add(FastaEntry::new(
+            String::from("real"),
+            b"--ATGTAG".to_vec(),
+            0,
+        ));
+        fasta.add(FastaEntry::new(
+            String::from("all_gap"),
+            b"--------".to_vec(),
+            1,
+        ));
+        fasta.add(FastaEntry::new(
+            String::from("also_real"),
+            b"ATG--TAG".to_vec(),
+            2,
+        ));
+
+        let (kept, dropped) = drop_empty(&fasta, "./output.fasta");
+        assert_eq!(deflines(&dropped), vec![String::from("all_gap")]);
+        assert_eq!(kept.num_entries(), 2);
+        assert_eq!(kept.indexed_entry(1).defline(), "also_real");
+        assert_eq!(kept.indexed_entry(1).entry_num(), 1);
+    }
+
+    #[test]
+    fn drop_empty_and_n() {
+        let fake_fasta_short = open_fasta("fake_short.fna").unwrap();
+        let (kept, dropped) = drop_empty(&fake_fasta_short, "./output.fasta");
+        assert_eq!(deflines(&dropped), vec![String::from("ns")]);
+        assert_eq!(kept.num_entries(), 8);
+
+        let mut fasta = Fasta::new("empty_seq.fna");
+        fasta.add(FastaEntry::new(String::from("empty"), Vec::new(), 0));
+        fasta.add(FastaEntry::new(
+            String::from("gaps_and_ns"),
+            b"-nN-".to_vec(),
+            1,
+        ));
+        let (kept, dropped) = drop_empty(&fasta, "./output.fasta");
+        assert_eq!(kept.num_entries(), 0);
+        assert_eq!(dropped.len(), 2);
+    }
+
+    #[test]
+    fn filter_on_segment() {
+        let mut fasta = Fasta::new("segments.fna");
+        fasta.add(FastaEntry::new(
+            String::from("CY1|A/one|4"),
+            b"ATG".to_vec(),
+            0,
+        ));
+        fasta.add(FastaEntry::new(
+            String::from("CY2|A/two|6"),
+            b"ATG".to_vec(),
+            1,
+        ));
+        fasta.add(FastaEntry::new(
+            String::from("CY3|A/three|4"),
+            b"ATG".to_vec(),
+            2,
+        ));
+        fasta.add(FastaEntry::new(
+            String::from("no_fields"),
+            b"ATG".to_vec(),
+            3,
+        ));
+
+        let (kept, dropped) = filter_by_field(&fasta, "|", 2, "4", "./output.fasta");
+        assert_eq!(kept.num_entries(), 2);
+        assert_eq!(kept.indexed_entry(0).defline(), "CY1|A/one|4");
+        assert_eq!(kept.indexed_entry(1).defline(), "CY3|A/three|4");
+        assert_eq!(
+            deflines(&dropped),
+            vec![String::from("CY2|A/two|6"), String::from("no_fields")]
+        );
+    }
+
+    #[test]
+    fn drop_mostly_gaps() {
+        let mut fasta = Fasta::new("gappy.fna");
+        fasta.add(FastaEntry::new(
+            String::from("seventy_percent"),
+            b"AT-------G".to_vec(),
+            0,
+        ));
+        fasta.add(FastaEntry::new(
+            String::from("half"),
+            b"ATGAT-----".to_vec(),
+            1,
+        ));
+
+        let (kept, dropped) = filter_gappy(&fasta, 0.5, "./output.fasta");
+        let dropped: Vec<(String, f64)> = dropped
+            .iter()
+            .map(|(entry, fraction)| (entry.defline(), *fraction))
+            .collect();
+        assert_eq!(dropped, vec![(String::from("seventy_percent"), 0.7)]);
+        assert_eq!(kept.num_entries(), 1);
+        assert_eq!(kept.indexed_entry(0).defline(), "half");
+        assert_eq!(kept.indexed_entry(0).entry_num(), 0);
+    }
+
+    #[test]
+    fn keep_one_subtype() {
+        let mut fasta = Fasta::new("flu.fna");
+        for (i, defline) in [
+            "CY1{A_HA_H3}",
+            "CY2{A_HA_H1}",
+            "CY3{B_HA}",
+            "CY4",
+            "CY5{A_NA_h3}",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            fasta.add(FastaEntry::new(String::from(defline), b"ATG".to_vec(), i));
+        }
+
+        let (kept, dropped) = filter_subtype(&fasta, "H3", "./output.fasta");
+        assert_eq!(kept.num_entries(), 2);
+        assert_eq!(kept.indexed_entry(1).defline(), "CY5{A_NA_h3}");
+        assert_eq!(
+            deflines(&dropped),
+            vec![
+                String::from("CY2{A_HA_H1}"),
+                String::from("CY3{B_HA}"),
+                String::from("CY4")
+            ]
+        );
+    }
+
+    #[test]
+    fn dedup_keeps_input_order() {
+        let mut fasta = Fasta::new("duplicates.fna");
+        for (i, defline) in ["a", "b", "a"].into_iter().enumerate() {
+            fasta.add(FastaEntry::new(
+                String::from(defline),
+                i.to_string().into_bytes(),
+                i,
+            ));
+        }
+        let kept_entries = |kept: &Fasta| -> Vec<(String, Vec<u8>, usize)> {
+            kept.into_iter()
+                .map(|entry| {
+                    (
+                        entry.defline(),
+                        entry.sequence().into_owned(),
+                        entry.entry_num(),
+                    )
+                })
+                .collect()
+        };
+
+        let (kept, dropped) = dedup_by_defline(&fasta, KeepDuplicate::First, "first.fna");
+        assert_eq!(
+            kept_entries(&kept),
+            [
+                (String::from("a"), b"0".to_vec(), 0),
+                (String::from("b"), b"1".to_vec(), 1)
+            ]
+        );
+        assert_eq!(&*dropped[0].sequence(), b"2");
+
+        let (kept, dropped) = dedup_by_defline(&fasta, KeepDuplicate::Last, "last.fna");
+        assert_eq!(
+            kept_entries(&kept),
+            [
+                (String::from("b"), b"1".to_vec(), 0),
+                (String::from("a"), b"2".to_vec(), 1)
+            ]
+        );
+        assert_eq!(&*dropped[0].sequence(), b"0");
+    }
+
+    #[test]
+    fn screen_out_unrelated_sequence() {
+        let mut fasta = open_fasta("test_data/a_ha_h3_raw_500.fna").unwrap();
+        let mut references = Fasta::new("references.fna");
+        for (i, entry) in (&fasta).into_iter().step_by(4).enumerate() {
+            references.add(FastaEntry::new(
+                entry.defline(),
+                entry.sequence().into_owned(),
+                i,
+            ));
+        }
+        let mut state = 17;
+        let unrelated: Vec<u8> = (0..1000)
+            .map(|_| b"ACGT"[usize::try_from(next_random(&mut state) % 4).unwrap()])
+            .collect();
+        let num_h3 = fasta.num_entries();
+        fasta.add(FastaEntry::new(
+            String::from("unrelated"),
+            unrelated,
+            num_h3,
+        ));
+
+        let kmers = KmerSet::new(&references, 15);
+        let (kept, dropped, containments) =
+            screen_contaminants(&fasta, &kmers, 0.1, "screened.fna");
+        assert_eq!(kept.num_entries(), num_h3);
+        assert_eq!(
+            deflines(&dropped.iter().map(|(entry, _)| *entry).collect::<Vec<_>>()),
+            ["unrelated"]
+        );
+        assert!(dropped[0].1 < 0.01);
+        assert_eq!(containments.len(), num_h3 + 1);
+        assert!(kept.indexed_entry(0).get_meta("containment").is_some());
+    }
+}
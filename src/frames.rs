@@ -0,0 +1,377 @@
+use crate::fasta_manager::{is_gap, Fasta, FastaEntry};
+use crate::orf_trimmer::{find_orf, ContextualError, TrimToOrfOptions};
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+
+/// Gap runs further apart than this many columns are never merged, so a
+/// repair only ever rearranges gaps locally
+const REPAIR_WINDOW: usize = 12;
+
+/// A repair as the gap runs before it and the single run after it
+type Repair = (Vec<Range<usize>>, Range<usize>);
+
+/// One repair to an entry's gap placement. Columns start at 0.
+#[derive(Debug, PartialEq)]
+pub(crate) struct FrameRepair {
+    defline: String,
+    before: Vec<Range<usize>>,
+    after: Range<usize>,
+}
+
+impl FrameRepair {
+    /// Returns the gap runs as they were before the repair
+    pub(crate) fn before(&self) -> &[Range<usize>] {
+        &self.before
+    }
+
+    /// Returns the single gap run the repair left in their place
+    pub(crate) fn after(&self) -> &Range<usize> {
+        &self.after
+    }
+}
+
+/// Displays the repair with 1-based, inclusive column ranges
+impl fmt::Display for FrameRepair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let columns = |range: &Range<usize>| format!("{}-{}", range.start + 1, range.end);
+        let before: Vec<String> = self.before.iter().map(columns).collect();
+        write!(
+            f,
+            "{}: columns {} -> {}",
+            self.defline,
+            before.join(", "),
+            columns(&self.after)
+        )
+    }
+}
+
+/// Records what the `FixFrames` module did
+#[derive(Debug, Default)]
+pub(crate) struct FrameReport {
+    group_start: usize,
+    group_stop: usize,
+    repairs: Vec<FrameRepair>,
+    out_of_frame: Vec<String>,
+}
+
+impl FrameReport {
+    /// Returns the position of the group start codon. Positions start at 0.
+    pub(crate) fn group_start(&self) -> usize {
+        self.group_start
+    }
+
+    /// Returns the position of the first base of the group stop codon.
+    /// Positions start at 0.
+    pub(crate) fn group_stop(&self) -> usize {
+        self.group_stop
+    }
+
+    /// Returns every repair made, in entry order
+    pub(crate) fn repairs(&self) -> &[FrameRepair] {
+        &self.repairs
+    }
+
+    /// Returns the deflines of entries still out of frame after repair
+    pub(crate) fn out_of_frame(&self) -> &[String] {
+        &self.out_of_frame
+    }
+}
+
+/// The main function of the `FixFrames` module. Finds the group ORF, then for
+/// each entry with a gap run inside it that would break the reading frame
+/// (a length that is not a multiple of 3, or a start off a codon boundary),
+/// tries to rearrange nearby gaps to restore the frame. Only gap placement
+/// ever changes; the degapped sequence of every entry is left exactly as it
+/// was. Entries that cannot be repaired are listed in the report.
+pub(crate) fn fix_frames(
+    inp_fasta: &Fasta,
+    options: &TrimToOrfOptions,
+    out_fasta: impl AsRef<Path>,
+) -> Result<(Fasta, FrameReport), ContextualError> {
+    let orf = find_orf(inp_fasta, options)?;
+    let mut report = FrameReport {
+        group_start: orf.group_start(),
+        group_stop: orf.group_stop(),
+        ..FrameReport::default()
+    };
+    let fixed_fasta = repair_fasta(inp_fasta, &mut report, out_fasta, |len| {
+        orf.group_start()..orf.group_stop().saturating_add(3).min(len)
+    });
+    Ok((fixed_fasta, report))
+}
+
+/// The `--normalize-codon-gaps` pass of the `TrimToORF` module. Each entry of
+/// a trimmed Fasta begins at the group start codon, so its whole length is in
+/// the ORF frame; gap runs are slid and merged as `fix_frames` does until they
+/// fill whole codons. The degapped sequences are unchanged, and the report
+/// lists the entries with a true frameshift that no rearrangement can fix.
+/// Its group start and stop are those of the trimmed Fasta, so are always 0.
+pub(crate) fn normalize_codon_gaps(
+    trimmed_fasta: &Fasta,
+    out_fasta: impl AsRef<Path>,
+) -> (Fasta, FrameReport) {
+    let mut report = FrameReport::default();
+    let normalized_fasta = repair_fasta(trimmed_fasta, &mut report, out_fasta, |len| 0..len);
+    (normalized_fasta, report)
+}
+
+/// Repairs the gap runs of every entry inside the columns `window` returns for
+/// its length, recording the repairs and the entries left out of frame in
+/// `report`
+fn repair_fasta(
+    inp_fasta: &Fasta,
+    report: &mut FrameReport,
+    out_fasta: impl AsRef<Path>,
+    window: impl Fn(usize) -> Range<usize>,
+) -> Fasta {
+    let mut fixed_fasta = Fasta::new(out_fasta);
+    for entry in inp_fasta {
+        let mut sequence = entry.sequence().into_owned();
+        let window = window(sequence.len());
+        let (repairs, in_frame) = repair_entry(&mut sequence, &window);
+
+        report
+            .repairs
+            .extend(repairs.into_iter().map(|(before, after)| FrameRepair {
+                defline: entry.defline(),
+                before,
+                after,
+            }));
+        if !in_frame {
+            report.out_of_frame.push(entry.defline());
+        }
+        fixed_fasta.add(
+            FastaEntry::new(entry.defline(), sequence, entry.entry_num()).with_meta_from(entry),
+        );
+    }
+    fixed_fasta
+}
+
+/// Returns the maximal runs of gaps in `sequence`, clipped to `window`
+fn gap_runs(sequence: &[u8], window: &Range<usize>) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for i in window.clone() {
+        if is_gap(sequence[i]) {
+            match runs.last_mut() {
+                Some(run) if run.end == i => run.end += 1,
+                _ => runs.push(i..i + 1),
+            }
+        }
+    }
+    runs
+}
+
+/// Returns true if a gap run keeps the reading frame that begins at
+/// `frame_start`: it spans whole codons and starts on a codon boundary
+fn in_frame(run: &Range<usize>, frame_start: usize) -> bool {
+    run.len().is_multiple_of(3) && (run.start - frame_start).is_multiple_of(3)
+}
+
+/// Repairs the gap runs inside `window` that break the reading frame, one at a
+/// time. Returns each repair as the runs before and the run after, and whether
+/// every run in the window ended up in frame.
+fn repair_entry(sequence: &mut [u8], window: &Range<usize>) -> (Vec<Repair>, bool) {
+    let mut repairs = Vec::new();
+    let mut unrepairable: Vec<usize> = Vec::new();
+
+    loop {
+        let runs = gap_runs(sequence, window);
+        let Some(i) = runs
+            .iter()
+            .position(|run| !in_frame(run, window.start) && !unrepairable.contains(&run.start))
+        else {
+            break;
+        };
+
+        let repair = shift_run(sequence, window, &runs, i)
+            .or_else(|| merge_runs(sequence, window, &runs, i));
+        match repair {
+            Some(repair) => repairs.push(repair),
+            None => unrepairable.push(runs[i].start),
+        }
+    }
+    (repairs, unrepairable.is_empty())
+}
+
+/// Moves a run whose length is a multiple of 3 by one or two columns onto a
+/// codon boundary, swapping it with the bases beside it. The run must not
+/// touch another gap or leave the window once moved.
+fn shift_run(
+    sequence: &mut [u8],
+    window: &Range<usize>,
+    runs: &[Range<usize>],
+    i: usize,
+) -> Option<Repair> {
+    let run = runs[i].clone();
+    if !run.len().is_multiple_of(3) {
+        return None;
+    }
+    let offset = (run.start - window.start) % 3;
+    let prev_end = if i == 0 {
+        window.start
+    } else {
+        runs[i - 1].end + 1
+    };
+    let next_start = runs.get(i + 1).map_or(window.end, |next| next.start - 1);
+
+    if run.start - offset >= prev_end {
+        let new_start = run.start - offset;
+        sequence[new_start..run.end].rotate_right(run.len());
+        return Some((vec![run.clone()], new_start..new_start + run.len()));
+    }
+    if run.end + (3 - offset) <= next_start {
+        let new_start = run.start + (3 - offset);
+        sequence[run.start..new_start + run.len()].rotate_left(run.len());
+        return Some((vec![run.clone()], new_start..new_start + run.len()));
+    }
+    None
+}
+
+/// Merges a run with the next one when they are close together and their
+/// combined length is a multiple of 3, moving the bases between them aside so
+/// the merged run starts on a codon boundary
+fn merge_runs(
+    sequence: &mut [u8],
+    window: &Range<usize>,
+    runs: &[Range<usize>],
+    i: usize,
+) -> Option<Repair> {
+    let (first, second) = (runs[i].clone(), runs.get(i + 1)?.clone());
+    let num_gaps = first.len() + second.len();
+    if second.start - first.end > REPAIR_WINDOW || !num_gaps.is_multiple_of(3) {
+        return None;
+    }
+
+    let span = first.start..second.end;
+    let new_start = (span.start..=span.end - num_gaps)
+        .find(|&start| (start - window.start).is_multiple_of(3))?;
+    let gap_char = sequence[first.start];
+    let bases: Vec<u8> = sequence[first.end..second.start].to_vec();
+    let num_before = new_start - span.start;
+
+    sequence[span.start..new_start].copy_from_slice(&bases[..num_before]);
+    sequence[new_start..new_start + num_gaps].fill(gap_char);
+    sequence[new_start + num_gaps..span.end].copy_from_slice(&bases[num_before..]);
+    Some((vec![first, second], new_start..new_start + num_gaps))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fix_frames, normalize_codon_gaps, repair_entry};
+    use crate::fasta_manager::{is_gap, Fasta, FastaEntry, SequenceOps};
+    use crate::orf_trimmer::{trim_to_orf, TrimToOrfOptions};
+    use crate::translation::GeneticCode;
+
+    fn degapped(sequence: &[u8]) -> Vec<u8> {
+        sequence
+            .iter()
+            .copied()
+            .filter(|&base| !is_gap(base))
+            .collect()
+    }
+
+    #[test]
+    fn split_gap_becomes_one_codon() {
+        let mut fasta = Fasta::new("split.fna");
+        fasta.add(FastaEntry::new(
+            String::from("clean"),
+            b"ATGAAACCC---GGGTTTTAA".to_vec(),
+            0,
+        ));
+        fasta.add(FastaEntry::new(
+            String::from("split"),
+            b"ATGAAA-CCC--GGGTTTTAA".to_vec(),
+            1,
+        ));
+
+        let (fixed, report) =
+            fix_frames(&fasta, &TrimToOrfOptions::default(), "./output.fasta").unwrap();
+        assert_eq!(report.group_start(), 0);
+        assert_eq!(report.group_stop(), 18);
+        assert!(report.out_of_frame().is_empty());
+        assert_eq!(report.repairs().len(), 1);
+        assert_eq!(report.repairs()[0].before(), [6..7, 10..12]);
+        assert_eq!(report.repairs()[0].after(), &(6..9));
+        assert_eq!(
+            report.repairs()[0].to_string(),
+            "split: columns 7-7, 11-12 -> 7-9"
+        );
+
+        assert_eq!(
+            &*fixed.indexed_entry(1).sequence(),
+            b"ATGAAA---CCCGGGTTTTAA"
+        );
+        assert_eq!(
+            &*fixed.indexed_entry(0).sequence(),
+            b"ATGAAACCC---GGGTTTTAA"
+        );
+    }
+
+    #[test]
+    fn normalized_orf_translates_gap_clean() {
+        let mut fasta = Fasta::new("codon_gaps.fna");
+        for (i, (defline, sequence)) in [
+            ("clean", "ATGAAACCCGGGTTTTAA"),
+            ("split", "ATGAA-A--GGGTTTTAA"),
+            ("shifted", "ATGA---AACCCTTTTAA"),
+            ("frameshift", "ATGAA-ACCCGGGTTTAA"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            fasta.add(FastaEntry::new(
+                String::from(defline),
+                sequence.as_bytes().to_vec(),
+                i,
+            ));
+        }
+        let translations = |fasta: &Fasta| -> Vec<Vec<u8>> {
+            fasta
+                .into_iter()
+                .map(|entry| entry.sequence().translate(GeneticCode::Standard))
+                .collect()
+        };
+
+        let (trimmed, _) = trim_to_orf(&fasta, &TrimToOrfOptions::default()).unwrap();
+        assert_eq!(translations(&trimmed)[1], b"MXXGF*");
+        let (normalized, report) = normalize_codon_gaps(&trimmed, "./output.fasta");
+        assert_eq!(
+            translations(&normalized)[..3],
+            [b"MKPGF*".to_vec(), b"MK-GF*".to_vec(), b"M-KPF*".to_vec()]
+        );
+        assert_eq!(report.repairs().len(), 2);
+        assert_eq!(report.out_of_frame(), ["frameshift"]);
+        for (entry, original) in normalized.into_iter().zip(&fasta) {
+            assert_eq!(degapped(&entry.sequence()), degapped(&original.sequence()));
+        }
+    }
+
+    #[test]
+    fn codon_gap_shifts_onto_boundary() {
+        let mut sequence = b"ATGA---AACCC".to_vec();
+        let (repairs, in_frame) = repair_entry(&mut sequence, &(0..12));
+        assert!(in_frame);
+        let moved_run = 4..7;
+        assert_eq!(repairs, vec![(vec![moved_run], 3..6)]);
+        assert_eq!(sequence, b"ATG---AAACCC");
+    }
+
+    #[test]
+    fn lone_frameshift_is_left_alone() {
+        let original = b"ATGAA-ACCCGGGTTTAA".to_vec();
+        let mut sequence = original.clone();
+        let (repairs, in_frame) = repair_entry(&mut sequence, &(0..18));
+        assert!(!in_frame);
+        assert!(repairs.is_empty());
+        assert_eq!(sequence, original);
+    }
+
+    #[test]
+    fn repairs_keep_bases() {
+        let original = b"ATG-AA.CCGG-G--TTTAAA".to_vec();
+        let mut sequence = original.clone();
+        repair_entry(&mut sequence, &(0..21));
+        assert_eq!(degapped(&sequence), degapped(&original));
+    }
+}
@@ -0,0 +1,281 @@
+use crate::fasta_manager::{is_gap, write_atomically, Fasta, FastaEntry, SequenceOps};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// The first line of a gap map file
+const HEADER: &str = "# defline\taligned_length\tgap_runs";
+
+/// One run of the same gap character. Columns start at 0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GapRun {
+    column: usize,
+    length: usize,
+    gap: u8,
+}
+
+impl fmt::Display for GapRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.column + 1, self.length)?;
+        if self.gap != b'-' {
+            write!(f, ":{}", char::from(self.gap))?;
+        }
+        Ok(())
+    }
+}
+
+/// Where one entry's gaps were in the alignment, enough to restore the entry
+/// from its ungapped bases
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct GapMap {
+    aligned_length: usize,
+    runs: Vec<GapRun>,
+}
+
+impl GapMap {
+    /// Records the gap runs of an aligned sequence
+    pub(crate) fn from_sequence(sequence: &[u8]) -> Self {
+        let mut runs: Vec<GapRun> = Vec::new();
+        for (column, &base) in sequence.iter().enumerate() {
+            if !is_gap(base) {
+                continue;
+            }
+            match runs.last_mut() {
+                Some(run) if run.gap == base && run.column + run.length == column => {
+                    run.length += 1;
+                }
+                _ => runs.push(GapRun {
+                    column,
+                    length: 1,
+                    gap: base,
+                }),
+            }
+        }
+        GapMap {
+            aligned_length: sequence.len(),
+            runs,
+        }
+    }
+
+    /// Returns the number of bases the map leaves room for
+    pub(crate) fn num_bases(&self) -> usize {
+        self.aligned_length - self.runs.iter().map(|run| run.length).sum::<usize>()
+    }
+
+    /// Puts the gaps back among `bases`, or returns None if there are not
+    /// exactly as many bases as the map leaves room for
+    pub(crate) fn apply(&self, bases: &[u8]) -> Option<Vec<u8>> {
+        if bases.len() != self.num_bases() {
+            return None;
+        }
+        let mut sequence = Vec::with_capacity(self.aligned_length);
+        let mut bases = bases.iter();
+        for run in &self.runs {
+            sequence.extend(bases.by_ref().take(run.column - sequence.len()));
+            sequence.extend(std::iter::repeat_n(run.gap, run.length));
+        }
+        sequence.extend(bases);
+        Some(sequence)
+    }
+
+    /// Parses the `aligned_length` and `gap_runs` fields of one line, checking
+    /// that the runs are in order, don't overlap and fit in the alignment
+    fn parse(aligned_length: &str, gap_runs: &str) -> Result<Self, String> {
+        let aligned_length: usize = aligned_length
+            .parse()
+            .map_err(|_| format!("has an invalid aligned length, '{aligned_length}'"))?;
+        let mut runs: Vec<GapRun> = Vec::new();
+        for run in gap_runs.split(',').filter(|run| !run.is_empty()) {
+            let invalid = || format!("has an invalid gap run, '{run}'");
+            let mut fields = run.split(':');
+            let (Some(Ok(column)), Some(Ok(length))) = (
+                fields.next().map(str::parse::<usize>),
+                fields.next().map(str::parse::<usize>),
+            ) else {
+                return Err(invalid());
+            };
+            let gap = match fields.next().map(str::as_bytes) {
+                None => b'-',
+                Some(&[gap]) if is_gap(gap) => gap,
+                Some(_) => return Err(invalid()),
+            };
+            let after_last = runs.last().map_or(0, |last| last.column + last.length);
+            if fields.next().is_some()
+                || column == 0
+                || length == 0
+                || column - 1 < after_last
+                || column - 1 + length > aligned_length
+            {
+                return Err(invalid());
+            }
+            runs.push(GapRun {
+                column: column - 1,
+                length,
+                gap,
+            });
+        }
+        Ok(GapMap {
+            aligned_length,
+            runs,
+        })
+    }
+}
+
+/// Displays the map as the `aligned_length` and `gap_runs` fields of its line
+impl fmt::Display for GapMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let runs: Vec<String> = self.runs.iter().map(ToString::to_string).collect();
+        write!(f, "{}\t{}", self.aligned_length, runs.join(","))
+    }
+}
+
+/// The main function of the `Degap` module. Removes every gap and returns the
+/// ungapped Fasta along with each entry's defline and gap map, in entry order
+pub(crate) fn degap_with_map(
+    inp_fasta: &Fasta,
+    out_fasta: impl AsRef<Path>,
+) -> (Fasta, Vec<(String, GapMap)>) {
+    let mut degapped_fasta = Fasta::new(out_fasta);
+    let mut gap_maps = Vec::with_capacity(inp_fasta.num_entries());
+    for entry in inp_fasta {
+        let sequence = entry.sequence();
+        gap_maps.push((entry.defline(), GapMap::from_sequence(&sequence)));
+        degapped_fasta.add(
+            FastaEntry::new(entry.defline(), sequence.degapped(), entry.entry_num())
+                .with_meta_from(entry),
+        );
+    }
+    (degapped_fasta, gap_maps)
+}
+
+/// Writes a gap map file: one `DEFLINE<TAB>ALIGNED_LENGTH<TAB>GAP_RUNS` line
+/// per entry, in entry order, after a '#' header. `GAP_RUNS` lists each run of
+/// consecutive gap characters as `COLUMN:LENGTH`, separated by commas, with
+/// COLUMN the alignment column the run starts at counted from 1. Runs of '.'
+/// are written `COLUMN:LENGTH:.` so the original gap characters come back. An
+/// entry with no gaps has an empty `GAP_RUNS`.
+pub(crate) fn write_gap_maps(
+    gap_maps: &[(String, GapMap)],
+    path: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    write_atomically(path.as_ref(), |writer| {
+        writeln!(writer, "{HEADER}")?;
+        for (defline, gap_map) in gap_maps {
+            writeln!(writer, "{defline}\t{gap_map}")?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Reads a gap map file written by `write_gap_maps`, keeping the file's order
+pub(crate) fn read_gap_maps(path: impl AsRef<Path>) -> Result<Vec<(String, GapMap)>, io::Error> {
+    let contents = fs::read_to_string(path)?;
+    let mut gap_maps = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |problem: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {} of the gap map {problem}", i + 1),
+            )
+        };
+        // Split from the right so a tab in a defline survives
+        let &[gap_runs, aligned_length, defline] =
+            line.rsplitn(3, '\t').collect::<Vec<_>>().as_slice()
+        else {
+            return Err(invalid("is not DEFLINE<TAB>ALIGNED_LENGTH<TAB>GAP_RUNS"));
+        };
+        let gap_map = GapMap::parse(aligned_length, gap_runs).map_err(|err| invalid(&err))?;
+        gap_maps.push((String::from(defline), gap_map));
+    }
+    Ok(gap_maps)
+}
+
+/// The main function of the `Regap` module. Restores each entry, degapped, to
+/// its alignment with the gap map of the same defline. Entries are matched by
+/// defline, and entries sharing a defline take that defline's maps in order, so
+/// entries may come back reordered or filtered. An entry with no map, or with
+/// a different number of bases than its map, is an error naming it.
+pub(crate) fn regap(
+    inp_fasta: &Fasta,
+    gap_maps: Vec<(String, GapMap)>,
+    out_fasta: impl AsRef<Path>,
+) -> Result<Fasta, String> {
+    let mut maps_by_defline: HashMap<String, VecDeque<GapMap>> = HashMap::new();
+    for (defline, gap_map) in gap_maps {
+        maps_by_defline
+            .entry(defline)
+            .or_default()
+            .push_back(gap_map);
+    }
+
+    let mut regapped_fasta = Fasta::new(out_fasta);
+    for entry in inp_fasta {
+        let defline = entry.defline();
+        let Some(gap_map) = maps_by_defline
+            .get_mut(&defline)
+            .and_then(VecDeque::pop_front)
+        else {
+            return Err(format!("No gap map left for '{defline}'"));
+        };
+        let bases = entry.sequence().degapped();
+        let Some(sequence) = gap_map.apply(&bases) else {
+            return Err(format!(
+                "'{defline}' has {} bases but its gap map has room for {}",
+                bases.len(),
+                gap_map.num_bases()
+            ));
+        };
+        regapped_fasta
+            .add(FastaEntry::new(defline, sequence, entry.entry_num()).with_meta_from(entry));
+    }
+    Ok(regapped_fasta)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{degap_with_map, read_gap_maps, regap, write_gap_maps, GapMap};
+    use crate::fasta_manager::{open_fasta, FastaEntry};
+
+    #[test]
+    fn degap_then_regap_round_trips() {
+        let mut fasta = open_fasta("fake_short.fna").unwrap();
+        let num_entries = fasta.num_entries();
+        fasta.add(FastaEntry::new(
+            String::from("dots\tand dashes"),
+            b"..AC-.-G--".to_vec(),
+            num_entries,
+        ));
+
+        let (degapped, gap_maps) = degap_with_map(&fasta, "degapped.fna");
+        assert_eq!(&*degapped.indexed_entry(2).sequence(), b"AATGATGACC");
+        assert_eq!(
+            gap_maps[num_entries].1.to_string(),
+            "10\t1:2:.,5:1,6:1:.,7:1,9:2"
+        );
+
+        let path = std::env::temp_dir().join("clean_genes_round_trip.gapmap.tsv");
+        write_gap_maps(&gap_maps, &path).unwrap();
+        let read_back = read_gap_maps(&path).unwrap();
+        assert_eq!(read_back, gap_maps);
+        let regapped = regap(&degapped, read_back, "regapped.fna").unwrap();
+        for (original, restored) in fasta.into_iter().zip(&regapped) {
+            assert_eq!(original.defline(), restored.defline());
+            assert_eq!(original.sequence(), restored.sequence());
+        }
+
+        let mut short = degapped.clone();
+        short.iter_mut().next().unwrap().sequence_mut().pop();
+        let err = regap(&short, gap_maps, "regapped.fna").unwrap_err();
+        assert_eq!(err, "'first' has 8 bases but its gap map has room for 9");
+
+        assert!(GapMap::parse("4", "3:3").is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
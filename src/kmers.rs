@@ -0,0 +1,162 @@
+use crate::fasta_manager::{Fasta, SequenceOps};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+/// Returns a sequence's bases without gaps, uppercased with U as T, so k-mers
+/// compare on bases alone
+fn normalized_bases(sequence: &[u8]) -> Vec<u8> {
+    sequence
+        .degapped()
+        .iter()
+        .map(|base| match base.to_ascii_uppercase() {
+            b'U' => b'T',
+            upper => upper,
+        })
+        .collect()
+}
+
+/// Returns the k-mers of normalized bases that hold only A, C, G and T
+fn acgt_kmers(bases: &[u8], kmer_size: usize) -> impl Iterator<Item = &[u8]> {
+    bases.windows(kmer_size).filter(|kmer| {
+        kmer.iter()
+            .all(|base| matches!(base, b'A' | b'C' | b'G' | b'T'))
+    })
+}
+
+/// Returns the lesser of a k-mer and its reverse complement, so a k-mer and
+/// its match on the other strand are stored alike
+fn canonical_kmer(kmer: &[u8]) -> Vec<u8> {
+    let reverse = kmer.reverse_complement();
+    if reverse.as_slice() < kmer {
+        reverse
+    } else {
+        kmer.to_vec()
+    }
+}
+
+/// The k-mers found in a set of reference sequences on either strand, for
+/// screening other sequences against them
+#[derive(Debug)]
+pub(crate) struct KmerSet {
+    kmer_size: usize,
+    kmers: HashSet<Vec<u8>>,
+}
+
+impl KmerSet {
+    /// Collects every k-mer of length `kmer_size` in the references, counted
+    /// as in `kmer_counts`
+    pub(crate) fn new(references: &Fasta, kmer_size: usize) -> Self {
+        let mut kmers = HashSet::new();
+        for entry in references {
+            let bases = normalized_bases(&entry.sequence());
+            kmers.extend(acgt_kmers(&bases, kmer_size).map(canonical_kmer));
+        }
+        KmerSet { kmer_size, kmers }
+    }
+
+    /// Returns whether the references had no k-mers at all
+    pub(crate) fn is_empty(&self) -> bool {
+        self.kmers.is_empty()
+    }
+
+    /// Returns the fraction of a sequence's k-mers found in the set on either
+    /// strand. A sequence with no k-mers has a containment of 0.
+    #[expect(clippy::cast_precision_loss)]
+    pub(crate) fn containment(&self, sequence: &[u8]) -> f64 {
+        let bases = normalized_bases(sequence);
+        let (mut found, mut total) = (0_usize, 0_usize);
+        for kmer in acgt_kmers(&bases, self.kmer_size) {
+            total += 1;
+            if self.kmers.contains(&canonical_kmer(kmer)) {
+                found += 1;
+            }
+        }
+        if total == 0 {
+            0.0
+        } else {
+            found as f64 / total as f64
+        }
+    }
+}
+
+/// Counts every k-mer of length `kmer_size` across all sequences, after
+/// removing gaps. K-mers are uppercased with U counted as T, and any k-mer
+/// containing an ambiguity code or other non-ACGT character is skipped.
+/// Returns the k-mers sorted by descending count, ties broken alphabetically.
+pub(crate) fn kmer_counts(inp_fasta: &Fasta, kmer_size: usize) -> Vec<(Vec<u8>, usize)> {
+    let mut counts: HashMap<Vec<u8>, usize> = HashMap::new();
+
+    for entry in inp_fasta {
+        let bases = normalized_bases(&entry.sequence());
+        for kmer in acgt_kmers(&bases, kmer_size) {
+            *counts.entry(kmer.to_vec()).or_insert(0) += 1;
+        }
+    }
+
+    let mut sorted: Vec<(Vec<u8>, usize)> = counts.into_iter().collect();
+    sorted.sort_by(|(kmer_a, count_a), (kmer_b, count_b)| {
+        count_b.cmp(count_a).then_with(|| kmer_a.cmp(kmer_b))
+    });
+    sorted
+}
+
+/// The main function of the `KmerCount` module. Builds a TSV of k-mers and
+/// their counts, most frequent first, keeping only the first `top` rows if
+/// given.
+pub(crate) fn kmer_table(inp_fasta: &Fasta, kmer_size: usize, top: Option<usize>) -> String {
+    let counts = kmer_counts(inp_fasta, kmer_size);
+    let mut table = String::from("kmer\tcount\n");
+    for (kmer, count) in counts.iter().take(top.unwrap_or(usize::MAX)) {
+        let _ = writeln!(table, "{}\t{count}", String::from_utf8_lossy(kmer));
+    }
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::{kmer_counts, kmer_table, KmerSet};
+    use crate::fasta_manager::{Fasta, FastaEntry};
+
+    fn known_fasta() -> Fasta {
+        let mut fasta = Fasta::new("kmers.fna");
+        fasta.add(FastaEntry::new(String::from("a"), b"AC-GTac".to_vec(), 0));
+        fasta.add(FastaEntry::new(String::from("b"), b"ACNAC".to_vec(), 1));
+        fasta
+    }
+
+    #[test]
+    fn count_2mers() {
+        let counts = kmer_counts(&known_fasta(), 2);
+        assert_eq!(
+            counts,
+            [
+                (b"AC".to_vec(), 4),
+                (b"CG".to_vec(), 1),
+                (b"GT".to_vec(), 1),
+                (b"TA".to_vec(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn table_with_top() {
+        let table = kmer_table(&known_fasta(), 2, Some(2));
+        assert_eq!(table, "kmer\tcount\nAC\t4\nCG\t1\n");
+    }
+
+    #[test]
+    fn containment_on_either_strand() {
+        let kmers = KmerSet::new(&known_fasta(), 3);
+        assert!((kmers.containment(b"ACGT") - 1.0).abs() < f64::EPSILON);
+        // The reverse complement of entry "a"
+        assert!((kmers.containment(b"GTACGT") - 1.0).abs() < f64::EPSILON);
+        assert!((kmers.containment(b"ACGTTT") - 0.5).abs() < f64::EPSILON);
+        assert!((kmers.containment(b"TTTTT")).abs() < f64::EPSILON);
+        assert!((kmers.containment(b"AC")).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn kmer_longer_than_sequences() {
+        assert!(kmer_counts(&known_fasta(), 10).is_empty());
+    }
+}
@@ -0,0 +1,153 @@
+use std::fmt;
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+
+/// Why a run was stopped for going over one of its `Limits`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LimitError {
+    /// The run took longer than its time budget
+    TimedOut(Duration),
+    /// The input is larger than the byte cap
+    InputTooLarge(u64),
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::TimedOut(budget) => write!(
+                f,
+                "Timed out: the run took longer than --max-runtime-secs, {}s",
+                budget.as_secs_f64()
+            ),
+            LimitError::InputTooLarge(max_bytes) => write!(
+                f,
+                "Input is larger than --max-input-bytes, {max_bytes} bytes"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+impl From<LimitError> for io::Error {
+    fn from(err: LimitError) -> Self {
+        let kind = match err {
+            LimitError::TimedOut(_) => io::ErrorKind::TimedOut,
+            LimitError::InputTooLarge(_) => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// Caps on the input size and running time of one run, so a shared service
+/// can turn away pathological inputs. The time budget is checked
+/// cooperatively, while the input is read, between modules, and per sequence
+/// inside the heaviest modules, so a run stops with an error rather than being
+/// killed.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Limits {
+    max_input_bytes: Option<u64>,
+    budget: Option<(Instant, Duration)>,
+}
+
+impl Limits {
+    /// Constructor for `Limits`. The time budget starts now.
+    pub(crate) fn new(max_input_bytes: Option<u64>, max_runtime: Option<Duration>) -> Self {
+        Limits {
+            max_input_bytes,
+            budget: max_runtime.map(|max_runtime| (Instant::now(), max_runtime)),
+        }
+    }
+
+    /// Returns an error if the time budget has run out
+    pub(crate) fn check_time(&self) -> Result<(), LimitError> {
+        match self.budget {
+            Some((start, max_runtime)) if start.elapsed() > max_runtime => {
+                Err(LimitError::TimedOut(max_runtime))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns an error if an input of `num_bytes` is over the byte cap
+    pub(crate) fn check_input_size(&self, num_bytes: u64) -> Result<(), LimitError> {
+        match self.max_input_bytes {
+            Some(max_bytes) if num_bytes > max_bytes => Err(LimitError::InputTooLarge(max_bytes)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Wraps a reader to enforce `Limits` as it is read: each read fails once the
+/// time budget has run out or more bytes than the cap have come through
+pub(crate) struct LimitedReader<R> {
+    inner: R,
+    limits: Limits,
+    bytes_read: u64,
+}
+
+impl<R: Read> LimitedReader<R> {
+    /// Constructor for `LimitedReader`
+    pub(crate) fn new(inner: R, limits: Limits) -> Self {
+        LimitedReader {
+            inner,
+            limits,
+            bytes_read: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.limits.check_time()?;
+        let num_read = self.inner.read(buf)?;
+        self.bytes_read += num_read as u64;
+        self.limits.check_input_size(self.bytes_read)?;
+        Ok(num_read)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LimitError, LimitedReader, Limits};
+    use std::io::{ErrorKind, Read};
+    use std::time::Duration;
+
+    #[test]
+    fn tiny_budget_times_out() {
+        let limits = Limits::new(None, Some(Duration::from_nanos(1)));
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(
+            limits.check_time(),
+            Err(LimitError::TimedOut(Duration::from_nanos(1)))
+        );
+        let mut reader = LimitedReader::new(&b">a\nATG\n"[..], limits);
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+
+        let unlimited = Limits::default();
+        assert!(unlimited.check_time().is_ok());
+        assert!(unlimited.check_input_size(u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn byte_cap() {
+        let input = b">a\nATGAAATAG\n";
+        let mut output = Vec::new();
+        let fits = Limits::new(Some(input.len() as u64), None);
+        LimitedReader::new(&input[..], fits)
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, input);
+
+        let too_small = Limits::new(Some(4), None);
+        let err = LimitedReader::new(&input[..], too_small)
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "Input is larger than --max-input-bytes, 4 bytes"
+        );
+    }
+}
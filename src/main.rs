@@ -1,42 +1,2111 @@
-#![feature(iter_array_chunks)]
-//test
-use clap::Parser;
-use fasta_manager::{open_fasta, write_fasta};
-use orf_trimmer::trim_to_orf;
+use alignment::{
+    flag_outliers, nearest_neighbor_table, polish_log, polish_to_consensus, select_blocks,
+    transpose_alignment,
+};
+use annotate::annotate_length;
+use back_translate::{back_translate, Mismatch};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use defline_table::defline_table;
+use edits::{normalize_gaps, recode_missing, uppercase_sequences};
+use fasta_manager::{
+    check_protein, open_fasta, open_input, replace_base, write_atomically, write_fasta,
+    write_fasta_to_file, write_fasta_to_file_with_fai, Fasta, FastaEntry, MoleculeType, Rejects,
+    SequenceOps,
+};
+use filters::{
+    dedup_by_defline, drop_empty, filter_by_field, filter_gappy, filter_subtype,
+    screen_contaminants, KeepDuplicate,
+};
+use frames::{fix_frames, normalize_codon_gaps, FrameReport};
+use gapmap::{degap_with_map, read_gap_maps, regap, write_gap_maps};
+use kmers::{kmer_table, KmerSet};
+use limits::Limits;
+use manifest::Manifest;
+use orf_trimmer::{
+    load_segment_reference_table, trim_by_segment_table, trim_to_entry_orfs, trim_to_group_orf,
+    trim_to_guided_orf, trim_to_orf_collecting, write_start_report_tsv, EntryErrors, ProteinGuide,
+    TrimReport, TrimToOrfOptions,
+};
+use output::{name_map_path, write_gff3, write_phylip, OutputFormat};
+use overview::{render_overview, LABEL_WIDTH};
 use process_args::Config;
+use protein_stats::protein_stats_table;
+use quality::{sequence_quality_report, write_quality_report_tsv};
+use regions::{read_regions, region_qc, trim_regions};
+use relabel::{read_name_map, read_rename_map, relabel_fasta, relabel_newick, rename_fasta};
+use split::split_by_field;
+use stats::{stats_table, total_bases, ModuleStats};
+use std::borrow::Cow;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::Instant;
+use strands::reverse_complement_fasta;
+use translation::{
+    trim_signal_peptide, write_aa_composition_tsv, write_hydrophobicity_tsv,
+    write_isoelectric_point_tsv, write_molecular_weight_tsv, GeneticCode,
+};
+use validate::{findings_json, has_failures, validate, Finding, Severity};
 
+mod alignment;
+mod annotate;
+mod back_translate;
+mod defline_table;
+mod edits;
 mod fasta_manager;
+mod filters;
+mod frames;
+mod gapmap;
+mod kmers;
+mod limits;
+mod manifest;
 mod math;
 mod orf_trimmer;
+mod output;
+mod overview;
+mod packed;
 mod process_args;
+mod progress;
+mod protein_stats;
+mod quality;
+mod regions;
+mod relabel;
+mod split;
+mod stats;
+mod strands;
+mod translation;
+mod validate;
+
+/// Modules that write their own output, such as a table, in place of an output
+/// fasta, so nothing can run after them
+const FINAL_MODULES: &[&str] = &[
+    "DeflineTable",
+    "KmerCount",
+    "ProteinStats",
+    "TrimRegions",
+    "SplitByField",
+    "Overview",
+    "NearestNeighbor",
+];
+
+/// Modules that only make sense for protein input
+const PROTEIN_MODULES: &[&str] = &["ProteinStats", "TrimSignalPeptide"];
 
 fn main() {
     let args = Config::parse();
+    let limits = args.limits();
+    prepare_out_dirs(&args);
+    eprintln!("Activating module '{}'", args.module());
+
+    let inp_fasta = match open_input(
+        args.inp_fasta(),
+        args.in_format(),
+        args.low_memory(),
+        args.tolerate_formatted(),
+        args.progress(),
+        limits,
+    ) {
+        Ok(success_fasta) => success_fasta,
+        Err(err) if args.modules() == ["Validate"] => {
+            report_validation(&args, &[Finding::error("parse", err.to_string())])
+        }
+        Err(err) => {
+            eprintln!(
+                "\nFailed to open input file, '{}', \nproducing the error: '{}'\n",
+                args.inp_fasta().display(),
+                err
+            );
+            process::exit(1);
+        }
+    };
+
+    if let Some(reference) = args.reference() {
+        if inp_fasta
+            .find_by_id(reference, args.ignore_id_case())
+            .is_none()
+        {
+            eprintln!(
+                "\nFailed to find reference sequence, '{reference}', in input fasta file, '{}'\n",
+                args.inp_fasta().display()
+            );
+            process::exit(1);
+        }
+    }
+
+    check_molecule_type(&args, &inp_fasta);
+    if let Some(report_path) = args.quality_report() {
+        write_quality_report(&args, &inp_fasta, report_path);
+    }
+    write_protein_tables(&args, &inp_fasta);
+
+    let modules = args.modules();
+    check_module_options(&args, &modules);
+
+    let mut parameters: Vec<(&str, String)> = Vec::new();
+    let mut all_stats = Vec::new();
+    let Some(mut out_fasta) = run_pipeline(
+        &args,
+        &inp_fasta,
+        &modules,
+        &limits,
+        &mut parameters,
+        &mut all_stats,
+    ) else {
+        report_stats(&args, &all_stats);
+        return;
+    };
+
+    if args.uppercase() {
+        uppercase_sequences(&mut out_fasta);
+        parameters.push(("uppercase", String::from("true")));
+    }
+    if let Some(gap) = args.normalize_gaps() {
+        normalize_gaps(&mut out_fasta, gap);
+        parameters.push(("normalize_gaps", char::from(gap).to_string()));
+    }
+    if let Some(missing) = args.recode_missing() {
+        recode_missing(&mut out_fasta, missing);
+        parameters.push(("recode_missing", char::from(missing).to_string()));
+    }
+
+    if args.dry_run() {
+        report_dry_run(&args, &out_fasta, &parameters);
+        report_stats(&args, &all_stats);
+        check_min_confidence(&args, &parameters);
+        return;
+    }
+
+    write_outputs(&args, &out_fasta, &all_stats);
 
-    if args.module() == "TrimToORF" {
-        eprintln!("Activating module 'TrimToORF'");
+    if let Some(manifest_out) = args.manifest_out() {
+        let mut manifest = Manifest::new(&inp_fasta, &out_fasta, args.module());
+        for (key, value) in &parameters {
+            manifest.add_parameter(key, value);
+        }
+        for (key, value) in all_stats.iter().flat_map(ModuleStats::key_values) {
+            manifest.add_parameter(&key, value);
+        }
+        if let Err(err) = manifest.write(manifest_out) {
+            eprintln!(
+                "\nFailed to write manifest file, '{}', \nproducing the error: '{err}'\n",
+                manifest_out.display()
+            );
+            process::exit(1);
+        }
+    }
+    report_stats(&args, &all_stats);
+    check_min_confidence(&args, &parameters);
+}
+
+/// Runs each module in turn on the output of the one before, recording their
+/// settings in `parameters` and what each did in `all_stats`. Exits with a
+/// timeout error once a module finishes past the runtime budget in `limits`.
+/// Returns the final Fasta, or None if the last module wrote its own
+/// output.
+fn run_pipeline<'a>(
+    args: &'a Config,
+    inp_fasta: &Fasta,
+    modules: &[&'a str],
+    limits: &Limits,
+    parameters: &mut Vec<(&'a str, String)>,
+    all_stats: &mut Vec<ModuleStats>,
+) -> Option<Fasta> {
+    let mut rejects = args.rejects().filter(|_| !args.dry_run()).map(Rejects::new);
+    let mut stage_output: Option<Fasta> = None;
+    let subtype_filter = args.filter_subtype().map(|_| "FilterSubtype");
+    for module in subtype_filter.iter().chain(modules) {
+        let stage_input = stage_output.as_ref().unwrap_or(inp_fasta);
+        let num_parameters = parameters.len();
+        let mut stats = ModuleStats::new(module, stage_input);
+        check_entry_count(args, module, stage_input.num_entries());
+        let start = Instant::now();
+        let output = run_module(
+            args,
+            module,
+            stage_input,
+            parameters,
+            rejects.as_mut(),
+            &mut stats,
+            limits,
+        );
+        let output = output.map(|mut out_fasta| {
+            out_fasta.renumber();
+            out_fasta
+        });
+        stats.finish(stage_input, output.as_ref(), start.elapsed());
+        for (key, value) in &parameters[num_parameters..] {
+            stats.add_detail(key, value);
+        }
+        all_stats.push(stats);
+        if let Err(err) = limits.check_time() {
+            eprintln!("\nStopped after module '{module}', producing the error: '{err}'\n");
+            process::exit(1);
+        }
+
+        stage_output = output;
+        if stage_output.is_none() {
+            break;
+        }
+    }
+    finish_rejects(rejects);
+    stage_output
+}
+
+/// Prints the table of per-module stats to stderr, unless `--quiet` is set.
+/// With `--report`, the table is written to the report path instead, once the
+/// output fasta is complete, so the two never share a stream.
+fn report_stats(args: &Config, all_stats: &[ModuleStats]) {
+    if let Some(report_path) = args.report() {
+        if args.dry_run() {
+            eprintln!(
+                "Dry run: would write stats report to '{}'",
+                report_path.display()
+            );
+        } else if let Err(err) = write_atomically(report_path, |writer| {
+            writer.write_all(stats_table(all_stats).as_bytes())
+        }) {
+            eprintln!(
+                "\nFailed to write stats report, '{}', \nproducing the error: '{err}'\n",
+                report_path.display()
+            );
+            process::exit(1);
+        }
+        return;
+    }
+    if !args.quiet() && !all_stats.is_empty() {
+        eprint!("{}", stats_table(all_stats));
+    }
+}
+
+/// Exits with an error naming the requirement if a module's input has fewer
+/// sequences than it can work on: one for modules built on a consensus or
+/// columns, and two for pairwise ones. Warns when the group ORF vote runs on
+/// fewer than three, where it means little.
+fn check_entry_count(args: &Config, module: &str, num_entries: usize) {
+    let group_vote = !args.per_sequence()
+        && args.protein_reference().is_none()
+        && args.segment_table().is_none()
+        && args.force_frame().is_none();
+    let (required, recommended) = match module {
+        "TrimToORF" | "FixFrames" if group_vote => (1, 3),
+        "TrimToORF" | "FixFrames" | "FlagOutliers" | "SelectBlocks" | "TransposeAlignment"
+        | "Overview" | "PolishToConsensus" => (1, 1),
+        "NearestNeighbor" => (2, 2),
+        _ => return,
+    };
+    if num_entries < required {
+        eprintln!(
+            "\n{module} needs at least {required} sequence(s), but its input has {num_entries}\n"
+        );
+        process::exit(1);
+    }
+    if num_entries < recommended {
+        eprintln!(
+            "Warning: {module} votes on the group ORF across sequences, which means little \
+            with fewer than {recommended}; its input has {num_entries}"
+        );
+    }
+}
+
+/// Checks, and with `--create-dirs` creates, the output directories before the
+/// input is read. A dry run only reports the directories it would create.
+fn prepare_out_dirs(args: &Config) {
+    match args.prepare_out_dirs() {
+        Ok(would_create) => {
+            for dir in would_create {
+                eprintln!("Dry run: would create directory '{}'", dir.display());
+            }
+        }
+        Err(err) => Config::command()
+            .error(ErrorKind::ValueValidation, err)
+            .exit(),
+    }
+}
 
-        let inp_fasta = match open_fasta(args.inp_fasta()) {
+/// Runs one module, recording its settings in `parameters` and any entries it
+/// removes in `rejects`. Returns the output Fasta, or None for modules that
+/// write their own output. The heaviest modules check the time budget in
+/// `limits` as they go.
+fn run_module(
+    args: &Config,
+    module: &str,
+    inp_fasta: &Fasta,
+    parameters: &mut Vec<(&str, String)>,
+    rejects: Option<&mut Rejects>,
+    stats: &mut ModuleStats,
+    limits: &Limits,
+) -> Option<Fasta> {
+    let out_fasta = match module {
+        "TrimToORF" => run_trim_to_orf_mode(args, inp_fasta, parameters, rejects, stats, limits),
+        "AnnotateLength" => {
+            parameters.push(("length_key", String::from(args.length_key())));
+            annotate_length(inp_fasta, args.length_key(), args.out_fasta())
+        }
+        "DropEmpty" => {
+            let (out_fasta, dropped) = drop_empty(inp_fasta, args.out_fasta());
+            let dropped: Vec<_> = dropped.into_iter().map(|entry| (entry, None)).collect();
+            report_dropped(
+                &dropped,
+                module,
+                "empty",
+                "empty or all gaps/N",
+                rejects,
+                stats,
+            );
+            out_fasta
+        }
+        "Dedup" => run_dedup(args, inp_fasta, module, parameters, rejects, stats),
+        "BackTranslate" => run_back_translate(args, inp_fasta, module, rejects, stats),
+        "Degap" => run_degap(args, inp_fasta),
+        "Regap" => run_regap(args, inp_fasta),
+        "Relabel" => run_relabel(args, inp_fasta),
+        "Rename" => run_rename(args, inp_fasta, parameters),
+        "Screen" => run_screen(args, inp_fasta, module, parameters, rejects, stats),
+        "FilterGappy" => {
+            parameters.push(("max_gap_fraction", args.max_gap_fraction().to_string()));
+            let (out_fasta, dropped) =
+                filter_gappy(inp_fasta, args.max_gap_fraction(), args.out_fasta());
+            let dropped: Vec<_> = dropped
+                .into_iter()
+                .map(|(entry, fraction)| (entry, Some(fraction)))
+                .collect();
+            report_dropped(
+                &dropped,
+                module,
+                "gap_fraction",
+                &format!("gap fraction above {}", args.max_gap_fraction()),
+                rejects,
+                stats,
+            );
+            out_fasta
+        }
+        "FilterByField" => run_filter_by_field(args, inp_fasta, parameters, rejects, stats),
+        "FlagOutliers" => run_flag_outliers(args, inp_fasta, parameters, rejects, stats),
+        "PolishToConsensus" => run_polish_to_consensus(args, inp_fasta, parameters, stats),
+        "FilterSubtype" => run_filter_subtype(args, inp_fasta, parameters, rejects, stats),
+        "ReverseComplement" => {
+            parameters.push(("emit_both_strands", args.emit_both_strands().to_string()));
+            stats.add_modified(total_bases(inp_fasta));
+            reverse_complement_fasta(inp_fasta, args.emit_both_strands(), args.out_fasta())
+        }
+        "DeflineTable" => {
+            run_defline_table(args, inp_fasta);
+            return None;
+        }
+        "KmerCount" => {
+            let table = kmer_table(inp_fasta, args.kmer_size(), args.top());
+            write_table(args, &table, "k-mer table");
+            return None;
+        }
+        "Overview" => {
+            run_overview(args, inp_fasta);
+            return None;
+        }
+        "NearestNeighbor" => {
+            run_nearest_neighbor(args, inp_fasta, limits);
+            return None;
+        }
+        "ProteinStats" => {
+            write_table(args, &protein_stats_table(inp_fasta), "protein stats");
+            return None;
+        }
+        "TrimSignalPeptide" => run_trim_signal_peptide(args, inp_fasta, parameters),
+        "TrimRegions" => {
+            run_trim_regions(args, inp_fasta, parameters);
+            return None;
+        }
+        "SplitByField" => {
+            run_split_by_field(args, inp_fasta, parameters);
+            return None;
+        }
+        "SelectBlocks" => run_select_blocks(args, inp_fasta, parameters),
+        "FixFrames" => run_fix_frames(args, inp_fasta, parameters),
+        "ReplaceBase" => run_replace_base(args, inp_fasta, parameters, stats),
+        "Validate" => report_validation(args, &validate(inp_fasta, args.molecule_type())),
+        "TransposeAlignment" => match transpose_alignment(inp_fasta, args.out_fasta()) {
             Ok(success_fasta) => success_fasta,
             Err(err) => {
-                eprintln!(
-                    "\nFailed to open input fasta file, '{}', \nproducing the error: '{}'\n",
-                    args.inp_fasta(),
-                    err
-                );
+                eprintln!("\nFailed to transpose alignment, producing the error: '{err}'\n");
                 process::exit(1);
             }
+        },
+        _ => return None,
+    };
+    Some(out_fasta)
+}
+
+/// Writes the output fasta to stdout or to the `-o` file
+fn write_output(args: &Config, out_fasta: &Fasta) {
+    let write_result = if args.out_fasta() == Path::new("-") {
+        write_fasta(out_fasta, args.strict(), args.line_ending())
+    } else if args.write_fai() {
+        write_fasta_to_file_with_fai(
+            out_fasta,
+            args.out_fasta(),
+            args.strict(),
+            args.line_ending(),
+        )
+    } else {
+        write_fasta_to_file(
+            out_fasta,
+            args.out_fasta(),
+            args.strict(),
+            args.line_ending(),
+        )
+    };
+    if let Err(err) = write_result {
+        eprintln!(
+            "\nFailed to write output fasta file, '{}', \nproducing the error: '{err}'\n",
+            args.out_fasta().display()
+        );
+        process::exit(1);
+    }
+}
+
+/// Writes the output in each format given by `--output-format`, all from the
+/// one final Fasta and set of module stats
+fn write_outputs(args: &Config, out_fasta: &Fasta, all_stats: &[ModuleStats]) {
+    for &format in args.output_formats() {
+        let path = format.output_path(args.out_fasta());
+        let (description, write_result): (&str, Result<(), Box<dyn Error>>) = match format {
+            OutputFormat::Fasta => {
+                write_output(args, out_fasta);
+                continue;
+            }
+            OutputFormat::Gff3 => ("GFF3", write_gff3(out_fasta, &path)),
+            OutputFormat::Stats => (
+                "stats",
+                write_atomically(&path, |writer| {
+                    writer.write_all(stats_table(all_stats).as_bytes())
+                })
+                .map_err(Into::into),
+            ),
+            OutputFormat::Phylip => ("PHYLIP", write_phylip(out_fasta, &path)),
         };
+        if let Err(err) = write_result {
+            eprintln!(
+                "\nFailed to write {description} file, '{}', \nproducing the error: '{err}'\n",
+                path.display()
+            );
+            process::exit(1);
+        }
+    }
+}
 
-        let out_fasta = match trim_to_orf(&inp_fasta, args.out_fasta()) {
-            Ok(success_fasta) => success_fasta,
+/// Describes the files a run would have written, in place of writing them
+fn report_dry_run(args: &Config, out_fasta: &Fasta, parameters: &[(&str, String)]) {
+    for &format in args.output_formats() {
+        let path = format.output_path(args.out_fasta());
+        match format {
+            OutputFormat::Fasta => eprintln!(
+                "Dry run: would write {} sequence(s) to '{}'",
+                out_fasta.num_entries(),
+                path.display()
+            ),
+            OutputFormat::Gff3 => eprintln!("Dry run: would write GFF3 to '{}'", path.display()),
+            OutputFormat::Stats => eprintln!("Dry run: would write stats to '{}'", path.display()),
+            OutputFormat::Phylip => eprintln!(
+                "Dry run: would write PHYLIP to '{}' and its name map to '{}'",
+                path.display(),
+                name_map_path(&path).display()
+            ),
+        }
+    }
+    if args.write_fai() {
+        eprintln!(
+            "Dry run: would write index to '{}.fai'",
+            args.out_fasta().display()
+        );
+    }
+    for (key, value) in parameters {
+        eprintln!("Dry run: {key} = {value}");
+    }
+    if let Some(manifest_out) = args.manifest_out() {
+        eprintln!(
+            "Dry run: would write manifest to '{}'",
+            manifest_out.display()
+        );
+    }
+}
+
+/// Exits with an error if an option that belongs to one module is used without
+/// it, or a module is used without the option it needs
+fn check_option_modules(args: &Config, modules: &[&str]) {
+    for (option, given, module) in [
+        (
+            "--emit-both-strands",
+            args.emit_both_strands(),
+            "ReverseComplement",
+        ),
+        (
+            "--protein-reference",
+            args.protein_reference().is_some(),
+            "TrimToORF",
+        ),
+        (
+            "--segment-table",
+            args.segment_table().is_some(),
+            "TrimToORF",
+        ),
+        (
+            "--normalize-codon-gaps",
+            args.normalize_codon_gaps(),
+            "TrimToORF",
+        ),
+        ("--gapmap-out", args.gapmap_out().is_some(), "Degap"),
+        ("--newick", args.newick().is_some(), "Relabel"),
+    ] {
+        if given && !modules.contains(&module) {
+            eprintln!("\n{option} can only be used with the {module} module\n");
+            process::exit(1);
+        }
+    }
+    for (module, option, given) in [
+        (
+            "BackTranslate",
+            "--protein-alignment",
+            args.protein_alignment().is_some(),
+        ),
+        ("Regap", "--gapmap-in", args.gapmap_in().is_some()),
+        (
+            "PolishToConsensus",
+            "--polish-log",
+            args.polish_log().is_some(),
+        ),
+        ("Relabel", "--name-map", args.name_map().is_some()),
+        ("Rename", "--map", args.rename_map().is_some()),
+        ("Screen", "--screen-fasta", args.screen_fasta().is_some()),
+    ] {
+        if modules.contains(&module) != given {
+            eprintln!("\n{module} and {option} must be used together\n");
+            process::exit(1);
+        }
+    }
+    if args.newick().is_some() != args.newick_out().is_some() {
+        eprintln!("\n--newick and --newick-out must be used together\n");
+        process::exit(1);
+    }
+}
+
+/// Exits with an error if two of the files written for `--output-format` would
+/// have the same path, such as the GFF3 for `-o out.gff3`, so that one would
+/// overwrite the other
+fn check_output_paths_differ(args: &Config) {
+    let mut paths: Vec<(PathBuf, &str)> = Vec::new();
+    for &format in args.output_formats() {
+        let path = format.output_path(args.out_fasta());
+        if format == OutputFormat::Phylip {
+            paths.push((name_map_path(&path), "PHYLIP name map"));
+        }
+        let description = match format {
+            OutputFormat::Fasta => "output fasta",
+            OutputFormat::Gff3 => "GFF3 file",
+            OutputFormat::Stats => "stats file",
+            OutputFormat::Phylip => "PHYLIP file",
+        };
+        paths.push((path, description));
+    }
+    for (i, (path, description)) in paths.iter().enumerate() {
+        if let Some((_, other)) = paths[..i].iter().find(|(earlier, _)| earlier == path) {
+            eprintln!(
+                "\nThe {other} and the {description} would both be written to '{}'; choose \
+                an -o path with a different extension\n",
+                path.display()
+            );
+            process::exit(1);
+        }
+    }
+}
+
+/// Exits with an error if an option is used without the module it belongs to,
+/// or the modules are chained in an order that cannot work
+fn check_module_options(args: &Config, modules: &[&str]) {
+    check_option_modules(args, modules);
+    if args.write_fai() && args.out_fasta() == Path::new("-") {
+        eprintln!("\n--write-fai needs an output file, not stdout\n");
+        process::exit(1);
+    }
+    if args.out_fasta() == Path::new("-")
+        && args
+            .output_formats()
+            .iter()
+            .any(|&format| format != OutputFormat::Fasta)
+    {
+        eprintln!("\n--output-format gff3, stats and phylip need an output file, not stdout\n");
+        process::exit(1);
+    }
+    check_output_paths_differ(args);
+    if let Some(protein_module) = modules
+        .iter()
+        .find(|module| PROTEIN_MODULES.contains(module))
+        .filter(|_| args.molecule_type() != MoleculeType::Protein)
+    {
+        eprintln!("\n{protein_module} needs protein input; pass --alphabet protein\n");
+        process::exit(1);
+    }
+    if args.segment_table().is_some() != args.segment().is_some() {
+        eprintln!("\n--segment-table and --segment must be used together\n");
+        process::exit(1);
+    }
+    let trim_modes: Vec<&str> = [
+        ("--per-sequence", args.per_sequence()),
+        ("--segment-table", args.segment_table().is_some()),
+        ("--protein-reference", args.protein_reference().is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(option, given)| given.then_some(option))
+    .collect();
+    if trim_modes.len() > 1 {
+        eprintln!(
+            "\n{} each choose a different way to find the ORF, so only one can be used\n",
+            trim_modes.join(" and ")
+        );
+        process::exit(1);
+    }
+    for (option, given) in [
+        ("--report-readthrough", args.report_readthrough()),
+        ("--force-frame", args.force_frame().is_some()),
+    ] {
+        if given
+            && (!modules.contains(&"TrimToORF")
+                || args.per_sequence()
+                || args.protein_reference().is_some()
+                || args.segment_table().is_some())
+        {
+            eprintln!(
+                "\n{option} can only be used with the TrimToORF module's group ORF vote, not \
+                with --per-sequence, --protein-reference or --segment-table\n"
+            );
+            process::exit(1);
+        }
+    }
+    if args.circular() && !args.per_sequence() {
+        eprintln!("\n--circular can only be used with --per-sequence\n");
+        process::exit(1);
+    }
+    if modules.contains(&"FlagOutliers") && args.outliers_out().is_none() {
+        eprintln!("\nFlagOutliers needs --outliers-out to write the outliers to\n");
+        process::exit(1);
+    }
+    if modules.len() > 1 && modules.contains(&"Validate") {
+        eprintln!("\nValidate checks the input file, so it must be the only module\n");
+        process::exit(1);
+    }
+    if let Some(final_module) = modules[..modules.len() - 1]
+        .iter()
+        .find(|module| FINAL_MODULES.contains(module))
+    {
+        eprintln!("\n{final_module} writes its own output, so it must be the last module\n");
+        process::exit(1);
+    }
+    if modules.contains(&"TrimRegions")
+        && (args.regions_file().is_none() || args.outdir().is_none())
+    {
+        eprintln!("\nTrimRegions needs --regions-file and --outdir\n");
+        process::exit(1);
+    }
+    if modules.contains(&"SplitByField") {
+        if let Err(err) = args.split_field() {
+            eprintln!("\n{err}\n");
+            process::exit(1);
+        }
+        if args.outdir().is_none() {
+            eprintln!("\nSplitByField needs --outdir\n");
+            process::exit(1);
+        }
+    }
+}
+
+/// Exits with an error naming the first invalid character if the input is
+/// protein and any entry holds a character that is not an amino acid
+fn check_molecule_type(args: &Config, inp_fasta: &Fasta) {
+    if args.molecule_type() != MoleculeType::Protein {
+        return;
+    }
+    if let Some(err) = inp_fasta
+        .into_iter()
+        .find_map(|entry| check_protein(&entry.defline(), &entry.sequence()).err())
+    {
+        eprintln!(
+            "\nFailed to read protein input file, '{}', \nproducing the error: '{err}'\n",
+            args.inp_fasta().display()
+        );
+        process::exit(1);
+    }
+}
+
+/// Writes the per-sequence quality report of the input to `report_path`
+fn write_quality_report(args: &Config, inp_fasta: &Fasta, report_path: &Path) {
+    let records = sequence_quality_report(inp_fasta);
+    if args.dry_run() {
+        eprintln!(
+            "Dry run: would write quality report with {} row(s) to '{}'",
+            records.len(),
+            report_path.display()
+        );
+        return;
+    }
+    if let Err(err) = write_quality_report_tsv(&records, report_path) {
+        eprintln!(
+            "\nFailed to write quality report, '{}', \nproducing the error: '{err}'\n",
+            report_path.display()
+        );
+        process::exit(1);
+    }
+}
+
+/// Returns the genetic code from `--codon-table-file`, or None to use the
+/// standard code
+fn custom_genetic_code(args: &Config) -> Option<GeneticCode> {
+    let table_path = args.codon_table_file()?;
+    let (code, missing) = match GeneticCode::from_table_file(table_path) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!(
+                "\nFailed to read codon table file, '{}', \nproducing the error: '{err}'\n",
+                table_path.display()
+            );
+            process::exit(1);
+        }
+    };
+    if !missing.is_empty() {
+        eprintln!(
+            "Warning: {} codon(s) missing from the codon table translate to X: {}",
+            missing.len(),
+            missing.join(", ")
+        );
+    }
+    Some(code)
+}
+
+/// Builds the ORF options shared by `TrimToORF` and `FixFrames`, taking the
+/// stop codons from `--codon-table-file` when one is given
+fn orf_options(args: &Config) -> TrimToOrfOptions {
+    let options = TrimToOrfOptions::default()
+        .respect_softmask(args.respect_softmask())
+        .start_coherence_window(args.start_coherence_window());
+    match custom_genetic_code(args) {
+        Some(code) => options.stop_codons(&code.stop_codons()),
+        None => options,
+    }
+}
+
+/// Returns the guide built from the first entry of the `--protein-reference`
+/// fasta at `reference_path`
+fn protein_guide(args: &Config, reference_path: &Path) -> ProteinGuide {
+    let reference = open_fasta(reference_path).and_then(|fasta| {
+        (&fasta)
+            .into_iter()
+            .next()
+            .map(|entry| entry.sequence().into_owned())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no sequences found"))
+    });
+    let guide = reference
+        .map_err(|err| err.to_string())
+        .and_then(|reference| {
+            ProteinGuide::new(&reference, custom_genetic_code(args).unwrap_or_default())
+        });
+    match guide {
+        Ok(guide) => guide,
+        Err(err) => {
+            eprintln!(
+                "\nFailed to read protein reference, '{}', \nproducing the error: '{err}'\n",
+                reference_path.display()
+            );
+            process::exit(1);
+        }
+    }
+}
+
+/// Returns the input as protein: protein input as it is, and nucleotide input
+/// degapped and translated from its first base. Exits if a translation holds
+/// a character that is not an amino acid.
+fn input_proteins<'a>(args: &Config, inp_fasta: &'a Fasta) -> Cow<'a, Fasta> {
+    if args.molecule_type() == MoleculeType::Protein {
+        return Cow::Borrowed(inp_fasta);
+    }
+    let code = custom_genetic_code(args).unwrap_or_default();
+    let mut translated = Fasta::new(inp_fasta.filename());
+    for entry in inp_fasta {
+        let protein = entry.sequence().degapped().translate(code);
+        match FastaEntry::new_checked(
+            entry.defline(),
+            protein,
+            entry.entry_num(),
+            MoleculeType::Protein,
+        ) {
+            Ok(protein_entry) => translated.add(protein_entry),
             Err(err) => {
-                eprintln!("\nFailed to trim to ORF, producing the error: '{err}'\n");
+                eprintln!("\nFailed to translate the input, producing the error: '{err}'\n");
+                process::exit(1);
+            }
+        }
+    }
+    Cow::Owned(translated)
+}
+
+/// Writes each requested per-sequence protein table: amino acid composition,
+/// molecular weight, isoelectric point and hydrophobicity
+fn write_protein_tables(args: &Config, inp_fasta: &Fasta) {
+    if let Some(path) = args.aa_composition_out() {
+        write_protein_table(
+            args,
+            inp_fasta,
+            path,
+            "amino acid composition",
+            |fasta, path| write_aa_composition_tsv(fasta, path),
+        );
+    }
+    if let Some(path) = args.molecular_weight_out() {
+        write_protein_table(args, inp_fasta, path, "molecular weights", |fasta, path| {
+            write_molecular_weight_tsv(fasta, path)
+        });
+    }
+    if let Some(path) = args.isoelectric_point_out() {
+        write_protein_table(
+            args,
+            inp_fasta,
+            path,
+            "isoelectric points",
+            |fasta, path| write_isoelectric_point_tsv(fasta, path),
+        );
+    }
+    if let Some(path) = args.hydrophobicity_out() {
+        let window = args.hydrophobicity_window();
+        write_protein_table(args, inp_fasta, path, "hydrophobicity", |fasta, path| {
+            write_hydrophobicity_tsv(fasta, window, path)
+        });
+    }
+}
+
+/// Writes one table about each input entry as protein to `table_path` with
+/// `write_tsv`, naming the table `description` in messages
+fn write_protein_table(
+    args: &Config,
+    inp_fasta: &Fasta,
+    table_path: &Path,
+    description: &str,
+    write_tsv: impl Fn(&Fasta, &Path) -> Result<(), Box<dyn Error>>,
+) {
+    let proteins = input_proteins(args, inp_fasta);
+    if args.dry_run() {
+        eprintln!(
+            "Dry run: would write {description} of {} sequence(s) to '{}'",
+            proteins.num_entries(),
+            table_path.display()
+        );
+        return;
+    }
+    if let Err(err) = write_tsv(&proteins, table_path) {
+        eprintln!(
+            "\nFailed to write {description}, '{}', \nproducing the error: '{err}'\n",
+            table_path.display()
+        );
+        process::exit(1);
+    }
+}
+
+/// Exits with status 3 if `TrimToORF` reported an ORF confidence below
+/// `--min-confidence`. Called once all output is written, so a pipeline can
+/// quarantine the files rather than lose them.
+fn check_min_confidence(args: &Config, parameters: &[(&str, String)]) {
+    let Some(min_confidence) = args.min_confidence() else {
+        return;
+    };
+    let confidence = parameters
+        .iter()
+        .find(|(key, _)| *key == "orf_confidence")
+        .and_then(|(_, value)| value.parse::<f64>().ok());
+    if let Some(confidence) = confidence.filter(|&confidence| confidence < min_confidence) {
+        eprintln!("\nORF confidence {confidence:.3} is below the minimum of {min_confidence}\n");
+        process::exit(3);
+    }
+}
+
+/// Runs the `TrimToORF` module in the mode its options choose: per sequence,
+/// from a segment table, guided by a reference protein, or by start codon vote
+fn run_trim_to_orf_mode(
+    args: &Config,
+    inp_fasta: &Fasta,
+    parameters: &mut Vec<(&str, String)>,
+    rejects: Option<&mut Rejects>,
+    stats: &mut ModuleStats,
+    limits: &Limits,
+) -> Fasta {
+    let out_fasta = if args.per_sequence() {
+        run_trim_to_entry_orfs(args, inp_fasta, parameters)
+    } else if args.segment_table().is_some() {
+        run_trim_by_segment_table(args, inp_fasta, parameters)
+    } else if let Some(reference) = args.protein_reference() {
+        run_trim_to_guided_orf(args, inp_fasta, reference, parameters)
+    } else {
+        run_trim_to_orf(args, inp_fasta, parameters, rejects, stats, limits)
+    };
+    if !args.normalize_codon_gaps() {
+        return out_fasta;
+    }
+
+    let (out_fasta, report) = normalize_codon_gaps(&out_fasta, args.out_fasta());
+    parameters.push(("codon_gap_repairs", report.repairs().len().to_string()));
+    parameters.push(("frameshifts", report.out_of_frame().len().to_string()));
+    report_frame_repairs(&report);
+    out_fasta
+}
+
+/// Runs the `TrimToORF` module, recording the chosen loci in `parameters`
+fn run_trim_to_orf(
+    args: &Config,
+    inp_fasta: &Fasta,
+    parameters: &mut Vec<(&str, String)>,
+    rejects: Option<&mut Rejects>,
+    stats: &mut ModuleStats,
+    limits: &Limits,
+) -> Fasta {
+    let options = orf_options(args)
+        .limits(*limits)
+        .out_fasta(args.out_fasta())
+        .report_readthrough(args.report_readthrough())
+        .force_frame(args.force_frame())
+        .frame_occupancy(args.frame_occupancy());
+    let (trimmed, report, errors) = match trim_to_orf_collecting(inp_fasta, &options) {
+        Ok(success) => success,
+        Err(err) => {
+            eprintln!("\nFailed to trim to ORF, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    };
+    let mut out_fasta = if errors.is_empty() {
+        trimmed
+    } else {
+        report_entry_errors(args, inp_fasta, &errors);
+        trim_to_group_orf(inp_fasta, &report, args.out_fasta())
+    };
+
+    parameters.push(("group_start_locus", (report.group_start() + 1).to_string()));
+    parameters.push(("group_stop_locus", (report.group_stop() + 1).to_string()));
+    parameters.push(("start_support", format!("{:.3}", report.start_support())));
+    if let Some(window) = args.start_coherence_window() {
+        parameters.push(("start_coherence_window", window.to_string()));
+    }
+    if let Some(frame) = args.force_frame() {
+        parameters.push(("forced_frame", frame.to_string()));
+    }
+    let confidence = report.confidence();
+    eprintln!(
+        "ORF confidence {:.3} (start fraction {:.3}, score margin {:.3}, stop agreement {:.3}, \
+        short fraction {:.3})",
+        confidence.score(),
+        confidence.start_fraction,
+        confidence.score_margin,
+        confidence.stop_agreement,
+        confidence.short_fraction
+    );
+    parameters.push(("orf_confidence", format!("{:.3}", confidence.score())));
+    for (key, value) in [
+        ("orf_start_fraction", confidence.start_fraction),
+        ("orf_score_margin", confidence.score_margin),
+        ("orf_stop_agreement", confidence.stop_agreement),
+        ("orf_short_fraction", confidence.short_fraction),
+    ] {
+        parameters.push((key, format!("{value:.3}")));
+    }
+    if report.start_tied() {
+        eprintln!(
+            "Warning: another start codon locus scored as highly as the group start; the \
+            earliest, {}, was chosen",
+            report.group_start() + 1
+        );
+        parameters.push(("start_tied", String::from("true")));
+    }
+    if args.respect_softmask() {
+        eprintln!(
+            "Ignored {} soft-masked start codon(s)",
+            report.masked_starts()
+        );
+        parameters.push(("masked_starts", report.masked_starts().to_string()));
+    }
+    if args.report_readthrough() {
+        report_readthrough(inp_fasta, &report, &mut out_fasta, parameters);
+    }
+
+    if let Some(min_agreement) = args.min_agreement() {
+        if report.start_support() < min_agreement {
+            eprintln!(
+                "Warning: only {:.1}% of sequences have their top start codon at the group start \
+                locus {}, below the minimum agreement of {:.1}%",
+                report.start_support() * 100.0,
+                report.group_start() + 1,
+                min_agreement * 100.0
+            );
+        }
+    }
+    check_start_agreement(
+        args, inp_fasta, &report, out_fasta, parameters, rejects, stats,
+    )
+}
+
+/// Lists every entry `TrimToORF` found no in-frame stop codon for. Under
+/// `--strict` this is an error; otherwise the entries are trimmed to the group
+/// ORF regardless, as they always have been.
+fn report_entry_errors(args: &Config, inp_fasta: &Fasta, errors: &EntryErrors) {
+    if args.strict() {
+        eprintln!(
+            "\nFailed to trim to ORF; {} sequence(s) failed:",
+            errors.len()
+        );
+    } else {
+        eprintln!(
+            "Warning: {} sequence(s) failed and were trimmed to the group ORF anyway:",
+            errors.len()
+        );
+    }
+    for (i, err) in errors {
+        let defline = inp_fasta.get_entry(*i).map(FastaEntry::defline);
+        eprintln!("\tentry {} '{}': {err}", i + 1, defline.unwrap_or_default());
+    }
+    if args.strict() {
+        eprintln!();
+        process::exit(1);
+    }
+}
+
+/// Records each sequence's next stop codon past the group stop as `next_stop`,
+/// counted from 1 in the input alignment, and the bases the ORF would gain by
+/// reading through to it as `readthrough_bases`
+fn report_readthrough(
+    inp_fasta: &Fasta,
+    report: &TrimReport,
+    out_fasta: &mut Fasta,
+    parameters: &mut Vec<(&str, String)>,
+) {
+    let past_stop = report.group_stop() + 3;
+    let mut num_found = 0;
+    for ((inp_entry, out_entry), next_stop) in inp_fasta
+        .into_iter()
+        .zip(out_fasta.iter_mut())
+        .zip(report.next_stops())
+    {
+        if let Some(next_stop) = *next_stop {
+            let readthrough_bases = inp_entry.sequence()[past_stop..next_stop + 3]
+                .degapped()
+                .len();
+            out_entry.set_meta("next_stop", next_stop + 1);
+            out_entry.set_meta("readthrough_bases", readthrough_bases);
+            num_found += 1;
+        }
+    }
+    eprintln!(
+        "Found a stop codon past the group stop in {num_found} of {} sequence(s)",
+        inp_fasta.num_entries()
+    );
+    parameters.push(("readthrough_stops", num_found.to_string()));
+}
+
+/// Runs the protein-guided mode of the `TrimToORF` module, trimming to the ORF
+/// of the alignment consensus that most resembles `--protein-reference`
+fn run_trim_to_guided_orf(
+    args: &Config,
+    inp_fasta: &Fasta,
+    reference_path: &Path,
+    parameters: &mut Vec<(&str, String)>,
+) -> Fasta {
+    let guide = protein_guide(args, reference_path);
+    let options = orf_options(args).out_fasta(args.out_fasta());
+    let (out_fasta, report) = match trim_to_guided_orf(inp_fasta, &options, &guide) {
+        Ok(success) => success,
+        Err(err) => {
+            eprintln!("\nFailed to trim to ORF, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    };
+
+    let score = report.score();
+    eprintln!(
+        "Reference protein similarity {:.3}, coverage {:.3}",
+        score.similarity, score.coverage
+    );
+    parameters.push(("group_start_locus", (report.group_start() + 1).to_string()));
+    parameters.push(("group_stop_locus", (report.group_stop() + 1).to_string()));
+    parameters.push(("reference_similarity", format!("{:.3}", score.similarity)));
+    parameters.push(("reference_coverage", format!("{:.3}", score.coverage)));
+    out_fasta
+}
+
+/// Runs the `Dedup` module, reporting the duplicate entries it drops
+fn run_dedup(
+    args: &Config,
+    inp_fasta: &Fasta,
+    module: &str,
+    parameters: &mut Vec<(&str, String)>,
+    rejects: Option<&mut Rejects>,
+    stats: &mut ModuleStats,
+) -> Fasta {
+    let keep = match args.keep() {
+        KeepDuplicate::First => "first",
+        KeepDuplicate::Last => "last",
+    };
+    parameters.push(("keep", String::from(keep)));
+    let (out_fasta, dropped) = dedup_by_defline(inp_fasta, args.keep(), args.out_fasta());
+    let dropped: Vec<_> = dropped.into_iter().map(|entry| (entry, None)).collect();
+    report_dropped(
+        &dropped,
+        module,
+        "duplicate",
+        "duplicate defline",
+        rejects,
+        stats,
+    );
+    out_fasta
+}
+
+/// Runs the `BackTranslate` module. Entries that can't be threaded through the
+/// protein alignment are dropped, and proteins with no nucleotide entry are
+/// warned about; with `--strict` either is an error instead.
+fn run_back_translate(
+    args: &Config,
+    inp_fasta: &Fasta,
+    module: &str,
+    mut rejects: Option<&mut Rejects>,
+    stats: &mut ModuleStats,
+) -> Fasta {
+    let Some(alignment_path) = args.protein_alignment() else {
+        return inp_fasta.clone();
+    };
+    let protein_alignment = match open_fasta(alignment_path) {
+        Ok(protein_alignment) => protein_alignment,
+        Err(err) => {
+            eprintln!(
+                "\nFailed to read protein alignment, '{}', \nproducing the error: '{err}'\n",
+                alignment_path.display()
+            );
+            process::exit(1);
+        }
+    };
+    let stop_codons = custom_genetic_code(args).unwrap_or_default().stop_codons();
+    let (out_fasta, report) = match back_translate(
+        inp_fasta,
+        &protein_alignment,
+        &stop_codons,
+        args.out_fasta(),
+    ) {
+        Ok(success) => success,
+        Err(err) => {
+            eprintln!("\nFailed to back-translate, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    };
+
+    if args.strict() && (!report.mismatched.is_empty() || !report.missing_nucleotides.is_empty()) {
+        eprintln!("\nFailed to back-translate every sequence:");
+        for (entry, mismatch) in &report.mismatched {
+            eprintln!("\t{} ({mismatch})", entry.defline());
+        }
+        for defline in &report.missing_nucleotides {
+            eprintln!("\t{defline} (no nucleotide sequence with this defline)");
+        }
+        eprintln!();
+        process::exit(1);
+    }
+    for (entry, mismatch) in &report.mismatched {
+        if let Mismatch::Length { .. } = mismatch {
+            eprintln!("Warning: '{}' has {mismatch}", entry.defline());
+        }
+    }
+    for (code, reason) in [
+        ("no_protein", "no protein with the same defline"),
+        ("length_mismatch", "length does not match the protein"),
+    ] {
+        let dropped: Vec<_> = report
+            .mismatched
+            .iter()
+            .filter(|(_, mismatch)| mismatch.code() == code)
+            .map(|&(entry, _)| (entry, None))
+            .collect();
+        if !dropped.is_empty() {
+            report_dropped(
+                &dropped,
+                module,
+                code,
+                reason,
+                rejects.as_deref_mut(),
+                stats,
+            );
+        }
+    }
+    for defline in &report.missing_nucleotides {
+        eprintln!("Warning: protein '{defline}' has no nucleotide sequence");
+    }
+    out_fasta
+}
+
+/// Runs the `FilterSubtype` module, dropping entries of any other subtype
+fn run_filter_subtype(
+    args: &Config,
+    inp_fasta: &Fasta,
+    parameters: &mut Vec<(&str, String)>,
+    rejects: Option<&mut Rejects>,
+    stats: &mut ModuleStats,
+) -> Fasta {
+    let subtype = args.filter_subtype().unwrap_or_default();
+    parameters.push(("filter_subtype", String::from(subtype)));
+    let (out_fasta, dropped) = filter_subtype(inp_fasta, subtype, args.out_fasta());
+    let dropped: Vec<_> = dropped.into_iter().map(|entry| (entry, None)).collect();
+    report_dropped(
+        &dropped,
+        "FilterSubtype",
+        "subtype_mismatch",
+        &format!("subtype is not '{subtype}'"),
+        rejects,
+        stats,
+    );
+    out_fasta
+}
+
+/// Runs the `Screen` module, dropping entries with too few k-mers in
+/// `--screen-fasta` and summarizing the containment of every entry
+fn run_screen(
+    args: &Config,
+    inp_fasta: &Fasta,
+    module: &str,
+    parameters: &mut Vec<(&str, String)>,
+    rejects: Option<&mut Rejects>,
+    stats: &mut ModuleStats,
+) -> Fasta {
+    let Some(screen_path) = args.screen_fasta() else {
+        return inp_fasta.clone();
+    };
+    let references = match open_fasta(screen_path) {
+        Ok(references) => KmerSet::new(&references, args.screen_kmer_size()),
+        Err(err) => {
+            eprintln!(
+                "\nFailed to read screen references, '{}', \nproducing the error: '{err}'\n",
+                screen_path.display()
+            );
+            process::exit(1);
+        }
+    };
+    if references.is_empty() {
+        eprintln!(
+            "\nThe screen references, '{}', have no {}-mers to screen against\n",
+            screen_path.display(),
+            args.screen_kmer_size()
+        );
+        process::exit(1);
+    }
+    parameters.push(("screen_kmer_size", args.screen_kmer_size().to_string()));
+    parameters.push(("min_containment", args.min_containment().to_string()));
+
+    let (out_fasta, dropped, mut containments) = screen_contaminants(
+        inp_fasta,
+        &references,
+        args.min_containment(),
+        args.out_fasta(),
+    );
+    containments.sort_by(f64::total_cmp);
+    if let (Some(min), Some(max)) = (containments.first(), containments.last()) {
+        let quartile = |q: usize| containments[(containments.len() - 1) * q / 4];
+        eprintln!(
+            "Containment min {min:.3}, quartiles {:.3}/{:.3}/{:.3}, max {max:.3}",
+            quartile(1),
+            quartile(2),
+            quartile(3)
+        );
+        parameters.push(("median_containment", format!("{:.3}", quartile(2))));
+    }
+
+    let dropped: Vec<_> = dropped
+        .into_iter()
+        .map(|(entry, containment)| (entry, Some(containment)))
+        .collect();
+    report_dropped(
+        &dropped,
+        module,
+        "containment",
+        &format!("k-mer containment below {}", args.min_containment()),
+        rejects,
+        stats,
+    );
+    out_fasta
+}
+
+/// Runs the `Degap` module, writing each entry's gap map to `--gapmap-out` if
+/// it was given
+fn run_degap(args: &Config, inp_fasta: &Fasta) -> Fasta {
+    let (out_fasta, gap_maps) = degap_with_map(inp_fasta, args.out_fasta());
+    let Some(gapmap_out) = args.gapmap_out() else {
+        return out_fasta;
+    };
+    if args.dry_run() {
+        eprintln!(
+            "Dry run: would write {} gap map(s) to '{}'",
+            gap_maps.len(),
+            gapmap_out.display()
+        );
+    } else if let Err(err) = write_gap_maps(&gap_maps, gapmap_out) {
+        eprintln!(
+            "\nFailed to write gap map, '{}', \nproducing the error: '{err}'\n",
+            gapmap_out.display()
+        );
+        process::exit(1);
+    }
+    out_fasta
+}
+
+/// Runs the `Regap` module, restoring the alignment recorded in `--gapmap-in`
+fn run_regap(args: &Config, inp_fasta: &Fasta) -> Fasta {
+    let Some(gapmap_in) = args.gapmap_in() else {
+        return inp_fasta.clone();
+    };
+    let gap_maps = match read_gap_maps(gapmap_in) {
+        Ok(gap_maps) => gap_maps,
+        Err(err) => {
+            eprintln!(
+                "\nFailed to read gap map, '{}', \nproducing the error: '{err}'\n",
+                gapmap_in.display()
+            );
+            process::exit(1);
+        }
+    };
+    match regap(inp_fasta, gap_maps, args.out_fasta()) {
+        Ok(out_fasta) => out_fasta,
+        Err(err) => {
+            eprintln!("\nFailed to restore gaps, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    }
+}
+
+/// Runs the `Relabel` module, giving exported names back their original
+/// deflines from `--name-map`, and relabeling the `--newick` tree into
+/// `--newick-out` too if one was given
+fn run_relabel(args: &Config, inp_fasta: &Fasta) -> Fasta {
+    let Some(name_map) = args.name_map() else {
+        return inp_fasta.clone();
+    };
+    let names = match read_name_map(name_map) {
+        Ok(names) => names,
+        Err(err) => {
+            eprintln!(
+                "\nFailed to read name map, '{}', \nproducing the error: '{err}'\n",
+                name_map.display()
+            );
+            process::exit(1);
+        }
+    };
+    let (out_fasta, _) = relabel_fasta(inp_fasta, &names, args.out_fasta());
+
+    let (Some(newick), Some(newick_out)) = (args.newick(), args.newick_out()) else {
+        return out_fasta;
+    };
+    let tree = match fs::read_to_string(newick) {
+        Ok(tree) => tree,
+        Err(err) => {
+            eprintln!(
+                "\nFailed to read tree, '{}', \nproducing the error: '{err}'\n",
+                newick.display()
+            );
+            process::exit(1);
+        }
+    };
+    let relabeled = relabel_newick(&tree, &names);
+    if args.dry_run() {
+        eprintln!(
+            "Dry run: would write relabeled tree to '{}'",
+            newick_out.display()
+        );
+    } else if let Err(err) =
+        write_atomically(newick_out, |writer| writer.write_all(relabeled.as_bytes()))
+    {
+        eprintln!(
+            "\nFailed to write tree, '{}', \nproducing the error: '{err}'\n",
+            newick_out.display()
+        );
+        process::exit(1);
+    }
+    out_fasta
+}
+
+/// Runs the `Rename` module with the rename map from `--map`, reporting how
+/// much of it matched. Under `--strict`, an entry the map doesn't match is an
+/// error.
+fn run_rename(args: &Config, inp_fasta: &Fasta, parameters: &mut Vec<(&str, String)>) -> Fasta {
+    let Some(map) = args.rename_map() else {
+        return inp_fasta.clone();
+    };
+    let renames = match read_rename_map(map) {
+        Ok(renames) => renames,
+        Err(err) => {
+            eprintln!(
+                "\nFailed to read rename map, '{}', \nproducing the error: '{err}'\n",
+                map.display()
+            );
+            process::exit(1);
+        }
+    };
+    let (out_fasta, report) = match rename_fasta(inp_fasta, &renames, args.out_fasta()) {
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!("\nFailed to rename sequences, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    };
+
+    if args.strict() && !report.unmatched.is_empty() {
+        eprintln!("\nFailed to rename every sequence; no row of the map matches:");
+        for entry in &report.unmatched {
+            eprintln!("\t{}", entry.defline());
+        }
+        eprintln!();
+        process::exit(1);
+    }
+    eprintln!(
+        "Renamed {} sequence(s); {} left unmatched; {} map row(s) unused",
+        report.renamed,
+        report.unmatched.len(),
+        report.unused.len()
+    );
+    parameters.push(("renamed", report.renamed.to_string()));
+    parameters.push(("unmatched", report.unmatched.len().to_string()));
+    parameters.push(("unused_map_rows", report.unused.len().to_string()));
+    out_fasta
+}
+
+/// Runs the segment-table mode of the `TrimToORF` module, trimming to the ORF
+/// boundaries `--segment-table` gives for `--segment`
+fn run_trim_by_segment_table(
+    args: &Config,
+    inp_fasta: &Fasta,
+    parameters: &mut Vec<(&str, String)>,
+) -> Fasta {
+    let (Some(table_path), Some(segment)) = (args.segment_table(), args.segment()) else {
+        return inp_fasta.clone();
+    };
+    let table = match load_segment_reference_table(table_path) {
+        Ok(table) => table,
+        Err(err) => {
+            eprintln!(
+                "\nFailed to read segment table, '{}', \nproducing the error: '{err}'\n",
+                table_path.display()
+            );
+            process::exit(1);
+        }
+    };
+    let out_fasta = match trim_by_segment_table(inp_fasta, segment, &table, args.out_fasta()) {
+        Ok(out_fasta) => out_fasta,
+        Err(err) => {
+            eprintln!("\nFailed to trim to ORF, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    };
+
+    let (start, stop) = table[segment];
+    parameters.push(("segment", String::from(segment)));
+    parameters.push(("group_start_locus", start.to_string()));
+    parameters.push(("group_stop_locus", stop.to_string()));
+    out_fasta
+}
+
+/// Runs the per-sequence mode of the `TrimToORF` module, trimming each entry to
+/// its own longest ORF
+fn run_trim_to_entry_orfs(
+    args: &Config,
+    inp_fasta: &Fasta,
+    parameters: &mut Vec<(&str, String)>,
+) -> Fasta {
+    let options = orf_options(args).out_fasta(args.out_fasta());
+    let guide = args
+        .protein_reference()
+        .map(|reference_path| protein_guide(args, reference_path));
+    let (out_fasta, num_without_orf) =
+        trim_to_entry_orfs(inp_fasta, &options, args.circular(), guide.as_ref());
+    let num_wrapping = (&out_fasta)
+        .into_iter()
+        .filter(|entry| entry.get_meta("orf_wraps") == Some("true"))
+        .count();
+
+    parameters.push(("per_sequence", String::from("true")));
+    parameters.push(("circular", args.circular().to_string()));
+    parameters.push(("no_orf", num_without_orf.to_string()));
+    if num_without_orf > 0 {
+        eprintln!("Warning: {num_without_orf} sequence(s) have no ORF and were left empty");
+    }
+    if args.circular() {
+        eprintln!("{num_wrapping} ORF(s) wrap around the origin");
+        parameters.push(("wrapping_orfs", num_wrapping.to_string()));
+    }
+    out_fasta
+}
+
+/// Reports the entries whose own top-scored start codon disagrees with the
+/// `TrimToORF` group start, writing `--start-report` if it was requested.
+/// Exits if more than `--max-start-disagreement` of them disagree, and with
+/// `--reject-start-disagreement` drops them from `out_fasta`.
+fn check_start_agreement(
+    args: &Config,
+    inp_fasta: &Fasta,
+    report: &TrimReport,
+    mut out_fasta: Fasta,
+    parameters: &mut Vec<(&str, String)>,
+    rejects: Option<&mut Rejects>,
+    stats: &mut ModuleStats,
+) -> Fasta {
+    let num_disagreeing = report.start_disagreements();
+    eprintln!("{num_disagreeing} sequence(s) have their top start codon away from the group start");
+    parameters.push(("start_disagreements", num_disagreeing.to_string()));
+
+    if let Some(report_path) = args.start_report() {
+        if args.dry_run() {
+            eprintln!(
+                "Dry run: would write start report to '{}'",
+                report_path.display()
+            );
+        } else if let Err(err) = write_start_report_tsv(inp_fasta, report, report_path) {
+            eprintln!(
+                "\nFailed to write start report, '{}', \nproducing the error: '{err}'\n",
+                report_path.display()
+            );
+            process::exit(1);
+        }
+    }
+
+    #[expect(clippy::cast_precision_loss)]
+    let disagreement = num_disagreeing as f64 / inp_fasta.num_entries() as f64;
+    if let Some(max_disagreement) = args.max_start_disagreement() {
+        if disagreement > max_disagreement {
+            eprintln!(
+                "\nStart disagreement {disagreement:.3} is above the maximum of \
+                {max_disagreement}\n"
+            );
+            process::exit(1);
+        }
+    }
+
+    if args.reject_start_disagreement() {
+        let disagrees: Vec<bool> = report
+            .top_starts()
+            .iter()
+            .map(|&top_start| report.start_disagrees(top_start))
+            .collect();
+        let dropped: Vec<_> = inp_fasta
+            .into_iter()
+            .zip(&disagrees)
+            .filter(|(_, &disagrees)| disagrees)
+            .map(|(entry, _)| (entry, None))
+            .collect();
+        report_dropped(
+            &dropped,
+            "TrimToORF",
+            "start_disagreement",
+            "top start codon away from the group start",
+            rejects,
+            stats,
+        );
+        let mut disagrees = disagrees.into_iter();
+        out_fasta.retain(|_| !disagrees.next().unwrap_or(false));
+    }
+    out_fasta
+}
+
+/// Runs the `FixFrames` module, reporting each repair and any entry left out
+/// of frame
+fn run_fix_frames(args: &Config, inp_fasta: &Fasta, parameters: &mut Vec<(&str, String)>) -> Fasta {
+    let options = orf_options(args);
+    let (out_fasta, report) = match fix_frames(inp_fasta, &options, args.out_fasta()) {
+        Ok(success) => success,
+        Err(err) => {
+            eprintln!("\nFailed to fix reading frames, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    };
+
+    parameters.push(("group_start_locus", (report.group_start() + 1).to_string()));
+    parameters.push(("group_stop_locus", (report.group_stop() + 1).to_string()));
+    parameters.push(("frame_repairs", report.repairs().len().to_string()));
+    report_frame_repairs(&report);
+    out_fasta
+}
+
+/// Reports each gap repair made by `FixFrames` or `--normalize-codon-gaps`,
+/// and warns about each entry left out of frame
+fn report_frame_repairs(report: &FrameReport) {
+    eprintln!("Made {} frame repair(s)", report.repairs().len());
+    for repair in report.repairs() {
+        eprintln!("\t{repair}");
+    }
+    for defline in report.out_of_frame() {
+        eprintln!("Warning: '{defline}' is out of frame and could not be repaired");
+    }
+}
+
+/// Runs the `ReplaceBase` module, counting the replaced bases in `stats`
+fn run_replace_base(
+    args: &Config,
+    inp_fasta: &Fasta,
+    parameters: &mut Vec<(&str, String)>,
+    stats: &mut ModuleStats,
+) -> Fasta {
+    let (from, to) = match args.replacement() {
+        Ok(replacement) => replacement,
+        Err(err) => {
+            eprintln!("\nInvalid base replacement, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    };
+    parameters.push((
+        "replace_base",
+        format!("{}>{}", char::from(from), char::from(to)),
+    ));
+    parameters.push(("match_case", args.match_case().to_string()));
+
+    let mut out_fasta = replace_base(inp_fasta, from, to, args.match_case());
+    out_fasta.set_filename(args.out_fasta());
+    let num_replaced: usize = inp_fasta
+        .into_iter()
+        .zip(&out_fasta)
+        .map(|(before, after)| {
+            let (before, after) = (before.sequence(), after.sequence());
+            before
+                .iter()
+                .zip(after.iter())
+                .filter(|(a, b)| a != b)
+                .count()
+        })
+        .sum();
+    stats.add_modified(num_replaced);
+    out_fasta
+}
+
+/// Runs the `FilterByField` module, reporting the entries it drops
+fn run_filter_by_field(
+    args: &Config,
+    inp_fasta: &Fasta,
+    parameters: &mut Vec<(&str, String)>,
+    rejects: Option<&mut Rejects>,
+    stats: &mut ModuleStats,
+) -> Fasta {
+    let (field, value) = match args.filter_field() {
+        Ok(filter) => filter,
+        Err(err) => {
+            eprintln!("\nInvalid field filter, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    };
+    parameters.push(("filter_field", format!("{field}={value}")));
+    let (out_fasta, dropped) = filter_by_field(
+        inp_fasta,
+        args.defline_delim(),
+        field,
+        value,
+        args.out_fasta(),
+    );
+    let dropped: Vec<_> = dropped.into_iter().map(|entry| (entry, None)).collect();
+    report_dropped(
+        &dropped,
+        "FilterByField",
+        "field_mismatch",
+        &format!("field {field} is not '{value}'"),
+        rejects,
+        stats,
+    );
+    out_fasta
+}
+
+/// Runs the `DeflineTable` module, writing the metadata table in place of an
+/// output fasta
+fn run_defline_table(args: &Config, inp_fasta: &Fasta) {
+    let (table, num_unmatched) = defline_table(inp_fasta, args.defline_delim());
+    if num_unmatched > 0 {
+        eprintln!(
+            "{num_unmatched} sequence(s) did not match the ACCESSION{{GENE_TAG}} defline pattern"
+        );
+    }
+
+    write_table(args, &table, "defline table");
+}
+
+/// Runs the `Overview` module, sampling the alignment down to the width of the
+/// terminal, as given by `COLUMNS` (80 if unset), unless `--overview-columns`
+/// picks a range
+fn run_overview(args: &Config, inp_fasta: &Fasta) {
+    let terminal_width = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse::<usize>().ok())
+        .unwrap_or(80);
+    let width = terminal_width.saturating_sub(LABEL_WIDTH + 1).max(10);
+    match render_overview(inp_fasta, args.overview_columns(), width, args.max_seqs()) {
+        Ok(overview) => write_table(args, &overview, "overview"),
+        Err(err) => {
+            eprintln!("\nFailed to draw overview, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    }
+}
+
+/// Runs the `NearestNeighbor` module, which needs aligned input
+fn run_nearest_neighbor(args: &Config, inp_fasta: &Fasta, limits: &Limits) {
+    match nearest_neighbor_table(inp_fasta, limits) {
+        Ok(table) => write_table(args, &table, "nearest neighbor table"),
+        Err(err) => {
+            eprintln!("\nFailed to find nearest neighbors, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    }
+}
+
+/// Writes a TSV produced by a table module to stdout or to the `-o` file
+fn write_table(args: &Config, table: &str, description: &str) {
+    if args.dry_run() {
+        eprintln!(
+            "Dry run: would write {description} with {} row(s) to '{}'",
+            table.lines().count().saturating_sub(1),
+            args.out_fasta().display()
+        );
+        return;
+    }
+
+    let write_result = if args.out_fasta() == Path::new("-") {
+        io::stdout().lock().write_all(table.as_bytes())
+    } else {
+        write_atomically(args.out_fasta(), |writer| {
+            writer.write_all(table.as_bytes())
+        })
+    };
+    if let Err(err) = write_result {
+        eprintln!(
+            "\nFailed to write {description}, '{}', \nproducing the error: '{err}'\n",
+            args.out_fasta().display()
+        );
+        process::exit(1);
+    }
+}
+
+/// Prints the findings of the `Validate` module to stderr and writes them as
+/// JSON to the `-o` file, then exits, with a failure status if any finding is
+/// an error (or a warning, with `--warnings-as-errors`)
+fn report_validation(args: &Config, findings: &[Finding]) -> ! {
+    let num_errors = findings
+        .iter()
+        .filter(|finding| finding.severity() == Severity::Error)
+        .count();
+    eprintln!(
+        "Validation found {num_errors} error(s) and {} warning(s)",
+        findings.len() - num_errors
+    );
+    for finding in findings {
+        eprintln!("\t{finding}");
+    }
+    write_table(args, &findings_json(findings), "validation findings");
+
+    if has_failures(findings, args.warnings_as_errors()) {
+        process::exit(1);
+    }
+    process::exit(0);
+}
+
+/// Runs the `SelectBlocks` module, recording its settings in `parameters`
+fn run_select_blocks(
+    args: &Config,
+    inp_fasta: &Fasta,
+    parameters: &mut Vec<(&str, String)>,
+) -> Fasta {
+    parameters.push(("min_occupancy", args.min_occupancy().to_string()));
+    parameters.push(("min_block_length", args.min_block_length().to_string()));
+    match select_blocks(
+        inp_fasta,
+        args.min_occupancy(),
+        args.min_block_length(),
+        args.out_fasta(),
+    ) {
+        Ok((out_fasta, kept_columns)) => {
+            eprintln!(
+                "Kept {} alignment column(s) in conserved blocks",
+                kept_columns.len()
+            );
+            parameters.push(("kept_columns", kept_columns.len().to_string()));
+            out_fasta
+        }
+        Err(err) => {
+            eprintln!("\nFailed to select conserved blocks, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    }
+}
+
+/// Runs the `TrimSignalPeptide` module, reporting how many entries had a
+/// signal peptide removed
+fn run_trim_signal_peptide(
+    args: &Config,
+    inp_fasta: &Fasta,
+    parameters: &mut Vec<(&str, String)>,
+) -> Fasta {
+    let max_length = args.max_signal_peptide_length();
+    parameters.push(("max_signal_peptide_length", max_length.to_string()));
+    let out_fasta = match trim_signal_peptide(inp_fasta, max_length, args.out_fasta()) {
+        Ok(out_fasta) => out_fasta,
+        Err(err) => {
+            eprintln!("\nFailed to trim signal peptides, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    };
+    let num_trimmed = (&out_fasta)
+        .into_iter()
+        .filter(|entry| entry.get_meta("signal_peptide_length") != Some("0"))
+        .count();
+    eprintln!("Trimmed a signal peptide from {num_trimmed} sequence(s)");
+    parameters.push(("signal_peptides", num_trimmed.to_string()));
+    out_fasta
+}
+
+/// Runs the `TrimRegions` module, writing one fasta per region into `--outdir`
+/// and, with `--region-qc`, reporting entries that fail the ORF checks
+fn run_trim_regions(args: &Config, inp_fasta: &Fasta, parameters: &mut Vec<(&str, String)>) {
+    let (Some(regions_file), Some(outdir)) = (args.regions_file(), args.outdir()) else {
+        return;
+    };
+    let regions = match read_regions(regions_file) {
+        Ok(regions) => regions,
+        Err(err) => {
+            eprintln!(
+                "\nFailed to read regions file, '{}', \nproducing the error: '{err}'\n",
+                regions_file.display()
+            );
+            process::exit(1);
+        }
+    };
+    let region_fastas = match trim_regions(inp_fasta, &regions, outdir) {
+        Ok(region_fastas) => region_fastas,
+        Err(err) => {
+            eprintln!("\nFailed to trim regions, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    };
+    parameters.push(("regions", regions.len().to_string()));
+
+    let stop_codons = custom_genetic_code(args).unwrap_or_default().stop_codons();
+    for (region, region_fasta) in regions.iter().zip(&region_fastas) {
+        if args.region_qc() {
+            let qc = region_qc(region_fasta, &stop_codons);
+            eprintln!(
+                "Region '{}': {} sequence(s) not a multiple of 3, {} with a premature stop",
+                region.name(),
+                qc.not_mod3.len(),
+                qc.premature_stop.len()
+            );
+            for defline in &qc.not_mod3 {
+                eprintln!("\t{defline} (not_mod3)");
+            }
+            for defline in &qc.premature_stop {
+                eprintln!("\t{defline} (premature_stop)");
+            }
+        }
+        write_split_fasta(args, region_fasta, "region");
+    }
+}
+
+/// Runs the `SplitByField` module, writing one fasta per field value into
+/// `--outdir`
+fn run_split_by_field(args: &Config, inp_fasta: &Fasta, parameters: &mut Vec<(&str, String)>) {
+    let (Ok(field), Some(outdir)) = (args.split_field(), args.outdir()) else {
+        return;
+    };
+    let split_fastas = match split_by_field(inp_fasta, field, args.defline_delim(), outdir) {
+        Ok(split_fastas) => split_fastas,
+        Err(err) => {
+            eprintln!("\nFailed to split by field, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    };
+    parameters.push(("groups", split_fastas.len().to_string()));
+    for split_fasta in &split_fastas {
+        eprintln!(
+            "Split {} sequence(s) into '{}'",
+            split_fasta.num_entries(),
+            split_fasta.filename()
+        );
+        write_split_fasta(args, split_fasta, "split");
+    }
+}
+
+/// Writes one of the fasta files a module splits its output into, to the path
+/// it is named by, or reports what would be written on a dry run
+fn write_split_fasta(args: &Config, fasta: &Fasta, description: &str) {
+    let path = Path::new(fasta.filename());
+    if args.dry_run() {
+        eprintln!(
+            "Dry run: would write {} sequence(s) to '{}'",
+            fasta.num_entries(),
+            path.display()
+        );
+    } else if let Err(err) = write_fasta_to_file(fasta, path, args.strict(), args.line_ending()) {
+        eprintln!(
+            "\nFailed to write {description} fasta file, '{}', \nproducing the error: '{err}'\n",
+            path.display()
+        );
+        process::exit(1);
+    }
+}
+
+/// Runs the `FlagOutliers` module, writing the outliers to `--outliers-out`
+/// unless this is a dry run
+fn run_flag_outliers(
+    args: &Config,
+    inp_fasta: &Fasta,
+    parameters: &mut Vec<(&str, String)>,
+    rejects: Option<&mut Rejects>,
+    stats: &mut ModuleStats,
+) -> Fasta {
+    parameters.push(("outlier_identity", args.outlier_identity().to_string()));
+    parameters.push(("clean_policy", args.clean_policy().to_string()));
+    let (out_fasta, outliers) = match flag_outliers(
+        inp_fasta,
+        args.outlier_identity(),
+        args.clean_policy(),
+        args.out_fasta(),
+    ) {
+        Ok(flagged) => flagged,
+        Err(err) => {
+            eprintln!("\nFailed to flag outliers, producing the error: '{err}'\n");
+            process::exit(1);
+        }
+    };
+
+    let Some(outliers_out) = args.outliers_out() else {
+        unreachable!("--outliers-out is checked before any module runs");
+    };
+    let mut outlier_fasta = Fasta::new(outliers_out);
+    for (entry, _) in &outliers {
+        outlier_fasta.add((*entry).clone());
+    }
+    if args.dry_run() {
+        eprintln!(
+            "Dry run: would write {} outlier(s) to '{}'",
+            outlier_fasta.num_entries(),
+            outliers_out.display()
+        );
+    } else if let Err(err) = write_fasta_to_file(
+        &outlier_fasta,
+        outliers_out,
+        args.strict(),
+        args.line_ending(),
+    ) {
+        eprintln!(
+            "\nFailed to write outliers file, '{}', \nproducing the error: '{err}'\n",
+            outliers_out.display()
+        );
+        process::exit(1);
+    }
+
+    let outliers: Vec<_> = outliers
+        .into_iter()
+        .map(|(entry, identity)| (entry, Some(identity)))
+        .collect();
+    report_dropped(
+        &outliers,
+        "FlagOutliers",
+        "identity",
+        &format!("identity to consensus below {}", args.outlier_identity()),
+        rejects,
+        stats,
+    );
+    out_fasta
+}
+
+/// Runs the `PolishToConsensus` module, logging every change it makes to
+/// `--polish-log` unless this is a dry run
+fn run_polish_to_consensus(
+    args: &Config,
+    inp_fasta: &Fasta,
+    parameters: &mut Vec<(&str, String)>,
+    stats: &mut ModuleStats,
+) -> Fasta {
+    parameters.push(("polish_support", args.polish_support().to_string()));
+    let (out_fasta, corrections) =
+        match polish_to_consensus(inp_fasta, args.polish_support(), args.out_fasta()) {
+            Ok(polished) => polished,
+            Err(err) => {
+                eprintln!("\nFailed to polish to the consensus, producing the error: '{err}'\n");
                 process::exit(1);
             }
         };
+    stats.add_modified(corrections.len());
+    eprintln!("Corrected {} base(s) to the consensus", corrections.len());
+
+    let Some(polish_log_path) = args.polish_log() else {
+        unreachable!("--polish-log is checked before any module runs");
+    };
+    if args.dry_run() {
+        eprintln!(
+            "Dry run: would log {} correction(s) to '{}'",
+            corrections.len(),
+            polish_log_path.display()
+        );
+    } else if let Err(err) = write_atomically(polish_log_path, |writer| {
+        writer.write_all(polish_log(&corrections).as_bytes())
+    }) {
+        eprintln!(
+            "\nFailed to write polish log, '{}', \nproducing the error: '{err}'\n",
+            polish_log_path.display()
+        );
+        process::exit(1);
+    }
+    out_fasta
+}
+
+/// Reports the deflines of entries removed by a filtering module to stderr,
+/// along with the metric that caused their removal if there is one, and
+/// records them in `rejects` under the short `reason_code`
+fn report_dropped(
+    dropped: &[(&FastaEntry, Option<f64>)],
+    module: &str,
+    reason_code: &str,
+    reason: &str,
+    rejects: Option<&mut Rejects>,
+    stats: &mut ModuleStats,
+) {
+    stats.add_rejected(reason_code, dropped.len());
+    eprintln!("Dropped {} sequence(s) ({reason})", dropped.len());
+    for (entry, metric) in dropped {
+        match metric {
+            Some(metric) => eprintln!("\t{} ({reason_code} {metric:.3})", entry.defline()),
+            None => eprintln!("\t{}", entry.defline()),
+        }
+    }
 
-        write_fasta(&out_fasta);
+    if let Some(rejects) = rejects {
+        for (entry, metric) in dropped {
+            if let Err(err) = rejects.add(entry, module, reason_code, *metric) {
+                eprintln!(
+                    "\nFailed to write rejects file, '{}', \nproducing the error: '{err}'\n",
+                    rejects.fasta_path().display()
+                );
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Flushes the rejects files and reports where they were written
+fn finish_rejects(rejects: Option<Rejects>) {
+    let Some(rejects) = rejects else {
+        return;
+    };
+    if rejects.num_rejected() > 0 {
+        eprintln!(
+            "Wrote {} rejected sequence(s) to '{}' and '{}'",
+            rejects.num_rejected(),
+            rejects.fasta_path().display(),
+            rejects.table_path().display()
+        );
+    }
+    let fasta_path = rejects.fasta_path();
+    if let Err(err) = rejects.finish() {
+        eprintln!(
+            "\nFailed to write rejects file, '{}', \nproducing the error: '{err}'\n",
+            fasta_path.display()
+        );
+        process::exit(1);
     }
 }
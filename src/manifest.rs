@@ -0,0 +1,81 @@
+use crate::fasta_manager::{write_atomically, Fasta};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// A reproducibility receipt for a clean-genes run. Records what went in, what
+/// came out, which module(s) ran with which parameters, and a hash of the
+/// output sequences.
+pub(crate) struct Manifest {
+    input_filename: String,
+    input_entries: usize,
+    output_entries: usize,
+    modules: Vec<String>,
+    parameters: Vec<(String, String)>,
+    output_hash: u64,
+}
+
+impl Manifest {
+    /// Constructor for Manifest
+    pub(crate) fn new(inp_fasta: &Fasta, out_fasta: &Fasta, module: &str) -> Self {
+        Manifest {
+            input_filename: String::from(inp_fasta.filename()),
+            input_entries: inp_fasta.num_entries(),
+            output_entries: out_fasta.num_entries(),
+            modules: vec![String::from(module)],
+            parameters: Vec::new(),
+            output_hash: out_fasta.sequence_hash(),
+        }
+    }
+
+    /// Records a parameter chosen or inferred during the run
+    pub(crate) fn add_parameter(&mut self, key: &str, value: impl fmt::Display) {
+        self.parameters.push((String::from(key), value.to_string()));
+    }
+
+    /// Writes the manifest to a file
+    pub(crate) fn write(&self, out_name: &Path) -> Result<(), io::Error> {
+        write_atomically(out_name, |writer| write!(writer, "{self}"))
+    }
+}
+
+/// Displays the manifest as tab-separated key-value lines, as written to file
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "clean_genes_version\t{}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(f, "input_filename\t{}", self.input_filename)?;
+        writeln!(f, "input_entries\t{}", self.input_entries)?;
+        writeln!(f, "output_entries\t{}", self.output_entries)?;
+        writeln!(f, "modules\t{}", self.modules.join(","))?;
+        for (key, value) in &self.parameters {
+            writeln!(f, "{key}\t{value}")?;
+        }
+        writeln!(f, "output_sequence_hash\t{:016x}", self.output_hash)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Manifest;
+    use crate::fasta_manager::open_fasta;
+    use crate::orf_trimmer::{trim_to_orf, TrimToOrfOptions};
+
+    #[test]
+    fn trim_manifest() {
+        let inp_fasta = open_fasta("fake_short.fna").unwrap();
+        let (out_fasta, report) = trim_to_orf(&inp_fasta, &TrimToOrfOptions::default()).unwrap();
+        let mut manifest = Manifest::new(&inp_fasta, &out_fasta, "TrimToORF");
+        manifest.add_parameter("group_start_locus", report.group_start() + 1);
+        manifest.add_parameter("group_stop_locus", report.group_stop() + 1);
+
+        let text = manifest.to_string();
+        assert!(text.contains("input_filename\tfake_short.fna\n"));
+        assert!(text.contains("input_entries\t9\n"));
+        assert!(text.contains("output_entries\t9\n"));
+        assert!(text.contains("modules\tTrimToORF\n"));
+        assert!(text.contains("group_start_locus\t3\n"));
+        assert!(text.contains("group_stop_locus\t9\n"));
+
+        assert!(text.ends_with("output_sequence_hash\tfe9d28efc96c1933\n"));
+    }
+}
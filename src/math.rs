@@ -1,20 +1,108 @@
 #![allow(clippy::should_panic_without_expect)]
+use crate::fasta_manager::is_gap;
 use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum MathError {
+    EmptyInput,
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MathError::EmptyInput => write!(f, "Cannot calculate statistic on an empty input"),
+        }
+    }
+}
+
+impl std::error::Error for MathError {}
 
 /// Calculates the mathematical mode of a vector of usizes.
-pub(crate) fn mode_vec_usize(list: &Vec<usize>) -> Option<usize> {
+pub(crate) fn mode_vec_usize(list: &Vec<usize>) -> Result<usize, MathError> {
     let mut counts: HashMap<usize, usize> = HashMap::new();
 
     for &num in list {
         *counts.entry(num).or_default() += 1;
     }
 
-    if counts.is_empty() {
+    match counts.iter().max_by_key(|&(_, count)| count) {
+        Some((&mode, _)) => Ok(mode),
+        None => Err(MathError::EmptyInput),
+    }
+}
+
+/// Calculates the sample variance of a list of f64s in a single pass with
+/// Welford's online algorithm, which avoids the cancellation a sum of squares
+/// suffers on large, close values. Returns None for an empty list and 0 for a
+/// single value.
+#[expect(clippy::cast_precision_loss)]
+pub(crate) fn variance_f64(list: &[f64]) -> Option<f64> {
+    if list.is_empty() {
         return None;
     }
 
-    let mode = *counts.iter().max_by_key(|&(_, count)| count)?.0;
-    Some(mode)
+    let mut mean = 0.0;
+    let mut sum_sq_diffs = 0.0;
+    for (i, &value) in list.iter().enumerate() {
+        let delta = value - mean;
+        mean += delta / (i + 1) as f64;
+        sum_sq_diffs += delta * (value - mean);
+    }
+    if list.len() == 1 {
+        Some(0.0)
+    } else {
+        Some(sum_sq_diffs / (list.len() - 1) as f64)
+    }
+}
+
+/// Calculates the sample standard deviation of a list of f64s, as the square
+/// root of `variance_f64`
+pub(crate) fn std_dev_f64(list: &[f64]) -> Option<f64> {
+    variance_f64(list).map(f64::sqrt)
+}
+
+/// Counts the positions where two aligned sequences differ, ignoring case, or
+/// returns None if their lengths differ. Gaps are compared like any other
+/// character, except that '-' and '.' are the same gap: a gap facing a base is
+/// a difference and two gaps are not.
+pub(crate) fn hamming_distance(a: &[u8], b: &[u8]) -> Option<usize> {
+    if a.len() != b.len() {
+        return None;
+    }
+    Some(
+        a.iter()
+            .zip(b)
+            .filter(|&(&x, &y)| !(x.eq_ignore_ascii_case(&y) || is_gap(x) && is_gap(y)))
+            .count(),
+    )
+}
+
+/// Calculates a stable 64-bit FNV-1a hash over a series of byte slices, treated
+/// as one continuous stream. Unlike the std hashers, the result does not change
+/// between runs or Rust versions.
+pub(crate) fn fnv1a_hash(chunks: impl IntoIterator<Item = impl AsRef<[u8]>>) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let mut hash = FNV_OFFSET;
+    for chunk in chunks {
+        for &byte in chunk.as_ref() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    hash
+}
+
+/// A small xorshift generator so the fuzz-style tests are reproducible
+/// without an external crate
+#[cfg(test)]
+pub(crate) fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
 }
 
 #[expect(unused_imports)]
@@ -36,4 +124,47 @@ mod test {
         let mode = mode_vec_usize(&the_list);
         mode.expect("Failed to calculate mode: input list is empty");
     }
+
+    #[test]
+    fn stable_hash() {
+        assert_eq!(fnv1a_hash([b"".as_slice()]), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a_hash([b"a".as_slice()]), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(
+            fnv1a_hash([b"ATG".as_slice(), b"TAG".as_slice()]),
+            fnv1a_hash([b"ATGTAG".as_slice()])
+        );
+    }
+
+    #[test]
+    fn sample_variance() {
+        let variance = variance_f64(&[1.0, 2.0, 3.0, 4.0, 5.0]).unwrap();
+        assert!((variance - 2.5).abs() < 1e-12);
+        let std_dev = std_dev_f64(&[1.0, 2.0, 3.0, 4.0, 5.0]).unwrap();
+        assert!((std_dev - 2.5_f64.sqrt()).abs() < 1e-12);
+
+        assert_eq!(variance_f64(&[1e9 + 0.5; 4]), Some(0.0));
+        assert_eq!(variance_f64(&[3.0]), Some(0.0));
+        assert_eq!(variance_f64(&[]), None);
+        assert_eq!(std_dev_f64(&[]), None);
+
+        // Large offsets that a sum of squares would lose to cancellation
+        let shifted = variance_f64(&[1e9 + 4.0, 1e9 + 7.0, 1e9 + 13.0, 1e9 + 16.0]).unwrap();
+        assert!((shifted - 30.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn hamming() {
+        assert_eq!(hamming_distance(b"ATGAAA", b"atgaaa"), Some(0));
+        assert_eq!(hamming_distance(b"ATGAAA", b"ATGCAA"), Some(1));
+        assert_eq!(hamming_distance(b"ATG-.A", b"ATG.-A"), Some(0));
+        assert_eq!(hamming_distance(b"ATG--A", b"ATGAAA"), Some(2));
+        assert_eq!(hamming_distance(b"ATGAAA", b"ATGAA"), None);
+        assert_eq!(hamming_distance(b"", b""), Some(0));
+    }
+
+    #[test]
+    fn empty_mode_error() {
+        let the_list: Vec<usize> = Vec::new();
+        assert_eq!(mode_vec_usize(&the_list), Err(MathError::EmptyInput));
+    }
 }
@@ -1,85 +1,935 @@
 #![allow(clippy::allow_attributes)]
 #![allow(clippy::should_panic_without_expect)]
 
-use crate::fasta_manager::{Fasta, FastaEntry};
-use crate::math::mode_vec_usize;
-use std::collections::HashMap;
+use crate::alignment::{consensus, AlignmentError};
+use crate::fasta_manager::{
+    is_gap, write_atomically, CoordinateMap, Fasta, FastaEntry, LineEnding, SequenceOps,
+    DEFAULT_LINE_WIDTH,
+};
+use crate::limits::{LimitError, Limits};
+use crate::math::{mode_vec_usize, MathError};
+use crate::translation::GeneticCode;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
 use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
 pub(crate) enum OrfTrimError {
+    EmptyInput,
     NoStartCodons,
     NoGroupStart,
+    NoFrameStart(usize),
     NoStopCodons(usize),
     TrimFailed,
-    NoModeFound,
+    Unaligned(AlignmentError),
+    NoReferenceMatch,
+    UnknownSegment(String),
+    Limit(LimitError),
 }
 
 impl fmt::Display for OrfTrimError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            OrfTrimError::EmptyInput => write!(f, "Input contains no sequences"),
             OrfTrimError::NoStartCodons => write!(f, "Failed to find start codons in input alignment"),
             OrfTrimError::NoGroupStart => write!(f, "Failed to find a group start codon"),
+            OrfTrimError::NoFrameStart(frame) => write!(
+                f,
+                "Failed to find a column in frame {frame} with enough sequences not gapped to \
+                start the ORF"
+            ),
             OrfTrimError::NoStopCodons(pos) => write!(
                 f,
                 "Failed to find any stop codons in the frame of the group start codon at locus {pos}",
-            
             ),
             OrfTrimError::TrimFailed => write!(f, "Failed to trim fasta"),
-            OrfTrimError::NoModeFound => write!(f,"Failed to find mode for stopping position"),
+            OrfTrimError::Unaligned(err) => write!(f, "{err}"),
+            OrfTrimError::NoReferenceMatch => write!(
+                f,
+                "Failed to find an ORF whose translation resembles the reference protein"
+            ),
+            OrfTrimError::UnknownSegment(segment) => {
+                write!(f, "Segment '{segment}' is not in the segment table")
+            }
+            OrfTrimError::Limit(err) => write!(f, "{err}"),
         }
     }
 }
 
 impl std::error::Error for OrfTrimError {}
 
+/// An `OrfTrimError` along with the name of the input file that caused it, so
+/// failures can be traced back when many files are processed in one batch
+#[derive(Debug)]
+pub(crate) struct ContextualError {
+    source_file: String,
+    cause: OrfTrimError,
+}
+
+impl ContextualError {
+    /// Returns the name of the input file that caused the error
+    pub(crate) fn source_file(&self) -> &str {
+        &self.source_file
+    }
+
+    /// Returns the underlying `OrfTrimError`
+    pub(crate) fn cause(&self) -> &OrfTrimError {
+        &self.cause
+    }
+}
+
+impl fmt::Display for ContextualError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (input file: '{}')", self.cause, self.source_file)
+    }
+}
+
+impl std::error::Error for ContextualError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.cause)
+    }
+}
+
+/// The options controlling how `trim_to_orf` infers and trims the ORF. The
+/// defaults reproduce the standard behavior of the `TrimToORF` module.
+#[derive(Debug, Clone)]
+pub(crate) struct TrimToOrfOptions {
+    out_fasta: PathBuf,
+    start_codons: Vec<[u8; 3]>,
+    stop_codons: Vec<[u8; 3]>,
+    start_weights: Vec<usize>,
+    start_coherence_window: Option<usize>,
+    respect_softmask: bool,
+    report_readthrough: bool,
+    force_frame: Option<usize>,
+    frame_occupancy: f64,
+    limits: Limits,
+}
+
+impl Default for TrimToOrfOptions {
+    fn default() -> Self {
+        TrimToOrfOptions {
+            out_fasta: PathBuf::from("./output.fasta"),
+            start_codons: vec![*b"ATG", *b"AUG"],
+            stop_codons: vec![*b"TAG", *b"TGA", *b"TAA", *b"UAG", *b"UGA", *b"UAA"],
+            //This scoring matrix is arbitrary and should be adjusted based
+            //on the quality of results observed
+            start_weights: vec![8, 4, 2, 1],
+            start_coherence_window: None,
+            respect_softmask: false,
+            report_readthrough: false,
+            force_frame: None,
+            frame_occupancy: 0.5,
+            limits: Limits::default(),
+        }
+    }
+}
+
+impl TrimToOrfOptions {
+    /// Sets the name of the trimmed output fasta
+    pub(crate) fn out_fasta(mut self, out_fasta: impl AsRef<Path>) -> Self {
+        self.out_fasta = out_fasta.as_ref().to_path_buf();
+        self
+    }
+
+    /// Sets the codons recognized as start codons. Codons are matched
+    /// case-insensitively and should be given in uppercase.
+    pub(crate) fn start_codons(mut self, start_codons: &[[u8; 3]]) -> Self {
+        self.start_codons = start_codons.to_vec();
+        self
+    }
+
+    /// Sets the codons recognized as stop codons. Codons are matched
+    /// case-insensitively and should be given in uppercase.
+    pub(crate) fn stop_codons(mut self, stop_codons: &[[u8; 3]]) -> Self {
+        self.stop_codons = stop_codons.to_vec();
+        self
+    }
+
+    /// Sets the score given to the 1st, 2nd, 3rd... start codon of each
+    /// sequence when voting for the group start. Later starts score 0.
+    pub(crate) fn start_weights(mut self, start_weights: &[usize]) -> Self {
+        self.start_weights = start_weights.to_vec();
+        self
+    }
+
+    /// Sets the window, in columns either side, within which start codons in
+    /// other sequences support a start codon when voting for the group start.
+    /// None, the default, scores each start codon on its own.
+    pub(crate) fn start_coherence_window(mut self, window: Option<usize>) -> Self {
+        self.start_coherence_window = window;
+        self
+    }
+
+    /// Sets whether start codons containing lowercase (soft-masked) bases are
+    /// left out of the group start vote. Masked bases are still kept when
+    /// trimming, and stop codons are matched regardless of case.
+    pub(crate) fn respect_softmask(mut self, respect_softmask: bool) -> Self {
+        self.respect_softmask = respect_softmask;
+        self
+    }
+
+    /// Sets whether the report records, for each sequence, the next stop codon
+    /// in frame after the group stop, for readthrough analysis
+    pub(crate) fn report_readthrough(mut self, report_readthrough: bool) -> Self {
+        self.report_readthrough = report_readthrough;
+        self
+    }
+
+    /// Sets a reading frame, 0, 1 or 2, to use in place of the group start
+    /// vote. The group start becomes the first column in that frame, counting
+    /// from column 0, where at least `frame_occupancy` of the sequences are not
+    /// gapped. None, the default, votes on start codons as usual.
+    pub(crate) fn force_frame(mut self, frame: Option<usize>) -> Self {
+        self.force_frame = frame;
+        self
+    }
+
+    /// Sets the fraction of sequences that must not be gapped at the group
+    /// start chosen by `force_frame`
+    pub(crate) fn frame_occupancy(mut self, frame_occupancy: f64) -> Self {
+        self.frame_occupancy = frame_occupancy;
+        self
+    }
+
+    /// Sets the limits whose time budget is checked before each sequence
+    pub(crate) fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+/// Records the decisions made while trimming so they can be reported
+#[derive(Debug, PartialEq)]
+pub(crate) struct TrimReport {
+    group_start: usize,
+    group_stop: usize,
+    start_support: f64,
+    masked_starts: usize,
+    start_tied: bool,
+    confidence: OrfConfidence,
+    top_starts: Vec<Option<usize>>,
+    next_stops: Vec<Option<usize>>,
+}
+
+/// How far to trust the group ORF, from quantities found while inferring it.
+/// Each part is a fraction from 0 to 1, where 1 is best.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct OrfConfidence {
+    /// Fraction of sequences with any start codon at the group start
+    pub(crate) start_fraction: f64,
+    /// Gap between the top two start scores as a fraction of the top score;
+    /// 0 on a tie and 1 when only one locus scored
+    pub(crate) score_margin: f64,
+    /// Fraction of sequences whose first in-frame stop is the group stop
+    pub(crate) stop_agreement: f64,
+    /// Fraction of sequences too short to reach the end of the group stop,
+    /// which trimming cuts short
+    pub(crate) short_fraction: f64,
+}
+
+impl OrfConfidence {
+    /// Returns the confidence score, the mean of `start_fraction`,
+    /// `score_margin`, `stop_agreement`, and `1 - short_fraction`
+    pub(crate) fn score(&self) -> f64 {
+        (self.start_fraction
+            + self.score_margin
+            + self.stop_agreement
+            + (1.0 - self.short_fraction))
+            / 4.0
+    }
+}
+
+impl TrimReport {
+    /// Returns the position of the group start codon. Positions start at 0.
+    pub(crate) fn group_start(&self) -> usize {
+        self.group_start
+    }
+
+    /// Returns the position of the first base of the group stop codon.
+    /// Positions start at 0.
+    pub(crate) fn group_stop(&self) -> usize {
+        self.group_stop
+    }
+
+    /// Returns the fraction of sequences whose own top-scored start codon is at
+    /// the group start. A low value means the group start won on scores pooled
+    /// from many different loci rather than broad agreement.
+    pub(crate) fn start_support(&self) -> f64 {
+        self.start_support
+    }
+
+    /// Returns the number of soft-masked start codons left out of the group
+    /// start vote. Always 0 unless `respect_softmask` is set.
+    pub(crate) fn masked_starts(&self) -> usize {
+        self.masked_starts
+    }
+
+    /// Returns true if another locus scored as highly as the group start. Ties
+    /// go to the earliest locus.
+    pub(crate) fn start_tied(&self) -> bool {
+        self.start_tied
+    }
+
+    /// Returns the confidence in the group ORF and the parts it is built from
+    pub(crate) fn confidence(&self) -> OrfConfidence {
+        self.confidence
+    }
+
+    /// Returns each entry's own top-scored start codon locus, in entry order,
+    /// or None for an entry with no start codon. Positions start at 0.
+    pub(crate) fn top_starts(&self) -> &[Option<usize>] {
+        &self.top_starts
+    }
+
+    /// Returns whether an entry's own top-scored start codon is somewhere
+    /// other than the group start. An entry with no start codon does not
+    /// disagree, since it offers no alternative.
+    pub(crate) fn start_disagrees(&self, top_start: Option<usize>) -> bool {
+        top_start.is_some_and(|locus| locus != self.group_start)
+    }
+
+    /// Returns each entry's next in-frame stop codon after the group stop, in
+    /// entry order, or None for an entry with no later stop. Positions are of
+    /// the first base of the stop codon and start at 0. Empty unless
+    /// `report_readthrough` is set.
+    pub(crate) fn next_stops(&self) -> &[Option<usize>] {
+        &self.next_stops
+    }
+
+    /// Returns the number of entries whose top-scored start disagrees with the
+    /// group start
+    pub(crate) fn start_disagreements(&self) -> usize {
+        self.top_starts
+            .iter()
+            .filter(|&&top_start| self.start_disagrees(top_start))
+            .count()
+    }
+}
+
+/// The length of the amino acid k-mers compared between the translation of a
+/// candidate ORF and the reference protein
+const GUIDE_KMER_SIZE: usize = 3;
+
+/// A reference protein that picks, from every candidate ORF, the one whose
+/// translation most resembles it. Used instead of the start codon vote for
+/// sequences too divergent for the vote to find the right start.
+#[derive(Debug, Clone)]
+pub(crate) struct ProteinGuide {
+    kmers: HashSet<Vec<u8>>,
+    code: GeneticCode,
+}
+
+/// How closely the translation of a candidate ORF resembles the reference
+/// protein, compared by the sets of amino acid k-mers in each
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct GuideScore {
+    /// Twice the number of shared k-mers over the number of k-mers in both, so
+    /// an ORF much longer or shorter than the reference scores lower
+    pub(crate) similarity: f64,
+    /// Fraction of the reference's k-mers found in the candidate
+    pub(crate) coverage: f64,
+}
+
+impl ProteinGuide {
+    /// Builds a guide from a reference protein, translating candidate ORFs with
+    /// `code`. Gaps, stops and case are ignored. Errors if the reference has
+    /// too few residues to hold a k-mer.
+    pub(crate) fn new(reference: &[u8], code: GeneticCode) -> Result<Self, String> {
+        let kmers: HashSet<Vec<u8>> = protein_kmers(&residues(reference))
+            .into_iter()
+            .map(<[u8]>::to_vec)
+            .collect();
+        if kmers.is_empty() {
+            return Err(format!(
+                "The reference protein needs at least {GUIDE_KMER_SIZE} residues"
+            ));
+        }
+        Ok(ProteinGuide { kmers, code })
+    }
+
+    /// Scores the bases of one candidate ORF, translated from its first base
+    fn score(&self, orf_bases: &[u8]) -> GuideScore {
+        let protein = residues(&orf_bases.translate(self.code));
+        let kmers = protein_kmers(&protein);
+        let shared = kmers
+            .iter()
+            .filter(|&&kmer| self.kmers.contains(kmer))
+            .count();
+        #[expect(clippy::cast_precision_loss)]
+        let score = GuideScore {
+            similarity: 2.0 * shared as f64 / (kmers.len() + self.kmers.len()) as f64,
+            coverage: shared as f64 / self.kmers.len() as f64,
+        };
+        score
+    }
+
+    /// Returns the candidate ORF in `bases` with the highest similarity, the
+    /// earliest winning a tie, or None if no candidate shares a k-mer with the
+    /// reference
+    fn best_orf(&self, bases: &[u8], candidates: Vec<EntryOrf>) -> Option<(EntryOrf, GuideScore)> {
+        candidates
+            .into_iter()
+            .map(|orf| (orf, self.score(&orf.extract(bases))))
+            .filter(|(_, score)| score.similarity > 0.0)
+            .rev()
+            .max_by(|(_, a), (_, b)| a.similarity.total_cmp(&b.similarity))
+    }
+}
+
+/// Returns the residues of a protein in upper case, without gaps or stops
+fn residues(protein: &[u8]) -> Vec<u8> {
+    protein
+        .iter()
+        .filter(|&&residue| residue.is_ascii_alphabetic())
+        .map(u8::to_ascii_uppercase)
+        .collect()
+}
+
+/// Returns the distinct amino acid k-mers of a protein
+fn protein_kmers(protein: &[u8]) -> HashSet<&[u8]> {
+    protein.windows(GUIDE_KMER_SIZE).collect()
+}
+
+/// Records the ORF chosen by a reference protein so it can be reported
+#[derive(Debug, PartialEq)]
+pub(crate) struct GuidedReport {
+    group_start: usize,
+    group_stop: usize,
+    score: GuideScore,
+}
+
+impl GuidedReport {
+    /// Returns the position of the chosen start codon. Positions start at 0.
+    pub(crate) fn group_start(&self) -> usize {
+        self.group_start
+    }
+
+    /// Returns the position of the first base of the chosen stop codon.
+    /// Positions start at 0.
+    pub(crate) fn group_stop(&self) -> usize {
+        self.group_stop
+    }
+
+    /// Returns how closely the chosen ORF resembles the reference protein
+    pub(crate) fn score(&self) -> GuideScore {
+        self.score
+    }
+}
+
+/// The locus chosen as the group start, the number of sequences whose own
+/// top-scored start codon is at that locus, and whether another locus scored
+/// as highly
+#[derive(Debug, PartialEq)]
+struct GroupStart {
+    locus: usize,
+    supporting_seqs: usize,
+    tied: bool,
+    score_margin: f64,
+}
+
 /// The main functon of the `TrimToORF` module. Takes a Fasta object as input and
 /// returns a Fasta object trimmed to what is determined to be the group start
-/// and stop codons
-pub(crate) fn trim_to_orf(inp_fasta: &Fasta, out_fasta: &str) -> Result<Fasta, OrfTrimError> {
+/// and stop codons, along with a report of the chosen loci. Any error is tagged
+/// with the filename of the input Fasta.
+pub(crate) fn trim_to_orf(
+    inp_fasta: &Fasta,
+    options: &TrimToOrfOptions,
+) -> Result<(Fasta, TrimReport), ContextualError> {
+    infer_and_trim(inp_fasta, options).map_err(|cause| ContextualError {
+        source_file: String::from(inp_fasta.filename()),
+        cause,
+    })
+}
+
+/// The errors `trim_to_orf_collecting` found, each with the index of its entry
+pub(crate) type EntryErrors = Vec<(usize, OrfTrimError)>;
+
+/// Trims to the group ORF as `trim_to_orf` does, but checks every entry for a
+/// stop codon in the frame of the group start instead of trimming it blindly.
+/// Returns the entries that trimmed cleanly, with their original entry
+/// numbers, alongside an error for each entry that did not, tagged with its
+/// index in the input. Failing to infer the group ORF at all is still a single
+/// error, as there are no per-entry results to collect.
+pub(crate) fn trim_to_orf_collecting(
+    inp_fasta: &Fasta,
+    options: &TrimToOrfOptions,
+) -> Result<(Fasta, TrimReport, EntryErrors), ContextualError> {
+    let report = find_orf(inp_fasta, options)?;
+    let mut trimmed_fasta = Fasta::new(&options.out_fasta);
+    let mut errors = Vec::new();
+    for (i, entry) in inp_fasta.into_iter().enumerate() {
+        options.limits.check_time().map_err(|err| ContextualError {
+            source_file: String::from(inp_fasta.filename()),
+            cause: OrfTrimError::Limit(err),
+        })?;
+        if first_stop(&entry.sequence(), report.group_start, &options.stop_codons).is_some() {
+            trimmed_fasta.add(trim_entry(entry, report.group_start, report.group_stop));
+        } else {
+            errors.push((i, OrfTrimError::NoStopCodons(report.group_start + 1)));
+        }
+    }
+    Ok((trimmed_fasta, report, errors))
+}
+
+/// Trims every entry to the group ORF in `report`, whether or not it has a
+/// stop codon in frame, as `trim_to_orf` does once the ORF is inferred
+pub(crate) fn trim_to_group_orf(
+    inp_fasta: &Fasta,
+    report: &TrimReport,
+    out_fasta: impl AsRef<Path>,
+) -> Fasta {
+    let mut trimmed_fasta = Fasta::new(out_fasta);
+    for entry in inp_fasta {
+        trimmed_fasta.add(trim_entry(entry, report.group_start, report.group_stop));
+    }
+    trimmed_fasta
+}
+
+/// Infers the group start and stop codons as `trim_to_orf` does, without
+/// trimming anything, for modules that work relative to the group ORF
+pub(crate) fn find_orf(
+    inp_fasta: &Fasta,
+    options: &TrimToOrfOptions,
+) -> Result<TrimReport, ContextualError> {
+    infer_orf(inp_fasta, options).map_err(|cause| ContextualError {
+        source_file: String::from(inp_fasta.filename()),
+        cause,
+    })
+}
+
+/// Runs each step of the `TrimToORF` module in order
+fn infer_and_trim(
+    inp_fasta: &Fasta,
+    options: &TrimToOrfOptions,
+) -> Result<(Fasta, TrimReport), OrfTrimError> {
+    let report = infer_orf(inp_fasta, options)?;
+    let trimmed_fasta = perform_trimming(
+        inp_fasta,
+        report.group_start,
+        report.group_stop,
+        &options.out_fasta,
+    )?;
+    Ok((trimmed_fasta, report))
+}
+
+/// Determines the group start and stop codons without trimming anything
+fn infer_orf(inp_fasta: &Fasta, options: &TrimToOrfOptions) -> Result<TrimReport, OrfTrimError> {
     let num_seqs = inp_fasta.num_entries();
-    let starts = find_starts(inp_fasta, num_seqs)?;
-    let group_start = find_group_start(&starts)?;
-    let first_stops = find_first_stops(inp_fasta, group_start)?;
-    let group_stop = mode_vec_usize(&first_stops).ok_or( OrfTrimError::NoModeFound)?;
-    perform_trimming(inp_fasta, group_start, group_stop, out_fasta)
+    if num_seqs == 0 {
+        return Err(OrfTrimError::EmptyInput);
+    }
+    let (starts, masked_starts, group) = if let Some(frame) = options.force_frame {
+        // No start codon vote, so no sequence has starts of its own
+        let locus = find_frame_start(inp_fasta, frame, options.frame_occupancy)
+            .ok_or(OrfTrimError::NoFrameStart(frame))?;
+        let group = GroupStart {
+            locus,
+            supporting_seqs: 0,
+            tied: false,
+            score_margin: 1.0,
+        };
+        (vec![Vec::new(); num_seqs], 0, group)
+    } else {
+        let (starts, masked_starts) = find_starts(
+            inp_fasta,
+            num_seqs,
+            &options.start_codons,
+            options.respect_softmask,
+            &options.limits,
+        )?;
+        let group = find_group_start(
+            &starts,
+            &options.start_weights,
+            options.start_coherence_window,
+        )?;
+        (starts, masked_starts, group)
+    };
+    let GroupStart {
+        locus: group_start,
+        supporting_seqs,
+        tied: start_tied,
+        score_margin,
+    } = group;
+    let first_stops = find_first_stops(inp_fasta, group_start, &options.stop_codons)?;
+    let group_stop = mode_vec_usize(&first_stops).map_err(|err| match err {
+        MathError::EmptyInput => OrfTrimError::NoStopCodons(group_start + 1),
+    })?;
+
+    let fraction = |count: usize| {
+        #[expect(clippy::cast_precision_loss)]
+        let fraction = count as f64 / num_seqs as f64;
+        fraction
+    };
+    let confidence = OrfConfidence {
+        start_fraction: fraction(
+            starts
+                .iter()
+                .filter(|entry| entry.contains(&group_start))
+                .count(),
+        ),
+        score_margin,
+        stop_agreement: fraction(
+            first_stops
+                .iter()
+                .filter(|&&stop| stop == group_stop)
+                .count(),
+        ),
+        short_fraction: fraction(
+            inp_fasta
+                .into_iter()
+                .filter(|entry| entry.sequence().len() < group_stop.saturating_add(3))
+                .count(),
+        ),
+    };
+    Ok(TrimReport {
+        group_start,
+        group_stop,
+        start_support: fraction(supporting_seqs),
+        masked_starts,
+        start_tied,
+        confidence,
+        top_starts: starts
+            .iter()
+            .map(|entry_starts| top_start(entry_starts, &options.start_weights))
+            .collect(),
+        next_stops: if options.report_readthrough {
+            inp_fasta
+                .into_iter()
+                .map(|entry| first_stop(&entry.sequence(), group_stop + 3, &options.stop_codons))
+                .collect()
+        } else {
+            Vec::new()
+        },
+    })
 }
 
-/// Identifies all start codons in all reading frames for a Fasta object
-fn find_starts(inp_fasta: &Fasta, num_seqs: usize) -> Result<Vec<Vec<usize>>, OrfTrimError> {
-    let mut starts: Vec<Vec<usize>> = vec![Vec::new(); num_seqs];
+/// The protein-guided mode of the `TrimToORF` module. Rather than voting on
+/// start codons, finds every ORF in the degapped majority-rule consensus of
+/// the alignment and trims to the one whose translation most resembles the
+/// reference protein of `guide`. Any error is tagged with the filename of the
+/// input Fasta.
+pub(crate) fn trim_to_guided_orf(
+    inp_fasta: &Fasta,
+    options: &TrimToOrfOptions,
+    guide: &ProteinGuide,
+) -> Result<(Fasta, GuidedReport), ContextualError> {
+    let trim = || {
+        let report = infer_guided_orf(inp_fasta, options, guide)?;
+        let trimmed_fasta = perform_trimming(
+            inp_fasta,
+            report.group_start,
+            report.group_stop,
+            &options.out_fasta,
+        )?;
+        Ok((trimmed_fasta, report))
+    };
+    trim().map_err(|cause| ContextualError {
+        source_file: String::from(inp_fasta.filename()),
+        cause,
+    })
+}
+
+/// Chooses the group ORF of an alignment with a reference protein, mapping the
+/// ORF found in the degapped consensus back to alignment columns
+fn infer_guided_orf(
+    inp_fasta: &Fasta,
+    options: &TrimToOrfOptions,
+    guide: &ProteinGuide,
+) -> Result<GuidedReport, OrfTrimError> {
+    if inp_fasta.num_entries() == 0 {
+        return Err(OrfTrimError::EmptyInput);
+    }
+    let consensus = consensus(inp_fasta).map_err(OrfTrimError::Unaligned)?;
+    let map = CoordinateMap::new(&consensus);
+    let bases = consensus.degapped();
+    let (orf, score) = guide
+        .best_orf(&bases, entry_orfs(&bases, options, false))
+        .ok_or(OrfTrimError::NoReferenceMatch)?;
+    let (Some(group_start), Some(group_stop)) = (
+        map.seq_to_align(orf.start),
+        map.seq_to_align(orf.start + orf.len - 3),
+    ) else {
+        return Err(OrfTrimError::TrimFailed);
+    };
+    Ok(GuidedReport {
+        group_start,
+        group_stop,
+        score,
+    })
+}
+
+/// Reads a segment table: one segment per line as NAME, START and STOP
+/// separated by tabs, where START is the first base of the start codon and
+/// STOP the first base of the stop codon, both alignment columns counted
+/// from 1. Blank lines, lines starting with '#' and a header line starting
+/// with "segment" are skipped. Returns each segment's (START, STOP) by name.
+pub(crate) fn load_segment_reference_table(
+    path: impl AsRef<Path>,
+) -> Result<HashMap<String, (usize, usize)>, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    let invalid = |line_num: usize, problem: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line {line_num} of the segment table {problem}"),
+        )
+    };
+
+    let mut table: HashMap<String, (usize, usize)> = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || (i == 0 && line.starts_with("segment")) {
+            continue;
+        }
+        let &[segment, start, stop] = line.split('\t').collect::<Vec<_>>().as_slice() else {
+            return Err(invalid(i + 1, "is not SEGMENT<TAB>START<TAB>STOP").into());
+        };
+        let (Ok(start), Ok(stop)) = (start.parse::<usize>(), stop.parse::<usize>()) else {
+            return Err(invalid(i + 1, "needs whole-number START and STOP").into());
+        };
+        if start == 0 || stop < start {
+            return Err(invalid(i + 1, "needs 1 <= START <= STOP").into());
+        }
+        if table.insert(String::from(segment), (start, stop)).is_some() {
+            return Err(invalid(i + 1, &format!("repeats the segment '{segment}'")).into());
+        }
+    }
+    Ok(table)
+}
 
+/// The segment-table mode of the `TrimToORF` module. Trims every entry from
+/// the start codon through the stop codon that `table` gives for `segment`,
+/// rather than inferring them, for segments whose ORF boundaries are known.
+pub(crate) fn trim_by_segment_table(
+    fasta: &Fasta,
+    segment: &str,
+    table: &HashMap<String, (usize, usize)>,
+    out_fasta: impl AsRef<Path>,
+) -> Result<Fasta, OrfTrimError> {
+    let &(start, stop) = table
+        .get(segment)
+        .ok_or_else(|| OrfTrimError::UnknownSegment(String::from(segment)))?;
+    perform_trimming(fasta, start - 1, stop - 1, out_fasta)
+}
+
+/// An ORF found in one sequence on its own. Positions are in the degapped
+/// sequence and start at 0. On a circular sequence the ORF may run off the
+/// end and continue from the beginning.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct EntryOrf {
+    start: usize,
+    len: usize,
+    seq_len: usize,
+}
+
+impl EntryOrf {
+    /// Returns the position of the first base of the start codon
+    pub(crate) fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Returns the position of the last base of the stop codon, modulo the
+    /// sequence length
+    pub(crate) fn end(&self) -> usize {
+        (self.start + self.len - 1) % self.seq_len
+    }
+
+    /// Returns true if the ORF runs past the end of the sequence and wraps
+    /// around to the beginning
+    pub(crate) fn wraps(&self) -> bool {
+        self.start + self.len > self.seq_len
+    }
+
+    /// Returns the ORF's bases from `bases`, the degapped sequence it was found
+    /// in, rotated so a wrapping ORF is contiguous
+    fn extract(&self, bases: &[u8]) -> Vec<u8> {
+        bases
+            .iter()
+            .cycle()
+            .skip(self.start)
+            .take(self.len)
+            .copied()
+            .collect()
+    }
+}
+
+/// The per-sequence mode of the `TrimToORF` module, for unaligned input.
+/// Trims each entry, degapped, to its own longest ORF: a start codon through
+/// the first stop codon in its frame, the earliest start winning a tie. With
+/// `circular`, each sequence is scanned as if joined end to start, so an ORF
+/// may wrap around the origin, but never be longer than the sequence. The
+/// coordinates of each ORF (from 1) are attached as `orf_start`, `orf_end` and
+/// `orf_wraps` metadata. With a `guide`, each entry is instead trimmed to the
+/// ORF whose translation most resembles the reference protein, and its
+/// `ref_similarity` and `ref_coverage` are attached too. Entries with no ORF
+/// are left empty; their number is returned alongside the trimmed Fasta.
+pub(crate) fn trim_to_entry_orfs(
+    inp_fasta: &Fasta,
+    options: &TrimToOrfOptions,
+    circular: bool,
+    guide: Option<&ProteinGuide>,
+) -> (Fasta, usize) {
+    let mut trimmed_fasta = Fasta::new(&options.out_fasta);
+    let mut num_without_orf = 0;
     for entry in inp_fasta {
-        for (i, codon) in entry.sequence().to_ascii_uppercase().windows(3).enumerate() {
-            if codon == b"ATG" || codon == b"AUG" {
-                starts[entry.entry_num()].push(i);
+        let bases = entry.sequence().degapped();
+        let (orf, score) = match guide {
+            Some(guide) => guide
+                .best_orf(&bases, entry_orfs(&bases, options, circular))
+                .unzip(),
+            None => (find_entry_orf(&bases, options, circular), None),
+        };
+        let sequence = orf.map_or_else(Vec::new, |orf| orf.extract(&bases));
+        let mut trimmed =
+            FastaEntry::new(entry.defline(), sequence, entry.entry_num()).with_meta_from(entry);
+        match orf {
+            Some(orf) => {
+                trimmed.set_meta("orf_start", orf.start() + 1);
+                trimmed.set_meta("orf_end", orf.end() + 1);
+                trimmed.set_meta("orf_wraps", orf.wraps());
             }
+            None => num_without_orf += 1,
+        }
+        if let Some(score) = score {
+            trimmed.set_meta("ref_similarity", format!("{:.3}", score.similarity));
+            trimmed.set_meta("ref_coverage", format!("{:.3}", score.coverage));
         }
+        trimmed_fasta.add(trimmed);
     }
+    (trimmed_fasta, num_without_orf)
+}
 
-    if starts.is_empty() {
+/// Returns the longest ORF in one degapped sequence, as described for
+/// `trim_to_entry_orfs`, or None if no start codon has a stop codon after it
+fn find_entry_orf(bases: &[u8], options: &TrimToOrfOptions, circular: bool) -> Option<EntryOrf> {
+    entry_orfs(bases, options, circular)
+        .into_iter()
+        .rev()
+        .max_by_key(|orf| orf.len)
+}
+
+/// Returns every ORF in one degapped sequence, in order of start: each start
+/// codon through the first stop codon in its frame. Start codons with no stop
+/// codon after them are left out.
+fn entry_orfs(bases: &[u8], options: &TrimToOrfOptions, circular: bool) -> Vec<EntryOrf> {
+    let seq_len = bases.len();
+    let scanned = if circular {
+        bases.repeat(2)
+    } else {
+        bases.to_vec()
+    };
+    let is_codon = |codons: &[[u8; 3]], codon: &[u8]| codons.iter().any(|c| codon.same_bases(c));
+
+    //on a circle a start codon may itself straddle the origin, so every base
+    //can begin one
+    let num_starts = if circular && seq_len >= 3 {
+        seq_len
+    } else {
+        seq_len.saturating_sub(2)
+    };
+    let mut orfs: Vec<EntryOrf> = Vec::new();
+    for start in 0..num_starts {
+        if !is_codon(&options.start_codons, &scanned[start..start + 3]) {
+            continue;
+        }
+        //an ORF may not be longer than the sequence, even when it wraps
+        let limit = if circular { start + seq_len } else { seq_len };
+        let stop = scanned[start..limit]
+            .chunks_exact(3)
+            .position(|codon| is_codon(&options.stop_codons, codon));
+        if let Some(codon_num) = stop {
+            orfs.push(EntryOrf {
+                start,
+                len: 3 * (codon_num + 1),
+                seq_len,
+            });
+        }
+    }
+    orfs
+}
+
+/// A streaming version of `trim_to_orf` that writes each trimmed entry to the
+/// output file as soon as it is trimmed rather than collecting a trimmed Fasta
+/// in memory, so peak memory stays close to the size of the input.
+#[cfg(feature = "streaming")]
+pub(crate) fn trim_to_orf_streaming(
+    inp_fasta: &Fasta,
+    options: &TrimToOrfOptions,
+) -> Result<TrimReport, Box<dyn Error>> {
+    let report = infer_orf(inp_fasta, options).map_err(|cause| ContextualError {
+        source_file: String::from(inp_fasta.filename()),
+        cause,
+    })?;
+
+    let mut trimmer = StreamingTrimmer::new(&options.out_fasta)?;
+    for entry in inp_fasta {
+        trimmer.push(&trim_entry(entry, report.group_start, report.group_stop))?;
+    }
+    trimmer.finish()?;
+    Ok(report)
+}
+
+/// Identifies all start codons in all reading frames for a Fasta object. With
+/// `respect_softmask`, start codons containing any lowercase base are skipped
+/// and counted instead; the count is returned alongside the starts. The time
+/// budget in `limits` is checked before each sequence.
+fn find_starts(
+    inp_fasta: &Fasta,
+    num_seqs: usize,
+    start_codons: &[[u8; 3]],
+    respect_softmask: bool,
+    limits: &Limits,
+) -> Result<(Vec<Vec<usize>>, usize), OrfTrimError> {
+    let mut starts: Vec<Vec<usize>> = vec![Vec::new(); num_seqs];
+    let mut masked_starts = 0;
+
+    //entries are indexed by position, not entry number, which may not run
+    //from 0 to num_seqs if the Fasta was built by hand
+    for (entry_starts, entry) in starts.iter_mut().zip(inp_fasta) {
+        limits.check_time().map_err(OrfTrimError::Limit)?;
+        for (i, codon) in entry.sequence().windows(3).enumerate() {
+            if start_codons.iter().any(|start| codon.same_bases(start)) {
+                if respect_softmask && codon.iter().any(u8::is_ascii_lowercase) {
+                    masked_starts += 1;
+                } else {
+                    entry_starts.push(i);
+                }
+            }
+        }
+    }
+
+    if starts.iter().all(Vec::is_empty) {
         Err(OrfTrimError::NoStartCodons)
     } else {
-        Ok(starts)
+        Ok((starts, masked_starts))
     }
 }
 
 /// Identifies the common start codon locus based on the location and
-/// consistency of available start codons in the provided fasta file.
-fn find_group_start(starts: &Vec<Vec<usize>>) -> Result<usize, OrfTrimError> {
+/// consistency of available start codons in the provided fasta file. The
+/// nth start codon of each sequence scores `start_weights[n]`. With a
+/// `coherence_window`, that score is multiplied by the number of start codons,
+/// in any sequence, within that many columns either side, so a start only one
+/// sequence has counts for less than starts aligned across many. When loci tie
+/// on score the earliest one is chosen and the tie is recorded.
+fn find_group_start(
+    starts: &Vec<Vec<usize>>,
+    start_weights: &[usize],
+    coherence_window: Option<usize>,
+) -> Result<GroupStart, OrfTrimError> {
+    let coherence = coherence_window.map(|window| start_coherence(starts, window));
     let mut start_scores: HashMap<usize, usize> = HashMap::new();
     for entry in starts {
         let mut this_score;
         for (i, start) in entry.iter().enumerate() {
-            this_score = match i + 1 {
-                //This scoring matrix is arbitrary and should be adjusted based
-                //on the quality of results observed
-                1 => 8,
-                2 => 4,
-                3 => 2,
-                4 => 1,
-                _ => 0,
-            };
+            this_score = start_weights.get(i).copied().unwrap_or(0);
+            if let Some(coherence) = &coherence {
+                this_score *= coherence[start];
+            }
 
             if let Some(value) = start_scores.get_mut(start) {
                 *value += this_score;
@@ -89,47 +939,129 @@ fn find_group_start(starts: &Vec<Vec<usize>>) -> Result<usize, OrfTrimError> {
         }
     }
 
-    let mut max_value = usize::MIN;
-    let mut max_key = None;
-    for (&key, &value) in &start_scores {
-        if value > max_value {
-            max_value = value;
-            max_key = Some(key);
-        }
-    }
+    //rank by score, then by locus so that the earliest locus wins a tie no
+    //matter the order the HashMap is iterated in
+    let mut ranked: Vec<(usize, usize)> = start_scores.into_iter().collect();
+    ranked.sort_unstable_by_key(|&(locus, score)| (Reverse(score), locus));
 
-    match max_key {
-        Some(locus) => Ok(locus),
-        None => Err(OrfTrimError::NoGroupStart),
-    }
+    //a locus that scored 0 is not supported by any weighted start codon, so it
+    //cannot be the group start even if it is the best available
+    let (locus, max_value) = match ranked.first() {
+        Some(&(locus, score)) if score > 0 => (locus, score),
+        _ => return Err(OrfTrimError::NoGroupStart),
+    };
+    let runner_up = ranked.get(1).map_or(0, |&(_, score)| score);
+    let tied = runner_up == max_value;
+    #[expect(clippy::cast_precision_loss)]
+    let score_margin = (max_value - runner_up) as f64 / max_value as f64;
+
+    let supporting_seqs = starts
+        .iter()
+        .filter(|entry| top_start(entry, start_weights) == Some(locus))
+        .count();
+
+    Ok(GroupStart {
+        locus,
+        supporting_seqs,
+        tied,
+        score_margin,
+    })
+}
+
+/// Returns the first column in `frame` where at least `min_occupancy` of the
+/// sequences are not gapped, for a group start forced into that frame, or None
+/// if there is no such column
+fn find_frame_start(inp_fasta: &Fasta, frame: usize, min_occupancy: f64) -> Option<usize> {
+    let sequences: Vec<_> = inp_fasta.into_iter().map(FastaEntry::sequence).collect();
+    let length = sequences.iter().map(|sequence| sequence.len()).max()?;
+    (frame..length).step_by(3).find(|&column| {
+        let occupied = sequences
+            .iter()
+            .filter(|sequence| sequence.get(column).is_some_and(|&base| !is_gap(base)))
+            .count();
+        #[expect(clippy::cast_precision_loss)]
+        let occupancy = occupied as f64 / sequences.len() as f64;
+        occupancy >= min_occupancy
+    })
+}
+
+/// Returns, for each locus with a start codon, the number of start codons in
+/// all sequences within `window` columns of it, counting itself. The starts
+/// are sorted so each count is two binary searches, however wide the window.
+fn start_coherence(starts: &[Vec<usize>], window: usize) -> HashMap<usize, usize> {
+    let mut sorted_starts: Vec<usize> = starts.iter().flatten().copied().collect();
+    sorted_starts.sort_unstable();
+    sorted_starts
+        .iter()
+        .map(|&locus| {
+            let first =
+                sorted_starts.partition_point(|&start| start < locus.saturating_sub(window));
+            let last =
+                sorted_starts.partition_point(|&start| start <= locus.saturating_add(window));
+            (locus, last - first)
+        })
+        .collect()
+}
+
+/// Returns the top-scored start of one sequence, the one given the highest
+/// weight, taking the earliest start when weights tie
+fn top_start(entry_starts: &[usize], start_weights: &[usize]) -> Option<usize> {
+    entry_starts
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|&(i, _)| start_weights.get(i).copied().unwrap_or(0))
+        .map(|(_, &start)| start)
+}
+
+/// Writes a TSV with one row per entry of `inp_fasta`: the defline, the
+/// entry's own top-scored start locus (from 1, or NA with no start codon),
+/// whether it matches the group start in `report`, and the entry's position in
+/// the input file, counted from 1, which differs from the row once earlier
+/// modules have filtered entries out
+pub(crate) fn write_start_report_tsv(
+    inp_fasta: &Fasta,
+    report: &TrimReport,
+    path: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    write_atomically(path.as_ref(), |writer| {
+        writeln!(
+            writer,
+            "defline\ttop_start_locus\tmatches_group_start\tinput_position"
+        )?;
+        for (entry, &top_start) in inp_fasta.into_iter().zip(&report.top_starts) {
+            let locus = top_start.map_or(String::from("NA"), |locus| (locus + 1).to_string());
+            writeln!(
+                writer,
+                "{}\t{locus}\t{}\t{}",
+                entry.defline(),
+                !report.start_disagrees(top_start),
+                entry.original_index() + 1
+            )?;
+        }
+        Ok(())
+    })?;
+    Ok(())
 }
 
 /// Identifies the common stop codon locus. Uses the determined common start
 /// codon locus to define the reading frame and then identifies the first stop
 /// codon for each sequence in that frame
-fn find_first_stops(inp_fasta: &Fasta, group_start: usize) -> Result<Vec<usize>, OrfTrimError> {
-    let mut first_stops: Vec<usize> = Vec::new();
-
-    for entry in inp_fasta {
-        //if the group start codon is past the length of this sequence, move to
-        //the next sequence
-        if group_start < entry.sequence().len() {
-            for (codon_index, codon) in entry.sequence()[group_start..]
-                .iter()
-                .copied()
-                .map(|b| b.to_ascii_uppercase())
-                .enumerate()
-                .filter(|(_, b)| *b != b'-')
-                .array_chunks::<3>()
-                .map(|a| (a[0].0, [a[0].1, a[1].1, a[2].1]))
-            {
-                if matches!(&codon, b"TAG" | b"TGA" | b"TAA" | b"UAG" | b"UGA" | b"UAA") {
-                    first_stops.push(group_start + codon_index);
-                    break;
-                }
-            }
-        }
-    }
+///
+/// Gaps are skipped when reading codons, so a codon may be split across gaps,
+/// but each returned locus is an index into the *original* (gapped) sequence:
+/// the position of the first base of the stop codon. For `AT--GTAG` read from
+/// 0, the codons are `ATG` and `TAG`, and the stop is reported at 5, not at
+/// the gap-stripped position 3.
+fn find_first_stops(
+    inp_fasta: &Fasta,
+    group_start: usize,
+    stop_codons: &[[u8; 3]],
+) -> Result<Vec<usize>, OrfTrimError> {
+    let first_stops: Vec<usize> = inp_fasta
+        .into_iter()
+        .filter_map(|entry| first_stop(&entry.sequence(), group_start, stop_codons))
+        .collect();
 
     if first_stops.is_empty() {
         Err(OrfTrimError::NoStopCodons(group_start + 1))
@@ -138,6 +1070,32 @@ fn find_first_stops(inp_fasta: &Fasta, group_start: usize) -> Result<Vec<usize>,
     }
 }
 
+/// Returns the first stop codon in one sequence in the frame that begins at
+/// column `from`, reading codons from the ungapped bases as `find_first_stops`
+/// does, or None if `from` is past the end of the sequence or no stop follows
+fn first_stop(sequence: &[u8], from: usize, stop_codons: &[[u8; 3]]) -> Option<usize> {
+    // The enumerate happens before the gap filter, so each base keeps its
+    // original index and a codon is labeled with the original index of its
+    // first base. Codons are read lazily; nothing past the first stop is read.
+    let mut codon = [0_u8; 3];
+    let mut codon_start = 0;
+    let bases = sequence
+        .get(from..)?
+        .iter()
+        .enumerate()
+        .filter(|&(_, &base)| !is_gap(base));
+    for (num_bases, (offset, &base)) in bases.enumerate() {
+        if num_bases % 3 == 0 {
+            codon_start = from + offset;
+        }
+        codon[num_bases % 3] = base;
+        if num_bases % 3 == 2 && stop_codons.iter().any(|stop| codon.same_bases(stop)) {
+            return Some(codon_start);
+        }
+    }
+    None
+}
+
 /// Does the actual trimming step, taking in the Fasta object, the group start
 /// and stop codons (the locus at which to trim), and the name of the output
 /// file and returns a trimmed Fasta object with a new name matching the name
@@ -146,20 +1104,12 @@ fn perform_trimming(
     inp_fasta: &Fasta,
     start: usize,
     stop: usize,
-    out_fasta_name: &str,
+    out_fasta_name: impl AsRef<Path>,
 ) -> Result<Fasta, OrfTrimError> {
     let mut trimmed_fasta = Fasta::new(out_fasta_name);
 
     for entry in inp_fasta {
-        let mut trimmed_sequence: Vec<u8> = Vec::new();
-        for (i, base) in entry.sequence().iter().enumerate() {
-            if i >= start && i < stop + 3 {
-                trimmed_sequence.push(*base);
-            }
-        }
-
-        let trimmed_entry = FastaEntry::new(entry.defline(), trimmed_sequence, entry.entry_num());
-        trimmed_fasta.add(trimmed_entry);
+        trimmed_fasta.add(trim_entry(entry, start, stop));
     }
 
     if trimmed_fasta.num_entries() == 0 {
@@ -169,17 +1119,109 @@ fn perform_trimming(
     }
 }
 
+/// Trims a single entry to the bases from `start` through the end of the stop
+/// codon beginning at `stop`. Both bounds are clamped to the sequence, so a
+/// start past the end gives an empty sequence and a stop near `usize::MAX`
+/// cannot overflow.
+fn trim_entry(entry: &FastaEntry, start: usize, stop: usize) -> FastaEntry {
+    let sequence = entry.sequence();
+    let end = stop.saturating_add(3).min(sequence.len());
+    let trimmed_sequence = sequence[start.min(end)..end].to_vec();
+
+    FastaEntry::new(entry.defline(), trimmed_sequence, entry.entry_num()).with_meta_from(entry)
+}
+
+/// Writes trimmed entries to an output fasta one at a time as they are
+/// produced. Entries go to a `<out_fasta>.partial` file that is renamed over
+/// `out_fasta` by `finish`; if the trimmer is dropped without finishing, the
+/// partial file is removed.
+pub(crate) struct StreamingTrimmer {
+    out_fasta: PathBuf,
+    partial_path: PathBuf,
+    writer: Option<BufWriter<File>>,
+}
+
+impl StreamingTrimmer {
+    /// Constructor for `StreamingTrimmer`. Opens the output immediately.
+    pub(crate) fn new(out_fasta: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let out_fasta = out_fasta.as_ref().to_path_buf();
+        let mut partial_name = out_fasta.as_os_str().to_owned();
+        partial_name.push(".partial");
+        let partial_path = PathBuf::from(partial_name);
+        let writer = BufWriter::new(File::create(&partial_path)?);
+
+        Ok(StreamingTrimmer {
+            out_fasta,
+            partial_path,
+            writer: Some(writer),
+        })
+    }
+
+    /// Writes one entry to the output
+    pub(crate) fn push(&mut self, entry: &FastaEntry) -> Result<(), Box<dyn Error>> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Err("StreamingTrimmer has already finished".into());
+        };
+
+        entry.write_record_to(writer, DEFAULT_LINE_WIDTH, LineEnding::Lf)?;
+        Ok(())
+    }
+
+    /// Flushes and syncs the output and moves it to its final path
+    pub(crate) fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(writer) = self.writer.take() {
+            let file = writer
+                .into_inner()
+                .map_err(io::IntoInnerError::into_error)?;
+            file.sync_all()?;
+            fs::rename(&self.partial_path, &self.out_fasta)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for StreamingTrimmer {
+    fn drop(&mut self) {
+        if self.writer.take().is_some() {
+            let _ = fs::remove_file(&self.partial_path);
+        }
+    }
+}
+
 #[allow(unused_imports)]
 mod test {
-    use super::{find_starts, find_group_start, find_first_stops, trim_to_orf};
-    use crate::fasta_manager::{open_fasta, Fasta};
+    use super::{
+        find_entry_orf, find_first_stops, find_group_start, find_orf, find_starts, first_stop,
+        infer_orf, load_segment_reference_table, trim_by_segment_table, trim_entry,
+        trim_to_entry_orfs, trim_to_guided_orf, trim_to_orf, trim_to_orf_collecting,
+        write_start_report_tsv, OrfTrimError, ProteinGuide, StreamingTrimmer, TrimToOrfOptions,
+    };
+    use crate::fasta_manager::{open_fasta, Fasta, FastaEntry};
+    use crate::limits::{LimitError, Limits};
+    #[cfg(test)]
+    use crate::math::next_random;
+    use crate::translation::GeneticCode;
+    use std::time::Duration;
+
+    fn default_starts(fasta: &Fasta) -> Vec<Vec<usize>> {
+        let options = TrimToOrfOptions::default();
+        find_starts(
+            fasta,
+            fasta.num_entries(),
+            &options.start_codons,
+            false,
+            &Limits::default(),
+        )
+        .unwrap()
+        .0
+    }
 
     #[test]
     fn good_starts() {
         let fake_fasta_short: Fasta = open_fasta("fake_short.fna").unwrap();
-        let starts = find_starts(&fake_fasta_short, fake_fasta_short.num_entries());
+        let starts = default_starts(&fake_fasta_short);
         assert_eq!(
-            starts.unwrap(),
+            starts,
             Vec::from([
                 Vec::from([2, 5]),
                 Vec::from([2]),
@@ -198,65 +1240,617 @@ mod test {
     #[test]
     fn no_starts() {
         let no_fasta: Fasta = Fasta::new("fakeFile.fna");
-        let starts = find_starts(&no_fasta, no_fasta.num_entries());
-        starts.expect(  "Failed to find start codons in input alignment");
-
+        let options = TrimToOrfOptions::default();
+        let starts = find_starts(
+            &no_fasta,
+            no_fasta.num_entries(),
+            &options.start_codons,
+            false,
+            &Limits::default(),
+        );
+        starts.expect("Failed to find start codons in input alignment");
     }
 
     #[test]
     fn good_group_starts() {
         let fake_fasta_short: Fasta = open_fasta("fake_short.fna").unwrap();
-        let starts = find_starts(&fake_fasta_short, fake_fasta_short.num_entries());
-        let group_start = find_group_start(&starts.unwrap());
-        assert_eq!(group_start.unwrap(), 2);
+        let starts = default_starts(&fake_fasta_short);
+        let options = TrimToOrfOptions::default();
+        let group_start = find_group_start(&starts, &options.start_weights, None).unwrap();
+        assert_eq!(group_start.locus, 2);
+        assert_eq!(group_start.supporting_seqs, 7);
+    }
+
+    #[test]
+    fn start_support_fraction() {
+        let options = TrimToOrfOptions::default();
+        let starts = Vec::from([
+            Vec::from([2, 5]),
+            Vec::from([5]),
+            Vec::from([2]),
+            Vec::from([7]),
+        ]);
+        let group_start = find_group_start(&starts, &options.start_weights, None).unwrap();
+        assert_eq!(group_start.locus, 2);
+        assert_eq!(group_start.supporting_seqs, 2);
+
+        let fake_fasta_short: Fasta = open_fasta("fake_short.fna").unwrap();
+        let (_, report) = trim_to_orf(&fake_fasta_short, &options).unwrap();
+        assert!((report.start_support() - 7.0 / 9.0).abs() < f64::EPSILON);
     }
 
     #[should_panic]
     #[test]
     fn no_group_starts() {
-        let group_start = find_group_start(&Vec::from([Vec::new()]));
-        group_start.expect( "Failed to find a group start codon");
-  
+        let options = TrimToOrfOptions::default();
+        let group_start = find_group_start(&Vec::from([Vec::new()]), &options.start_weights, None);
+        group_start.expect("Failed to find a group start codon");
+    }
+
+    #[test]
+    fn all_empty_starts() {
+        let options = TrimToOrfOptions::default();
+        let starts: Vec<Vec<usize>> = vec![Vec::new(); 5];
+        let group_start = find_group_start(&starts, &options.start_weights, None);
+        assert!(matches!(group_start, Err(OrfTrimError::NoGroupStart)));
+    }
+
+    #[test]
+    fn start_disagreements() {
+        let mut fasta = Fasta::new("starts.fna");
+        for (i, sequence) in [
+            "ATGAAACCCTAG",
+            "ATGAAACCCTAG",
+            "CCCATGAAATAG",
+            "CCCCCCCCCTAG",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            fasta.add(FastaEntry::new(
+                i.to_string(),
+                sequence.as_bytes().to_vec(),
+                i,
+            ));
+        }
+        let (_, report) = trim_to_orf(&fasta, &TrimToOrfOptions::default()).unwrap();
+        assert_eq!(report.group_start(), 0);
+        assert_eq!(report.top_starts(), [Some(0), Some(0), Some(3), None]);
+        assert_eq!(report.start_disagreements(), 1);
+
+        let path = std::env::temp_dir().join("clean_genes_start_report.tsv");
+        write_start_report_tsv(&fasta, &report, &path).unwrap();
+        let table = std::fs::read_to_string(&path).unwrap();
+        let rows: Vec<&str> = table.lines().collect();
+        assert_eq!(
+            rows[0],
+            "defline\ttop_start_locus\tmatches_group_start\tinput_position"
+        );
+        assert_eq!(rows[3], "2\t4\tfalse\t3");
+        assert_eq!(rows[4], "3\tNA\ttrue\t4");
+    }
+
+    #[test]
+    fn confidence_scores() {
+        let options = TrimToOrfOptions::default();
+        let fake_fasta_short: Fasta = open_fasta("fake_short.fna").unwrap();
+        let (_, report) = trim_to_orf(&fake_fasta_short, &options).unwrap();
+        let confidence = report.confidence();
+        assert!((confidence.start_fraction - 7.0 / 9.0).abs() < 1e-9);
+        assert!((confidence.score_margin - 44.0 / 56.0).abs() < 1e-9);
+        assert!((confidence.stop_agreement - 5.0 / 9.0).abs() < 1e-9);
+        assert!(confidence.short_fraction.abs() < 1e-9);
+        assert!((confidence.score() - 0.7798).abs() < 1e-4);
+
+        let mut clean = Fasta::new("clean.fna");
+        let mut messy = Fasta::new("messy.fna");
+        for i in 0..4 {
+            clean.add(FastaEntry::new(i.to_string(), b"ATGAAACCCTAG".to_vec(), i));
+        }
+        messy.add(FastaEntry::new(
+            String::from("a"),
+            b"ATGAAATAGCCC".to_vec(),
+            0,
+        ));
+        messy.add(FastaEntry::new(
+            String::from("b"),
+            b"ATGAAATAGTAG".to_vec(),
+            1,
+        ));
+        messy.add(FastaEntry::new(String::from("c"), b"CCATGTGA".to_vec(), 2));
+        messy.add(FastaEntry::new(String::from("d"), b"CCATGA".to_vec(), 3));
+        let (_, clean_report) = trim_to_orf(&clean, &options).unwrap();
+        let (_, messy_report) = trim_to_orf(&messy, &options).unwrap();
+        assert!((clean_report.confidence().score() - 1.0).abs() < 1e-9);
+        assert!(messy_report.start_tied());
+        assert!((messy_report.confidence().score() - 0.375).abs() < 1e-9);
+    }
+
+    #[test]
+    fn tied_group_start_is_earliest() {
+        let options = TrimToOrfOptions::default();
+        let starts = Vec::from([
+            Vec::from([40]),
+            Vec::from([10]),
+            Vec::from([40]),
+            Vec::from([10]),
+            Vec::from([25]),
+        ]);
+        for _ in 0..50 {
+            let group_start = find_group_start(&starts, &options.start_weights, None).unwrap();
+            assert_eq!(group_start.locus, 10);
+            assert!(group_start.tied);
+        }
+
+        let fake_fasta_short: Fasta = open_fasta("fake_short.fna").unwrap();
+        let (_, report) = trim_to_orf(&fake_fasta_short, &options).unwrap();
+        assert!(!report.start_tied());
+    }
+
+    #[test]
+    fn coherence_outvotes_isolated_start() {
+        let options = TrimToOrfOptions::default();
+        let starts = Vec::from([
+            Vec::from([2]),
+            Vec::from([9]),
+            Vec::from([10]),
+            Vec::from([11]),
+        ]);
+        let plain = find_group_start(&starts, &options.start_weights, None).unwrap();
+        assert_eq!(plain.locus, 2);
+        assert!(plain.tied);
+
+        let coherent = find_group_start(&starts, &options.start_weights, Some(1)).unwrap();
+        assert_eq!(coherent.locus, 10);
+        assert!(!coherent.tied);
+        assert!((coherent.score_margin - 8.0 / 24.0).abs() < 1e-9);
+
+        let everywhere =
+            find_group_start(&starts, &options.start_weights, Some(usize::MAX)).unwrap();
+        assert_eq!(everywhere.locus, 2);
+        assert!(everywhere.tied);
+    }
+
+    #[test]
+    fn circular_orf_wraps_origin() {
+        let options = TrimToOrfOptions::default();
+        let straddling = b"CCCTAGGGGATGAAA";
+        assert_eq!(find_entry_orf(straddling, &options, false), None);
+        let orf = find_entry_orf(straddling, &options, true).unwrap();
+        assert_eq!((orf.start(), orf.end(), orf.wraps()), (9, 5, true));
+        assert_eq!(orf.extract(straddling), b"ATGAAACCCTAG");
+
+        let inside = b"GGATGCCCTAAGG";
+        let linear = find_entry_orf(inside, &options, false).unwrap();
+        assert_eq!(find_entry_orf(inside, &options, true), Some(linear));
+        assert_eq!(
+            (linear.start(), linear.end(), linear.wraps()),
+            (2, 10, false)
+        );
+
+        let split_start = b"TGAAACCCTAGGGA";
+        assert_eq!(find_entry_orf(split_start, &options, false), None);
+        let orf = find_entry_orf(split_start, &options, true).unwrap();
+        assert_eq!((orf.start(), orf.end(), orf.wraps()), (13, 10, true));
+        assert_eq!(orf.extract(split_start), b"ATGAAACCCTAG");
+
+        //reading round the whole of ATGAAA never meets a stop
+        assert_eq!(find_entry_orf(b"ATGAAA", &options, true), None);
+
+        let mut fasta = Fasta::new("circles.fna");
+        fasta.add(FastaEntry::new(
+            String::from("straddling"),
+            b"CCC-TAGGGGATGAAA".to_vec(),
+            0,
+        ));
+        fasta.add(FastaEntry::new(String::from("none"), b"CCCC".to_vec(), 1));
+        let (trimmed, num_without_orf) = trim_to_entry_orfs(&fasta, &options, true, None);
+        assert_eq!(num_without_orf, 1);
+        let entry = trimmed.indexed_entry(0);
+        assert_eq!(&*entry.sequence(), b"ATGAAACCCTAG");
+        assert_eq!(entry.get_meta("orf_start"), Some("10"));
+        assert_eq!(entry.get_meta("orf_end"), Some("6"));
+        assert_eq!(entry.get_meta("orf_wraps"), Some("true"));
+        assert!(trimmed.indexed_entry(1).sequence().is_empty());
+    }
+
+    #[test]
+    fn protein_guide_skips_misleading_atg() {
+        //an ATG upstream of the real start, in another frame, is every
+        //sequence's first start and opens the longest ORF
+        let fasta = open_fasta("test_data/misleading_atg.fna").unwrap();
+        let reference = open_fasta("test_data/misleading_atg_reference.faa").unwrap();
+        let guide = ProteinGuide::new(
+            &reference.indexed_entry(0).sequence(),
+            GeneticCode::Standard,
+        )
+        .unwrap();
+        let options = TrimToOrfOptions::default();
+
+        let (_, voted) = trim_to_orf(&fasta, &options).unwrap();
+        assert_eq!(voted.group_start(), 2);
+        let (trimmed, report) = trim_to_guided_orf(&fasta, &options, &guide).unwrap();
+        assert_eq!((report.group_start(), report.group_stop()), (9, 195));
+        assert!(report.score().similarity > 0.5);
+        assert!(report.score().coverage > 0.5);
+        assert!(trimmed
+            .indexed_entry(0)
+            .sequence()
+            .starts_with(b"ATGAAAACT"));
+        assert!(trimmed.indexed_entry(2).sequence().ends_with(b"TCTTAA"));
+
+        let (longest, _) = trim_to_entry_orfs(&fasta, &options, false, None);
+        assert_eq!(longest.indexed_entry(0).get_meta("orf_start"), Some("3"));
+        let (guided, num_without_orf) = trim_to_entry_orfs(&fasta, &options, false, Some(&guide));
+        assert_eq!(num_without_orf, 0);
+        for entry in &guided {
+            assert_eq!(entry.get_meta("orf_start"), Some("10"));
+            assert!(entry.get_meta("ref_similarity").is_some());
+        }
+        assert!(ProteinGuide::new(b"MK*", GeneticCode::Standard).is_err());
+    }
+
+    #[test]
+    fn segment_table_boundaries() {
+        let table = load_segment_reference_table("test_data/segment_table.tsv").unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table["N2"], (20, 1429));
+
+        let fasta = open_fasta("test_data/a_ha_h3_raw_500.fna").unwrap();
+        let (inferred, _) = trim_to_orf(&fasta, &TrimToOrfOptions::default()).unwrap();
+        let trimmed = trim_by_segment_table(&fasta, "H3", &table, "segment.fna").unwrap();
+        assert_eq!(trimmed.num_entries(), fasta.num_entries());
+        let first = fasta.indexed_entry(0).sequence();
+        assert_eq!(&*trimmed.indexed_entry(0).sequence(), &first[29..1758]);
+        assert_eq!(
+            trimmed.indexed_entry(0).sequence(),
+            inferred.indexed_entry(0).sequence()
+        );
+
+        let unknown = trim_by_segment_table(&fasta, "H1", &table, "segment.fna");
+        assert!(matches!(unknown, Err(OrfTrimError::UnknownSegment(_))));
+    }
+
+    #[test]
+    fn collect_entries_without_stops() {
+        let fasta = open_fasta("test_data/missing_stops.fna").unwrap();
+        let (trimmed, report, errors) =
+            trim_to_orf_collecting(&fasta, &TrimToOrfOptions::default()).unwrap();
+        assert_eq!(trimmed.num_entries(), 7);
+        assert_eq!(&*trimmed.indexed_entry(0).sequence(), b"ATGAAACCCTAA");
+        let indices: Vec<usize> = errors.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, [2, 6]);
+        assert!(errors
+            .iter()
+            .all(|(_, err)| matches!(err, OrfTrimError::NoStopCodons(locus) if *locus == report.group_start() + 1)));
+
+        let err = trim_to_orf_collecting(&Fasta::new("empty.fna"), &TrimToOrfOptions::default())
+            .unwrap_err();
+        assert!(matches!(err.cause(), OrfTrimError::EmptyInput));
+    }
+
+    #[test]
+    fn trim_times_out() {
+        let fasta = open_fasta("test_data/missing_stops.fna").unwrap();
+        let limits = Limits::new(None, Some(Duration::from_nanos(1)));
+        std::thread::sleep(Duration::from_millis(1));
+        let options = TrimToOrfOptions::default().limits(limits);
+        let err = trim_to_orf_collecting(&fasta, &options).unwrap_err();
+        assert!(matches!(
+            err.cause(),
+            OrfTrimError::Limit(LimitError::TimedOut(_))
+        ));
+    }
+
+    #[test]
+    fn readthrough_finds_next_stop() {
+        let mut fasta = Fasta::new("readthrough.fna");
+        for (i, sequence) in [
+            "ATGAAATAGCCC-TGAGG",
+            "ATGAAATAGCCCC-----",
+            "ATGAAATAG---------",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            fasta.add(FastaEntry::new(
+                i.to_string(),
+                sequence.as_bytes().to_vec(),
+                i,
+            ));
+        }
+
+        let (_, report) = trim_to_orf(&fasta, &TrimToOrfOptions::default()).unwrap();
+        assert!(report.next_stops().is_empty());
+        let options = TrimToOrfOptions::default().report_readthrough(true);
+        let (trimmed, report) = trim_to_orf(&fasta, &options).unwrap();
+        assert_eq!(report.group_stop(), 6);
+        assert_eq!(report.next_stops(), [Some(13), None, None]);
+        assert_eq!(&*trimmed.indexed_entry(0).sequence(), b"ATGAAATAG");
+    }
+
+    #[test]
+    fn forced_frame_overrides_start_vote() {
+        let mut fasta = Fasta::new("forced_frame.fna");
+        for (i, sequence) in [
+            "ATGCATGAAACCCTAAGGTAG",
+            "ATGCATGAAACCCTAAGGTAG",
+            "----ATGAAACCCTAAGGTAG",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            fasta.add(FastaEntry::new(
+                i.to_string(),
+                sequence.as_bytes().to_vec(),
+                i,
+            ));
+        }
+
+        let (_, voted) = trim_to_orf(&fasta, &TrimToOrfOptions::default()).unwrap();
+        assert_eq!((voted.group_start(), voted.group_stop()), (0, 18));
+
+        let options = TrimToOrfOptions::default()
+            .force_frame(Some(1))
+            .frame_occupancy(1.0);
+        let (trimmed, report) = trim_to_orf(&fasta, &options).unwrap();
+        assert_eq!((report.group_start(), report.group_stop()), (4, 13));
+        for entry in &trimmed {
+            assert_eq!(&*entry.sequence(), b"ATGAAACCCTAA");
+        }
+        assert_eq!(report.top_starts(), [None, None, None]);
+
+        // Two of three sequences are enough at the default occupancy
+        let options = TrimToOrfOptions::default().force_frame(Some(1));
+        let report = find_orf(&fasta, &options).unwrap();
+        assert_eq!(report.group_start(), 1);
+
+        let mut gapped = Fasta::new("gapped.fna");
+        gapped.add(FastaEntry::new(String::from("gaps"), b"A--A--".to_vec(), 0));
+        let options = TrimToOrfOptions::default().force_frame(Some(1));
+        assert!(matches!(
+            infer_orf(&gapped, &options),
+            Err(OrfTrimError::NoFrameStart(1))
+        ));
+    }
+
+    #[test]
+    fn all_zero_scores() {
+        let starts = Vec::from([Vec::from([0, 5]), Vec::from([0])]);
+        let group_start = find_group_start(&starts, &[0, 0], None);
+        assert!(matches!(group_start, Err(OrfTrimError::NoGroupStart)));
     }
 
     #[test]
     fn good_first_stops() {
         let fake_fasta_short: Fasta = open_fasta("fake_short.fna").unwrap();
-        let starts = find_starts(&fake_fasta_short, fake_fasta_short.num_entries());
-        let group_start = find_group_start(&starts.unwrap()).unwrap();
-        let first_stops = find_first_stops(&fake_fasta_short, group_start);
+        let starts = default_starts(&fake_fasta_short);
+        let options = TrimToOrfOptions::default();
+        let group_start = find_group_start(&starts, &options.start_weights, None)
+            .unwrap()
+            .locus;
+        let first_stops = find_first_stops(&fake_fasta_short, group_start, &options.stop_codons);
 
         assert_eq!(first_stops.unwrap(), Vec::from([8, 5, 8, 8, 8, 8]));
     }
 
+    #[test]
+    fn gapped_first_stop_uses_original_index() {
+        let mut gapped = Fasta::new("gapped.fna");
+        gapped.add(FastaEntry::new(
+            String::from("gapped"),
+            b"AT--GTAG".to_vec(),
+            0,
+        ));
+        let options = TrimToOrfOptions::default();
+        let first_stops = find_first_stops(&gapped, 0, &options.stop_codons);
+
+        assert_eq!(first_stops.unwrap(), [5]);
+
+        let stops = &options.stop_codons;
+        assert_eq!(first_stop(b"CCATGT-AGA", 2, stops), Some(5));
+        assert_eq!(first_stop(b"ATGTAG", 6, stops), None);
+        assert_eq!(first_stop(b"ATGTAG", 7, stops), None);
+    }
+
     #[should_panic]
     #[test]
     fn bad_first_stop() {
         let fake_fasta_short: Fasta = open_fasta("fake_short.fna").unwrap();
         let group_start = 70;
-        let first_stops = find_first_stops(&fake_fasta_short, group_start);
+        let options = TrimToOrfOptions::default();
+        let first_stops = find_first_stops(&fake_fasta_short, group_start, &options.stop_codons);
 
-        first_stops.expect("Failed to find any stop codons in the frame of the group start codon at locus 71");
-      
+        first_stops.expect(
+            "Failed to find any stop codons in the frame of the group start codon at locus 71",
+        );
     }
 
     #[test]
     fn full_trim_small() {
         let fake_fasta_short: Fasta = open_fasta("fake_short.fna").unwrap();
-        let trimmed_fasta = trim_to_orf(&fake_fasta_short, "./output.fasta").unwrap();
+        let options = TrimToOrfOptions::default().out_fasta("./output.fasta");
+        let (trimmed_fasta, report) = trim_to_orf(&fake_fasta_short, &options).unwrap();
+        assert_eq!(report.group_start(), 2);
+        assert_eq!(report.group_stop(), 8);
         for entry in &trimmed_fasta {
             match entry.entry_num() {
-                0 => assert_eq!(entry.sequence(), b"ATGATGTAG"),
-                1 => assert_eq!(entry.sequence(), b"ATGTGATAA"),
-                2 => assert_eq!(entry.sequence(), b"ATG--ATGA"),
-                3 => assert_eq!(entry.sequence(), b"atgatgtag"),
-                4 => assert_eq!(entry.sequence(), b"atGAtGTAG"),
-                5 => assert_eq!(entry.sequence(), b"ATGWKDTAG"),
-                6 => assert_eq!(entry.sequence(), b"ATGKSMTAA"),
-                7 => assert_eq!(entry.sequence(), b"NNNNNNNNN"),
-                8 => assert_eq!(entry.sequence(), b"GNG--TTGA"),
+                0 => assert_eq!(&*entry.sequence(), b"ATGATGTAG"),
+                1 => assert_eq!(&*entry.sequence(), b"ATGTGATAA"),
+                2 => assert_eq!(&*entry.sequence(), b"ATG--ATGA"),
+                3 => assert_eq!(&*entry.sequence(), b"atgatgtag"),
+                4 => assert_eq!(&*entry.sequence(), b"atGAtGTAG"),
+                5 => assert_eq!(&*entry.sequence(), b"ATGWKDTAG"),
+                6 => assert_eq!(&*entry.sequence(), b"ATGKSMTAA"),
+                7 => assert_eq!(&*entry.sequence(), b"NNNNNNNNN"),
+                8 => assert_eq!(&*entry.sequence(), b"GNG--TTGA"),
                 _ => panic!(),
             }
         }
     }
+
+    #[test]
+    fn non_default_weights() {
+        let fake_fasta_short: Fasta = open_fasta("fake_short.fna").unwrap();
+        let options = TrimToOrfOptions::default().start_weights(&[0, 8]);
+        let (trimmed_fasta, report) = trim_to_orf(&fake_fasta_short, &options).unwrap();
+        assert_eq!(report.group_start(), 5);
+        assert_eq!(&*trimmed_fasta.indexed_entry(0).sequence(), b"ATGTAG");
+    }
+
+    #[test]
+    fn error_names_input_file() {
+        let mut ns_fasta: Fasta = Fasta::new("fakeFile.fna");
+        ns_fasta.add(FastaEntry::new(
+            String::from("ns"),
+            b"NNNNNNNNN".to_vec(),
+            0,
+        ));
+        let err = trim_to_orf(&ns_fasta, &TrimToOrfOptions::default()).unwrap_err();
+        assert!(matches!(err.cause(), OrfTrimError::NoStartCodons));
+        assert_eq!(err.source_file(), "fakeFile.fna");
+        assert!(err.to_string().contains("fakeFile.fna"));
+    }
+
+    #[test]
+    fn empty_input() {
+        let empty_fasta = open_fasta("test_data/empty_file.fna").unwrap();
+        let err = trim_to_orf(&empty_fasta, &TrimToOrfOptions::default()).unwrap_err();
+        assert!(matches!(err.cause(), OrfTrimError::EmptyInput));
+        assert_eq!(
+            err.to_string(),
+            "Input contains no sequences (input file: 'test_data/empty_file.fna')"
+        );
+    }
+
+    #[test]
+    fn streaming_trimmer_keeps_order() {
+        let out_path = std::env::temp_dir().join("clean_genes_streaming_trimmer.fna");
+        let fake_fasta_short: Fasta = open_fasta("fake_short.fna").unwrap();
+        let mut trimmer = StreamingTrimmer::new(&out_path).unwrap();
+        for entry in &fake_fasta_short {
+            trimmer.push(entry).unwrap();
+        }
+        trimmer.finish().unwrap();
+
+        let written = open_fasta(&out_path).unwrap();
+        assert_eq!(written.num_entries(), fake_fasta_short.num_entries());
+        for (orig, streamed) in fake_fasta_short.into_iter().zip(&written) {
+            assert_eq!(orig.defline(), streamed.defline());
+            assert_eq!(orig.sequence(), streamed.sequence());
+        }
+    }
+
+    #[test]
+    fn dropped_streaming_trimmer_leaves_nothing() {
+        let out_path = std::env::temp_dir().join("clean_genes_dropped_trimmer.fna");
+        let partial = std::env::temp_dir().join("clean_genes_dropped_trimmer.fna.partial");
+        let mut trimmer = StreamingTrimmer::new(&out_path).unwrap();
+        trimmer
+            .push(&FastaEntry::new(String::from("seq"), b"ATG".to_vec(), 0))
+            .unwrap();
+        drop(trimmer);
+        assert!(!out_path.exists());
+        assert!(!partial.exists());
+    }
+
+    #[cfg(feature = "streaming")]
+    #[test]
+    fn streaming_matches_in_memory() {
+        use super::trim_to_orf_streaming;
+        use crate::fasta_manager::DEFAULT_LINE_WIDTH;
+
+        let out_path = std::env::temp_dir().join("clean_genes_streaming_trim.fna");
+        let fake_fasta_short: Fasta = open_fasta("fake_short.fna").unwrap();
+        let options = TrimToOrfOptions::default().out_fasta(&out_path);
+        let report = trim_to_orf_streaming(&fake_fasta_short, &options).unwrap();
+        let (in_memory, in_memory_report) = trim_to_orf(&fake_fasta_short, &options).unwrap();
+
+        assert_eq!(report, in_memory_report);
+        assert_eq!(
+            std::fs::read_to_string(&out_path).unwrap(),
+            in_memory.to_fasta_string(DEFAULT_LINE_WIDTH)
+        );
+    }
+
+    #[test]
+    fn softmasked_start_moves_group_start() {
+        // every sequence has a soft-masked ATG upstream of the real start
+        let mut masked = Fasta::new("masked.fna");
+        for (i, sequence) in [b"atgATGCCCTAG", b"atgATGCCATAG", b"atgATGCCGTAG"]
+            .into_iter()
+            .enumerate()
+        {
+            masked.add(FastaEntry::new(format!("seq{i}"), sequence.to_vec(), i));
+        }
+
+        let (trimmed, report) = trim_to_orf(&masked, &TrimToOrfOptions::default()).unwrap();
+        assert_eq!(report.group_start(), 0);
+        assert_eq!(report.masked_starts(), 0);
+        assert_eq!(&*trimmed.indexed_entry(0).sequence(), b"atgATGCCCTAG");
+
+        let options = TrimToOrfOptions::default().respect_softmask(true);
+        let (trimmed, report) = trim_to_orf(&masked, &options).unwrap();
+        assert_eq!(report.group_start(), 3);
+        assert_eq!(report.masked_starts(), 3);
+        assert_eq!(&*trimmed.indexed_entry(0).sequence(), b"ATGCCCTAG");
+    }
+
+    #[test]
+    fn random_fasta_never_panics() {
+        let path = std::env::temp_dir().join("clean_genes_random_trim.fna");
+        let alphabet = b"ACGTACGTACGT--N.acgtRY>\n\r ";
+        let codons: [&[u8]; 4] = [b"ATG", b"TAA", b"TAG", b"TGA"];
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+
+        for round in 0..300 {
+            let mut bytes = Vec::new();
+            let num_entries = next_random(&mut state) % 6;
+            for i in 0..num_entries {
+                bytes.extend_from_slice(format!(">seq{i}\n").as_bytes());
+                let len = next_random(&mut state) % 90;
+                for _ in 0..len {
+                    let value = usize::try_from(next_random(&mut state)).unwrap();
+                    if round % 3 == 0 {
+                        bytes.push(alphabet[value % alphabet.len()]);
+                    } else {
+                        bytes.extend_from_slice(codons[value % codons.len()]);
+                        bytes.push(b"ACGT-"[value / 7 % 5]);
+                    }
+                }
+                bytes.push(b'\n');
+            }
+            let cut = usize::try_from(next_random(&mut state)).unwrap() % (bytes.len() + 1);
+            std::fs::write(&path, &bytes[..cut]).unwrap();
+
+            if let Ok(fasta) = open_fasta(&path) {
+                let options = TrimToOrfOptions::default().respect_softmask(round % 2 == 0);
+                if let Ok((trimmed, report)) = trim_to_orf(&fasta, &options) {
+                    assert_eq!(trimmed.num_entries(), fasta.num_entries());
+                    assert!(report.group_start() <= report.group_stop());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_order_entry_numbers_do_not_panic() {
+        let mut fasta = Fasta::new("renumbered.fna");
+        fasta.add(FastaEntry::new(String::from("a"), b"ATGAAATAG".to_vec(), 7));
+        fasta.add(FastaEntry::new(String::from("b"), b"ATGCCCTAA".to_vec(), 3));
+        let (trimmed, _) = trim_to_orf(&fasta, &TrimToOrfOptions::default()).unwrap();
+        assert_eq!(&*trimmed.indexed_entry(1).sequence(), b"ATGCCCTAA");
+    }
+
+    #[test]
+    fn huge_stop_does_not_overflow() {
+        let entry = FastaEntry::new(String::from("seq"), b"ATGAAATAG".to_vec(), 0);
+        assert_eq!(&*trim_entry(&entry, 3, usize::MAX).sequence(), b"AAATAG");
+        assert_eq!(
+            &*trim_entry(&entry, 3, usize::MAX - 2).sequence(),
+            b"AAATAG"
+        );
+        assert!(trim_entry(&entry, 20, usize::MAX).sequence().is_empty());
+        assert!(trim_entry(&entry, 6, 1).sequence().is_empty());
+    }
 }
@@ -0,0 +1,170 @@
+use crate::alignment::alignment_length;
+use crate::fasta_manager::{write_atomically, Fasta, FastaEntry, SequenceOps};
+use crate::relabel::{export_names, write_name_map, PHYLIP_NAME_LENGTH};
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The kinds of output one run can write, each to its own file
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub(crate) enum OutputFormat {
+    /// The output sequences
+    Fasta,
+    /// One GFF3 feature per output sequence
+    Gff3,
+    /// The table of per-module stats
+    Stats,
+    /// The alignment as strict PHYLIP, with a name map beside it
+    Phylip,
+}
+
+impl OutputFormat {
+    /// Returns the path this format is written to, derived from the output
+    /// path `template`: the template itself for fasta, and the template with
+    /// its extension replaced by `gff3`, `stats.tsv` or `phy` otherwise
+    pub(crate) fn output_path(self, template: &Path) -> PathBuf {
+        match self {
+            OutputFormat::Fasta => template.to_path_buf(),
+            OutputFormat::Gff3 => template.with_extension("gff3"),
+            OutputFormat::Stats => template.with_extension("stats.tsv"),
+            OutputFormat::Phylip => template.with_extension("phy"),
+        }
+    }
+}
+
+/// Returns the path of the name map written beside a PHYLIP file
+pub(crate) fn name_map_path(phylip_path: &Path) -> PathBuf {
+    phylip_path.with_extension("phy.names.tsv")
+}
+
+/// Percent-encodes the characters GFF3 reserves in a column or attribute
+fn escape_gff(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if matches!(c, ';' | '=' | '&' | ',' | '%') || c.is_ascii_control() {
+                format!("%{:02X}", u32::from(c))
+            } else {
+                c.to_string()
+            }
+        })
+        .collect()
+}
+
+/// Writes a GFF3 file with one `region` feature per non-empty entry, spanning
+/// its ungapped bases. The seqid is the defline up to the first whitespace,
+/// and each entry's metadata is carried over as attributes alongside its ID
+/// and full defline.
+pub(crate) fn write_gff3(fasta: &Fasta, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+    write_atomically(path.as_ref(), |writer| {
+        writeln!(writer, "##gff-version 3")?;
+        for entry in fasta {
+            let num_bases = entry.sequence().degapped().len();
+            if num_bases == 0 {
+                continue;
+            }
+            let defline = entry.defline();
+            let seqid = defline.split_whitespace().next().unwrap_or_default();
+            let mut attributes = vec![
+                format!("ID=entry_{}", entry.entry_num() + 1),
+                format!("Name={}", escape_gff(&defline)),
+            ];
+            for key in entry.meta_keys() {
+                let value = entry.get_meta(key).unwrap_or_default();
+                attributes.push(format!("{}={}", escape_gff(key), escape_gff(value)));
+            }
+            writeln!(
+                writer,
+                "{}\tclean-genes\tregion\t1\t{num_bases}\t.\t.\t.\t{}",
+                escape_gff(seqid),
+                attributes.join(";")
+            )?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Writes the alignment as strict sequential PHYLIP, one line per entry. Strict
+/// PHYLIP names are at most 10 characters, so each entry is written under a
+/// unique name from `export_names`, and the name map from those names back to
+/// the deflines is written to `name_map_path` for relabeling trees built from
+/// the file.
+pub(crate) fn write_phylip(fasta: &Fasta, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+    let length = alignment_length(fasta)?;
+    let deflines: Vec<String> = fasta.into_iter().map(FastaEntry::defline).collect();
+    let names = export_names(&deflines, PHYLIP_NAME_LENGTH);
+    write_atomically(path.as_ref(), |writer| {
+        writeln!(writer, "{} {length}", fasta.num_entries())?;
+        for (name, entry) in names.iter().zip(fasta) {
+            write!(writer, "{name:<PHYLIP_NAME_LENGTH$}")?;
+            writer.write_all(&entry.sequence())?;
+            writeln!(writer)?;
+        }
+        Ok(())
+    })?;
+    write_name_map(&names, &deflines, name_map_path(path.as_ref()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{name_map_path, write_gff3, write_phylip, OutputFormat};
+    use crate::fasta_manager::{Fasta, FastaEntry};
+    use crate::relabel::read_name_map;
+    use std::path::Path;
+
+    #[test]
+    fn derived_paths_and_gff3() {
+        let template = Path::new("out/trimmed.fna");
+        assert_eq!(OutputFormat::Fasta.output_path(template), template);
+        assert_eq!(
+            OutputFormat::Gff3.output_path(template),
+            Path::new("out/trimmed.gff3")
+        );
+        assert_eq!(
+            OutputFormat::Stats.output_path(template),
+            Path::new("out/trimmed.stats.tsv")
+        );
+
+        let mut fasta = Fasta::new("features.fna");
+        let mut entry = FastaEntry::new(String::from("CY1 seg=4;x"), b"AT-G".to_vec(), 0);
+        entry.set_meta("length", 3);
+        fasta.add(entry);
+        fasta.add(FastaEntry::new(String::from("empty"), b"--".to_vec(), 1));
+        let path = std::env::temp_dir().join("clean_genes_features.gff3");
+        write_gff3(&fasta, &path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "##gff-version 3\n\
+            CY1\tclean-genes\tregion\t1\t3\t.\t.\t.\tID=entry_1;Name=CY1 seg%3D4%3Bx;length=3\n"
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn phylip_with_name_map() {
+        let mut fasta = Fasta::new("aligned.fna");
+        fasta.add(FastaEntry::new(
+            String::from("CY121680 tag=1"),
+            b"AT-G".to_vec(),
+            0,
+        ));
+        fasta.add(FastaEntry::new(
+            String::from("CY121680 tag=2"),
+            b"ATCG".to_vec(),
+            1,
+        ));
+        let path = std::env::temp_dir().join("clean_genes_aligned.phy");
+        write_phylip(&fasta, &path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "2 4\nCY121680_tAT-G\nCY121680_1ATCG\n"
+        );
+        let names = read_name_map(name_map_path(&path)).unwrap();
+        assert_eq!(names["CY121680_1"], "CY121680 tag=2");
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(name_map_path(&path));
+
+        fasta.add(FastaEntry::new(String::from("short"), b"AT".to_vec(), 2));
+        assert!(write_phylip(&fasta, &path).is_err());
+    }
+}
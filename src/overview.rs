@@ -0,0 +1,156 @@
+use crate::alignment::consensus;
+use crate::fasta_manager::{is_gap, Fasta};
+use std::fmt::Write;
+use std::ops::Range;
+
+/// The width deflines are padded or truncated to in the overview
+pub(crate) const LABEL_WIDTH: usize = 16;
+
+/// Returns a character as itself if it is printable ASCII, or '?' otherwise
+fn printable(base: u8) -> char {
+    if base.is_ascii_graphic() {
+        char::from(base)
+    } else {
+        '?'
+    }
+}
+
+/// Returns how one base is drawn against its consensus base: '.' for a match
+/// ignoring case, '-' for a gap, 'n' for an N, and the base itself otherwise
+fn overview_char(base: u8, consensus_base: u8) -> char {
+    if is_gap(base) {
+        '-'
+    } else if base.eq_ignore_ascii_case(&b'N') {
+        'n'
+    } else if base.eq_ignore_ascii_case(&consensus_base) {
+        '.'
+    } else {
+        printable(base)
+    }
+}
+
+/// Returns a label padded or truncated to exactly `LABEL_WIDTH` ASCII
+/// characters, followed by a space
+fn label(text: &str) -> String {
+    let ascii: String = text
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() || c == ' ' {
+                c
+            } else {
+                '?'
+            }
+        })
+        .take(LABEL_WIDTH)
+        .collect();
+    format!("{ascii:<LABEL_WIDTH$} ")
+}
+
+/// The main function of the `Overview` module. Draws the alignment as plain
+/// ASCII: a header naming the columns shown, the consensus, and one row per
+/// entry in which matches to the consensus are '.', gaps '-' and Ns 'n'.
+/// `columns` picks a range to show in full; without it, the whole alignment is
+/// sampled down to `width` evenly spaced columns. Only the first `max_seqs`
+/// entries get a row, followed by a note counting the rest.
+pub(crate) fn render_overview(
+    inp_fasta: &Fasta,
+    columns: Option<Range<usize>>,
+    width: usize,
+    max_seqs: Option<usize>,
+) -> Result<String, String> {
+    let consensus = consensus(inp_fasta).map_err(|err| err.to_string())?;
+    let shown: Vec<usize> = match columns {
+        Some(range) if range.end > consensus.len() => {
+            return Err(format!(
+                "Columns {}-{} run past the end of the alignment at column {}",
+                range.start + 1,
+                range.end,
+                consensus.len()
+            ));
+        }
+        Some(range) => range.collect(),
+        None if consensus.len() <= width => (0..consensus.len()).collect(),
+        None => (0..width).map(|i| i * consensus.len() / width).collect(),
+    };
+
+    let mut overview = String::new();
+    let (first, last) = (
+        shown.first().map_or(0, |&c| c + 1),
+        shown.last().map_or(0, |&c| c + 1),
+    );
+    let _ = writeln!(
+        overview,
+        "{}columns {first}-{last}, {} of {} shown",
+        label(""),
+        shown.len(),
+        consensus.len()
+    );
+    let consensus_row: String = shown
+        .iter()
+        .map(|&column| printable(consensus[column]))
+        .collect();
+    let _ = writeln!(overview, "{}{consensus_row}", label("consensus"));
+
+    let num_rows = max_seqs.unwrap_or(usize::MAX);
+    for entry in inp_fasta.into_iter().take(num_rows) {
+        let sequence = entry.sequence();
+        let row: String = shown
+            .iter()
+            .map(|&column| overview_char(sequence[column], consensus[column]))
+            .collect();
+        let _ = writeln!(overview, "{}{row}", label(&entry.defline()));
+    }
+    let num_hidden = inp_fasta.num_entries().saturating_sub(num_rows);
+    if num_hidden > 0 {
+        let _ = writeln!(overview, "... {num_hidden} more sequence(s) not shown");
+    }
+    Ok(overview)
+}
+
+#[cfg(test)]
+mod test {
+    use super::render_overview;
+    use crate::fasta_manager::{Fasta, FastaEntry};
+
+    fn tiny_alignment() -> Fasta {
+        let mut fasta = Fasta::new("tiny.fna");
+        for (i, (defline, sequence)) in [
+            ("first", "ATGAAATAG"),
+            ("a_much_longer_defline_here", "ATGAnATAG"),
+            ("third", "ATG-CATAa"),
+            ("fourth", "ATGAAATAG"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            fasta.add(FastaEntry::new(
+                String::from(defline),
+                sequence.as_bytes().to_vec(),
+                i,
+            ));
+        }
+        fasta
+    }
+
+    #[test]
+    fn render_tiny_alignment() {
+        let overview = render_overview(&tiny_alignment(), None, 80, Some(3)).unwrap();
+        assert_eq!(
+            overview,
+            "                 columns 1-9, 9 of 9 shown\n\
+            consensus        ATGAAATAG\n\
+            first            .........\n\
+            a_much_longer_de ....n....\n\
+            third            ...-C...a\n\
+            ... 1 more sequence(s) not shown\n"
+        );
+
+        let sampled = render_overview(&tiny_alignment(), None, 3, None).unwrap();
+        assert!(sampled.starts_with("                 columns 1-7, 3 of 9 shown\n"));
+        assert!(sampled.contains("\nconsensus        AAT\n"));
+
+        let range = render_overview(&tiny_alignment(), Some(3..5), 80, None).unwrap();
+        assert!(range.contains("\nthird            -C\n"));
+        assert!(render_overview(&tiny_alignment(), Some(3..10), 80, None).is_err());
+    }
+}
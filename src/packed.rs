@@ -0,0 +1,123 @@
+use std::ops::Range;
+
+/// The 16 symbols that fit in a 4-bit code: the gap and the uppercase IUPAC
+/// nucleotide codes. A symbol's code is its position here.
+const CODES: &[u8; 16] = b"-ACGTRYSWKMBDHVN";
+
+/// Returns the 4-bit code of an uppercase symbol, or None if it has none
+fn encode(base: u8) -> Option<u8> {
+    CODES
+        .iter()
+        .position(|&code| code == base)
+        .and_then(|code| u8::try_from(code).ok())
+}
+
+/// A sequence stored at 4 bits per base. Lowercase bases are recorded as runs
+/// and any byte outside the IUPAC alphabet (e.g. '.', 'U', '*', or non-ASCII)
+/// is kept exactly in an escape list, so unpacking always reproduces the
+/// original bytes. Typical alignments pack to a little over half their size.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PackedSequence {
+    codes: Vec<u8>,
+    len: usize,
+    lowercase_runs: Vec<Range<usize>>,
+    escapes: Vec<(usize, u8)>,
+}
+
+impl PackedSequence {
+    /// Packs a sequence
+    pub(crate) fn pack(sequence: &[u8]) -> Self {
+        let mut codes = vec![0; sequence.len().div_ceil(2)];
+        let mut lowercase_runs: Vec<Range<usize>> = Vec::new();
+        let mut escapes = Vec::new();
+
+        for (i, &base) in sequence.iter().enumerate() {
+            if base.is_ascii_lowercase() {
+                match lowercase_runs.last_mut() {
+                    Some(run) if run.end == i => run.end += 1,
+                    _ => lowercase_runs.push(i..i + 1),
+                }
+            }
+            let code = encode(base.to_ascii_uppercase()).unwrap_or_else(|| {
+                escapes.push((i, base));
+                0
+            });
+            codes[i / 2] |= code << (4 * (i % 2));
+        }
+
+        codes.shrink_to_fit();
+        lowercase_runs.shrink_to_fit();
+        escapes.shrink_to_fit();
+        PackedSequence {
+            codes,
+            len: sequence.len(),
+            lowercase_runs,
+            escapes,
+        }
+    }
+
+    /// Returns the number of bases in the sequence
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Decodes the sequence back into its original bytes
+    pub(crate) fn unpack(&self) -> Vec<u8> {
+        let mut sequence: Vec<u8> = (0..self.len)
+            .map(|i| CODES[usize::from((self.codes[i / 2] >> (4 * (i % 2))) & 0xF)])
+            .collect();
+        for run in &self.lowercase_runs {
+            sequence[run.clone()].make_ascii_lowercase();
+        }
+        for &(i, base) in &self.escapes {
+            sequence[i] = base;
+        }
+        sequence
+    }
+
+    /// Returns the number of heap bytes used to store the sequence
+    pub(crate) fn heap_size(&self) -> usize {
+        self.codes.capacity()
+            + self.lowercase_runs.capacity() * size_of::<Range<usize>>()
+            + self.escapes.capacity() * size_of::<(usize, u8)>()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PackedSequence, CODES};
+
+    fn assert_round_trip(sequence: &[u8]) {
+        assert_eq!(PackedSequence::pack(sequence).unpack(), sequence);
+    }
+
+    #[test]
+    fn every_iupac_code_round_trips() {
+        assert_round_trip(CODES);
+        assert_round_trip(&CODES.to_ascii_lowercase());
+        assert_round_trip(b"ACGTURYSWKMBDHVN-.acgturyswkmbdhvn");
+    }
+
+    #[test]
+    fn mixed_case_and_odd_lengths() {
+        assert_round_trip(b"");
+        assert_round_trip(b"a");
+        assert_round_trip(b"AcGtNnR");
+        assert_round_trip(b"acgtACGTacgt--..");
+    }
+
+    #[test]
+    fn escapes_unknown_bytes() {
+        let sequence = b"ATG*X~ \xFFuU";
+        let packed = PackedSequence::pack(sequence);
+        assert_eq!(packed.unpack(), sequence);
+        assert_eq!(packed.len(), sequence.len());
+    }
+
+    #[test]
+    fn packs_to_about_half() {
+        let sequence = b"ACGT".repeat(500);
+        let packed = PackedSequence::pack(&sequence);
+        assert_eq!(packed.heap_size(), sequence.len() / 2);
+    }
+}
@@ -1,14 +1,69 @@
+use crate::fasta_manager::{CleaningPolicy, InputFormat, LineEnding, MoleculeType};
+use crate::filters::KeepDuplicate;
+use crate::limits::Limits;
+use crate::output::OutputFormat;
+use crate::split::SplitField;
 use clap::Parser;
-use std::path::Path;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The crate version followed by the short hash of the commit it was built
+/// from, or `unknown` if git wasn't available at build time
+const VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("GIT_COMMIT_HASH"),
+    ")"
+);
+
+/// The names of all modules recognized by clean-genes
+const KNOWN_MODULES: &[&str] = &[
+    "TrimToORF",
+    "AnnotateLength",
+    "DropEmpty",
+    "TransposeAlignment",
+    "FilterByField",
+    "ReverseComplement",
+    "DeflineTable",
+    "KmerCount",
+    "SelectBlocks",
+    "FilterGappy",
+    "FixFrames",
+    "Validate",
+    "ReplaceBase",
+    "FlagOutliers",
+    "ProteinStats",
+    "TrimSignalPeptide",
+    "TrimRegions",
+    "SplitByField",
+    "Dedup",
+    "BackTranslate",
+    "Degap",
+    "Regap",
+    "Screen",
+    "Overview",
+    "NearestNeighbor",
+    "Relabel",
+    "Rename",
+    "PolishToConsensus",
+    "placeholder",
+];
 
 /// Contains the parameters set by all user arguments into clean-genes
+// Each independent on/off flag is naturally a bool
+#[expect(clippy::struct_excessive_bools)]
 #[derive(Parser, Default, Debug)]
 #[command(
     name = "clean-genes",
     author = "David E. Hufnagel",
-    version = env!("CARGO_PKG_VERSION"),
+    version = VERSION,
     about = "A CLI tool for automatically cleaning up gene alignments by \n\
         trimming to ORG and identifying and/or removing problematic sequences",
+    after_help = "Every option can also be set with the CLEAN_GENES_ environment \n\
+        variable shown beside it, such as CLEAN_GENES_MODULE for --module. An \n\
+        option given on the command line overrides its variable.",
     help_template = "{before-help}\
         \n\nTool: {name}\
         \nDescription: {about-with-newline}\
@@ -20,17 +75,632 @@ use std::path::Path;
 )]
 pub struct Config {
     #[arg(short, long, help = "Input Fasta file",
-    value_parser = validate_filename)]
-    inp_fasta: String,
+    value_parser = |name: &str| validate_filename(Path::new(name)),
+    env = "CLEAN_GENES_INP_FASTA")]
+    inp_fasta: PathBuf,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_IN_FORMAT",
+        help = "Format of the input file. Guessed from the extension when \n\
+        omitted (.gb and .gbk are GenBank, .embl and .dat are EMBL, \n\
+        anything else is fasta)"
+    )]
+    in_format: Option<InputFormat>,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_MOLECULE_TYPE",
+        visible_alias = "alphabet",
+        help = "Kind of sequence in the input; protein input is checked \n\
+        for characters that are not amino acids",
+        default_value = "nucleotide"
+    )]
+    molecule_type: MoleculeType,
+
+    #[arg(short, long, help = "Output Fasta file, or '-' for stdout",
+        default_value = "-",
+    value_parser = validate_out_fasta,
+    env = "CLEAN_GENES_OUT_FASTA")]
+    out_fasta: PathBuf,
+
+    #[arg(long, help = "Formats to write in one run: fasta to the output path, \n\
+        and gff3, stats and phylip to the output path with its extension \n\
+        replaced by .gff3, .stats.tsv or .phy. PHYLIP names are cut to 10 \n\
+        characters and made unique, with a .phy.names.tsv map back to the \n\
+        deflines for Relabel",
+        num_args = 1..,
+        value_delimiter = ',',
+        default_value = "fasta",
+        env = "CLEAN_GENES_OUTPUT_FORMAT")]
+    output_format: Vec<OutputFormat>,
 
-    #[arg(short, long, help = "Output Fasta file", 
-        default_value_t = String::from("./output.fasta"),
-    value_parser = validate_out_fasta)]
-    out_fasta: String,
+    #[arg(
+        long,
+        help = "Create missing parent directories of the output files",
+        env = "CLEAN_GENES_CREATE_DIRS"
+    )]
+    create_dirs: bool,
 
-    #[arg(short, long, help = "The selected module(s)",
-    value_parser = validate_modules)]
+    #[arg(short, long, help = "The selected module(s). Several modules separated by \n\
+        commas run in order, each on the output of the last",
+    value_parser = validate_modules,
+    env = "CLEAN_GENES_MODULE")]
     module: String,
+
+    #[arg(long, help = "Write a run manifest (counts, parameters, output hash) to this file",
+    value_parser = validate_out_fasta,
+    env = "CLEAN_GENES_MANIFEST_OUT")]
+    manifest_out: Option<PathBuf>,
+
+    #[arg(long, help = "Write the per-module stats and parameters to this file instead \n\
+        of stderr. Opened directly, so a named pipe or /dev/fd/3 works",
+    value_parser = validate_report,
+    env = "CLEAN_GENES_REPORT")]
+    report: Option<PathBuf>,
+
+    #[arg(long, help = "Write entries removed by filtering modules to <REJECTS>.fna \n\
+        and a table of why to <REJECTS>.tsv",
+    value_parser = validate_out_fasta,
+    env = "CLEAN_GENES_REJECTS")]
+    rejects: Option<PathBuf>,
+
+    #[arg(long, help = "Write a TSV of quality measures for each input sequence \n\
+        (length, GC content, gap, N and ambiguity fractions)",
+    value_parser = validate_out_fasta,
+    env = "CLEAN_GENES_QUALITY_REPORT")]
+    quality_report: Option<PathBuf>,
+
+    #[arg(long, help = "Write a TSV of the amino acid composition of each input \n\
+        sequence, translating nucleotide input from its first base",
+    value_parser = validate_out_fasta,
+    env = "CLEAN_GENES_AA_COMPOSITION_OUT")]
+    aa_composition_out: Option<PathBuf>,
+
+    #[arg(long, help = "Write a TSV of the molecular weight in daltons of each \n\
+        input sequence, translating nucleotide input from its first base",
+    value_parser = validate_out_fasta,
+    env = "CLEAN_GENES_MOLECULAR_WEIGHT_OUT")]
+    molecular_weight_out: Option<PathBuf>,
+
+    #[arg(long, help = "Write a TSV of the isoelectric point of each input sequence, \n\
+        translating nucleotide input from its first base",
+    value_parser = validate_out_fasta,
+    env = "CLEAN_GENES_ISOELECTRIC_POINT_OUT")]
+    isoelectric_point_out: Option<PathBuf>,
+
+    #[arg(long, help = "Write a TSV of the Kyte-Doolittle hydrophobicity profile of \n\
+        each input sequence, translating nucleotide input from its first base",
+    value_parser = validate_out_fasta,
+    env = "CLEAN_GENES_HYDROPHOBICITY_OUT")]
+    hydrophobicity_out: Option<PathBuf>,
+
+    #[arg(long, help = "Number of residues averaged for each hydrophobicity score",
+        default_value_t = 9,
+    value_parser = validate_window_size,
+    env = "CLEAN_GENES_HYDROPHOBICITY_WINDOW")]
+    hydrophobicity_window: usize,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_CODON_TABLE_FILE",
+        help = "Read the genetic code from a file of CODON<TAB>AMINO lines; \n\
+        TrimToORF and FixFrames use its stop codons"
+    )]
+    codon_table_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Defline of the reference sequence in the input fasta",
+        env = "CLEAN_GENES_REFERENCE"
+    )]
+    reference: Option<String>,
+
+    #[arg(
+        long,
+        help = "Ignore case when matching the reference defline",
+        env = "CLEAN_GENES_IGNORE_ID_CASE"
+    )]
+    ignore_id_case: bool,
+
+    #[arg(long, help = "Key used by AnnotateLength when appending lengths to deflines",
+        default_value = "length",
+    value_parser = validate_length_key,
+    env = "CLEAN_GENES_LENGTH_KEY")]
+    length_key: String,
+
+    #[arg(long, help = "Warn when fewer than this fraction of sequences have their \n\
+        top start codon at the TrimToORF group start",
+    value_parser = validate_fraction,
+    env = "CLEAN_GENES_MIN_AGREEMENT")]
+    min_agreement: Option<f64>,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_START_COHERENCE_WINDOW",
+        help = "Weight each TrimToORF start codon by the number of start codons \n\
+        in all sequences within this many columns of it, so isolated starts count for less"
+    )]
+    start_coherence_window: Option<usize>,
+
+    #[arg(long, help = "Exit with status 3, after writing all output, if the \n\
+        TrimToORF confidence score is below this",
+    value_parser = validate_fraction,
+    env = "CLEAN_GENES_MIN_CONFIDENCE")]
+    min_confidence: Option<f64>,
+
+    #[arg(long, help = "Write a TSV of each sequence's own top start codon locus and \n\
+        whether it matches the TrimToORF group start",
+    value_parser = validate_out_fasta,
+    env = "CLEAN_GENES_START_REPORT")]
+    start_report: Option<PathBuf>,
+
+    #[arg(long, help = "Fail if more than this fraction of sequences have their top \n\
+        start codon away from the TrimToORF group start",
+    value_parser = validate_fraction,
+    env = "CLEAN_GENES_MAX_START_DISAGREEMENT")]
+    max_start_disagreement: Option<f64>,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_REJECT_START_DISAGREEMENT",
+        help = "Drop sequences whose top start codon is away from the \n\
+        TrimToORF group start, sending them to --rejects if given"
+    )]
+    reject_start_disagreement: bool,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_RESPECT_SOFTMASK",
+        help = "Leave start codons with lowercase (soft-masked) bases out of the \n\
+        TrimToORF group start vote"
+    )]
+    respect_softmask: bool,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_NORMALIZE_CODON_GAPS",
+        help = "After TrimToORF, slide and merge gaps inside the ORF so gap runs \n\
+        fill whole codons, reporting sequences with a true frameshift"
+    )]
+    normalize_codon_gaps: bool,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_REPORT_READTHROUGH",
+        help = "With TrimToORF, record each sequence's next in-frame stop codon \n\
+        past the group stop as next_stop and readthrough_bases metadata"
+    )]
+    report_readthrough: bool,
+
+    #[arg(long, help = "Use this reading frame (0, 1 or 2, counted from the first \n\
+        column) for TrimToORF instead of voting on start codons",
+    value_parser = validate_frame,
+    env = "CLEAN_GENES_FORCE_FRAME")]
+    force_frame: Option<usize>,
+
+    #[arg(long, help = "Fraction of sequences that must not be gapped at the group \n\
+        start column chosen by --force-frame",
+        default_value = "0.5",
+    value_parser = validate_fraction,
+    env = "CLEAN_GENES_FRAME_OCCUPANCY")]
+    frame_occupancy: f64,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_PER_SEQUENCE",
+        help = "Trim each sequence, degapped, to its own longest ORF with \n\
+        TrimToORF instead of to the group ORF of an alignment"
+    )]
+    per_sequence: bool,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_CIRCULAR",
+        help = "Treat sequences as circular with --per-sequence, so an ORF \n\
+        may wrap around the origin"
+    )]
+    circular: bool,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_PROTEIN_REFERENCE",
+        help = "Protein fasta whose first entry guides TrimToORF: the candidate \n\
+        ORF whose translation shares the most amino acid 3-mers with it is \n\
+        chosen, instead of voting on start codons",
+        value_parser = |name: &str| validate_filename(Path::new(name))
+    )]
+    protein_reference: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_SEGMENT_TABLE",
+        help = "TSV of known ORF boundaries as SEGMENT, START and STOP (alignment \n\
+        columns of the first base of the start and stop codons, from 1); with \n\
+        --segment, TrimToORF trims to them instead of inferring the ORF",
+        value_parser = |name: &str| validate_filename(Path::new(name))
+    )]
+    segment_table: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Segment of the input, as named in --segment-table",
+        env = "CLEAN_GENES_SEGMENT"
+    )]
+    segment: Option<String>,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_PROTEIN_ALIGNMENT",
+        help = "Aligned protein fasta that BackTranslate threads the input \n\
+        nucleotides through, matching entries by defline",
+        value_parser = |name: &str| validate_filename(Path::new(name))
+    )]
+    protein_alignment: Option<PathBuf>,
+
+    #[arg(long, help = "Write where Degap removed each sequence's gaps to this \n\
+        gap map, for Regap to restore them",
+    value_parser = validate_out_fasta,
+    env = "CLEAN_GENES_GAPMAP_OUT")]
+    gapmap_out: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_GAPMAP_IN",
+        help = "Gap map written by Degap's --gapmap-out that Regap restores \n\
+        the input's alignment from",
+        value_parser = |name: &str| validate_filename(Path::new(name))
+    )]
+    gapmap_in: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_NAME_MAP",
+        help = "Name map written beside a PHYLIP output that Relabel gives \n\
+        exported names their original deflines back from",
+        value_parser = |name: &str| validate_filename(Path::new(name))
+    )]
+    name_map: Option<PathBuf>,
+
+    #[arg(
+        long = "map",
+        env = "CLEAN_GENES_MAP",
+        help = "Two column OLD_NAME<TAB>NEW_NAME TSV that Rename applies, \n\
+        matching the whole defline or its first word",
+        value_parser = |name: &str| validate_filename(Path::new(name))
+    )]
+    rename_map: Option<PathBuf>,
+
+    #[arg(long, help = "Newick tree whose taxon labels Relabel also maps back \n\
+        to the original deflines",
+        value_parser = |name: &str| validate_filename(Path::new(name)),
+        env = "CLEAN_GENES_NEWICK")]
+    newick: Option<PathBuf>,
+
+    #[arg(long, help = "Write the tree relabeled from --newick to this file",
+    value_parser = validate_out_fasta,
+    env = "CLEAN_GENES_NEWICK_OUT")]
+    newick_out: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_STRICT",
+        help = "Treat warnings that signal a failed run, such as writing \n\
+        an output with no sequences or TrimToORF finding no stop codon in \n\
+        frame for some sequences, as errors"
+    )]
+    strict: bool,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_WARNINGS_AS_ERRORS",
+        help = "Make the Validate module fail on warnings as well as errors"
+    )]
+    warnings_as_errors: bool,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_DRY_RUN",
+        help = "Run the selected module and report what would be written \n\
+        without writing any files or creating any directories"
+    )]
+    dry_run: bool,
+
+    #[arg(
+        short,
+        long,
+        env = "CLEAN_GENES_QUIET",
+        help = "Don't print the table of per-module stats at the end of \n\
+        the run"
+    )]
+    quiet: bool,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_WRITE_FAI",
+        help = "Also write a samtools-style .fai index next to the \n\
+        output fasta"
+    )]
+    write_fai: bool,
+
+    #[arg(
+        long,
+        help = "Line ending for the output fasta",
+        default_value = "lf",
+        env = "CLEAN_GENES_LINE_ENDING"
+    )]
+    line_ending: LineEnding,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_LOW_MEMORY",
+        help = "Store sequences packed at about half the memory, at the cost \n\
+        of decoding them each time they are read"
+    )]
+    low_memory: bool,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_TOLERATE_FORMATTED",
+        help = "Strip spaces and position numbers from sequence lines, as in \n\
+        sequences pasted from a GenBank ORIGIN block or EMBOSS output"
+    )]
+    tolerate_formatted: bool,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_PROGRESS",
+        help = "Show the percent of the input fasta read so far on stderr, \n\
+        when stderr is a terminal"
+    )]
+    progress: bool,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_MAX_INPUT_BYTES",
+        help = "Reject an input file larger than this many bytes before \n\
+        parsing it"
+    )]
+    max_input_bytes: Option<u64>,
+
+    #[arg(long, help = "Stop with a timeout error once the run has taken longer \n\
+        than this many seconds; checked while reading, between \n\
+        modules, and per sequence in NearestNeighbor and TrimToORF",
+    value_parser = validate_seconds,
+    env = "CLEAN_GENES_MAX_RUNTIME_SECS")]
+    max_runtime_secs: Option<f64>,
+
+    #[arg(long, help = "Rewrite all gap characters ('-', '.', '~', space) in the \n\
+        output to one character, '-' unless another is given",
+        num_args = 0..=1,
+        default_missing_value = "-",
+    value_parser = validate_gap_char,
+    env = "CLEAN_GENES_NORMALIZE_GAPS")]
+    normalize_gaps: Option<u8>,
+
+    #[arg(
+        long,
+        help = "Uppercase every sequence in the output",
+        env = "CLEAN_GENES_UPPERCASE"
+    )]
+    uppercase: bool,
+
+    #[arg(long, help = "Rewrite every gap and N in the output to one missing data \n\
+        character, '?' unless another is given",
+        num_args = 0..=1,
+        default_missing_value = "?",
+    value_parser = validate_gap_char,
+    env = "CLEAN_GENES_RECODE_MISSING")]
+    recode_missing: Option<u8>,
+
+    #[arg(long, help = "Base that ReplaceBase replaces", value_parser = validate_base_char,
+        env = "CLEAN_GENES_FROM_BASE")]
+    from_base: Option<u8>,
+
+    #[arg(long, help = "Base that ReplaceBase writes in place of --from-base",
+    value_parser = validate_base_char,
+    env = "CLEAN_GENES_TO_BASE")]
+    to_base: Option<u8>,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_MATCH_CASE",
+        help = "Make ReplaceBase match --from-base case-sensitively and \n\
+        write --to-base as given, instead of replacing both cases with \n\
+        uppercase"
+    )]
+    match_case: bool,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_KEEP",
+        help = "Which occurrence of a duplicated defline Dedup keeps; kept \n\
+        sequences stay in input order either way",
+        default_value = "first"
+    )]
+    keep: KeepDuplicate,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_DEFLINE_DELIM",
+        help = "Delimiter used to split deflines into fields",
+        default_value = "|"
+    )]
+    defline_delim: String,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_DEFLINE_FIELDS",
+        help = "Comma-separated names for the defline fields, in order",
+        value_delimiter = ','
+    )]
+    defline_fields: Vec<String>,
+
+    #[arg(long, help = "Field filter for FilterByField as FIELD=VALUE, where FIELD \n\
+        is a name from --defline-fields or a field number starting at 0",
+    value_parser = validate_field_filter,
+    env = "CLEAN_GENES_FILTER_FIELD")]
+    filter_field: Option<(String, String)>,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_FILTER_SUBTYPE",
+        help = "Keep only sequences whose {TYPE_GENE_SUBTYPE} defline tag has \n\
+        this influenza subtype (e.g. H3), before any module runs"
+    )]
+    filter_subtype: Option<String>,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_EMIT_BOTH_STRANDS",
+        help = "With ReverseComplement, output each sequence forward and then \n\
+        reverse-complemented with an '_rc' defline suffix"
+    )]
+    emit_both_strands: bool,
+
+    #[arg(long, help = "Length of the k-mers counted by KmerCount",
+        default_value = "3",
+    value_parser = validate_kmer_size,
+    env = "CLEAN_GENES_KMER_SIZE")]
+    kmer_size: usize,
+
+    #[arg(
+        long,
+        help = "Only output the N most frequent k-mers from KmerCount",
+        env = "CLEAN_GENES_TOP"
+    )]
+    top: Option<usize>,
+
+    #[arg(long, help = "Alignment columns drawn in full by Overview, as START-END \n\
+        counted from 1; by default the whole alignment is sampled down to \n\
+        the terminal width",
+    value_parser = validate_column_range,
+    env = "CLEAN_GENES_OVERVIEW_COLUMNS")]
+    overview_columns: Option<Range<usize>>,
+
+    #[arg(
+        long,
+        help = "Only draw the first N sequences in Overview",
+        env = "CLEAN_GENES_MAX_SEQS"
+    )]
+    max_seqs: Option<usize>,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_REGIONS_FILE",
+        help = "TSV of regions for TrimRegions to cut, one per line as \n\
+        NAME, START, END (alignment columns from 1, inclusive) and STRAND (+ or -)"
+    )]
+    regions_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_OUTDIR",
+        visible_alias = "out-dir",
+        help = "Directory TrimRegions writes one <NAME>.fna per region into, \n\
+        or SplitByField writes one <VALUE>.fasta per field value into"
+    )]
+    outdir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_SPLIT_FIELD",
+        help = "Defline field SplitByField groups sequences by: type, gene or \n\
+        subtype from the {TYPE_GENE_SUBTYPE} tag, a name from \n\
+        --defline-fields, or a field number starting at 0"
+    )]
+    split_field: Option<String>,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_REGION_QC",
+        help = "With TrimRegions, report sequences in each region whose length \n\
+        is not a multiple of 3 or that have a premature stop codon"
+    )]
+    region_qc: bool,
+
+    #[arg(long, help = "Longest signal peptide TrimSignalPeptide searches for, in residues",
+        default_value = "30",
+    value_parser = validate_length,
+    env = "CLEAN_GENES_MAX_SIGNAL_PEPTIDE_LENGTH")]
+    max_signal_peptide_length: usize,
+
+    #[arg(long, help = "Minimum fraction of sequences without a gap for SelectBlocks \n\
+        to consider a column conserved",
+        default_value = "0.5",
+    value_parser = validate_fraction,
+    env = "CLEAN_GENES_MIN_OCCUPANCY")]
+    min_occupancy: f64,
+
+    #[arg(long, help = "Maximum fraction of gaps in a sequence kept by FilterGappy",
+        default_value = "0.5",
+    value_parser = validate_fraction,
+    env = "CLEAN_GENES_MAX_GAP_FRACTION")]
+    max_gap_fraction: f64,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_SCREEN_FASTA",
+        help = "Reference fasta of expected genes that Screen checks each \n\
+        sequence's k-mers against",
+        value_parser = |name: &str| validate_filename(Path::new(name))
+    )]
+    screen_fasta: Option<PathBuf>,
+
+    #[arg(long, help = "Length of the k-mers compared by Screen",
+        default_value = "15",
+    value_parser = validate_kmer_size,
+    env = "CLEAN_GENES_SCREEN_KMER_SIZE")]
+    screen_kmer_size: usize,
+
+    #[arg(long, help = "Minimum fraction of a sequence's k-mers, on either strand, \n\
+        found in --screen-fasta for Screen to keep it",
+        default_value = "0.1",
+    value_parser = validate_fraction,
+    env = "CLEAN_GENES_MIN_CONTAINMENT")]
+    min_containment: f64,
+
+    #[arg(long, help = "Minimum identity to the consensus of a sequence kept by \n\
+        FlagOutliers; sequences below it are outliers",
+        default_value = "0.9",
+    value_parser = validate_fraction,
+    env = "CLEAN_GENES_OUTLIER_IDENTITY")]
+    outlier_identity: f64,
+
+    #[arg(
+        long,
+        env = "CLEAN_GENES_CLEAN_POLICY",
+        help = "Characters FlagOutliers leaves out when comparing a sequence \n\
+        to the consensus: gaps only, gaps and Ns, or gaps, Ns and every \n\
+        other ambiguous or unknown base",
+        default_value = "gaps-only"
+    )]
+    clean_policy: CleaningPolicy,
+
+    #[arg(long, help = "Write the outliers found by FlagOutliers to this fasta \n\
+        for manual review",
+    value_parser = validate_out_fasta,
+    env = "CLEAN_GENES_OUTLIERS_OUT")]
+    outliers_out: Option<PathBuf>,
+
+    #[arg(long, help = "Minimum run of conserved columns kept by SelectBlocks",
+        default_value = "10",
+    value_parser = validate_length,
+    env = "CLEAN_GENES_MIN_BLOCK_LENGTH")]
+    min_block_length: usize,
+
+    #[arg(long, help = "Minimum fraction of a column's bases, not counting Ns, \n\
+        that must be the consensus base before PolishToConsensus corrects a base \n\
+        that disagrees",
+        default_value = "0.9",
+    value_parser = validate_fraction,
+    env = "CLEAN_GENES_POLISH_SUPPORT")]
+    polish_support: f64,
+
+    #[arg(long, help = "Write every base PolishToConsensus changes to this TSV",
+    value_parser = validate_out_fasta,
+    env = "CLEAN_GENES_POLISH_LOG")]
+    polish_log: Option<PathBuf>,
 }
 
 impl Config {
@@ -39,69 +709,763 @@ impl Config {
         &self.module
     }
 
-    /// Returns a reference the name of the input fasta file
-    pub(crate) fn inp_fasta(&self) -> &str {
+    /// Returns the chosen modules in the order they run
+    pub(crate) fn modules(&self) -> Vec<&str> {
+        self.module.split(',').collect()
+    }
+
+    /// Returns a reference the path of the input fasta file
+    pub(crate) fn inp_fasta(&self) -> &Path {
         &self.inp_fasta
     }
 
-    /// Returns a reference to the name of teh output fasta file
-    pub(crate) fn out_fasta(&self) -> &str {
+    /// Returns the kind of sequence in the input
+    pub(crate) fn molecule_type(&self) -> MoleculeType {
+        self.molecule_type
+    }
+
+    /// Returns the format of the input file, if it was given explicitly
+    pub(crate) fn in_format(&self) -> Option<InputFormat> {
+        self.in_format
+    }
+
+    /// Returns a reference to the path of the output fasta file. A path of
+    /// "-" means stdout.
+    pub(crate) fn out_fasta(&self) -> &Path {
         &self.out_fasta
     }
+
+    /// Returns each output format requested, in the order given
+    pub(crate) fn output_formats(&self) -> &[OutputFormat] {
+        &self.output_format
+    }
+
+    /// Confirms that the parent directory of every output file, and
+    /// `--outdir`, exists and is writable, first creating missing directories
+    /// if `--create-dirs` was given. Runs before the input is read, so a long
+    /// run can't fail only once it comes to write. Returns an error naming the
+    /// first missing or unwritable directory otherwise. A dry run touches
+    /// nothing: it skips the write check and returns the directories it would
+    /// have created instead of creating them.
+    pub(crate) fn prepare_out_dirs(&self) -> Result<Vec<PathBuf>, String> {
+        let out_paths = [
+            Some(self.out_fasta.as_path()),
+            self.manifest_out(),
+            self.report(),
+            self.rejects(),
+            self.outliers_out(),
+            self.polish_log(),
+            self.gapmap_out(),
+            self.newick_out(),
+            self.quality_report(),
+            self.aa_composition_out(),
+            self.molecular_weight_out(),
+            self.start_report(),
+            self.isoelectric_point_out(),
+            self.hydrophobicity_out(),
+        ];
+        let file_dirs = out_paths
+            .into_iter()
+            .flatten()
+            .filter(|&path| path != Path::new("-"))
+            .filter_map(|path| {
+                let parent = path.parent()?;
+                let parent = if parent.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    parent
+                };
+                Some((parent, format!("output file '{}'", path.display())))
+            });
+        let outdir = self.outdir().map(|dir| (dir, String::from("--outdir")));
+        let mut would_create = Vec::new();
+        for (dir, needed_by) in file_dirs.chain(outdir) {
+            if dir.is_dir() {
+                if !self.dry_run {
+                    check_writable(dir, &needed_by)?;
+                }
+                continue;
+            }
+            if self.create_dirs && self.dry_run {
+                if !would_create.iter().any(|created: &PathBuf| created == dir) {
+                    would_create.push(dir.to_path_buf());
+                }
+            } else if self.create_dirs {
+                fs::create_dir_all(dir).map_err(|err| {
+                    format!("Failed to create directory '{}': {err}", dir.display())
+                })?;
+            } else {
+                return Err(format!(
+                    "Directory '{}' for {needed_by} does not exist; create it or pass \
+                    --create-dirs",
+                    dir.display()
+                ));
+            }
+        }
+        Ok(would_create)
+    }
+
+    /// Returns the path prefix of the rejects files, if they were requested
+    pub(crate) fn rejects(&self) -> Option<&Path> {
+        self.rejects.as_deref()
+    }
+
+    /// Returns the path of the custom codon table file, if one was given
+    pub(crate) fn codon_table_file(&self) -> Option<&Path> {
+        self.codon_table_file.as_deref()
+    }
+
+    /// Returns the path of the quality report, if one was requested
+    pub(crate) fn quality_report(&self) -> Option<&Path> {
+        self.quality_report.as_deref()
+    }
+
+    /// Returns the path of the amino acid composition table, if one was
+    /// requested
+    pub(crate) fn aa_composition_out(&self) -> Option<&Path> {
+        self.aa_composition_out.as_deref()
+    }
+
+    /// Returns the path of the molecular weight table, if one was requested
+    pub(crate) fn molecular_weight_out(&self) -> Option<&Path> {
+        self.molecular_weight_out.as_deref()
+    }
+
+    /// Returns the path of the isoelectric point table, if one was requested
+    pub(crate) fn isoelectric_point_out(&self) -> Option<&Path> {
+        self.isoelectric_point_out.as_deref()
+    }
+
+    /// Returns the path of the hydrophobicity profile table, if one was
+    /// requested
+    pub(crate) fn hydrophobicity_out(&self) -> Option<&Path> {
+        self.hydrophobicity_out.as_deref()
+    }
+
+    /// Returns the number of residues in each hydrophobicity window
+    pub(crate) fn hydrophobicity_window(&self) -> usize {
+        self.hydrophobicity_window
+    }
+
+    /// Returns the path the stats report is written to, if one was given
+    pub(crate) fn report(&self) -> Option<&Path> {
+        self.report.as_deref()
+    }
+
+    /// Returns the path of the manifest file, if one was requested
+    pub(crate) fn manifest_out(&self) -> Option<&Path> {
+        self.manifest_out.as_deref()
+    }
+
+    /// Returns the defline of the reference sequence, if one was provided
+    pub(crate) fn reference(&self) -> Option<&str> {
+        self.reference.as_deref()
+    }
+
+    /// Returns whether case is ignored when matching the reference defline
+    pub(crate) fn ignore_id_case(&self) -> bool {
+        self.ignore_id_case
+    }
+
+    /// Returns the key used by `AnnotateLength` in annotated deflines
+    pub(crate) fn length_key(&self) -> &str {
+        &self.length_key
+    }
+
+    /// Returns whether `TrimToORF` moves gaps inside the trimmed ORF onto whole
+    /// codons
+    pub(crate) fn normalize_codon_gaps(&self) -> bool {
+        self.normalize_codon_gaps
+    }
+
+    /// Returns whether `TrimToORF` trims each sequence to its own ORF rather
+    /// than to the group ORF
+    pub(crate) fn per_sequence(&self) -> bool {
+        self.per_sequence
+    }
+
+    /// Returns whether sequences are treated as circular when searching for
+    /// ORFs one sequence at a time
+    pub(crate) fn circular(&self) -> bool {
+        self.circular
+    }
+
+    /// Returns the path of the reference protein that guides `TrimToORF`, if
+    /// one was given
+    pub(crate) fn protein_reference(&self) -> Option<&Path> {
+        self.protein_reference.as_deref()
+    }
+
+    /// Returns the path of the segment table of known ORF boundaries, if one
+    /// was given
+    pub(crate) fn segment_table(&self) -> Option<&Path> {
+        self.segment_table.as_deref()
+    }
+
+    /// Returns the path of the protein alignment `BackTranslate` follows, if
+    /// one was given
+    pub(crate) fn protein_alignment(&self) -> Option<&Path> {
+        self.protein_alignment.as_deref()
+    }
+
+    /// Returns the path `Degap` writes its gap map to, if one was given
+    pub(crate) fn gapmap_out(&self) -> Option<&Path> {
+        self.gapmap_out.as_deref()
+    }
+
+    /// Returns the path of the gap map `Regap` restores gaps from, if one was
+    /// given
+    pub(crate) fn gapmap_in(&self) -> Option<&Path> {
+        self.gapmap_in.as_deref()
+    }
+
+    /// Returns the path of the name map `Relabel` reads, if one was given
+    pub(crate) fn name_map(&self) -> Option<&Path> {
+        self.name_map.as_deref()
+    }
+
+    /// Returns the path of the rename map `Rename` applies, if one was given
+    pub(crate) fn rename_map(&self) -> Option<&Path> {
+        self.rename_map.as_deref()
+    }
+
+    /// Returns the path of the Newick tree `Relabel` relabels, if one was given
+    pub(crate) fn newick(&self) -> Option<&Path> {
+        self.newick.as_deref()
+    }
+
+    /// Returns the path the relabeled Newick tree is written to, if one was
+    /// given
+    pub(crate) fn newick_out(&self) -> Option<&Path> {
+        self.newick_out.as_deref()
+    }
+
+    /// Returns the segment name looked up in the segment table, if one was
+    /// given
+    pub(crate) fn segment(&self) -> Option<&str> {
+        self.segment.as_deref()
+    }
+
+    /// Returns the window within which start codons support each other in
+    /// the `TrimToORF` group start vote, if one was given
+    pub(crate) fn start_coherence_window(&self) -> Option<usize> {
+        self.start_coherence_window
+    }
+
+    /// Returns the minimum start codon agreement before `TrimToORF` warns
+    pub(crate) fn min_agreement(&self) -> Option<f64> {
+        self.min_agreement
+    }
+
+    /// Returns the path of the per-sequence start codon report, if one was
+    /// requested
+    pub(crate) fn start_report(&self) -> Option<&Path> {
+        self.start_report.as_deref()
+    }
+
+    /// Returns the largest fraction of sequences whose top start codon may
+    /// disagree with the `TrimToORF` group start before the run fails
+    pub(crate) fn max_start_disagreement(&self) -> Option<f64> {
+        self.max_start_disagreement
+    }
+
+    /// Returns whether `TrimToORF` drops sequences whose top start codon
+    /// disagrees with the group start
+    pub(crate) fn reject_start_disagreement(&self) -> bool {
+        self.reject_start_disagreement
+    }
+
+    /// Returns the minimum `TrimToORF` confidence score, below which the run
+    /// exits with a nonzero status
+    pub(crate) fn min_confidence(&self) -> Option<f64> {
+        self.min_confidence
+    }
+
+    /// Returns whether `ReverseComplement` outputs both strands of each sequence
+    pub(crate) fn emit_both_strands(&self) -> bool {
+        self.emit_both_strands
+    }
+
+    /// Returns the k-mer length used by `KmerCount`
+    pub(crate) fn kmer_size(&self) -> usize {
+        self.kmer_size
+    }
+
+    /// Returns the columns `Overview` draws in full, if a range was given
+    pub(crate) fn overview_columns(&self) -> Option<Range<usize>> {
+        self.overview_columns.clone()
+    }
+
+    /// Returns the most sequences `Overview` draws, if a limit was given
+    pub(crate) fn max_seqs(&self) -> Option<usize> {
+        self.max_seqs
+    }
+
+    /// Returns the path of the regions file for `TrimRegions`, if one was given
+    pub(crate) fn regions_file(&self) -> Option<&Path> {
+        self.regions_file.as_deref()
+    }
+
+    /// Returns the directory `TrimRegions` or `SplitByField` writes its fasta
+    /// files into, if one was given
+    pub(crate) fn outdir(&self) -> Option<&Path> {
+        self.outdir.as_deref()
+    }
+
+    /// Returns whether `TrimRegions` checks each region for ORF problems
+    pub(crate) fn region_qc(&self) -> bool {
+        self.region_qc
+    }
+
+    /// Returns the longest signal peptide `TrimSignalPeptide` searches for
+    pub(crate) fn max_signal_peptide_length(&self) -> usize {
+        self.max_signal_peptide_length
+    }
+
+    /// Returns the maximum number of k-mers output by `KmerCount`, if capped
+    pub(crate) fn top(&self) -> Option<usize> {
+        self.top
+    }
+
+    /// Returns the minimum non-gap fraction of a conserved `SelectBlocks` column
+    pub(crate) fn min_occupancy(&self) -> f64 {
+        self.min_occupancy
+    }
+
+    /// Returns the path of the references `Screen` checks against, if one was
+    /// given
+    pub(crate) fn screen_fasta(&self) -> Option<&Path> {
+        self.screen_fasta.as_deref()
+    }
+
+    /// Returns the k-mer length used by `Screen`
+    pub(crate) fn screen_kmer_size(&self) -> usize {
+        self.screen_kmer_size
+    }
+
+    /// Returns the minimum k-mer containment of a sequence kept by `Screen`
+    pub(crate) fn min_containment(&self) -> f64 {
+        self.min_containment
+    }
+
+    /// Returns the maximum gap fraction of a sequence kept by `FilterGappy`
+    pub(crate) fn max_gap_fraction(&self) -> f64 {
+        self.max_gap_fraction
+    }
+
+    /// Returns the minimum identity to the consensus kept by `FlagOutliers`
+    pub(crate) fn outlier_identity(&self) -> f64 {
+        self.outlier_identity
+    }
+
+    /// Returns which characters `FlagOutliers` leaves out of its comparisons
+    pub(crate) fn clean_policy(&self) -> CleaningPolicy {
+        self.clean_policy
+    }
+
+    /// Returns the path the outliers found by `FlagOutliers` are written to
+    pub(crate) fn outliers_out(&self) -> Option<&Path> {
+        self.outliers_out.as_deref()
+    }
+
+    /// Returns the minimum length of a block kept by `SelectBlocks`
+    pub(crate) fn min_block_length(&self) -> usize {
+        self.min_block_length
+    }
+
+    /// Returns the consensus support `PolishToConsensus` needs to correct a base
+    pub(crate) fn polish_support(&self) -> f64 {
+        self.polish_support
+    }
+
+    /// Returns the path `PolishToConsensus` logs its changes to
+    pub(crate) fn polish_log(&self) -> Option<&Path> {
+        self.polish_log.as_deref()
+    }
+
+    /// Returns whether `TrimToORF` reports each sequence's next stop codon past
+    /// the group stop
+    pub(crate) fn report_readthrough(&self) -> bool {
+        self.report_readthrough
+    }
+
+    /// Returns the reading frame `TrimToORF` is forced into, if one was given
+    pub(crate) fn force_frame(&self) -> Option<usize> {
+        self.force_frame
+    }
+
+    /// Returns the fraction of sequences that must not be gapped at a group
+    /// start chosen by `--force-frame`
+    pub(crate) fn frame_occupancy(&self) -> f64 {
+        self.frame_occupancy
+    }
+
+    /// Returns whether `TrimToORF` ignores soft-masked start codons
+    pub(crate) fn respect_softmask(&self) -> bool {
+        self.respect_softmask
+    }
+
+    /// Returns whether warnings that signal a failed run are treated as errors
+    pub(crate) fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Returns whether `Validate` treats warnings as errors
+    pub(crate) fn warnings_as_errors(&self) -> bool {
+        self.warnings_as_errors
+    }
+
+    /// Returns whether output files are reported rather than written
+    pub(crate) fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Returns whether the per-module stats table is suppressed
+    pub(crate) fn quiet(&self) -> bool {
+        self.quiet
+    }
+
+    /// Returns the line ending used in the output fasta
+    pub(crate) fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// Returns whether a .fai index is written alongside the output fasta
+    pub(crate) fn write_fai(&self) -> bool {
+        self.write_fai
+    }
+
+    /// Returns the character all output gaps are rewritten to, if requested
+    pub(crate) fn normalize_gaps(&self) -> Option<u8> {
+        self.normalize_gaps
+    }
+
+    /// Returns whether output sequences are uppercased
+    pub(crate) fn uppercase(&self) -> bool {
+        self.uppercase
+    }
+
+    /// Returns the character output gaps and Ns are recoded to, if requested
+    pub(crate) fn recode_missing(&self) -> Option<u8> {
+        self.recode_missing
+    }
+
+    /// Returns whether sequences are stored packed to save memory
+    pub(crate) fn low_memory(&self) -> bool {
+        self.low_memory
+    }
+
+    /// Returns whether reading progress is shown on stderr
+    pub(crate) fn progress(&self) -> bool {
+        self.progress
+    }
+
+    /// Returns the input size and runtime caps, with the runtime budget
+    /// starting now
+    pub(crate) fn limits(&self) -> Limits {
+        Limits::new(
+            self.max_input_bytes,
+            self.max_runtime_secs.map(Duration::from_secs_f64),
+        )
+    }
+
+    /// Returns whether spaces and digits are stripped from fasta sequence lines
+    pub(crate) fn tolerate_formatted(&self) -> bool {
+        self.tolerate_formatted
+    }
+
+    /// Returns the delimiter used to split deflines into fields
+    pub(crate) fn defline_delim(&self) -> &str {
+        &self.defline_delim
+    }
+
+    /// Returns the influenza subtype to keep, if filtering by subtype
+    pub(crate) fn filter_subtype(&self) -> Option<&str> {
+        self.filter_subtype.as_deref()
+    }
+
+    /// Returns the bases `ReplaceBase` replaces and writes, as (from, to)
+    pub(crate) fn replacement(&self) -> Result<(u8, u8), String> {
+        match (self.from_base, self.to_base) {
+            (Some(from), Some(to)) => Ok((from, to)),
+            _ => Err(String::from(
+                "ReplaceBase requires both --from-base and --to-base",
+            )),
+        }
+    }
+
+    /// Returns whether `ReplaceBase` matches case-sensitively
+    pub(crate) fn match_case(&self) -> bool {
+        self.match_case
+    }
+
+    /// Returns which occurrence of a duplicated defline `Dedup` keeps
+    pub(crate) fn keep(&self) -> KeepDuplicate {
+        self.keep
+    }
+
+    /// Returns the field filter as the field number and the value to keep.
+    /// Field names are resolved using `--defline-fields`.
+    pub(crate) fn filter_field(&self) -> Result<(usize, &str), String> {
+        let Some((field, value)) = &self.filter_field else {
+            return Err(String::from("FilterByField requires --filter-field"));
+        };
+
+        Ok((self.field_index(field)?, value))
+    }
+
+    /// Returns the field `SplitByField` groups by. The gene tag parts are
+    /// matched first, then names from `--defline-fields`, then field numbers.
+    pub(crate) fn split_field(&self) -> Result<SplitField, String> {
+        let Some(field) = &self.split_field else {
+            return Err(String::from("SplitByField requires --split-field"));
+        };
+
+        match field.as_str() {
+            "type" => Ok(SplitField::FluType),
+            "gene" => Ok(SplitField::Gene),
+            "subtype" => Ok(SplitField::Subtype),
+            _ => self.field_index(field).map(SplitField::Index),
+        }
+    }
+
+    /// Resolves a defline field given as a number starting at 0 or as a name
+    /// from `--defline-fields`
+    fn field_index(&self, field: &str) -> Result<usize, String> {
+        match field.parse::<usize>() {
+            Ok(index) => Ok(index),
+            Err(_) => self
+                .defline_fields
+                .iter()
+                .position(|name| name == field)
+                .ok_or_else(|| format!("'{field}' is not a name given in --defline-fields")),
+        }
+    }
 }
 
 /// Confirms that a filename was provided and exists
-fn validate_filename(name: &str) -> Result<String, String> {
-    if name.is_empty() {
+fn validate_filename(path: &Path) -> Result<PathBuf, String> {
+    if path.as_os_str().is_empty() {
         Err(String::from("Filename cannot be empty"))
-    } else if !Path::new(name).exists() {
+    } else if !path.exists() {
         Err(String::from("Filename does not exist"))
     } else {
-        Ok(name.to_string())
+        Ok(path.to_path_buf())
     }
 }
 
-/// Confirms that a module name was provided and is recognized by clean-genes
-fn validate_modules(module: &str) -> Result<String, String> {
-    if module.is_empty() {
-        Err(String::from("Module name cannot be empty"))
-    } else if !["TrimToORF", "placeholder"].contains(&module) {
-        Err(format!("'{module}' not a known module"))
-    } else {
-        Ok(module.to_string())
+/// Confirms that module names were provided and each is recognized by
+/// clean-genes
+fn validate_modules(modules: &str) -> Result<String, String> {
+    for module in modules.split(',') {
+        if module.is_empty() {
+            return Err(String::from("Module name cannot be empty"));
+        } else if !KNOWN_MODULES.contains(&module) {
+            return Err(format!("'{module}' not a known module"));
+        }
     }
+    Ok(modules.to_string())
 }
 
 /// Conifirms that an output filename was provided
-fn validate_out_fasta(name: &str) -> Result<String, String> {
+fn validate_out_fasta(name: &str) -> Result<PathBuf, String> {
     if name.is_empty() {
         Err(String::from("Filename cannot be empty"))
     } else {
-        Ok(name.to_string())
+        Ok(PathBuf::from(name))
+    }
+}
+
+/// Confirms that the report path is non-empty and not stdout, which the output
+/// fasta may be written to
+fn validate_report(name: &str) -> Result<PathBuf, String> {
+    if name == "-" {
+        Err(String::from(
+            "The report cannot go to stdout ('-'), where the output fasta may be written; \
+            use /dev/stderr or a path such as /dev/fd/3",
+        ))
+    } else {
+        validate_out_fasta(name)
+    }
+}
+
+/// Confirms that a defline annotation key is non-empty and contains no
+/// whitespace or '=' that would make the annotation ambiguous to parse
+fn validate_length_key(key: &str) -> Result<String, String> {
+    if key.is_empty() {
+        Err(String::from("Length key cannot be empty"))
+    } else if key.contains(|c: char| c.is_whitespace() || c == '=') {
+        Err(format!(
+            "Length key '{key}' cannot contain whitespace or '='"
+        ))
+    } else {
+        Ok(key.to_string())
+    }
+}
+
+/// Confirms that a value is a whole number of at least 1, naming what the
+/// value is, e.g. "K-mer size", in the error
+fn validate_count(value: &str, description: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!(
+            "{description} '{value}' must be a whole number of at least 1"
+        )),
+    }
+}
+
+/// Confirms that a k-mer size is a whole number of at least 1
+fn validate_kmer_size(value: &str) -> Result<usize, String> {
+    validate_count(value, "K-mer size")
+}
+
+/// Confirms that a sliding window size is a whole number of at least 1
+fn validate_window_size(value: &str) -> Result<usize, String> {
+    validate_count(value, "Window size")
+}
+
+/// Confirms that a length in columns or residues is a whole number of at
+/// least 1
+fn validate_length(value: &str) -> Result<usize, String> {
+    validate_count(value, "Length")
+}
+
+/// Confirms that a gap character is a single printable ASCII character that
+/// cannot start a defline
+fn validate_gap_char(value: &str) -> Result<u8, String> {
+    match value.as_bytes() {
+        [gap] if gap.is_ascii_graphic() && *gap != b'>' => Ok(*gap),
+        _ => Err(format!(
+            "Gap character '{value}' must be a single printable character other than '>'"
+        )),
+    }
+}
+
+/// Confirms that a base for `ReplaceBase` is a single printable character that
+/// cannot be mistaken for a defline
+fn validate_base_char(value: &str) -> Result<u8, String> {
+    match value.as_bytes() {
+        [base] if base.is_ascii_graphic() && *base != b'>' => Ok(*base),
+        _ => Err(format!(
+            "Base '{value}' must be a single printable character other than '>'"
+        )),
+    }
+}
+
+/// Parses a 1-based, inclusive START-END column range into a 0-based range
+fn validate_column_range(value: &str) -> Result<Range<usize>, String> {
+    let invalid = || format!("'{value}' is not a column range START-END with 1 <= START <= END");
+    let (start, end) = value.split_once('-').ok_or_else(invalid)?;
+    match (start.trim().parse::<usize>(), end.trim().parse::<usize>()) {
+        (Ok(start), Ok(end)) if start >= 1 && start <= end => Ok(start - 1..end),
+        _ => Err(invalid()),
+    }
+}
+
+/// Confirms that a value is a positive, finite number of seconds
+fn validate_seconds(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Ok(seconds),
+        _ => Err(format!("'{value}' is not a positive number of seconds")),
+    }
+}
+
+/// Confirms that files can be created in an existing directory by creating and
+/// removing an empty probe file
+fn check_writable(dir: &Path, needed_by: &str) -> Result<(), String> {
+    let probe = dir.join(format!(".clean-genes-write-check-{}", std::process::id()));
+    match fs::File::create(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            Ok(())
+        }
+        Err(err) => Err(format!(
+            "Directory '{}' for {needed_by} is not writable: {err}",
+            dir.display()
+        )),
+    }
+}
+
+/// Confirms that a value is a reading frame: 0, 1 or 2
+fn validate_frame(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(frame) if frame < 3 => Ok(frame),
+        _ => Err(format!("'{value}' is not a reading frame; use 0, 1 or 2")),
+    }
+}
+
+/// Confirms that a value is a number between 0 and 1
+fn validate_fraction(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(fraction),
+        _ => Err(format!("'{value}' is not a fraction between 0 and 1")),
+    }
+}
+
+/// Confirms that a field filter has the form FIELD=VALUE with a non-empty FIELD
+fn validate_field_filter(filter: &str) -> Result<(String, String), String> {
+    match filter.split_once('=') {
+        Some((field, value)) if !field.is_empty() => Ok((field.to_string(), value.to_string())),
+        _ => Err(format!("'{filter}' is not of the form FIELD=VALUE")),
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn module_from_env() {
+        // Every other test passes --module, so setting the variable here can't
+        // change what they parse
+        std::env::set_var("CLEAN_GENES_MODULE", "TrimToORF");
+        let from_env = Config::try_parse_from(["clean-genes", "-i", "fake_short.fna"]);
+        let overridden =
+            Config::try_parse_from(["clean-genes", "-i", "fake_short.fna", "-m", "DropEmpty"]);
+        std::env::remove_var("CLEAN_GENES_MODULE");
+        assert_eq!(from_env.unwrap().module(), "TrimToORF");
+        assert_eq!(overridden.unwrap().module(), "DropEmpty");
+
+        let help = Config::command().render_long_help().to_string();
+        assert!(help.contains("[env: CLEAN_GENES_MODULE=]"));
+        assert!(help.contains("CLEAN_GENES_INP_FASTA"));
+    }
+
+    #[test]
+    fn version_has_commit() {
+        let version = Config::command().render_version();
+        assert!(version.starts_with("clean-genes "));
+        assert!(version.contains('.'));
+        assert!(version.chars().any(|c| c.is_ascii_digit()));
+        assert!(version.trim_end().ends_with(')'));
+        assert!(VERSION.contains(" ("));
+    }
 
     #[test]
     fn good_filename() {
-        let test_name = "test_data/a_ha_h3_raw_500.fna";
+        let test_name = Path::new("test_data/a_ha_h3_raw_500.fna");
         let result = validate_filename(test_name);
-        assert_eq!(result, Ok("test_data/a_ha_h3_raw_500.fna".to_string()));
+        assert_eq!(result, Ok(PathBuf::from("test_data/a_ha_h3_raw_500.fna")));
+    }
+
+    #[test]
+    fn good_pathbuf() {
+        let test_path = PathBuf::from("test_data").join("a_ha_h3_raw_500.fna");
+        let result = validate_filename(&test_path);
+        assert_eq!(result, Ok(test_path));
     }
 
     #[test]
     fn bad_filename() {
-        let test_name = "notreal.fna";
+        let test_name = Path::new("notreal.fna");
         let result = validate_filename(test_name);
         assert_eq!(result, Err(String::from("Filename does not exist")));
     }
 
     #[test]
     fn no_filename() {
-        let test_name = "";
+        let test_name = Path::new("");
         let result = validate_filename(test_name);
         assert_eq!(result, Err(String::from("Filename cannot be empty")));
     }
@@ -113,6 +1477,16 @@ mod test {
         assert_eq!(result, Ok(test_module.to_string()));
     }
 
+    #[test]
+    fn chained_modules() {
+        assert_eq!(
+            validate_modules("DropEmpty,FilterGappy"),
+            Ok(String::from("DropEmpty,FilterGappy"))
+        );
+        assert!(validate_modules("DropEmpty,").is_err());
+        assert!(validate_modules("DropEmpty,NotAModule").is_err());
+    }
+
     #[test]
     fn bad_module() {
         let test_module = "NotAModule";
@@ -126,4 +1500,146 @@ mod test {
         let result = validate_modules(test_module);
         assert_eq!(result, Err(String::from("Module name cannot be empty")));
     }
+
+    #[test]
+    fn length_keys() {
+        assert_eq!(validate_length_key("length"), Ok(String::from("length")));
+        assert!(validate_length_key("").is_err());
+        assert!(validate_length_key("my length").is_err());
+        assert!(validate_length_key("len=").is_err());
+    }
+
+    #[test]
+    fn fractions() {
+        assert_eq!(validate_fraction("0.5"), Ok(0.5));
+        assert_eq!(validate_fraction("1"), Ok(1.0));
+        assert!(validate_fraction("1.5").is_err());
+        assert!(validate_fraction("half").is_err());
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(validate_kmer_size("2"), Ok(2));
+        assert_eq!(
+            validate_kmer_size("0"),
+            Err(String::from(
+                "K-mer size '0' must be a whole number of at least 1"
+            ))
+        );
+        assert!(validate_window_size("-1")
+            .unwrap_err()
+            .starts_with("Window size '-1'"));
+        assert!(validate_length("ten")
+            .unwrap_err()
+            .starts_with("Length 'ten'"));
+    }
+
+    #[test]
+    fn gap_chars() {
+        assert_eq!(validate_gap_char("-"), Ok(b'-'));
+        assert!(validate_gap_char("--").is_err());
+        assert!(validate_gap_char(">").is_err());
+        assert!(validate_gap_char(" ").is_err());
+    }
+
+    #[test]
+    fn field_filters() {
+        assert_eq!(
+            validate_field_filter("segment=4"),
+            Ok((String::from("segment"), String::from("4")))
+        );
+        assert!(validate_field_filter("segment").is_err());
+        assert!(validate_field_filter("=4").is_err());
+
+        let args = Config::parse_from([
+            "clean-genes",
+            "-i",
+            "fake_short.fna",
+            "-m",
+            "FilterByField",
+            "--defline-fields",
+            "accession,organism,segment",
+            "--filter-field",
+            "segment=4",
+        ]);
+        assert_eq!(args.filter_field(), Ok((2, "4")));
+    }
+
+    #[test]
+    fn missing_out_dir() {
+        let out_dir = std::env::temp_dir().join("clean_genes_nonexistent_dir");
+        let _ = fs::remove_dir_all(&out_dir);
+        let out_fasta = out_dir.join("output.fna");
+        let out_fasta = out_fasta.to_str().unwrap();
+        let base_args = [
+            "clean-genes",
+            "-i",
+            "fake_short.fna",
+            "-m",
+            "TrimToORF",
+            "-o",
+        ];
+
+        let args = Config::parse_from(base_args.into_iter().chain([out_fasta]));
+        let err = args.prepare_out_dirs().unwrap_err();
+        assert!(err.contains("--create-dirs"));
+        assert!(!out_dir.exists());
+
+        let args = Config::parse_from(base_args.into_iter().chain([
+            out_fasta,
+            "--create-dirs",
+            "--dry-run",
+        ]));
+        assert_eq!(args.prepare_out_dirs(), Ok(vec![out_dir.clone()]));
+        assert!(!out_dir.exists());
+
+        let args = Config::parse_from(base_args.into_iter().chain([out_fasta, "--create-dirs"]));
+        assert_eq!(args.prepare_out_dirs(), Ok(Vec::new()));
+        assert!(out_dir.is_dir());
+        fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[test]
+    fn unwritable_out_dir() {
+        let base_args = [
+            "clean-genes",
+            "-i",
+            "fake_short.fna",
+            "-m",
+            "SplitByField",
+            "--split-field",
+            "1",
+            "--outdir",
+        ];
+
+        // A directory that can't be created because its parent is a file
+        let args = Config::parse_from(
+            base_args
+                .into_iter()
+                .chain(["fake_short.fna/split", "--create-dirs"]),
+        );
+        let err = args.prepare_out_dirs().unwrap_err();
+        assert!(err.starts_with("Failed to create directory 'fake_short.fna/split'"));
+
+        // procfs refuses new files even to root, which ignores permission bits
+        if Path::new("/proc").is_dir() {
+            let args = Config::parse_from(base_args.into_iter().chain(["/proc"]));
+            let err = args.prepare_out_dirs().unwrap_err();
+            assert!(err.starts_with("Directory '/proc' for --outdir is not writable"));
+        }
+    }
+
+    #[test]
+    fn out_in_current_dir() {
+        let args = Config::parse_from([
+            "clean-genes",
+            "-i",
+            "fake_short.fna",
+            "-m",
+            "TrimToORF",
+            "-o",
+            "output.fna",
+        ]);
+        assert_eq!(args.prepare_out_dirs(), Ok(Vec::new()));
+    }
 }
@@ -0,0 +1,75 @@
+use std::io::{self, Read};
+
+/// Wraps a reader to report on stderr what percent of its `total_bytes` has
+/// been read. The percent is reprinted in place each time it changes, and the
+/// line is finished once the end of the input is reached. With no total the
+/// reader only passes bytes through.
+pub(crate) struct ProgressReader<R> {
+    inner: R,
+    label: String,
+    total_bytes: Option<u64>,
+    bytes_read: u64,
+    last_percent: Option<u64>,
+}
+
+impl<R: Read> ProgressReader<R> {
+    /// Constructor for `ProgressReader`. `label` names the input in the
+    /// progress line.
+    pub(crate) fn new(inner: R, label: impl Into<String>, total_bytes: Option<u64>) -> Self {
+        ProgressReader {
+            inner,
+            label: label.into(),
+            total_bytes,
+            bytes_read: 0,
+            last_percent: None,
+        }
+    }
+
+    /// Returns the number of bytes read so far
+    pub(crate) fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Prints the percent read if it has changed since it was last printed
+    fn report(&mut self, finished: bool) {
+        let Some(total_bytes) = self.total_bytes.filter(|&total| total > 0) else {
+            return;
+        };
+        let percent = (self.bytes_read * 100 / total_bytes).min(100);
+        if self.last_percent != Some(percent) {
+            eprint!("\rReading '{}': {percent:>3}%", self.label);
+            self.last_percent = Some(percent);
+        }
+        if finished {
+            eprintln!();
+            self.total_bytes = None;
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let num_read = self.inner.read(buf)?;
+        self.bytes_read += num_read as u64;
+        self.report(num_read == 0 && !buf.is_empty());
+        Ok(num_read)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ProgressReader;
+    use std::io::Read;
+
+    #[test]
+    fn passes_bytes_through() {
+        let input: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        for total_bytes in [None, Some(10_000)] {
+            let mut reader = ProgressReader::new(&input[..], "test", total_bytes);
+            let mut output = Vec::new();
+            reader.read_to_end(&mut output).unwrap();
+            assert_eq!(output, input);
+            assert_eq!(reader.bytes_read(), 10_000);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use crate::fasta_manager::{is_gap, Fasta};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Summary statistics of a protein Fasta, as reported by `ProteinStats`
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ProteinStats {
+    lengths: Vec<usize>,
+    residue_counts: BTreeMap<u8, usize>,
+    internal_stops: usize,
+    ambiguous: usize,
+}
+
+impl ProteinStats {
+    /// Returns the number of times each residue appears across all sequences,
+    /// keyed in upper case. Gaps and stops are not counted.
+    pub(crate) fn residue_counts(&self) -> &BTreeMap<u8, usize> {
+        &self.residue_counts
+    }
+
+    /// Returns the number of sequences with a stop ('*') before their last
+    /// residue
+    pub(crate) fn internal_stops(&self) -> usize {
+        self.internal_stops
+    }
+
+    /// Returns the number of sequences with at least one ambiguous residue (X)
+    pub(crate) fn ambiguous(&self) -> usize {
+        self.ambiguous
+    }
+}
+
+/// Gathers the `ProteinStats` of a protein Fasta. A sequence's length is its
+/// number of residues, not counting gaps or a final stop.
+pub(crate) fn protein_stats(inp_fasta: &Fasta) -> ProteinStats {
+    let mut stats = ProteinStats::default();
+    for entry in inp_fasta {
+        let sequence = entry.sequence();
+        let mut residues: Vec<u8> = sequence
+            .iter()
+            .filter(|&&residue| !is_gap(residue))
+            .map(u8::to_ascii_uppercase)
+            .collect();
+        if residues.last() == Some(&b'*') {
+            residues.pop();
+        }
+
+        if residues.contains(&b'*') {
+            stats.internal_stops += 1;
+        }
+        if residues.contains(&b'X') {
+            stats.ambiguous += 1;
+        }
+        for &residue in residues.iter().filter(|&&residue| residue != b'*') {
+            *stats.residue_counts.entry(residue).or_default() += 1;
+        }
+        stats
+            .lengths
+            .push(residues.iter().filter(|&&residue| residue != b'*').count());
+    }
+    stats
+}
+
+/// The main function of the `ProteinStats` module. Builds a two column TSV of
+/// measure and value: the number of sequences, the shortest, mean and longest
+/// length, the number of sequences with internal stops or ambiguous residues,
+/// then the count and fraction of each residue found, in alphabetical order.
+pub(crate) fn protein_stats_table(inp_fasta: &Fasta) -> String {
+    let stats = protein_stats(inp_fasta);
+    let total_residues: usize = stats.residue_counts.values().sum();
+    #[expect(clippy::cast_precision_loss)]
+    let mean_length = total_residues as f64 / stats.lengths.len().max(1) as f64;
+
+    let mut table = String::from("measure\tvalue\n");
+    let _ = writeln!(table, "sequences\t{}", stats.lengths.len());
+    let _ = writeln!(
+        table,
+        "min_length\t{}",
+        stats.lengths.iter().min().unwrap_or(&0)
+    );
+    let _ = writeln!(table, "mean_length\t{mean_length:.2}");
+    let _ = writeln!(
+        table,
+        "max_length\t{}",
+        stats.lengths.iter().max().unwrap_or(&0)
+    );
+    let _ = writeln!(table, "internal_stops\t{}", stats.internal_stops);
+    let _ = writeln!(table, "ambiguous\t{}", stats.ambiguous);
+    for (&residue, &count) in &stats.residue_counts {
+        #[expect(clippy::cast_precision_loss)]
+        let fraction = count as f64 / total_residues as f64;
+        let residue = char::from(residue);
+        let _ = writeln!(table, "count_{residue}\t{count}");
+        let _ = writeln!(table, "fraction_{residue}\t{fraction:.4}");
+    }
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::{protein_stats, protein_stats_table};
+    use crate::fasta_manager::{Fasta, FastaEntry};
+
+    #[test]
+    fn counts_and_stops() {
+        let mut fasta = Fasta::new("proteins.faa");
+        for (i, sequence) in ["MKA-A*", "MK*AX", "mxa*"].into_iter().enumerate() {
+            fasta.add(FastaEntry::new(
+                i.to_string(),
+                sequence.as_bytes().to_vec(),
+                i,
+            ));
+        }
+
+        let stats = protein_stats(&fasta);
+        let counts: Vec<(char, usize)> = stats
+            .residue_counts()
+            .iter()
+            .map(|(&residue, &count)| (char::from(residue), count))
+            .collect();
+        assert_eq!(counts, [('A', 4), ('K', 2), ('M', 3), ('X', 2)]);
+        assert_eq!(stats.internal_stops(), 1);
+        assert_eq!(stats.ambiguous(), 2);
+
+        let table = protein_stats_table(&fasta);
+        let rows: Vec<&str> = table.lines().collect();
+        assert_eq!(
+            rows[..7],
+            [
+                "measure\tvalue",
+                "sequences\t3",
+                "min_length\t3",
+                "mean_length\t3.67",
+                "max_length\t4",
+                "internal_stops\t1",
+                "ambiguous\t2"
+            ]
+        );
+        assert_eq!(rows[7..9], ["count_A\t4", "fraction_A\t0.3636"]);
+    }
+}
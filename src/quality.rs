@@ -0,0 +1,115 @@
+use crate::fasta_manager::{is_gap, write_atomically, Fasta, SequenceOps};
+use crate::filters::gap_fraction;
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+
+/// Quality measures for one entry, as one row of the quality report
+#[derive(Debug, PartialEq)]
+pub(crate) struct SequenceQualityRecord {
+    pub(crate) defline: String,
+    pub(crate) length: usize,
+    pub(crate) ungapped_length: usize,
+    pub(crate) gc_content: Option<f64>,
+    pub(crate) gap_fraction: f64,
+    pub(crate) n_fraction: f64,
+    pub(crate) ambiguity_fraction: f64,
+    pub(crate) is_all_gaps: bool,
+}
+
+/// Returns `count` as a fraction of `total`, or 0 if `total` is 0
+fn fraction_of(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    #[expect(clippy::cast_precision_loss)]
+    let fraction = count as f64 / total as f64;
+    fraction
+}
+
+/// Measures the quality of every entry in a Fasta. GC content is None for
+/// entries without any A, C, G, or T bases. The N and ambiguity fractions are
+/// of the ungapped length, and the ambiguity fraction counts N as well.
+pub(crate) fn sequence_quality_report(fasta: &Fasta) -> Vec<SequenceQualityRecord> {
+    fasta
+        .into_iter()
+        .map(|entry| {
+            let sequence = entry.sequence();
+            let ungapped_length = sequence.iter().filter(|&&base| !is_gap(base)).count();
+            let num_n = sequence
+                .iter()
+                .filter(|base| base.eq_ignore_ascii_case(&b'N'))
+                .count();
+            SequenceQualityRecord {
+                defline: entry.defline(),
+                length: sequence.len(),
+                ungapped_length,
+                gc_content: sequence.gc_fraction(),
+                gap_fraction: gap_fraction(&sequence),
+                n_fraction: fraction_of(num_n, ungapped_length),
+                ambiguity_fraction: fraction_of(sequence.count_ambiguous(), ungapped_length),
+                is_all_gaps: entry.is_all_gaps(),
+            }
+        })
+        .collect()
+}
+
+/// Writes the quality report as a TSV with a header row. Fractions are given
+/// to 4 decimal places, and a missing GC content is left empty.
+pub(crate) fn write_quality_report_tsv(
+    records: &[SequenceQualityRecord],
+    path: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    write_atomically(path.as_ref(), |writer| {
+        writeln!(
+            writer,
+            "defline\tlength\tungapped_length\tgc_content\tgap_fraction\tn_fraction\t\
+            ambiguity_fraction\tis_all_gaps"
+        )?;
+        for record in records {
+            let gc_content = record
+                .gc_content
+                .map(|gc| format!("{gc:.4}"))
+                .unwrap_or_default();
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{gc_content}\t{:.4}\t{:.4}\t{:.4}\t{}",
+                record.defline,
+                record.length,
+                record.ungapped_length,
+                record.gap_fraction,
+                record.n_fraction,
+                record.ambiguity_fraction,
+                record.is_all_gaps
+            )?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sequence_quality_report, write_quality_report_tsv};
+    use crate::fasta_manager::open_fasta;
+
+    #[test]
+    fn h3_report() {
+        let fasta = open_fasta("test_data/a_ha_h3_raw_500.fna").unwrap();
+        let records = sequence_quality_report(&fasta);
+        let path = std::env::temp_dir().join("clean_genes_quality_report.tsv");
+        write_quality_report_tsv(&records, &path).unwrap();
+
+        let report = std::fs::read_to_string(&path).unwrap();
+        let mut lines = report.lines();
+        assert!(lines.next().unwrap().starts_with("defline\tlength\t"));
+        let rows: Vec<Vec<&str>> = lines.map(|line| line.split('\t').collect()).collect();
+        assert_eq!(rows.len(), 17);
+        for row in rows {
+            let gc_content: f64 = row[3].parse().unwrap();
+            let gap_fraction: f64 = row[4].parse().unwrap();
+            assert!((0.0..=1.0).contains(&gc_content));
+            assert!((0.0..=1.0).contains(&gap_fraction));
+        }
+    }
+}
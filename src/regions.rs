@@ -0,0 +1,185 @@
+use crate::alignment::alignment_length;
+use crate::fasta_manager::{Fasta, FastaEntry, SequenceOps};
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+/// One region of an alignment to cut out, as read from a regions file
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Region {
+    name: String,
+    columns: Range<usize>,
+    reverse: bool,
+}
+
+impl Region {
+    /// Returns the name of the region, which also names its output file
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// What the optional ORF QC found in the entries cut from one region
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct RegionQc {
+    /// Entries whose ungapped length is not a multiple of 3
+    pub(crate) not_mod3: Vec<String>,
+    /// Entries with a stop codon in frame before their last codon
+    pub(crate) premature_stop: Vec<String>,
+}
+
+/// Reads a regions file: one region per line as NAME, START, END and STRAND
+/// separated by tabs, with START and END counted from 1 and inclusive, and
+/// STRAND '+' or '-'. Blank lines, lines starting with '#' and a header line
+/// starting with "name" are skipped. Names must be unique and usable as file
+/// names.
+pub(crate) fn read_regions(path: impl AsRef<Path>) -> Result<Vec<Region>, io::Error> {
+    let contents = fs::read_to_string(path)?;
+    let invalid = |line_num: usize, problem: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line {line_num} of the regions file {problem}"),
+        )
+    };
+
+    let mut regions: Vec<Region> = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || (i == 0 && line.starts_with("name\t")) {
+            continue;
+        }
+        let &[name, start, end, strand] = line.split('\t').collect::<Vec<_>>().as_slice() else {
+            return Err(invalid(i + 1, "is not NAME<TAB>START<TAB>END<TAB>STRAND"));
+        };
+        if name.is_empty() || name.contains(['/', '\\']) {
+            return Err(invalid(i + 1, &format!("has an invalid name, '{name}'")));
+        }
+        if regions.iter().any(|region| region.name == name) {
+            return Err(invalid(i + 1, &format!("repeats the region '{name}'")));
+        }
+        let (Ok(start), Ok(end)) = (start.parse::<usize>(), end.parse::<usize>()) else {
+            return Err(invalid(i + 1, "needs whole-number START and END"));
+        };
+        if start == 0 || end < start {
+            return Err(invalid(i + 1, "needs 1 <= START <= END"));
+        }
+        let reverse = match strand {
+            "+" => false,
+            "-" => true,
+            _ => {
+                return Err(invalid(
+                    i + 1,
+                    &format!("has an invalid strand, '{strand}'"),
+                ))
+            }
+        };
+        regions.push(Region {
+            name: String::from(name),
+            columns: start - 1..end,
+            reverse,
+        });
+    }
+    Ok(regions)
+}
+
+/// The main function of the `TrimRegions` module. Cuts each region from every
+/// entry of the alignment, reverse complementing regions on the '-' strand,
+/// and returns one Fasta per region, named `<name>.fna` in `out_dir`. Regions
+/// may overlap. A region that runs past the end of the alignment is an error
+/// naming it.
+pub(crate) fn trim_regions(
+    inp_fasta: &Fasta,
+    regions: &[Region],
+    out_dir: impl AsRef<Path>,
+) -> Result<Vec<Fasta>, String> {
+    let aln_length = alignment_length(inp_fasta).map_err(|err| err.to_string())?;
+    if let Some(region) = regions
+        .iter()
+        .find(|region| region.columns.end > aln_length)
+    {
+        return Err(format!(
+            "Region '{}' ends at column {}, past the end of the alignment at column {aln_length}",
+            region.name, region.columns.end
+        ));
+    }
+
+    let region_fastas = regions
+        .iter()
+        .map(|region| {
+            let mut region_fasta =
+                Fasta::new(out_dir.as_ref().join(format!("{}.fna", region.name)));
+            for entry in inp_fasta {
+                let cut = &entry.sequence()[region.columns.clone()];
+                let sequence = if region.reverse {
+                    cut.reverse_complement()
+                } else {
+                    cut.to_vec()
+                };
+                region_fasta.add(
+                    FastaEntry::new(entry.defline(), sequence, entry.entry_num())
+                        .with_meta_from(entry),
+                );
+            }
+            region_fasta
+        })
+        .collect();
+    Ok(region_fastas)
+}
+
+/// Checks each entry of a region, ungapped, for a length that is not a
+/// multiple of 3 and for a stop codon before its last codon
+pub(crate) fn region_qc(region_fasta: &Fasta, stop_codons: &[[u8; 3]]) -> RegionQc {
+    let mut qc = RegionQc::default();
+    for entry in region_fasta {
+        let bases = entry.sequence().degapped();
+        if !bases.len().is_multiple_of(3) {
+            qc.not_mod3.push(entry.defline());
+        }
+        let codons: Vec<&[u8]> = bases.chunks_exact(3).collect();
+        if codons
+            .iter()
+            .take(codons.len().saturating_sub(1))
+            .any(|codon| stop_codons.iter().any(|stop| codon.same_bases(stop)))
+        {
+            qc.premature_stop.push(entry.defline());
+        }
+    }
+    qc
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_regions, region_qc, trim_regions};
+    use crate::fasta_manager::open_fasta;
+    use std::path::Path;
+
+    #[test]
+    fn cut_two_regions() {
+        let path = std::env::temp_dir().join("clean_genes_regions.tsv");
+        std::fs::write(
+            &path,
+            "name\tstart\tend\tstrand\norf\t3\t11\t+\nhead\t1\t5\t-\n",
+        )
+        .unwrap();
+        let regions = read_regions(&path).unwrap();
+        assert_eq!(regions.len(), 2);
+
+        let fasta = open_fasta("fake_short.fna").unwrap();
+        let region_fastas = trim_regions(&fasta, &regions, "out").unwrap();
+        let (orf, head) = (&region_fastas[0], &region_fastas[1]);
+        assert_eq!(Path::new(orf.filename()), Path::new("out/orf.fna"));
+        assert_eq!(orf.num_entries(), 9);
+        assert_eq!(&*orf.indexed_entry(0).sequence(), b"ATGATGTAG");
+        assert_eq!(&*orf.indexed_entry(1).sequence(), b"ATGTGATAA");
+        assert_eq!(&*head.indexed_entry(1).sequence(), b"CATCC");
+
+        let qc = region_qc(orf, &[*b"TAG", *b"TGA", *b"TAA"]);
+        assert_eq!(qc.premature_stop, ["second"]);
+        assert_eq!(qc.not_mod3, ["third", "different"]);
+
+        std::fs::write(&path, "past_end\t10\t14\t+\n").unwrap();
+        let err = trim_regions(&fasta, &read_regions(&path).unwrap(), "out").unwrap_err();
+        assert!(err.starts_with("Region 'past_end' ends at column 14"));
+    }
+}
@@ -0,0 +1,470 @@
+use crate::fasta_manager::{write_atomically, Fasta, FastaEntry};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// The most characters strict PHYLIP allows in a name
+pub(crate) const PHYLIP_NAME_LENGTH: usize = 10;
+
+/// The first line of a name map file
+const HEADER: &str = "# exported_name\toriginal_defline";
+
+/// Characters that cannot appear in an exported name, since tree formats use
+/// them as delimiters
+const RESERVED: &str = "()[]':;,";
+
+/// Returns a name of at most `max_length` characters for each defline, unique
+/// across the list. Whitespace, non-ASCII and tree delimiter characters become
+/// '_' and the name is truncated. A name already taken is truncated further to
+/// make room for a `_N` suffix, with N counting up from 1 until it is unique.
+/// The same deflines in the same order always get the same names.
+pub(crate) fn export_names(deflines: &[String], max_length: usize) -> Vec<String> {
+    let mut taken: HashSet<String> = HashSet::new();
+    let mut names = Vec::with_capacity(deflines.len());
+    for defline in deflines {
+        let cleaned: Vec<char> = defline
+            .chars()
+            .map(|c| {
+                if c.is_ascii_graphic() && !RESERVED.contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let mut name: String = cleaned.iter().take(max_length).collect();
+        let mut counter = 1;
+        while name.is_empty() || taken.contains(&name) {
+            let suffix = format!("_{counter}");
+            name = cleaned
+                .iter()
+                .take(max_length.saturating_sub(suffix.len()))
+                .collect();
+            name.push_str(&suffix);
+            counter += 1;
+        }
+        taken.insert(name.clone());
+        names.push(name);
+    }
+    names
+}
+
+/// Writes a name map file: a '#' header, then one
+/// `EXPORTED_NAME<TAB>ORIGINAL_DEFLINE` line per entry, in entry order
+pub(crate) fn write_name_map(
+    names: &[String],
+    deflines: &[String],
+    path: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    write_atomically(path.as_ref(), |writer| {
+        writeln!(writer, "{HEADER}")?;
+        for (name, defline) in names.iter().zip(deflines) {
+            writeln!(writer, "{name}\t{defline}")?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Reads a name map file written by `write_name_map` into a map from exported
+/// name to original defline. A repeated exported name is an error.
+pub(crate) fn read_name_map(path: impl AsRef<Path>) -> Result<HashMap<String, String>, io::Error> {
+    let contents = fs::read_to_string(path)?;
+    let mut names = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |problem: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {} of the name map {problem}", i + 1),
+            )
+        };
+        let Some((name, defline)) = line.split_once('\t') else {
+            return Err(invalid(String::from(
+                "is not EXPORTED_NAME<TAB>ORIGINAL_DEFLINE",
+            )));
+        };
+        if names
+            .insert(String::from(name), String::from(defline))
+            .is_some()
+        {
+            return Err(invalid(format!("repeats the name '{name}'")));
+        }
+    }
+    Ok(names)
+}
+
+/// The main function of the `Relabel` module. Gives each entry whose defline is
+/// an exported name in `names` back its original defline, keeping its sequence
+/// and metadata. Other entries are left as they are, so relabeling twice is the
+/// same as relabeling once. Returns the relabeled Fasta and the number of
+/// entries renamed.
+pub(crate) fn relabel_fasta(
+    inp_fasta: &Fasta,
+    names: &HashMap<String, String>,
+    out_fasta: impl AsRef<Path>,
+) -> (Fasta, usize) {
+    let mut relabeled_fasta = Fasta::new(out_fasta);
+    let mut num_renamed = 0;
+    for entry in inp_fasta {
+        let defline = match names.get(&entry.defline()) {
+            Some(original) => {
+                num_renamed += 1;
+                original.clone()
+            }
+            None => entry.defline(),
+        };
+        relabeled_fasta.add(
+            FastaEntry::new(defline, entry.sequence().into_owned(), entry.entry_num())
+                .with_meta_from(entry),
+        );
+    }
+    (relabeled_fasta, num_renamed)
+}
+
+/// Reads a two column `OLD_NAME<TAB>NEW_NAME` rename map, skipping blank and
+/// '#' lines, keeping the file's order. An old name given twice, or a new name
+/// given to two old names, which would duplicate deflines, is an error.
+pub(crate) fn read_rename_map(path: impl AsRef<Path>) -> Result<Vec<(String, String)>, io::Error> {
+    let contents = fs::read_to_string(path)?;
+    let mut renames = Vec::new();
+    let mut old_names: HashSet<String> = HashSet::new();
+    let mut new_names: HashSet<String> = HashSet::new();
+    for (i, line) in contents.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |problem: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {} of the rename map {problem}", i + 1),
+            )
+        };
+        let Some((old_name, new_name)) = line.split_once('\t') else {
+            return Err(invalid(String::from("is not OLD_NAME<TAB>NEW_NAME")));
+        };
+        if !old_names.insert(String::from(old_name)) {
+            return Err(invalid(format!("renames '{old_name}' a second time")));
+        }
+        if !new_names.insert(String::from(new_name)) {
+            return Err(invalid(format!(
+                "gives '{new_name}' to a second name, which would duplicate deflines"
+            )));
+        }
+        renames.push((String::from(old_name), String::from(new_name)));
+    }
+    Ok(renames)
+}
+
+/// What the `Rename` module matched
+#[derive(Debug, Default)]
+pub(crate) struct RenameReport<'a> {
+    /// The number of entries given a new defline
+    pub(crate) renamed: usize,
+    /// Entries no row of the map matched
+    pub(crate) unmatched: Vec<&'a FastaEntry>,
+    /// Old names of map rows that matched no entry, in map order
+    pub(crate) unused: Vec<String>,
+}
+
+/// The main function of the `Rename` module. Replaces each entry's defline with
+/// the new name of the map row whose old name is the whole defline or, failing
+/// that, its first whitespace-delimited token. Unmatched entries keep their
+/// defline. Sequences and metadata are kept. Renaming an entry to a defline
+/// another output entry also has, as when two entries share a first token or a
+/// new name is an unmatched entry's defline, is an error naming the defline.
+pub(crate) fn rename_fasta<'a>(
+    inp_fasta: &'a Fasta,
+    renames: &[(String, String)],
+    out_fasta: impl AsRef<Path>,
+) -> Result<(Fasta, RenameReport<'a>), String> {
+    let new_names: HashMap<&str, &str> = renames
+        .iter()
+        .map(|(old_name, new_name)| (old_name.as_str(), new_name.as_str()))
+        .collect();
+    let mut used: HashSet<&str> = HashSet::new();
+    let mut renamed_fasta = Fasta::new(out_fasta);
+    let mut report = RenameReport::default();
+    // Whether any entry with each output defline was renamed to it
+    let mut output_deflines: HashMap<String, bool> = HashMap::new();
+    for entry in inp_fasta {
+        let defline = entry.defline();
+        let token = defline.split_whitespace().next().unwrap_or_default();
+        let matched = [defline.as_str(), token]
+            .into_iter()
+            .find_map(|name| new_names.get_key_value(name));
+        let new_defline = if let Some((&old_name, &new_name)) = matched {
+            used.insert(old_name);
+            report.renamed += 1;
+            String::from(new_name)
+        } else {
+            report.unmatched.push(entry);
+            defline
+        };
+        let was_matched = matched.is_some();
+        if let Some(&earlier_matched) = output_deflines.get(&new_defline) {
+            if was_matched || earlier_matched {
+                return Err(format!(
+                    "Renaming would give more than one sequence the defline '{new_defline}'"
+                ));
+            }
+        }
+        output_deflines.insert(new_defline.clone(), was_matched);
+        renamed_fasta.add(
+            FastaEntry::new(
+                new_defline,
+                entry.sequence().into_owned(),
+                entry.entry_num(),
+            )
+            .with_meta_from(entry),
+        );
+    }
+    report.unused = renames
+        .iter()
+        .filter(|(old_name, _)| !used.contains(old_name.as_str()))
+        .map(|(old_name, _)| old_name.clone())
+        .collect();
+    Ok((renamed_fasta, report))
+}
+
+/// Returns a Newick label for `text`, in single quotes, with quotes doubled,
+/// if it has whitespace or a character Newick reserves
+fn newick_label(text: &str) -> String {
+    if text
+        .chars()
+        .any(|c| c.is_whitespace() || RESERVED.contains(c))
+    {
+        format!("'{}'", text.replace('\'', "''"))
+    } else {
+        String::from(text)
+    }
+}
+
+/// Replaces each taxon label of a Newick tree that is an exported name in
+/// `names` with its original defline, quoted if it needs to be. Branch
+/// lengths, comments in square brackets, whitespace, and labels not in `names`
+/// are kept exactly as written.
+pub(crate) fn relabel_newick(tree: &str, names: &HashMap<String, String>) -> String {
+    let mut relabeled = String::with_capacity(tree.len());
+    let mut rest = tree;
+    let mut in_branch_length = false;
+    while let Some(c) = rest.chars().next() {
+        let token_length = match c {
+            '(' | ')' | ',' | ';' => {
+                in_branch_length = false;
+                1
+            }
+            ':' => {
+                in_branch_length = true;
+                1
+            }
+            '[' => rest.find(']').map_or(rest.len(), |end| end + 1),
+            c if c.is_whitespace() => c.len_utf8(),
+            '\'' => {
+                // A quoted label runs to the next quote that isn't doubled
+                let mut end = 1;
+                loop {
+                    match rest[end..].find('\'') {
+                        Some(quote) if rest[end + quote + 1..].starts_with('\'') => {
+                            end += quote + 2;
+                        }
+                        Some(quote) => break end + quote + 1,
+                        None => break rest.len(),
+                    }
+                }
+            }
+            _ => rest[c.len_utf8()..]
+                .find(|c: char| RESERVED.contains(c) || c.is_whitespace())
+                .map_or(rest.len(), |end| end + c.len_utf8()),
+        };
+        let (token, remaining) = rest.split_at(token_length);
+        rest = remaining;
+
+        let is_label = !in_branch_length && !c.is_whitespace() && !"()[:;,".contains(c);
+        let label = if token.len() > 1 && token.starts_with('\'') && token.ends_with('\'') {
+            token[1..token.len() - 1].replace("''", "'")
+        } else {
+            String::from(token)
+        };
+        match names.get(&label) {
+            Some(original) if is_label => {
+                relabeled.push_str(&newick_label(original));
+            }
+            _ => relabeled.push_str(token),
+        }
+    }
+    relabeled
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        export_names, read_name_map, read_rename_map, relabel_fasta, relabel_newick, rename_fasta,
+        write_name_map, PHYLIP_NAME_LENGTH,
+    };
+    use crate::fasta_manager::{Fasta, FastaEntry};
+
+    fn deflines(fasta: &Fasta) -> Vec<String> {
+        fasta.into_iter().map(FastaEntry::defline).collect()
+    }
+
+    #[test]
+    fn colliding_names_are_made_unique() {
+        let originals: Vec<String> = [
+            "CY121680 tag=1",
+            "CY121680 tag=2",
+            "CY121680 tag=3",
+            "CY121680_1",
+            "short",
+            "",
+            "a(b):c",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        let names = export_names(&originals, PHYLIP_NAME_LENGTH);
+        assert_eq!(
+            names,
+            [
+                "CY121680_t",
+                "CY121680_1",
+                "CY121680_2",
+                "CY121680_3",
+                "short",
+                "_1",
+                "a_b__c"
+            ]
+        );
+        assert!(names.iter().all(|name| name.len() <= PHYLIP_NAME_LENGTH));
+        assert_eq!(export_names(&originals, PHYLIP_NAME_LENGTH), names);
+    }
+
+    #[test]
+    fn relabel_round_trips() {
+        let mut fasta = Fasta::new("exported.fna");
+        for (i, defline) in ["CY121680 tag=1", "CY121680 tag=2", "MK-2023 'x'"]
+            .into_iter()
+            .enumerate()
+        {
+            fasta.add(FastaEntry::new(String::from(defline), b"ATG".to_vec(), i));
+        }
+        let originals = deflines(&fasta);
+        let names = export_names(&originals, PHYLIP_NAME_LENGTH);
+        let path = std::env::temp_dir().join("clean_genes_round_trip.names.tsv");
+        write_name_map(&names, &originals, &path).unwrap();
+        let name_map = read_name_map(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let mut exported = Fasta::new("exported.fna");
+        for (i, name) in names.iter().enumerate() {
+            exported.add(FastaEntry::new(name.clone(), b"ATG".to_vec(), i));
+        }
+        let (relabeled, num_renamed) = relabel_fasta(&exported, &name_map, "relabeled.fna");
+        assert_eq!(num_renamed, 3);
+        assert_eq!(deflines(&relabeled), originals);
+
+        // Relabeling again changes nothing, and exporting again gives the same
+        // names
+        let (again, num_renamed) = relabel_fasta(&relabeled, &name_map, "again.fna");
+        assert_eq!(num_renamed, 0);
+        assert_eq!(deflines(&again), originals);
+        assert_eq!(export_names(&deflines(&again), PHYLIP_NAME_LENGTH), names);
+
+        let tree = format!(
+            "(({}:0.1,{}:0.2)90:0.3,'x y':0.4[&note],{});",
+            names[0], names[1], names[2]
+        );
+        assert_eq!(
+            relabel_newick(&tree, &name_map),
+            "(('CY121680 tag=1':0.1,'CY121680 tag=2':0.2)90:0.3,'x y':0.4[&note],\
+            'MK-2023 ''x''');"
+        );
+    }
+
+    #[test]
+    fn repeated_exported_name_is_an_error() {
+        let path = std::env::temp_dir().join("clean_genes_repeated.names.tsv");
+        std::fs::write(&path, "# header\na\tfirst\na\tsecond\n").unwrap();
+        let err = read_name_map(&path).unwrap_err();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(
+            err.to_string(),
+            "line 3 of the name map repeats the name 'a'"
+        );
+    }
+
+    #[test]
+    fn rename_by_defline_or_first_token() {
+        let mut fasta = Fasta::new("rename.fna");
+        for (i, defline) in ["CY121680 A/Texas/1/2020", "MK-2023", "other one", "CY9"]
+            .into_iter()
+            .enumerate()
+        {
+            fasta.add(FastaEntry::new(String::from(defline), b"ATG".to_vec(), i));
+        }
+        let path = std::env::temp_dir().join("clean_genes_rename.tsv");
+        std::fs::write(
+            &path,
+            "# old\tnew\nCY121680\tsample_1\nMK-2023\tsample_2\nnot_here\tsample_3\n",
+        )
+        .unwrap();
+        let renames = read_rename_map(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let (out_fasta, report) = rename_fasta(&fasta, &renames, "renamed.fna").unwrap();
+        assert_eq!(
+            deflines(&out_fasta),
+            ["sample_1", "sample_2", "other one", "CY9"]
+        );
+        assert_eq!(report.renamed, 2);
+        let unmatched: Vec<String> = report
+            .unmatched
+            .iter()
+            .map(|entry| entry.defline())
+            .collect();
+        assert_eq!(unmatched, ["other one", "CY9"]);
+        assert_eq!(report.unused, ["not_here"]);
+    }
+
+    #[test]
+    fn duplicate_rename_target_is_an_error() {
+        let path = std::env::temp_dir().join("clean_genes_duplicate_target.tsv");
+        std::fs::write(&path, "a\tsame\nb\tsame\n").unwrap();
+        let err = read_rename_map(&path).unwrap_err();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(
+            err.to_string(),
+            "line 2 of the rename map gives 'same' to a second name, which would duplicate \
+            deflines"
+        );
+    }
+
+    #[test]
+    fn rename_creating_duplicate_deflines_is_an_error() {
+        let renames = vec![(String::from("CY1"), String::from("sample_1"))];
+        let fasta_of = |deflines: &[&str]| {
+            let mut fasta = Fasta::new("rename.fna");
+            for (i, defline) in deflines.iter().enumerate() {
+                fasta.add(FastaEntry::new(String::from(*defline), b"ATG".to_vec(), i));
+            }
+            fasta
+        };
+        let expected = "Renaming would give more than one sequence the defline 'sample_1'";
+
+        let shared_token = fasta_of(&["CY1 a", "CY1 b"]);
+        let err = rename_fasta(&shared_token, &renames, "renamed.fna").unwrap_err();
+        assert_eq!(err, expected);
+
+        let taken = fasta_of(&["sample_1", "CY1"]);
+        let err = rename_fasta(&taken, &renames, "renamed.fna").unwrap_err();
+        assert_eq!(err, expected);
+
+        // Duplicates already in the input are not Rename's doing
+        let repeated = fasta_of(&["CY9", "CY9", "CY1"]);
+        assert!(rename_fasta(&repeated, &renames, "renamed.fna").is_ok());
+    }
+}
@@ -0,0 +1,188 @@
+use crate::fasta_manager::{Fasta, FastaEntry};
+use std::path::Path;
+
+/// The name of the output file for entries without the split field
+const UNASSIGNED: &str = "unassigned";
+
+/// The defline field `SplitByField` groups entries by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SplitField {
+    /// The type from the `{TYPE_GENE_SUBTYPE}` gene tag
+    FluType,
+    /// The gene from the gene tag
+    Gene,
+    /// The subtype from the gene tag
+    Subtype,
+    /// A field of the defline split on the delimiter, starting at 0
+    Index(usize),
+}
+
+/// Returns the value of `field` in an entry's defline, or None if the entry
+/// has no such field or it is empty
+fn field_value(entry: &FastaEntry, field: SplitField, delimiter: &str) -> Option<String> {
+    let value = match field {
+        SplitField::FluType => entry.gene_tag().map(|tag| tag.flu_type.to_string()),
+        SplitField::Gene => entry.gene_tag().map(|tag| tag.gene.to_string()),
+        SplitField::Subtype => entry
+            .gene_tag()
+            .and_then(|tag| tag.subtype)
+            .map(str::to_string),
+        SplitField::Index(index) => entry
+            .defline_fields(delimiter)
+            .get(index)
+            .map(ToString::to_string),
+    };
+    value.filter(|value| !value.trim().is_empty())
+}
+
+/// Makes a field value safe to use as a file name, replacing anything other
+/// than letters, digits, '-', '_' and '.' with '_'
+fn file_stem(value: &str) -> String {
+    value
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// The main function of the `SplitByField` module. Groups entries by the value
+/// of `field` in their deflines and returns one Fasta per group, named
+/// `<value>.fasta` in `out_dir`, in the order each value first appears.
+/// Entries without the field are grouped into `unassigned.fasta`, which comes
+/// last. Entries are renumbered within each group. Two different values whose
+/// file names would match, ignoring case, are an error rather than one file
+/// overwriting the other, as is a value named like the unassigned group when
+/// that group is written.
+pub(crate) fn split_by_field(
+    inp_fasta: &Fasta,
+    field: SplitField,
+    delimiter: &str,
+    out_dir: impl AsRef<Path>,
+) -> Result<Vec<Fasta>, String> {
+    let mut groups: Vec<(String, String, Fasta)> = Vec::new();
+    let mut unassigned = Fasta::new(out_dir.as_ref().join(format!("{UNASSIGNED}.fasta")));
+    for entry in inp_fasta {
+        let group = match field_value(entry, field, delimiter) {
+            Some(value) => {
+                let stem = file_stem(&value);
+                let index = match groups
+                    .iter()
+                    .position(|(_, group, _)| group.eq_ignore_ascii_case(&stem))
+                {
+                    Some(index) if groups[index].0 != value => {
+                        return Err(format!(
+                            "'{}' and '{value}' would both be written to '{}.fasta'",
+                            groups[index].0, groups[index].1
+                        ));
+                    }
+                    Some(index) => index,
+                    None => {
+                        let path = out_dir.as_ref().join(format!("{stem}.fasta"));
+                        groups.push((value, stem, Fasta::new(path)));
+                        groups.len() - 1
+                    }
+                };
+                &mut groups[index].2
+            }
+            None => &mut unassigned,
+        };
+        let entry_num = group.num_entries();
+        group.add(
+            FastaEntry::new(entry.defline(), entry.sequence().into_owned(), entry_num)
+                .with_meta_from(entry),
+        );
+    }
+
+    if unassigned.num_entries() > 0 {
+        if let Some((value, stem, _)) = groups
+            .iter()
+            .find(|(_, stem, _)| stem.eq_ignore_ascii_case(UNASSIGNED))
+        {
+            return Err(format!(
+                "'{value}' would be written to '{stem}.fasta', which holds entries without \
+                the field"
+            ));
+        }
+    }
+
+    let mut split_fastas: Vec<Fasta> = groups.into_iter().map(|(_, _, fasta)| fasta).collect();
+    if unassigned.num_entries() > 0 {
+        split_fastas.push(unassigned);
+    }
+    Ok(split_fastas)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{split_by_field, SplitField};
+    use crate::fasta_manager::{open_fasta, Fasta, FastaEntry};
+    use std::path::Path;
+
+    #[test]
+    fn split_h3_by_subtype() {
+        let mut fasta = open_fasta("test_data/a_ha_h3_raw_500.fna").unwrap();
+        let num_h3 = fasta.num_entries();
+        for (defline, entry_num) in [("CY1{A_HA_H1}", num_h3), ("untagged", num_h3 + 1)] {
+            fasta.add(FastaEntry::new(
+                String::from(defline),
+                b"ATG".to_vec(),
+                entry_num,
+            ));
+        }
+
+        let split = split_by_field(&fasta, SplitField::Subtype, "|", "by_subtype").unwrap();
+        let names: Vec<&Path> = split
+            .iter()
+            .map(|group| Path::new(group.filename()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                Path::new("by_subtype/H3.fasta"),
+                Path::new("by_subtype/H1.fasta"),
+                Path::new("by_subtype/unassigned.fasta")
+            ]
+        );
+        assert_eq!(split[0].num_entries(), num_h3);
+        assert_eq!(split[1].indexed_entry(0).defline(), "CY1{A_HA_H1}");
+        assert_eq!(split[1].indexed_entry(0).entry_num(), 0);
+        assert_eq!(split[2].indexed_entry(0).defline(), "untagged");
+
+        let by_gene = split_by_field(&fasta, SplitField::Gene, "|", "by_gene").unwrap();
+        assert_eq!(by_gene.len(), 2);
+        assert_eq!(by_gene[0].num_entries(), num_h3 + 1);
+    }
+
+    #[test]
+    fn colliding_file_names_are_errors() {
+        let mut fasta = Fasta::new("collide.fna");
+        for (i, defline) in ["a|x y", "b|x_y", "c|unassigned", "d"]
+            .into_iter()
+            .enumerate()
+        {
+            fasta.add(FastaEntry::new(String::from(defline), b"ATG".to_vec(), i));
+        }
+        let err = split_by_field(&fasta, SplitField::Index(1), "|", "out").unwrap_err();
+        assert_eq!(err, "'x y' and 'x_y' would both be written to 'x_y.fasta'");
+
+        let mut reserved = Fasta::new("reserved.fna");
+        for (i, defline) in ["c|Unassigned", "d"].into_iter().enumerate() {
+            reserved.add(FastaEntry::new(String::from(defline), b"ATG".to_vec(), i));
+        }
+        let err = split_by_field(&reserved, SplitField::Index(1), "|", "out").unwrap_err();
+        assert!(err.starts_with("'Unassigned' would be written to 'Unassigned.fasta'"));
+        reserved.retain(|entry| entry.defline() != "d");
+        assert_eq!(
+            split_by_field(&reserved, SplitField::Index(1), "|", "out")
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use crate::fasta_manager::Fasta;
+use std::fmt::{self, Write};
+use std::time::Duration;
+
+/// What one module did during a run: how many entries went in and came out,
+/// why any were rejected, how many bases were removed or rewritten, how long it
+/// took, and the coordinates or thresholds it used.
+#[derive(Debug, Default)]
+pub(crate) struct ModuleStats {
+    module: String,
+    entries_in: usize,
+    entries_out: usize,
+    rejected: Vec<(String, usize)>,
+    bases_removed: usize,
+    bases_modified: usize,
+    elapsed: Duration,
+    details: Vec<(String, String)>,
+}
+
+/// Counts the bases across all entries of a Fasta
+pub(crate) fn total_bases(fasta: &Fasta) -> usize {
+    fasta.into_iter().map(|entry| entry.sequence().len()).sum()
+}
+
+impl ModuleStats {
+    /// Constructor for `ModuleStats`, before the module has run
+    pub(crate) fn new(module: &str, inp_fasta: &Fasta) -> Self {
+        ModuleStats {
+            module: String::from(module),
+            entries_in: inp_fasta.num_entries(),
+            ..ModuleStats::default()
+        }
+    }
+
+    /// Records the module's output, counting the bases removed relative to
+    /// `inp_fasta`, along with how long the module took. Modules that write a
+    /// table in place of a Fasta have no output.
+    pub(crate) fn finish(
+        &mut self,
+        inp_fasta: &Fasta,
+        out_fasta: Option<&Fasta>,
+        elapsed: Duration,
+    ) {
+        if let Some(out_fasta) = out_fasta {
+            self.entries_out = out_fasta.num_entries();
+            self.bases_removed = total_bases(inp_fasta).saturating_sub(total_bases(out_fasta));
+        }
+        self.elapsed = elapsed;
+    }
+
+    /// Records entries rejected for `reason`
+    pub(crate) fn add_rejected(&mut self, reason: &str, count: usize) {
+        if count > 0 {
+            self.rejected.push((String::from(reason), count));
+        }
+    }
+
+    /// Records bases the module rewrote in place
+    pub(crate) fn add_modified(&mut self, count: usize) {
+        self.bases_modified += count;
+    }
+
+    /// Records a coordinate or threshold the module used
+    pub(crate) fn add_detail(&mut self, key: &str, value: impl fmt::Display) {
+        self.details.push((String::from(key), value.to_string()));
+    }
+
+    /// Returns the number of entries rejected for any reason
+    pub(crate) fn num_rejected(&self) -> usize {
+        self.rejected.iter().map(|(_, count)| count).sum()
+    }
+
+    /// Returns the stats as key-value pairs, prefixed by the module name
+    pub(crate) fn key_values(&self) -> Vec<(String, String)> {
+        let mut pairs = vec![
+            ("entries_in", self.entries_in.to_string()),
+            ("entries_out", self.entries_out.to_string()),
+            ("bases_removed", self.bases_removed.to_string()),
+            ("bases_modified", self.bases_modified.to_string()),
+            ("seconds", format!("{:.3}", self.elapsed.as_secs_f64())),
+        ];
+        pairs.extend(
+            self.rejected
+                .iter()
+                .map(|(reason, count)| ("rejected", format!("{reason}:{count}"))),
+        );
+        pairs
+            .into_iter()
+            .map(|(key, value)| (format!("{}.{key}", self.module), value))
+            .collect()
+    }
+}
+
+/// Formats the stats of each module in a run as a compact table, with the
+/// rejection reasons and details of each module on the lines below its row
+pub(crate) fn stats_table(all_stats: &[ModuleStats]) -> String {
+    let mut table = format!(
+        "{:<20}{:>10}{:>10}{:>10}{:>16}{:>16}{:>10}\n",
+        "module", "in", "out", "rejected", "bases_removed", "bases_modified", "seconds"
+    );
+    for stats in all_stats {
+        // Writing to a String cannot fail
+        let _ = writeln!(
+            table,
+            "{:<20}{:>10}{:>10}{:>10}{:>16}{:>16}{:>10.3}",
+            stats.module,
+            stats.entries_in,
+            stats.entries_out,
+            stats.num_rejected(),
+            stats.bases_removed,
+            stats.bases_modified,
+            stats.elapsed.as_secs_f64()
+        );
+        for (reason, count) in &stats.rejected {
+            let _ = writeln!(table, "    rejected {reason}: {count}");
+        }
+        for (key, value) in &stats.details {
+            let _ = writeln!(table, "    {key} = {value}");
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::{stats_table, ModuleStats};
+    use crate::fasta_manager::{Fasta, FastaEntry};
+    use std::time::Duration;
+
+    #[test]
+    fn counts_removed_bases_and_rejections() {
+        let mut inp_fasta = Fasta::new("inp.fna");
+        inp_fasta.add(FastaEntry::new(String::from("a"), b"ATGTAG".to_vec(), 0));
+        inp_fasta.add(FastaEntry::new(String::from("b"), b"----".to_vec(), 1));
+        let mut out_fasta = Fasta::new("out.fna");
+        out_fasta.add(FastaEntry::new(String::from("a"), b"ATGTAG".to_vec(), 0));
+
+        let mut stats = ModuleStats::new("DropEmpty", &inp_fasta);
+        stats.add_rejected("empty", 1);
+        stats.add_rejected("unused", 0);
+        stats.finish(&inp_fasta, Some(&out_fasta), Duration::from_millis(5));
+
+        assert_eq!(stats.num_rejected(), 1);
+        let table = stats_table(&[stats]);
+        assert!(table.contains("DropEmpty"));
+        assert!(table.contains("    rejected empty: 1\n"));
+        assert!(!table.contains("unused"));
+
+        let row: Vec<&str> = table.lines().nth(1).unwrap().split_whitespace().collect();
+        assert_eq!(row, vec!["DropEmpty", "2", "1", "1", "4", "0", "0.005"]);
+    }
+}
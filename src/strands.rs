@@ -0,0 +1,94 @@
+use crate::fasta_manager::{Fasta, FastaEntry, SequenceOps};
+use std::path::Path;
+
+/// The defline suffix given to reverse-complemented copies of sequences when
+/// both strands are emitted
+const RC_SUFFIX: &str = "_rc";
+
+/// The main function of the `ReverseComplement` module. Reverse-complements
+/// every sequence. When `emit_both_strands` is set, each sequence is instead
+/// output twice: first unchanged, then reverse-complemented with `_rc`
+/// appended to its defline. Entries are renumbered in output order.
+pub(crate) fn reverse_complement_fasta(
+    inp_fasta: &Fasta,
+    emit_both_strands: bool,
+    out_fasta: impl AsRef<Path>,
+) -> Fasta {
+    let mut out = Fasta::new(out_fasta);
+    let mut entry_num = 0;
+
+    for entry in inp_fasta {
+        if emit_both_strands {
+            out.add(
+                FastaEntry::new(entry.defline(), entry.sequence().into_owned(), entry_num)
+                    .with_meta_from(entry),
+            );
+            entry_num += 1;
+            out.add(
+                FastaEntry::new(
+                    format!("{}{RC_SUFFIX}", entry.defline()),
+                    entry.sequence().reverse_complement(),
+                    entry_num,
+                )
+                .with_meta_from(entry),
+            );
+        } else {
+            out.add(
+                FastaEntry::new(
+                    entry.defline(),
+                    entry.sequence().reverse_complement(),
+                    entry_num,
+                )
+                .with_meta_from(entry),
+            );
+        }
+        entry_num += 1;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::reverse_complement_fasta;
+    use crate::fasta_manager::{Fasta, FastaEntry};
+
+    fn two_entries() -> Fasta {
+        let mut fasta = Fasta::new("two.fna");
+        fasta.add(FastaEntry::new(String::from("a"), b"ATGCCN".to_vec(), 0));
+        fasta.add(FastaEntry::new(String::from("b"), b"AAG-T".to_vec(), 1));
+        fasta
+    }
+
+    #[test]
+    fn reverse_complement_only() {
+        let out = reverse_complement_fasta(&two_entries(), false, "./output.fasta");
+        assert_eq!(out.num_entries(), 2);
+        assert_eq!(out.indexed_entry(0).defline(), "a");
+        assert_eq!(&*out.indexed_entry(0).sequence(), b"NGGCAT");
+        assert_eq!(&*out.indexed_entry(1).sequence(), b"A-CTT");
+    }
+
+    #[test]
+    fn emit_both_strands() {
+        let inp = two_entries();
+        let out = reverse_complement_fasta(&inp, true, "./output.fasta");
+        assert_eq!(out.num_entries(), 4);
+
+        let deflines: Vec<String> = (&out).into_iter().map(FastaEntry::defline).collect();
+        assert_eq!(deflines, ["a", "a_rc", "b", "b_rc"]);
+        assert_eq!(
+            out.indexed_entry(0).sequence(),
+            inp.indexed_entry(0).sequence()
+        );
+        assert_eq!(&*out.indexed_entry(1).sequence(), b"NGGCAT");
+        assert_eq!(
+            out.indexed_entry(2).sequence(),
+            inp.indexed_entry(1).sequence()
+        );
+        assert_eq!(&*out.indexed_entry(3).sequence(), b"A-CTT");
+
+        let entry_nums: Vec<usize> = (&out).into_iter().map(FastaEntry::entry_num).collect();
+        assert_eq!(entry_nums, [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,749 @@
+use crate::fasta_manager::{
+    is_gap, is_protein_char, write_atomically, CoordinateMap, Fasta, FastaEntry, MoleculeType,
+    ResidueError,
+};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// The 20 standard amino acids, the first columns of a composition table
+const STANDARD_AMINO_ACIDS: &[u8; 20] = b"ACDEFGHIKLMNPQRSTVWY";
+
+/// The mass in daltons of one water molecule, lost with each peptide bond
+const WATER_MASS: f64 = 18.015_28;
+
+/// The average mass in daltons of each standard amino acid residue, that is,
+/// the free amino acid less one water molecule
+const RESIDUE_MASSES: [(u8, f64); 20] = [
+    (b'A', 71.0788),
+    (b'C', 103.1388),
+    (b'D', 115.0886),
+    (b'E', 129.1155),
+    (b'F', 147.1766),
+    (b'G', 57.0519),
+    (b'H', 137.1411),
+    (b'I', 113.1594),
+    (b'K', 128.1741),
+    (b'L', 113.1594),
+    (b'M', 131.1926),
+    (b'N', 114.1038),
+    (b'P', 97.1167),
+    (b'Q', 128.1307),
+    (b'R', 156.1875),
+    (b'S', 87.0782),
+    (b'T', 101.1051),
+    (b'V', 99.1326),
+    (b'W', 186.2132),
+    (b'Y', 163.1760),
+];
+
+/// The pKa values of the N and C termini, from Bjellqvist et al. (1993)
+const N_TERMINUS_PKA: f64 = 7.5;
+const C_TERMINUS_PKA: f64 = 3.55;
+
+/// The pKa values of the side chains that are positively charged when
+/// protonated, from Bjellqvist et al. (1993)
+const POSITIVE_PKAS: [(u8, f64); 3] = [(b'K', 10.0), (b'R', 12.0), (b'H', 5.98)];
+
+/// The pKa values of the side chains that are negatively charged when
+/// deprotonated, from Bjellqvist et al. (1993)
+const NEGATIVE_PKAS: [(u8, f64); 4] = [(b'D', 4.05), (b'E', 4.45), (b'C', 9.0), (b'Y', 10.0)];
+
+/// The Kyte-Doolittle hydropathy of each standard amino acid, from Kyte and
+/// Doolittle (1982). Positive is hydrophobic.
+const KYTE_DOOLITTLE: [(u8, f64); 20] = [
+    (b'A', 1.8),
+    (b'C', 2.5),
+    (b'D', -3.5),
+    (b'E', -3.5),
+    (b'F', 2.8),
+    (b'G', -0.4),
+    (b'H', -3.2),
+    (b'I', 4.5),
+    (b'K', -3.9),
+    (b'L', 3.8),
+    (b'M', 1.9),
+    (b'N', -3.5),
+    (b'P', -1.6),
+    (b'Q', -3.5),
+    (b'R', -4.5),
+    (b'S', -0.8),
+    (b'T', -0.7),
+    (b'V', 4.2),
+    (b'W', -0.9),
+    (b'Y', -1.3),
+];
+
+/// Residues at least this hydropathic can sit in the hydrophobic core of a
+/// signal peptide; this leaves out only charged and amide residues and proline
+const SIGNAL_CORE_HYDROPATHY: f64 = -1.5;
+
+/// The fewest residues in the hydrophobic core of a signal peptide
+const MIN_SIGNAL_CORE: usize = 8;
+
+/// The small neutral residues a signal peptide is cleaved after
+const SMALL_NEUTRAL: &[u8; 5] = b"ACGST";
+
+/// How close to the isoelectric point the bisection gets before stopping, in pH
+/// units
+const PI_TOLERANCE: f64 = 0.001;
+
+/// The genetic codes available for translation, numbered as in the NCBI
+/// translation tables
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) enum GeneticCode {
+    /// NCBI table 1
+    #[default]
+    Standard,
+    /// NCBI table 2
+    VertebrateMitochondrial,
+    /// A code loaded from a codon table file, with amino acids in the same
+    /// codon order as the built-in tables
+    Custom([u8; 64]),
+}
+
+/// The bases in the order used to index codons
+const BASES: [u8; 4] = *b"TCAG";
+
+/// Returns the index of a base in codon order, treating U as T
+fn base_index(base: u8) -> Option<usize> {
+    match base.to_ascii_uppercase() {
+        b'T' | b'U' => Some(0),
+        b'C' => Some(1),
+        b'A' => Some(2),
+        b'G' => Some(3),
+        _ => None,
+    }
+}
+
+/// Returns the codon at `index` in codon order
+fn codon_at(index: usize) -> [u8; 3] {
+    [BASES[index / 16], BASES[index / 4 % 4], BASES[index % 4]]
+}
+
+impl GeneticCode {
+    /// Reads a custom genetic code from a file with one `CODON<TAB>AMINO` line
+    /// per codon. Blank lines and lines starting with '#' are skipped. Codons
+    /// missing from the file translate to 'X'; they are returned alongside the
+    /// code so the caller can warn about them.
+    pub(crate) fn from_table_file(
+        path: impl AsRef<Path>,
+    ) -> Result<(GeneticCode, Vec<String>), io::Error> {
+        let contents = fs::read_to_string(path)?;
+        let invalid = |line_num: usize, problem: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {line_num} of the codon table {problem}"),
+            )
+        };
+
+        let mut amino_acids = [0_u8; 64];
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((codon, amino_acid)) = line.split_once('\t') else {
+                return Err(invalid(i + 1, "is not CODON<TAB>AMINO"));
+            };
+            let index = match codon.as_bytes() {
+                &[first, second, third] => [first, second, third]
+                    .into_iter()
+                    .try_fold(0, |index, base| Some(index * 4 + base_index(base)?)),
+                _ => None,
+            }
+            .ok_or_else(|| invalid(i + 1, &format!("has an invalid codon, '{codon}'")))?;
+            let &[amino_acid] = amino_acid.trim().as_bytes() else {
+                return Err(invalid(i + 1, "needs a single amino acid letter"));
+            };
+            if !is_protein_char(amino_acid) || is_gap(amino_acid) {
+                return Err(invalid(
+                    i + 1,
+                    &format!("has an invalid amino acid, '{}'", char::from(amino_acid)),
+                ));
+            }
+            if amino_acids[index] != 0 {
+                return Err(invalid(i + 1, &format!("repeats the codon '{codon}'")));
+            }
+            amino_acids[index] = amino_acid.to_ascii_uppercase();
+        }
+
+        let mut missing = Vec::new();
+        for (index, amino_acid) in amino_acids.iter_mut().enumerate() {
+            if *amino_acid == 0 {
+                *amino_acid = b'X';
+                missing.push(String::from_utf8_lossy(&codon_at(index)).into_owned());
+            }
+        }
+        Ok((GeneticCode::Custom(amino_acids), missing))
+    }
+
+    /// Returns the codons this code translates to a stop ('*'), in uppercase
+    pub(crate) fn stop_codons(&self) -> Vec<[u8; 3]> {
+        self.amino_acids()
+            .iter()
+            .enumerate()
+            .filter(|&(_, &amino_acid)| amino_acid == b'*')
+            .map(|(index, _)| codon_at(index))
+            .collect()
+    }
+
+    /// Returns the amino acids of this code for all 64 codons, ordered by
+    /// codon with bases in the order T, C, A, G (TTT, TTC, TTA, TTG, TCT, ...)
+    fn amino_acids(&self) -> &[u8; 64] {
+        match self {
+            GeneticCode::Standard => {
+                b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG"
+            }
+            GeneticCode::VertebrateMitochondrial => {
+                b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSS**VVVVAAAADDEEGGGG"
+            }
+            GeneticCode::Custom(amino_acids) => amino_acids,
+        }
+    }
+
+    /// Translates a single codon into an amino acid. Case-insensitive and
+    /// treats U as T. An all-gap codon translates to '-' and a codon with any
+    /// other unrecognized base (ambiguity codes, partial gaps) translates to 'X'.
+    pub(crate) fn translate_codon(&self, codon: &[u8]) -> u8 {
+        if codon.len() != 3 {
+            return b'X';
+        }
+        if codon.iter().all(|&base| base == b'-') {
+            return b'-';
+        }
+
+        let mut index = 0;
+        for &base in codon {
+            let Some(value) = base_index(base) else {
+                return b'X';
+            };
+            index = index * 4 + value;
+        }
+        self.amino_acids()[index]
+    }
+}
+
+/// Returns the fraction of a protein sequence made up of each amino acid
+/// letter, counted case-insensitively and keyed in upper case. Gaps and stops
+/// ('*') are skipped. A sequence with no letters has an empty composition.
+pub(crate) fn amino_acid_composition(seq: &[u8]) -> HashMap<u8, f64> {
+    let mut counts: HashMap<u8, usize> = HashMap::new();
+    for &residue in seq {
+        if residue.is_ascii_alphabetic() {
+            *counts.entry(residue.to_ascii_uppercase()).or_default() += 1;
+        }
+    }
+    let total: usize = counts.values().sum();
+    counts
+        .into_iter()
+        .map(|(residue, count)| {
+            #[expect(clippy::cast_precision_loss)]
+            let fraction = count as f64 / total as f64;
+            (residue, fraction)
+        })
+        .collect()
+}
+
+/// Returns the average molecular weight in daltons of a protein sequence: the
+/// sum of its residue masses plus one water molecule for the free ends.
+/// Residues are matched case-insensitively; gaps, stops and letters other than
+/// the 20 standard amino acids are skipped. A sequence with no standard
+/// residues weighs 0.0.
+pub(crate) fn molecular_weight(aa_seq: &[u8]) -> f64 {
+    let masses: Vec<f64> = aa_seq
+        .iter()
+        .filter_map(|residue| {
+            let residue = residue.to_ascii_uppercase();
+            RESIDUE_MASSES
+                .iter()
+                .find(|(amino_acid, _)| *amino_acid == residue)
+                .map(|&(_, mass)| mass)
+        })
+        .collect();
+    if masses.is_empty() {
+        return 0.0;
+    }
+    masses.iter().sum::<f64>() + WATER_MASS
+}
+
+/// Returns the defline and molecular weight of every entry of a protein Fasta
+pub(crate) fn molecular_weight_fasta(fasta: &Fasta) -> Vec<(String, f64)> {
+    fasta
+        .into_iter()
+        .map(|entry| (entry.defline(), molecular_weight(&entry.sequence())))
+        .collect()
+}
+
+/// Writes the molecular weight of every entry of a protein Fasta as a TSV of
+/// defline and weight in daltons, to 2 decimal places
+pub(crate) fn write_molecular_weight_tsv(
+    fasta: &Fasta,
+    path: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    let weights = molecular_weight_fasta(fasta);
+    write_atomically(path.as_ref(), |writer| {
+        writeln!(writer, "defline\tmolecular_weight")?;
+        for (defline, weight) in &weights {
+            writeln!(writer, "{defline}\t{weight:.2}")?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Returns the net charge of a protein at `ph`, given the pKa values of its
+/// termini and of each ionizable side chain
+fn net_charge(ph: f64, positive_pkas: &[f64], negative_pkas: &[f64]) -> f64 {
+    let positive: f64 = positive_pkas
+        .iter()
+        .map(|pka| 1.0 / (1.0 + 10_f64.powf(ph - pka)))
+        .sum();
+    let negative: f64 = negative_pkas
+        .iter()
+        .map(|pka| 1.0 / (1.0 + 10_f64.powf(pka - ph)))
+        .sum();
+    positive - negative
+}
+
+/// Returns the isoelectric point of a protein sequence, the pH at which its
+/// net charge is zero, found by bisecting the pH range 0-14. Residues are
+/// matched case-insensitively and gaps and stops are skipped. A sequence with
+/// no residues has no isoelectric point and returns NaN.
+pub(crate) fn isoelectric_point(aa_seq: &[u8]) -> f64 {
+    let residues: Vec<u8> = aa_seq
+        .iter()
+        .filter(|residue| residue.is_ascii_alphabetic())
+        .map(u8::to_ascii_uppercase)
+        .collect();
+    if residues.is_empty() {
+        return f64::NAN;
+    }
+    let side_chains = |pkas: &[(u8, f64)]| -> Vec<f64> {
+        residues
+            .iter()
+            .filter_map(|residue| {
+                pkas.iter()
+                    .find(|(amino_acid, _)| amino_acid == residue)
+                    .map(|&(_, pka)| pka)
+            })
+            .collect()
+    };
+    let mut positive_pkas = side_chains(&POSITIVE_PKAS);
+    positive_pkas.push(N_TERMINUS_PKA);
+    let mut negative_pkas = side_chains(&NEGATIVE_PKAS);
+    negative_pkas.push(C_TERMINUS_PKA);
+
+    let (mut low, mut high) = (0.0, 14.0);
+    while high - low > PI_TOLERANCE {
+        let mid = f64::midpoint(low, high);
+        if net_charge(mid, &positive_pkas, &negative_pkas) > 0.0 {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    f64::midpoint(low, high)
+}
+
+/// Writes the isoelectric point of every entry of a protein Fasta as a TSV of
+/// defline and pI, to 2 decimal places. Entries with no residues get NaN.
+pub(crate) fn write_isoelectric_point_tsv(
+    fasta: &Fasta,
+    path: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    write_atomically(path.as_ref(), |writer| {
+        writeln!(writer, "defline\tisoelectric_point")?;
+        for entry in fasta {
+            let point = isoelectric_point(&entry.sequence());
+            writeln!(writer, "{}\t{point:.2}", entry.defline())?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Returns the Kyte-Doolittle hydropathy of each residue of a protein
+/// sequence, matched case-insensitively. Gaps and stops are skipped, and
+/// letters other than the 20 standard amino acids score 0.
+fn residue_hydropathy(aa_seq: &[u8]) -> Vec<f64> {
+    aa_seq
+        .iter()
+        .filter(|residue| residue.is_ascii_alphabetic())
+        .map(|residue| {
+            let residue = residue.to_ascii_uppercase();
+            KYTE_DOOLITTLE
+                .iter()
+                .find(|(amino_acid, _)| *amino_acid == residue)
+                .map_or(0.0, |&(_, hydropathy)| hydropathy)
+        })
+        .collect()
+}
+
+/// Returns the Kyte-Doolittle hydrophobicity profile of a protein sequence:
+/// the mean hydropathy of each run of `window` residues, one value per
+/// window, so `len - window + 1` values in all. Returns no values if the
+/// window is 0 or longer than the sequence.
+pub(crate) fn hydrophobicity(aa_seq: &[u8], window: usize) -> Vec<f64> {
+    if window == 0 {
+        return Vec::new();
+    }
+    #[expect(clippy::cast_precision_loss)]
+    let window_len = window as f64;
+    residue_hydropathy(aa_seq)
+        .windows(window)
+        .map(|scores| scores.iter().sum::<f64>() / window_len)
+        .collect()
+}
+
+/// Returns the mean Kyte-Doolittle hydropathy of a whole protein sequence,
+/// its GRAVY score, or NaN if it has no residues
+pub(crate) fn mean_hydrophobicity(aa_seq: &[u8]) -> f64 {
+    let scores = residue_hydropathy(aa_seq);
+    #[expect(clippy::cast_precision_loss)]
+    let mean = scores.iter().sum::<f64>() / scores.len() as f64;
+    mean
+}
+
+/// Predicts the length of an N-terminal signal peptide with a simple
+/// heuristic, returning 0 if none is found. Within the first `max_sp_length`
+/// residues, the first run of at least 8 residues that could form a
+/// hydrophobic core must follow an N-terminal region holding 1-5 basic
+/// residues (K, R, H). The peptide is cleaved after the last small neutral
+/// residue (A, C, G, S, T) of the core, at least 8 residues in.
+pub(crate) fn detect_signal_peptide_length(aa_seq: &[u8], max_sp_length: usize) -> usize {
+    let residues: Vec<u8> = aa_seq
+        .iter()
+        .filter(|residue| residue.is_ascii_alphabetic())
+        .take(max_sp_length)
+        .map(u8::to_ascii_uppercase)
+        .collect();
+    let hydropathy = residue_hydropathy(&residues);
+
+    let Some(core_start) = (0..residues.len()).find(|&start| {
+        hydropathy[start..]
+            .iter()
+            .take(MIN_SIGNAL_CORE)
+            .filter(|&&score| score >= SIGNAL_CORE_HYDROPATHY)
+            .count()
+            == MIN_SIGNAL_CORE
+    }) else {
+        return 0;
+    };
+    let num_basic = residues[..core_start]
+        .iter()
+        .filter(|residue| b"KRH".contains(residue))
+        .count();
+    if !(1..=5).contains(&num_basic) {
+        return 0;
+    }
+
+    let core_end = hydropathy[core_start..]
+        .iter()
+        .position(|&score| score < SIGNAL_CORE_HYDROPATHY)
+        .map_or(residues.len(), |len| core_start + len);
+    (core_start + MIN_SIGNAL_CORE - 1..core_end)
+        .rev()
+        .find(|&i| SMALL_NEUTRAL.contains(&residues[i]))
+        .map_or(0, |i| i + 1)
+}
+
+/// The main function of the `TrimSignalPeptide` module. Removes the signal
+/// peptide that `detect_signal_peptide_length` predicts from the start of each
+/// protein entry, along with any gaps among it, and records its length as
+/// `signal_peptide_length` metadata. Entries with no predicted signal peptide
+/// are kept unchanged. Each trimmed entry is checked to hold only protein
+/// characters.
+pub(crate) fn trim_signal_peptide(
+    fasta: &Fasta,
+    max_sp_length: usize,
+    out_fasta: impl AsRef<Path>,
+) -> Result<Fasta, ResidueError> {
+    let mut trimmed_fasta = Fasta::new(out_fasta);
+    for entry in fasta {
+        let sequence = entry.sequence();
+        let sp_length = detect_signal_peptide_length(&sequence, max_sp_length);
+        let cut = CoordinateMap::new(&sequence)
+            .seq_to_align(sp_length)
+            .unwrap_or(sequence.len());
+        let start = if sp_length == 0 { 0 } else { cut };
+        let mut trimmed = FastaEntry::new_checked(
+            entry.defline(),
+            sequence[start..].to_vec(),
+            entry.entry_num(),
+            MoleculeType::Protein,
+        )?
+        .with_meta_from(entry);
+        trimmed.set_meta("signal_peptide_length", sp_length);
+        trimmed_fasta.add(trimmed);
+    }
+    Ok(trimmed_fasta)
+}
+
+/// Writes the hydrophobicity profile of every entry of a protein Fasta as a
+/// TSV of defline, window position and score, to 3 decimal places. Each
+/// window is placed at its middle residue, numbered from 1 in the degapped
+/// sequence.
+pub(crate) fn write_hydrophobicity_tsv(
+    fasta: &Fasta,
+    window: usize,
+    path: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    write_atomically(path.as_ref(), |writer| {
+        writeln!(writer, "defline\tposition\thydrophobicity")?;
+        for entry in fasta {
+            let defline = entry.defline();
+            for (i, score) in hydrophobicity(&entry.sequence(), window).iter().enumerate() {
+                writeln!(writer, "{defline}\t{}\t{score:.3}", i + window / 2 + 1)?;
+            }
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Writes the amino acid composition of every entry of a protein Fasta as a
+/// TSV, one row per defline and one column per amino acid. The columns are
+/// the 20 standard amino acids followed by any other letters found, such as X.
+/// Fractions are given to 4 decimal places.
+pub(crate) fn write_aa_composition_tsv(
+    fasta: &Fasta,
+    path: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    let compositions: Vec<(String, HashMap<u8, f64>)> = fasta
+        .into_iter()
+        .map(|entry| (entry.defline(), amino_acid_composition(&entry.sequence())))
+        .collect();
+    let mut columns = STANDARD_AMINO_ACIDS.to_vec();
+    let mut others: Vec<u8> = compositions
+        .iter()
+        .flat_map(|(_, composition)| composition.keys().copied())
+        .filter(|residue| !STANDARD_AMINO_ACIDS.contains(residue))
+        .collect();
+    others.sort_unstable();
+    others.dedup();
+    columns.extend(others);
+
+    write_atomically(path.as_ref(), |writer| {
+        write!(writer, "defline")?;
+        for &column in &columns {
+            write!(writer, "\t{}", char::from(column))?;
+        }
+        writeln!(writer)?;
+        for (defline, composition) in &compositions {
+            write!(writer, "{defline}")?;
+            for column in &columns {
+                write!(
+                    writer,
+                    "\t{:.4}",
+                    composition.get(column).copied().unwrap_or(0.0)
+                )?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        amino_acid_composition, detect_signal_peptide_length, hydrophobicity, isoelectric_point,
+        mean_hydrophobicity, molecular_weight, molecular_weight_fasta, trim_signal_peptide,
+        write_aa_composition_tsv, write_hydrophobicity_tsv, GeneticCode,
+    };
+    use crate::fasta_manager::check_protein;
+    use crate::fasta_manager::{Fasta, FastaEntry};
+    use std::fmt::Write;
+
+    #[test]
+    fn standard_codons() {
+        let code = GeneticCode::Standard;
+        assert_eq!(code.translate_codon(b"ATG"), b'M');
+        assert_eq!(code.translate_codon(b"aug"), b'M');
+        assert_eq!(code.translate_codon(b"TTT"), b'F');
+        assert_eq!(code.translate_codon(b"GGG"), b'G');
+        assert_eq!(code.translate_codon(b"TGA"), b'*');
+        assert_eq!(code.translate_codon(b"TAA"), b'*');
+        assert_eq!(code.translate_codon(b"AGA"), b'R');
+    }
+
+    #[test]
+    fn mitochondrial_codons() {
+        let code = GeneticCode::VertebrateMitochondrial;
+        assert_eq!(code.translate_codon(b"TGA"), b'W');
+        assert_eq!(code.translate_codon(b"AGA"), b'*');
+        assert_eq!(code.translate_codon(b"ATA"), b'M');
+    }
+
+    #[test]
+    fn unusual_codons() {
+        let code = GeneticCode::Standard;
+        assert_eq!(code.translate_codon(b"---"), b'-');
+        assert_eq!(code.translate_codon(b"A-G"), b'X');
+        assert_eq!(code.translate_codon(b"ANG"), b'X');
+        assert_eq!(code.translate_codon(b"AT"), b'X');
+    }
+
+    #[test]
+    fn translations_are_valid_protein() {
+        for code in [GeneticCode::Standard, GeneticCode::VertebrateMitochondrial] {
+            assert_eq!(check_protein("table", code.amino_acids()), Ok(()));
+        }
+        assert_eq!(check_protein("codons", b"M-X"), Ok(()));
+    }
+
+    #[test]
+    fn codon_table_file_reassigns_tga() {
+        let standard = GeneticCode::Standard;
+        let mut table = String::from("# standard code with TGA read as W\n");
+        for (i, first) in "TCAG".chars().enumerate() {
+            for (j, second) in "TCAG".chars().enumerate() {
+                for (k, third) in "TCAG".chars().enumerate() {
+                    let codon = format!("{first}{second}{third}");
+                    let amino_acid = if codon == "TGA" {
+                        'W'
+                    } else {
+                        char::from(standard.amino_acids()[i * 16 + j * 4 + k])
+                    };
+                    let _ = writeln!(table, "{codon}\t{amino_acid}");
+                }
+            }
+        }
+        let path = std::env::temp_dir().join("clean_genes_codon_table.tsv");
+        std::fs::write(&path, &table).unwrap();
+
+        let (code, missing) = GeneticCode::from_table_file(&path).unwrap();
+        assert!(missing.is_empty());
+        assert_eq!(code.translate_codon(b"TGA"), b'W');
+        assert_eq!(code.translate_codon(b"uga"), b'W');
+        assert_eq!(code.translate_codon(b"ATG"), b'M');
+        assert_eq!(code.stop_codons(), [*b"TAA", *b"TAG"]);
+    }
+
+    #[test]
+    fn codon_table_file_problems() {
+        let path = std::env::temp_dir().join("clean_genes_partial_codon_table.tsv");
+        std::fs::write(&path, "ATG\tM\nTAA\t*\n").unwrap();
+        let (code, missing) = GeneticCode::from_table_file(&path).unwrap();
+        assert_eq!(missing.len(), 62);
+        assert_eq!(code.translate_codon(b"TTT"), b'X');
+        assert_eq!(code.translate_codon(b"TAA"), b'*');
+
+        for (contents, problem) in [
+            ("ATG M\n", "line 1"),
+            ("ATG\tM\nANG\tK\n", "invalid codon, 'ANG'"),
+            ("ATG\tM\nATG\tM\n", "repeats the codon 'ATG'"),
+            ("ATG\tMet\n", "single amino acid"),
+            ("ATG\tZ\n", "invalid amino acid, 'Z'"),
+        ] {
+            std::fs::write(&path, contents).unwrap();
+            let err = GeneticCode::from_table_file(&path).unwrap_err();
+            assert!(err.to_string().contains(problem), "{err}");
+        }
+    }
+
+    #[test]
+    fn composition_of_maaac() {
+        let composition = amino_acid_composition(b"MaA-AC*");
+        assert_eq!(composition.len(), 3);
+        assert!((composition[&b'A'] - 0.6).abs() < 1e-9);
+        assert!((composition[&b'M'] - 0.2).abs() < 1e-9);
+        assert!((composition[&b'C'] - 0.2).abs() < 1e-9);
+        assert!(amino_acid_composition(b"--*").is_empty());
+
+        let mut fasta = Fasta::new("proteins.faa");
+        fasta.add(FastaEntry::new(String::from("p1"), b"MAAAC".to_vec(), 0));
+        fasta.add(FastaEntry::new(String::from("p2"), b"MXX".to_vec(), 1));
+        let path = std::env::temp_dir().join("clean_genes_aa_composition.tsv");
+        write_aa_composition_tsv(&fasta, &path).unwrap();
+        let table = std::fs::read_to_string(&path).unwrap();
+        let rows: Vec<Vec<&str>> = table
+            .lines()
+            .map(|line| line.split('\t').collect())
+            .collect();
+        assert_eq!(rows[0].len(), 22);
+        assert_eq!(rows[0][1], "A");
+        assert_eq!(rows[0][21], "X");
+        assert_eq!(rows[1][..2], ["p1", "0.6000"]);
+        assert_eq!(rows[2][21], "0.6667");
+    }
+
+    #[test]
+    fn weight_of_acde() {
+        assert!((molecular_weight(b"ACDE") - 436.44).abs() < 0.1);
+        assert!((molecular_weight(b"ac-de*") - 436.44).abs() < 0.1);
+        assert!(molecular_weight(b"").abs() < f64::EPSILON);
+        assert!(molecular_weight(b"--*").abs() < f64::EPSILON);
+
+        let mut fasta = Fasta::new("proteins.faa");
+        fasta.add(FastaEntry::new(String::from("p1"), b"ACDE".to_vec(), 0));
+        fasta.add(FastaEntry::new(String::from("empty"), Vec::new(), 1));
+        let weights = molecular_weight_fasta(&fasta);
+        assert_eq!(weights[1].0, "empty");
+        assert!(weights[1].1.abs() < f64::EPSILON);
+        assert!((weights[0].1 - 436.44).abs() < 0.1);
+    }
+
+    #[test]
+    fn isoelectric_points() {
+        assert!((isoelectric_point(b"EEEEEEEEEE") - 3.2).abs() < 0.15);
+        assert!((isoelectric_point(b"KKKKK") - 10.5).abs() < 0.15);
+        assert!((isoelectric_point(b"A") - 5.525).abs() < 0.01);
+        assert!((isoelectric_point(b"a-*") - 5.525).abs() < 0.01);
+        assert!(isoelectric_point(b"---").is_nan());
+        assert!(isoelectric_point(b"").is_nan());
+    }
+
+    #[test]
+    fn kyte_doolittle_profiles() {
+        assert!(mean_hydrophobicity(b"ILVAMFILVA") > 0.0);
+        assert!(mean_hydrophobicity(b"RKDENQRKDE") < 0.0);
+        assert!((mean_hydrophobicity(b"i-L*") - 4.15).abs() < 1e-9);
+        assert!(mean_hydrophobicity(b"--").is_nan());
+
+        let profile = hydrophobicity(b"ILVAMRKDEN", 3);
+        assert_eq!(profile.len(), 10 - 3 + 1);
+        assert!((profile[0] - (4.5 + 3.8 + 4.2) / 3.0).abs() < 1e-9);
+        assert!(profile[0] > 0.0 && profile[7] < 0.0);
+        assert!(hydrophobicity(b"ILV", 4).is_empty());
+        assert!(hydrophobicity(b"ILV", 0).is_empty());
+
+        let mut fasta = Fasta::new("proteins.faa");
+        fasta.add(FastaEntry::new(String::from("p1"), b"ILVAM".to_vec(), 0));
+        let path = std::env::temp_dir().join("clean_genes_hydrophobicity.tsv");
+        write_hydrophobicity_tsv(&fasta, 3, &path).unwrap();
+        let table = std::fs::read_to_string(&path).unwrap();
+        let rows: Vec<&str> = table.lines().collect();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[1], "p1\t2\t4.167");
+    }
+
+    #[test]
+    fn ha_signal_peptide() {
+        let signal = b"MKTIIALSYIFCLVFA";
+        let mature = b"QDLPGNDNSTATLCLGHHAVPNGTLVKTITDDQIEVTNATELV";
+        let ha = [&signal[..], &mature[..]].concat();
+        assert_eq!(detect_signal_peptide_length(&ha, 30), 16);
+        assert_eq!(detect_signal_peptide_length(mature, 30), 0);
+        assert_eq!(detect_signal_peptide_length(b"MTIIALSYIFCLVFAQ", 30), 0);
+
+        let mut fasta = Fasta::new("ha.faa");
+        fasta.add(FastaEntry::new(String::from("ha"), ha, 0));
+        fasta.add(FastaEntry::new(String::from("mature"), mature.to_vec(), 1));
+        let trimmed = trim_signal_peptide(&fasta, 30, "./output.fasta").unwrap();
+        assert_eq!(&*trimmed.indexed_entry(0).sequence(), mature);
+        assert_eq!(
+            trimmed.indexed_entry(0).get_meta("signal_peptide_length"),
+            Some("16")
+        );
+        assert_eq!(&*trimmed.indexed_entry(1).sequence(), mature);
+
+        let mut nucleotides = Fasta::new("nucleotides.faa");
+        nucleotides.add(FastaEntry::new(String::from("rna"), b"AUGAAA".to_vec(), 0));
+        assert!(trim_signal_peptide(&nucleotides, 30, "./output.fasta").is_err());
+    }
+}
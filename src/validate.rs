@@ -0,0 +1,278 @@
+use crate::alignment::alignment_length;
+use crate::fasta_manager::{is_gap, is_iupac, is_protein_char, Fasta, MoleculeType};
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+/// How serious a validation finding is. Errors fail validation; warnings only
+/// fail it with `--warnings-as-errors`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// One problem found while validating an input file
+#[derive(Debug, PartialEq)]
+pub(crate) struct Finding {
+    severity: Severity,
+    check: &'static str,
+    message: String,
+}
+
+impl Finding {
+    /// Constructor for an error finding
+    pub(crate) fn error(check: &'static str, message: impl Into<String>) -> Self {
+        Finding {
+            severity: Severity::Error,
+            check,
+            message: message.into(),
+        }
+    }
+
+    /// Constructor for a warning finding
+    pub(crate) fn warning(check: &'static str, message: impl Into<String>) -> Self {
+        Finding {
+            severity: Severity::Warning,
+            check,
+            message: message.into(),
+        }
+    }
+
+    /// Returns how serious the finding is
+    pub(crate) fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Returns the name of the check that produced the finding
+    pub(crate) fn check(&self) -> &str {
+        self.check
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]: {}", self.severity, self.check, self.message)
+    }
+}
+
+/// The main function of the `Validate` module. Checks a parsed Fasta for
+/// characters outside the alphabet of `molecule_type` (IUPAC codes or amino
+/// acids, plus gaps), duplicate deflines, sequences of differing lengths, and
+/// entries that carry no information. Returns every finding, errors and
+/// warnings alike, in the order the checks ran.
+pub(crate) fn validate(fasta: &Fasta, molecule_type: MoleculeType) -> Vec<Finding> {
+    let (is_allowed, alphabet): (fn(u8) -> bool, &str) = match molecule_type {
+        MoleculeType::Nucleotide => (|base| is_iupac(base) || is_gap(base), "IUPAC codes"),
+        MoleculeType::Protein => (is_protein_char, "amino acids"),
+    };
+    let mut findings = Vec::new();
+    if fasta.num_entries() == 0 {
+        findings.push(Finding::error("empty_input", "input contains no sequences"));
+        return findings;
+    }
+
+    for entry in fasta {
+        let sequence = entry.sequence();
+        if let Some(col) = sequence.iter().position(|&base| !is_allowed(base)) {
+            let illegal = sequence[col..]
+                .iter()
+                .filter(|&&base| !is_allowed(base))
+                .count();
+            findings.push(Finding::error(
+                "illegal_characters",
+                format!(
+                    "sequence '{}' has {illegal} character(s) that are not {alphabet} or gaps, \
+                    first '{}' at column {}",
+                    entry.defline(),
+                    sequence[col].escape_ascii(),
+                    col + 1
+                ),
+            ));
+        }
+        //N is asparagine in a protein, where X is the unknown residue instead
+        let (no_information, unknown) = match molecule_type {
+            MoleculeType::Nucleotide => (entry.is_all_n(), "Ns"),
+            MoleculeType::Protein => (
+                sequence
+                    .iter()
+                    .all(|&residue| is_gap(residue) || residue.eq_ignore_ascii_case(&b'X')),
+                "Xs",
+            ),
+        };
+        if no_information {
+            findings.push(Finding::warning(
+                "no_information",
+                format!(
+                    "sequence '{}' is empty or only gaps and {unknown}",
+                    entry.defline()
+                ),
+            ));
+        }
+    }
+
+    let mut defline_counts: HashMap<String, usize> = HashMap::new();
+    for entry in fasta {
+        *defline_counts.entry(entry.defline()).or_default() += 1;
+    }
+    let mut duplicates: Vec<(String, usize)> = defline_counts
+        .into_iter()
+        .filter(|&(_, count)| count > 1)
+        .collect();
+    duplicates.sort();
+    for (defline, count) in duplicates {
+        findings.push(Finding::error(
+            "duplicate_defline",
+            format!("defline '{defline}' appears {count} times"),
+        ));
+    }
+
+    if let Err(err) = alignment_length(fasta) {
+        findings.push(Finding::warning("unequal_lengths", err.to_string()));
+    }
+    findings
+}
+
+/// Returns true if the findings should fail validation
+pub(crate) fn has_failures(findings: &[Finding], warnings_as_errors: bool) -> bool {
+    findings
+        .iter()
+        .any(|finding| finding.severity == Severity::Error || warnings_as_errors)
+}
+
+/// Escapes a string for use inside a JSON string literal
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                // Writing to a String cannot fail
+                let _ = write!(escaped, "\\u{:04x}", u32::from(c));
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats the findings as a JSON object with the error and warning counts
+/// and a list of findings
+pub(crate) fn findings_json(findings: &[Finding]) -> String {
+    let count = |severity| {
+        findings
+            .iter()
+            .filter(|finding| finding.severity == severity)
+            .count()
+    };
+    let items: Vec<String> = findings
+        .iter()
+        .map(|finding| {
+            format!(
+                "{{\"severity\": \"{}\", \"check\": \"{}\", \"message\": \"{}\"}}",
+                finding.severity,
+                finding.check,
+                json_escape(&finding.message)
+            )
+        })
+        .collect();
+    format!(
+        "{{\"errors\": {}, \"warnings\": {}, \"findings\": [{}]}}\n",
+        count(Severity::Error),
+        count(Severity::Warning),
+        items.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{findings_json, has_failures, validate, Finding, Severity};
+    use crate::fasta_manager::{open_fasta, Fasta, FastaEntry, MoleculeType};
+
+    #[test]
+    fn clean_fixture_only_warns() {
+        let fasta = open_fasta("fake_short.fna").unwrap();
+        let findings = validate(&fasta, MoleculeType::Nucleotide);
+        assert_eq!(
+            findings,
+            [Finding::warning(
+                "no_information",
+                "sequence 'ns' is empty or only gaps and Ns"
+            )]
+        );
+        assert!(!has_failures(&findings, false));
+        assert!(has_failures(&findings, true));
+    }
+
+    #[test]
+    fn broken_fasta_errors() {
+        let mut fasta = Fasta::new("broken.fna");
+        fasta.add(FastaEntry::new(String::from("dup"), b"ATG".to_vec(), 0));
+        fasta.add(FastaEntry::new(String::from("dup"), b"AJG*".to_vec(), 1));
+        fasta.add(FastaEntry::new(String::from("ok"), b"ATG".to_vec(), 2));
+
+        let findings = validate(&fasta, MoleculeType::Nucleotide);
+        let checks: Vec<(Severity, &str)> = findings
+            .iter()
+            .map(|finding| (finding.severity(), finding.check()))
+            .collect();
+        assert_eq!(
+            checks,
+            [
+                (Severity::Error, "illegal_characters"),
+                (Severity::Error, "duplicate_defline"),
+                (Severity::Warning, "unequal_lengths")
+            ]
+        );
+        assert_eq!(
+            findings[0].to_string(),
+            "error [illegal_characters]: sequence 'dup' has 2 character(s) that are not IUPAC \
+            codes or gaps, first 'J' at column 2"
+        );
+        assert!(has_failures(&findings, false));
+    }
+
+    #[test]
+    fn protein_alphabet() {
+        let mut fasta = Fasta::new("proteins.faa");
+        fasta.add(FastaEntry::new(String::from("p1"), b"MKNW*".to_vec(), 0));
+        fasta.add(FastaEntry::new(String::from("p2"), b"XX-X-".to_vec(), 1));
+
+        let findings = validate(&fasta, MoleculeType::Protein);
+        assert_eq!(
+            findings,
+            [Finding::warning(
+                "no_information",
+                "sequence 'p2' is empty or only gaps and Xs"
+            )]
+        );
+        let findings = validate(&fasta, MoleculeType::Nucleotide);
+        let checks: Vec<&str> = findings.iter().map(Finding::check).collect();
+        assert_eq!(checks, ["illegal_characters", "illegal_characters"]);
+    }
+
+    #[test]
+    fn json_is_escaped() {
+        let findings = [Finding::error(
+            "duplicate_defline",
+            "defline 'a\"b\\c' twice",
+        )];
+        assert_eq!(
+            findings_json(&findings),
+            "{\"errors\": 1, \"warnings\": 0, \"findings\": [{\"severity\": \"error\", \
+            \"check\": \"duplicate_defline\", \"message\": \"defline 'a\\\"b\\\\c' twice\"}]}\n"
+        );
+    }
+}
@@ -0,0 +1,489 @@
+//! End-to-end tests that run the clean-genes binary and inspect its output
+
+use std::process::{Command, Output};
+
+/// Runs clean-genes with the given arguments
+fn run_clean_genes(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_clean-genes"))
+        .args(args)
+        .output()
+        .expect("failed to run clean-genes")
+}
+
+#[test]
+fn zero_entry_output_warns() {
+    let output = run_clean_genes(&["-i", "test_data/all_gaps.fna", "-m", "DropEmpty"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(stderr.contains("Warning: writing output '-' with no sequences"));
+}
+
+#[test]
+fn zero_entry_output_fails_when_strict() {
+    let output = run_clean_genes(&[
+        "-i",
+        "test_data/all_gaps.fna",
+        "-m",
+        "DropEmpty",
+        "--strict",
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(stderr.contains("output contains no sequences"));
+}
+
+#[test]
+fn dry_run_writes_nothing() {
+    let out_path = std::env::temp_dir().join("clean_genes_dry_run.fna");
+    let _ = std::fs::remove_file(&out_path);
+    let output = run_clean_genes(&[
+        "-i",
+        "test_data/a_ha_h3_raw_500.fna",
+        "-m",
+        "TrimToORF",
+        "-o",
+        out_path.to_str().unwrap(),
+        "--dry-run",
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success());
+    assert!(!out_path.exists());
+    assert!(stderr.contains(&format!(
+        "Dry run: would write 17 sequence(s) to '{}'",
+        out_path.display()
+    )));
+    assert!(stderr.contains("Dry run: group_start_locus = "));
+    assert!(stderr.contains("Dry run: group_stop_locus = "));
+}
+
+#[test]
+fn chained_filters_share_one_rejects_pair() {
+    let prefix = std::env::temp_dir().join("clean_genes_chained_rejects");
+    let fasta_path = prefix.with_extension("fna");
+    let table_path = prefix.with_extension("tsv");
+    let _ = std::fs::remove_file(&fasta_path);
+    let _ = std::fs::remove_file(&table_path);
+    let output = run_clean_genes(&[
+        "-i",
+        "fake_short.fna",
+        "-m",
+        "DropEmpty,FilterGappy",
+        "--max-gap-fraction",
+        "0.25",
+        "--rejects",
+        prefix.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.matches('>').count(), 4);
+
+    let rejects_fasta = std::fs::read_to_string(&fasta_path).unwrap();
+    assert_eq!(rejects_fasta.matches('>').count(), 5);
+    assert!(rejects_fasta.starts_with(">ns module=DropEmpty reason=empty\n"));
+    assert!(rejects_fasta.contains(">first module=FilterGappy reason=gap_fraction\n"));
+
+    let rejects_table = std::fs::read_to_string(&table_path).unwrap();
+    let rows: Vec<&str> = rejects_table.lines().collect();
+    assert_eq!(rows[0], "defline\tmodule\treason\tmetric\tinput_position");
+    assert_eq!(rows[1], "ns\tDropEmpty\tempty\t\t8");
+    assert!(rows[2].starts_with("first\tFilterGappy\tgap_fraction\t0.307"));
+    assert_eq!(rows.len(), 6);
+}
+
+#[test]
+fn trim_prints_module_stats() {
+    let out_path = std::env::temp_dir().join("clean_genes_stats.fna");
+    let out_arg = out_path.to_str().unwrap();
+    let output = run_clean_genes(&["-i", "fake_short.fna", "-m", "TrimToORF", "-o", out_arg]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success());
+
+    let row: Vec<&str> = stderr
+        .lines()
+        .find(|line| line.starts_with("TrimToORF "))
+        .expect("no stats row for TrimToORF")
+        .split_whitespace()
+        .collect();
+    assert_eq!(row[..6], ["TrimToORF", "9", "9", "0", "36", "0"]);
+    assert!(stderr.contains("    group_start_locus = 3\n"));
+    assert!(stderr.contains("    group_stop_locus = 9\n"));
+
+    let quiet = run_clean_genes(&[
+        "-i",
+        "fake_short.fna",
+        "-m",
+        "TrimToORF",
+        "-o",
+        out_arg,
+        "--quiet",
+    ]);
+    assert!(!String::from_utf8_lossy(&quiet.stderr).contains("bases_removed"));
+    let _ = std::fs::remove_file(&out_path);
+}
+
+#[test]
+fn validate_clean_fixture_passes() {
+    let output = run_clean_genes(&["-i", "fake_short.fna", "-m", "Validate"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let json = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert!(stderr.contains("Validation found 0 error(s) and 1 warning(s)"));
+    assert!(json.starts_with("{\"errors\": 0, \"warnings\": 1,"));
+
+    let strict = run_clean_genes(&[
+        "-i",
+        "fake_short.fna",
+        "-m",
+        "Validate",
+        "--warnings-as-errors",
+    ]);
+    assert!(!strict.status.success());
+}
+
+#[test]
+fn validate_broken_fixture_fails() {
+    let inp_path = std::env::temp_dir().join("clean_genes_validate_broken.fna");
+    std::fs::write(&inp_path, ">dup\nATG\n>dup\nAXG\n>short\nAT\n").unwrap();
+    let output = run_clean_genes(&["-i", inp_path.to_str().unwrap(), "-m", "Validate"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(stderr.contains("Validation found 2 error(s) and 1 warning(s)"));
+    assert!(stderr.contains("error [illegal_characters]: sequence 'dup'"));
+    assert!(stderr.contains("error [duplicate_defline]: defline 'dup' appears 2 times"));
+    assert!(stderr.contains("warning [unequal_lengths]: Sequence 'short'"));
+
+    std::fs::write(&inp_path, ">one\nATG\nA>TG\n").unwrap();
+    let output = run_clean_genes(&["-i", inp_path.to_str().unwrap(), "-m", "Validate"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("error [parse]: line 3"));
+    let _ = std::fs::remove_file(&inp_path);
+}
+
+#[test]
+fn low_confidence_exits_after_writing() {
+    let out_path = std::env::temp_dir().join("clean_genes_low_confidence.fna");
+    let _ = std::fs::remove_file(&out_path);
+    let output = run_clean_genes(&[
+        "-i",
+        "fake_short.fna",
+        "-m",
+        "TrimToORF",
+        "-o",
+        out_path.to_str().unwrap(),
+        "--min-confidence",
+        "0.9",
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(out_path.exists());
+    assert!(stderr.contains("ORF confidence 0.780 is below the minimum of 0.9"));
+
+    let output = run_clean_genes(&[
+        "-i",
+        "fake_short.fna",
+        "-m",
+        "TrimToORF",
+        "-o",
+        out_path.to_str().unwrap(),
+        "--min-confidence",
+        "0.5",
+    ]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn report_and_fasta_go_to_separate_files() {
+    let out_path = std::env::temp_dir().join("clean_genes_report_split.fna");
+    let report_path = std::env::temp_dir().join("clean_genes_report_split.txt");
+    let output = run_clean_genes(&[
+        "-i",
+        "fake_short.fna",
+        "-m",
+        "TrimToORF",
+        "-o",
+        out_path.to_str().unwrap(),
+        "--report",
+        report_path.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+
+    let fasta = std::fs::read_to_string(&out_path).unwrap();
+    let report = std::fs::read_to_string(&report_path).unwrap();
+    assert!(fasta.starts_with('>'));
+    assert!(!fasta.contains("group_start_locus"));
+    assert!(report.contains("    group_start_locus = 3\n"));
+    assert!(report.contains("    orf_confidence = "));
+    assert!(report.contains("    orf_stop_agreement = "));
+    assert!(!report.contains('>'));
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("group_start_locus"));
+
+    let to_stdout = run_clean_genes(&["-i", "fake_short.fna", "--report", "-"]);
+    assert!(!to_stdout.status.success());
+    let _ = std::fs::remove_file(&out_path);
+    let _ = std::fs::remove_file(&report_path);
+}
+
+#[test]
+fn fasta_and_stats_from_one_run() {
+    let out_path = std::env::temp_dir().join("clean_genes_formats.fna");
+    let stats_path = std::env::temp_dir().join("clean_genes_formats.stats.tsv");
+    let _ = std::fs::remove_file(&stats_path);
+    let output = run_clean_genes(&[
+        "-i",
+        "fake_short.fna",
+        "-m",
+        "TrimToORF",
+        "-o",
+        out_path.to_str().unwrap(),
+        "--output-format",
+        "fasta",
+        "stats",
+    ]);
+    assert!(output.status.success());
+
+    let fasta = std::fs::read_to_string(&out_path).unwrap();
+    let stats = std::fs::read_to_string(&stats_path).unwrap();
+    assert!(fasta.starts_with(">first\nATGATGTAG"));
+    assert!(stats.starts_with("module "));
+    assert!(stats.contains("TrimToORF"));
+    assert!(stats.contains("    group_start_locus = 3\n"));
+    assert!(!std::env::temp_dir()
+        .join("clean_genes_formats.gff3")
+        .exists());
+    let _ = std::fs::remove_file(&out_path);
+    let _ = std::fs::remove_file(&stats_path);
+}
+
+#[test]
+fn tiny_budgets_stop_the_run() {
+    let output = run_clean_genes(&[
+        "-i",
+        "fake_short.fna",
+        "-m",
+        "TrimToORF",
+        "--max-runtime-secs",
+        "0.000000001",
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("Timed out: the run took longer than --max-runtime-secs"));
+    assert!(output.stdout.is_empty());
+
+    let output = run_clean_genes(&[
+        "-i",
+        "fake_short.fna",
+        "-m",
+        "TrimToORF",
+        "--max-input-bytes",
+        "100",
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("Input is larger than --max-input-bytes, 100 bytes"));
+}
+
+#[test]
+fn rename_reports_unmatched_and_fails_when_strict() {
+    let map_path = std::env::temp_dir().join("clean_genes_cli_rename.tsv");
+    std::fs::write(
+        &map_path,
+        "first\tsample_1\nsecond\tsample_2\nmissing\tsample_3\n",
+    )
+    .unwrap();
+    let map = map_path.to_str().unwrap();
+
+    let output = run_clean_genes(&["-i", "fake_short.fna", "-m", "Rename", "--map", map]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success());
+    assert!(stdout.starts_with(">sample_1\n"));
+    assert!(stdout.contains("\n>sample_2\n"));
+    assert!(stdout.contains("\n>first_small\n"));
+    assert!(stderr.contains("Renamed 2 sequence(s); 7 left unmatched; 1 map row(s) unused"));
+
+    let output = run_clean_genes(&[
+        "-i",
+        "fake_short.fna",
+        "-m",
+        "Rename",
+        "--map",
+        map,
+        "--strict",
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let _ = std::fs::remove_file(&map_path);
+    assert!(!output.status.success());
+    assert!(stderr.contains("no row of the map matches:\n\tthird\n"));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn tiny_inputs_to_trim_to_orf() {
+    let output = run_clean_genes(&["-i", "test_data/empty_file.fna", "-m", "TrimToORF"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("TrimToORF needs at least 1 sequence(s), but its input has 0"));
+
+    for num_seqs in [1, 2] {
+        let path = std::env::temp_dir().join(format!("clean_genes_tiny_{num_seqs}.fna"));
+        std::fs::write(&path, ">seq\nCCATGAAATAGCC\n".repeat(num_seqs)).unwrap();
+        let output = run_clean_genes(&["-i", path.to_str().unwrap(), "-m", "TrimToORF"]);
+        let _ = std::fs::remove_file(&path);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success());
+        assert_eq!(stdout.matches("ATGAAATAG").count(), num_seqs);
+        assert!(stderr.contains(&format!(
+            "Warning: TrimToORF votes on the group ORF across sequences, which means little \
+            with fewer than 3; its input has {num_seqs}"
+        )));
+    }
+}
+
+#[test]
+fn reports_show_input_positions_after_filtering() {
+    let dir = std::env::temp_dir();
+    let inp_path = dir.join("clean_genes_positions.fna");
+    let prefix = dir.join("clean_genes_positions_rejects");
+    let start_report = dir.join("clean_genes_positions_starts.tsv");
+    std::fs::write(
+        &inp_path,
+        ">one\nATGAAACCCTAG\n>two\nATGAAACCCTAG\n>three\nATG---------\n\
+        >four\nATGAAACCCTAG\n>five\nATGAAACCCTAG\n",
+    )
+    .unwrap();
+    let output = run_clean_genes(&[
+        "-i",
+        inp_path.to_str().unwrap(),
+        "-m",
+        "FilterGappy,TrimToORF",
+        "--max-gap-fraction",
+        "0.5",
+        "--rejects",
+        prefix.to_str().unwrap(),
+        "--start-report",
+        start_report.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+
+    let rejects_table = std::fs::read_to_string(prefix.with_extension("tsv")).unwrap();
+    let rows: Vec<&str> = rejects_table.lines().collect();
+    assert_eq!(rows[0], "defline\tmodule\treason\tmetric\tinput_position");
+    assert_eq!(rows[1], "three\tFilterGappy\tgap_fraction\t0.75\t3");
+
+    // Rows follow TrimToORF's own input, so the row number and the position
+    // in the original input part ways after the filtered entry
+    let starts = std::fs::read_to_string(&start_report).unwrap();
+    let positions: Vec<(&str, &str)> = starts
+        .lines()
+        .skip(1)
+        .map(|row| {
+            let fields: Vec<&str> = row.split('\t').collect();
+            (fields[0], fields[3])
+        })
+        .collect();
+    assert_eq!(
+        positions,
+        [("one", "1"), ("two", "2"), ("four", "4"), ("five", "5")]
+    );
+}
+
+#[test]
+fn output_formats_cannot_share_a_path() {
+    let out_path = std::env::temp_dir().join("clean_genes_same_path.gff3");
+    let _ = std::fs::remove_file(&out_path);
+    let output = run_clean_genes(&[
+        "-i",
+        "fake_short.fna",
+        "-m",
+        "DropEmpty",
+        "-o",
+        out_path.to_str().unwrap(),
+        "--output-format",
+        "fasta,gff3",
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(stderr.contains("The output fasta and the GFF3 file would both be written to"));
+    assert!(!out_path.exists());
+}
+
+#[test]
+fn output_case_and_missing_data_options() {
+    let output = run_clean_genes(&[
+        "-i",
+        "fake_short.fna",
+        "-m",
+        "DropEmpty",
+        "--recode-missing",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert!(stdout.starts_with(">first\n??ATGATGTAG??\n"));
+
+    let output = run_clean_genes(&[
+        "-i",
+        "test_data/one_record.embl",
+        "-m",
+        "DropEmpty",
+        "--uppercase",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert!(stdout.contains("\nATGAAGACTATCATTGCTTTGAGC"));
+}
+
+#[test]
+fn trim_modes_conflict() {
+    let output = run_clean_genes(&[
+        "-i",
+        "test_data/missing_stops.fna",
+        "-m",
+        "TrimToORF",
+        "--per-sequence",
+        "--protein-reference",
+        "test_data/misleading_atg_reference.faa",
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(stderr.contains(
+        "--per-sequence and --protein-reference each choose a different way to find the ORF"
+    ));
+}
+
+#[test]
+fn trim_lists_every_entry_without_a_stop() {
+    let output = run_clean_genes(&["-i", "test_data/missing_stops.fna", "-m", "TrimToORF"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).matches('>').count(),
+        9
+    );
+    assert!(stderr.contains("Warning: 2 sequence(s) failed and were trimmed to the group ORF"));
+    assert!(stderr.contains("\tentry 3 '"));
+    assert!(stderr.contains("\tentry 7 '"));
+
+    let strict = run_clean_genes(&[
+        "-i",
+        "test_data/missing_stops.fna",
+        "-m",
+        "TrimToORF",
+        "--strict",
+    ]);
+    let stderr = String::from_utf8_lossy(&strict.stderr);
+    assert!(!strict.status.success());
+    assert!(stderr.contains("Failed to trim to ORF; 2 sequence(s) failed:"));
+    assert!(stderr.contains("\tentry 3 '") && stderr.contains("\tentry 7 '"));
+}